@@ -91,7 +91,15 @@ pub extern "C" fn rfc5444_read_packet(
         }
         Err(e) => match e {
             rfc5444::Error::UnexpectedEof => return -libc::EOF,
-            rfc5444::Error::PrefixTooLarge | rfc5444::Error::InvalidVersion => {
+            rfc5444::Error::PrefixTooLarge
+            | rfc5444::Error::InvalidVersion
+            | rfc5444::Error::InvalidTlvValue
+            | rfc5444::Error::BufferTooSmall
+            | rfc5444::Error::InvalidKey
+            | rfc5444::Error::InvalidSignature
+            | rfc5444::Error::InvalidTlvIndex
+            | rfc5444::Error::InvalidAddressBlock
+            | rfc5444::Error::InvalidMessage => {
                 return -libc::EINVAL;
             }
         },
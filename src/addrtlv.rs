@@ -8,11 +8,14 @@
 // option. This file may not be copied, modified, or distributed
 // except according to those terms.
 
-use crate::{Buf, Error, TlvBlock};
+use crate::{Buf, BufMut, Error, Tlv, TlvBlock};
 
 /// Maximum length of an address in octets.
 pub const MAX_ADDR_LEN: usize = 16;
 
+/// Source of the bytes of zero tails.
+static ZEROS: [u8; MAX_ADDR_LEN] = [0u8; MAX_ADDR_LEN];
+
 /// Address-TLVs
 #[derive(Debug, Clone, Eq, PartialEq)]
 pub struct AddressTlvs<'a> {
@@ -28,6 +31,133 @@ impl<'a> AddressTlvs<'a> {
             buf: self.buf.clone(),
         }
     }
+
+    /// Iterator over the distinct addresses with their prefix length.
+    pub(crate) fn unique(&self) -> UniqueAddresses<'a> {
+        UniqueAddresses {
+            address_tlv: self.clone(),
+            blocks: self.iter(),
+            current: None,
+            block: 0,
+            index: 0,
+        }
+    }
+
+    /// Call `f` with the TLVs of every appearance of `addr` with prefix
+    /// length `prefix_length`, and their value for it.
+    pub(crate) fn for_each_tlv<F>(
+        &self,
+        addr: &Address,
+        prefix_length: Option<u8>,
+        mut f: F,
+    ) -> Result<(), Error>
+    where
+        F: FnMut(&Tlv<'a>, &'a [u8]) -> Result<(), Error>,
+    {
+        for block in self.iter() {
+            let (block, tlvs) = block?;
+            for i in 0..block.num_addr {
+                if block.get_addr(i) != *addr
+                    || self.prefix_length(&block, i) != prefix_length
+                {
+                    continue;
+                }
+
+                for tlv in tlvs.iter() {
+                    let tlv = tlv?;
+                    if let Some(value) = tlv.value_at(i, block.num_addr)? {
+                        f(&tlv, value)?;
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Prefix length of an address, `None` if it's the maximum.
+    fn prefix_length(&self, block: &AddressBlock, index: usize) -> Option<u8> {
+        block
+            .prefix_length(index)
+            .filter(|p| usize::from(*p) != 8 * self.address_length)
+    }
+
+    /// Did `addr` appear before the address `index` of the address block
+    /// number `block`?
+    fn seen_before(
+        &self,
+        block: usize,
+        index: usize,
+        addr: &Address,
+        prefix_length: Option<u8>,
+    ) -> bool {
+        for (n, b) in self.iter().enumerate().take(block + 1) {
+            let b = match b {
+                Ok((b, _)) => b,
+                Err(_) => return false,
+            };
+            let end = if n == block { index } else { b.num_addr };
+            let seen = (0..end).any(|i| {
+                b.get_addr(i) == *addr
+                    && self.prefix_length(&b, i) == prefix_length
+            });
+            if seen {
+                return true;
+            }
+        }
+
+        false
+    }
+}
+
+/// Iterator over the distinct addresses of the address blocks of a message,
+/// with their prefix length (`None` for the maximum), in order of first
+/// appearance. An address with different prefix lengths is given once for
+/// each.
+#[derive(Debug)]
+pub(crate) struct UniqueAddresses<'a> {
+    address_tlv: AddressTlvs<'a>,
+    blocks: AddressTlvIter<'a>,
+    current: Option<AddressBlock<'a>>,
+    /// Number of the current address block.
+    block: usize,
+    /// Index of the next address in the current address block.
+    index: usize,
+}
+
+impl<'a> Iterator for UniqueAddresses<'a> {
+    type Item = (Address, Option<u8>);
+
+    fn next(&mut self) -> Option<(Address, Option<u8>)> {
+        loop {
+            let block = match self.current {
+                Some(ref b) if self.index < b.num_addr => b,
+                _ => {
+                    let first = self.current.is_none();
+                    let (block, _) = self.blocks.next()?.ok()?;
+                    if !first {
+                        self.block += 1;
+                    }
+                    self.current = Some(block);
+                    self.index = 0;
+                    continue;
+                }
+            };
+
+            let i = self.index;
+            self.index += 1;
+            let addr = block.get_addr(i);
+            let prefix_length = self.address_tlv.prefix_length(block, i);
+            if !self.address_tlv.seen_before(
+                self.block,
+                i,
+                &addr,
+                prefix_length,
+            ) {
+                return Some((addr, prefix_length));
+            }
+        }
+    }
 }
 
 /// Iterator over a TLV block
@@ -64,13 +194,28 @@ impl<'a> Iterator for AddressTlvIter<'a> {
 }
 
 /// An abstract address
-#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Ord, PartialOrd, Hash)]
 pub struct Address {
     buf: [u8; MAX_ADDR_LEN],
     len: usize,
 }
 
 impl Address {
+    /// Create an address from its bytes, returns `None` if the address is
+    /// larger than [`MAX_ADDR_LEN`](constant.MAX_ADDR_LEN.html).
+    pub fn from_bytes(bytes: &[u8]) -> Option<Address> {
+        if bytes.len() > MAX_ADDR_LEN {
+            return None;
+        }
+
+        let mut buf = [0u8; MAX_ADDR_LEN];
+        buf[..bytes.len()].copy_from_slice(bytes);
+        Some(Address {
+            buf,
+            len: bytes.len(),
+        })
+    }
+
     /// Get the bytes of the address.
     pub fn as_bytes(&self) -> &[u8] {
         &self.buf[..self.len]
@@ -80,6 +225,31 @@ impl Address {
     pub fn len(&self) -> usize {
         self.len
     }
+
+    /// Is the address empty?
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Is the address in the network `prefix/prefix_length`?
+    pub fn in_prefix(&self, prefix: &Address, prefix_length: u8) -> bool {
+        let bits = usize::from(prefix_length);
+        if self.len != prefix.len || bits > 8 * self.len {
+            return false;
+        }
+
+        let (bytes, rest) = (bits / 8, bits % 8);
+        let mask = !(0xffu8 >> rest);
+        self.buf[..bytes] == prefix.buf[..bytes]
+            && (rest == 0
+                || (self.buf[bytes] & mask) == (prefix.buf[bytes] & mask))
+    }
+}
+
+impl AsRef<Address> for Address {
+    fn as_ref(&self) -> &Address {
+        self
+    }
 }
 
 /// Address block
@@ -118,6 +288,7 @@ impl<'a> AddressBlock<'a> {
         // Parse (<tail-length><tail>?)?
         let mut tail_length = 0;
         let mut tail = None;
+        let mut zero_tail = false;
         let has_full_tail =
             addr_flags.contains(AddressBlockFlags::HAS_FULL_TAIL);
         let has_zero_tail =
@@ -136,11 +307,18 @@ impl<'a> AddressBlock<'a> {
             // parse <tail-length>
             (false, true) => {
                 tail_length = buf.get_u8().map(usize::from)?;
+                zero_tail = true;
             }
         }
 
         // Parse <mid>*
-        let mid_length = address_length - head_length - tail_length;
+        let mid_length = address_length
+            .checked_sub(head_length + tail_length)
+            .ok_or(Error::InvalidAddressBlock)?;
+        if zero_tail && tail_length != 0 {
+            tail = Some(&ZEROS[..tail_length]);
+        }
+
         let mid = if mid_length != 0 {
             Some(buf.get_bytes(mid_length * num_addr)?)
         } else {
@@ -183,6 +361,90 @@ impl<'a> AddressBlock<'a> {
         })
     }
 
+    /// Size in bytes of the `<address-block>` written by
+    /// [`write`](#method.write).
+    pub fn encoded_len<A: AsRef<Address>>(
+        address_length: usize,
+        addrs: &[A],
+        prefix_lengths: Option<&[u8]>,
+    ) -> Result<usize, Error> {
+        let c = Compression::new(address_length, addrs, prefix_lengths)?;
+        Ok(c.len())
+    }
+
+    /// Write an `<address-block>` with `addrs`, using the head and tail that
+    /// make it smaller.
+    ///
+    /// `prefix_lengths` is either a single prefix length for all the
+    /// addresses, or one for each, `None` means every address has the
+    /// maximum prefix length.
+    pub fn write<A: AsRef<Address>>(
+        buf: &mut BufMut,
+        address_length: usize,
+        addrs: &[A],
+        prefix_lengths: Option<&[u8]>,
+    ) -> Result<(), Error> {
+        let c = Compression::new(address_length, addrs, prefix_lengths)?;
+        let first = addrs[0].as_ref().as_bytes();
+
+        let mut flags = AddressBlockFlags::empty();
+        if c.head != 0 {
+            flags |= AddressBlockFlags::HAS_HEAD;
+        }
+        if c.tail != 0 {
+            if c.zero_tail {
+                flags |= AddressBlockFlags::HAS_ZERO_TAIL;
+            } else {
+                flags |= AddressBlockFlags::HAS_FULL_TAIL;
+            }
+        }
+        match c.prefix_lengths {
+            PrefixLengths::Full => (),
+            PrefixLengths::Single(_) => {
+                flags |= AddressBlockFlags::HAS_SINGLE_PRELEN
+            }
+            PrefixLengths::Multi(_) => {
+                flags |= AddressBlockFlags::HAS_MULTI_PRELEN
+            }
+        }
+
+        buf.put_u8(addrs.len() as u8)?;
+        buf.put_u8(flags.bits())?;
+        if c.head != 0 {
+            buf.put_u8(c.head as u8)?;
+            buf.put_bytes(&first[..c.head])?;
+        }
+        if c.tail != 0 {
+            buf.put_u8(c.tail as u8)?;
+            if !c.zero_tail {
+                buf.put_bytes(&first[address_length - c.tail..])?;
+            }
+        }
+        for addr in addrs {
+            let addr = addr.as_ref().as_bytes();
+            buf.put_bytes(&addr[c.head..address_length - c.tail])?;
+        }
+        match c.prefix_lengths {
+            PrefixLengths::Full => (),
+            PrefixLengths::Single(p) => buf.put_u8(p)?,
+            PrefixLengths::Multi(p) => buf.put_bytes(p)?,
+        }
+
+        Ok(())
+    }
+
+    /// Prefix length of the address at `index`, `None` if it's the maximum
+    /// prefix length (the address length in bits).
+    pub fn prefix_length(&self, index: usize) -> Option<u8> {
+        assert!(index < self.num_addr);
+
+        match self.prefix_lengths {
+            Some(p) if p.len() == 1 => Some(p[0]),
+            Some(p) => Some(p[index]),
+            None => None,
+        }
+    }
+
     /// Retrieve an address from the address block.
     pub fn get_addr(&self, index: usize) -> Address {
         assert!(index < self.num_addr);
@@ -223,6 +485,125 @@ impl<'a> AddressBlock<'a> {
     }
 }
 
+/// Prefix lengths of an address block to write.
+#[derive(Debug, Clone, Copy)]
+enum PrefixLengths<'p> {
+    Full,
+    Single(u8),
+    Multi(&'p [u8]),
+}
+
+/// Head and tail of an address block to write.
+#[derive(Debug)]
+struct Compression<'p> {
+    num_addr: usize,
+    address_length: usize,
+    head: usize,
+    tail: usize,
+    zero_tail: bool,
+    prefix_lengths: PrefixLengths<'p>,
+}
+
+impl<'p> Compression<'p> {
+    fn new<A: AsRef<Address>>(
+        address_length: usize,
+        addrs: &[A],
+        prefix_lengths: Option<&'p [u8]>,
+    ) -> Result<Compression<'p>, Error> {
+        if addrs.is_empty()
+            || addrs.len() > usize::from(core::u8::MAX)
+            || address_length == 0
+            || address_length > MAX_ADDR_LEN
+            || addrs.iter().any(|a| a.as_ref().len() != address_length)
+        {
+            return Err(Error::InvalidAddressBlock);
+        }
+
+        let max_prefix = 8 * address_length;
+        let prefix_lengths = match prefix_lengths {
+            None => PrefixLengths::Full,
+            Some(p) => {
+                if p.len() != 1 && p.len() != addrs.len() {
+                    return Err(Error::InvalidAddressBlock);
+                }
+                if p.iter().any(|p| usize::from(*p) > max_prefix) {
+                    return Err(Error::PrefixTooLarge);
+                }
+
+                if !p.iter().all(|x| *x == p[0]) {
+                    PrefixLengths::Multi(p)
+                } else if usize::from(p[0]) == max_prefix {
+                    PrefixLengths::Full
+                } else {
+                    PrefixLengths::Single(p[0])
+                }
+            }
+        };
+
+        let first = addrs[0].as_ref().as_bytes();
+        let max_head = addrs.iter().fold(address_length, |len, a| {
+            first[..len]
+                .iter()
+                .zip(a.as_ref().as_bytes())
+                .take_while(|(x, y)| x == y)
+                .count()
+        });
+        let max_tail = addrs.iter().fold(address_length, |len, a| {
+            first[address_length - len..]
+                .iter()
+                .rev()
+                .zip(a.as_ref().as_bytes().iter().rev())
+                .take_while(|(x, y)| x == y)
+                .count()
+        });
+        let zeros = first.iter().rev().take_while(|b| **b == 0).count();
+
+        let mut best = Compression {
+            num_addr: addrs.len(),
+            address_length,
+            head: 0,
+            tail: 0,
+            zero_tail: false,
+            prefix_lengths,
+        };
+        let mut best_len = best.len();
+        for head in 0..=max_head {
+            for tail in 0..=max_tail.min(address_length - head) {
+                let c = Compression {
+                    head,
+                    tail,
+                    zero_tail: tail <= zeros,
+                    ..best
+                };
+                if c.len() < best_len {
+                    best_len = c.len();
+                    best = c;
+                }
+            }
+        }
+
+        Ok(best)
+    }
+
+    /// Size of the encoded address block.
+    fn len(&self) -> usize {
+        let mut len = 2;
+        if self.head != 0 {
+            len += 1 + self.head;
+        }
+        if self.tail != 0 {
+            len += if self.zero_tail { 1 } else { 1 + self.tail };
+        }
+        len += self.num_addr * (self.address_length - self.head - self.tail);
+        len += match self.prefix_lengths {
+            PrefixLengths::Full => 0,
+            PrefixLengths::Single(_) => 1,
+            PrefixLengths::Multi(p) => p.len(),
+        };
+        len
+    }
+}
+
 bitflags! {
     struct AddressBlockFlags: u8 {
         const HAS_HEAD          = 0x80;
@@ -235,3 +616,77 @@ bitflags! {
         const RESERVED2         = 0x01;
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn roundtrip(addrs: &[Address], prefix_lengths: Option<&[u8]>) -> usize {
+        let mut out = [0u8; 64];
+        let mut buf = BufMut::new(&mut out);
+        AddressBlock::write(&mut buf, 4, addrs, prefix_lengths).unwrap();
+        let len = buf.pos();
+        assert_eq!(
+            AddressBlock::encoded_len(4, addrs, prefix_lengths),
+            Ok(len)
+        );
+
+        let mut buf = Buf::new(&out[..len]);
+        let block = AddressBlock::read(&mut buf, 4).unwrap();
+        assert!(buf.is_eof());
+        assert_eq!(block.num_addr, addrs.len());
+        for (i, addr) in addrs.iter().enumerate() {
+            assert_eq!(&block.get_addr(i), addr);
+        }
+        len
+    }
+
+    fn addr(bytes: &[u8]) -> Address {
+        Address::from_bytes(bytes).unwrap()
+    }
+
+    #[test]
+    fn test_address_block_write() {
+        // Single address, no compression.
+        assert_eq!(roundtrip(&[addr(&[10, 0, 0, 1])], None), 6);
+
+        // Common head.
+        let addrs = [addr(&[10, 0, 0, 1]), addr(&[10, 0, 0, 2])];
+        assert_eq!(roundtrip(&addrs, None), 2 + 4 + 2);
+
+        // Zero tail.
+        let addrs = [addr(&[10, 1, 0, 0]), addr(&[10, 2, 0, 0])];
+        assert_eq!(roundtrip(&addrs, Some(&[16])), 2 + 2 + 1 + 2 + 1);
+
+        // Full tail and multiple prefix lengths.
+        let addrs = [addr(&[10, 1, 2, 3]), addr(&[192, 168, 2, 3])];
+        assert_eq!(roundtrip(&addrs, Some(&[8, 16])), 2 + 3 + 4 + 2);
+
+        // Same prefix length for all is written once, maximum not at all.
+        assert_eq!(roundtrip(&addrs, Some(&[8, 8])), 2 + 3 + 4 + 1);
+        assert_eq!(roundtrip(&addrs, Some(&[32, 32])), 2 + 3 + 4);
+    }
+
+    #[test]
+    fn test_address_block_read_invalid() {
+        // <head-length> + <tail-length> larger than the address length.
+        const BIN: &[u8] =
+            &[0x01, 0xc0, 0x03, 0x0a, 0x00, 0x00, 0x02, 0x00, 0x00];
+        assert_eq!(
+            AddressBlock::read(&mut Buf::new(BIN), 4).err(),
+            Some(Error::InvalidAddressBlock)
+        );
+    }
+
+    #[test]
+    fn test_address_in_prefix() {
+        let a = addr(&[10, 1, 2, 3]);
+        assert!(a.in_prefix(&addr(&[10, 0, 0, 0]), 8));
+        assert!(a.in_prefix(&addr(&[10, 0, 0, 0]), 15));
+        assert!(!a.in_prefix(&addr(&[10, 0, 0, 0]), 16));
+        assert!(a.in_prefix(&a, 32));
+        assert!(a.in_prefix(&addr(&[0, 0, 0, 0]), 0));
+        assert!(!a.in_prefix(&a, 33));
+        assert!(!a.in_prefix(&addr(&[10, 1]), 8));
+    }
+}
@@ -0,0 +1,370 @@
+// Copyright 2020 Jean Pierre Dudey. See the LICENSE-MIT and
+// LICENSE-APACHE files at the top-level directory of this
+// distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use crate::{Buf, BufMut, Clock, Error, MsgHeader, PktHeader, SeqNum, Tlv};
+
+/// A place in the queue of an [`Aggregator`](struct.Aggregator.html).
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Slot(Option<Queued>);
+
+#[derive(Debug, Clone, Copy)]
+struct Queued {
+    /// Position of the message in the data buffer.
+    start: usize,
+    len: usize,
+    priority: u8,
+    deadline: u64,
+    /// Position in the queue, to keep the order of messages otherwise
+    /// equal.
+    order: u64,
+}
+
+impl Queued {
+    /// Is `self` sent before `other`?
+    fn before(&self, other: &Queued) -> bool {
+        (other.priority, self.deadline, self.order)
+            < (self.priority, other.deadline, other.order)
+    }
+}
+
+/// Queue of messages waiting to be sent on an interface, packed together in
+/// packets of up to `mtu` bytes.
+///
+/// Messages are encoded by their protocol and queued with a priority and a
+/// deadline, a time of the clock by when they must be sent. Packets are
+/// filled with the highest priority messages first, then the ones with the
+/// earliest deadline, then in the order they were queued. A message is never
+/// split across packets, smaller messages fill the space left by the ones
+/// that don't fit.
+///
+/// The queue lives in buffers given by the caller: `data` for the bytes of
+/// the messages and `slots`, one per message.
+#[derive(Debug)]
+pub struct Aggregator<'a, C> {
+    clock: C,
+    mtu: usize,
+    data: &'a mut [u8],
+    /// Bytes of `data` used, messages are kept together at its start.
+    used: usize,
+    slots: &'a mut [Slot],
+    order: u64,
+    seq_num: Option<SeqNum>,
+}
+
+impl<'a, C: Clock> Aggregator<'a, C> {
+    /// Create an empty queue for packets of up to `mtu` bytes.
+    pub fn new(
+        clock: C,
+        mtu: usize,
+        data: &'a mut [u8],
+        slots: &'a mut [Slot],
+    ) -> Self {
+        for slot in slots.iter_mut() {
+            *slot = Slot(None);
+        }
+
+        Aggregator {
+            clock,
+            mtu,
+            data,
+            used: 0,
+            slots,
+            order: 0,
+            seq_num: None,
+        }
+    }
+
+    /// The clock.
+    pub fn clock(&self) -> &C {
+        &self.clock
+    }
+
+    /// Maximum size of a packet.
+    pub fn mtu(&self) -> usize {
+        self.mtu
+    }
+
+    /// Set the `<pkt-seq-num>` of the next packet, incremented for each
+    /// packet. With `None` packets are sent without it.
+    pub fn set_seq_num(&mut self, seq_num: Option<u16>) {
+        self.seq_num = seq_num.map(SeqNum);
+    }
+
+    /// `<pkt-seq-num>` of the next packet.
+    pub fn seq_num(&self) -> Option<u16> {
+        self.seq_num.map(|s| s.0)
+    }
+
+    /// Number of messages queued.
+    pub fn len(&self) -> usize {
+        self.queued().count()
+    }
+
+    /// Is the queue empty?
+    pub fn is_empty(&self) -> bool {
+        self.queued().next().is_none()
+    }
+
+    /// Queue the message at the start of `msg`, it must be sent before
+    /// `deadline`.
+    ///
+    /// Fails with
+    /// [`Error::BufferTooSmall`](enum.Error.html#variant.BufferTooSmall)
+    /// if the queue is full, or if the message doesn't fit in a packet.
+    pub fn push(
+        &mut self,
+        msg: &[u8],
+        priority: u8,
+        deadline: u64,
+    ) -> Result<(), Error> {
+        let hdr = MsgHeader::read(&mut Buf::new(msg))?;
+        if hdr.size() < hdr.header_len() {
+            return Err(Error::InvalidMessage);
+        }
+        let msg = msg.get(..hdr.size()).ok_or(Error::UnexpectedEof)?;
+
+        if msg.len() > self.mtu.saturating_sub(self.header_len()) {
+            return Err(Error::BufferTooSmall);
+        }
+        let start = self.used;
+        let data = self
+            .data
+            .get_mut(start..start + msg.len())
+            .ok_or(Error::BufferTooSmall)?;
+        let slot = self
+            .slots
+            .iter_mut()
+            .find(|s| s.0.is_none())
+            .ok_or(Error::BufferTooSmall)?;
+
+        data.copy_from_slice(msg);
+        self.used += msg.len();
+        *slot = Slot(Some(Queued {
+            start,
+            len: msg.len(),
+            priority,
+            deadline,
+            order: self.order,
+        }));
+        self.order += 1;
+
+        Ok(())
+    }
+
+    /// Earliest deadline of the messages queued.
+    pub fn next_deadline(&self) -> Option<u64> {
+        self.queued().map(|q| q.deadline).min()
+    }
+
+    /// Must a packet be sent now? Either a deadline was reached or there are
+    /// enough messages to fill a packet.
+    pub fn is_due(&self) -> bool {
+        let now = self.clock.now();
+        let size: usize = self.queued().map(|q| q.len).sum();
+        self.next_deadline().map_or(false, |d| d <= now)
+            || self.header_len() + size > self.mtu
+    }
+
+    /// Write a packet to `buf` if [`is_due`](#method.is_due), see
+    /// [`packet`](#method.packet).
+    pub fn poll(
+        &mut self,
+        buf: &mut [u8],
+        tlvs: &[Tlv],
+    ) -> Result<Option<usize>, Error> {
+        if self.is_due() {
+            self.packet(buf, tlvs).map(Some)
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Write a packet with the packet TLVs `tlvs` and as many queued
+    /// messages as fit in `buf` and the MTU, returns its size or 0 if the
+    /// queue is empty. The messages written leave the queue.
+    ///
+    /// Fails with
+    /// [`Error::BufferTooSmall`](enum.Error.html#variant.BufferTooSmall)
+    /// if not even one message fits, the queue is left as is.
+    pub fn packet(
+        &mut self,
+        buf: &mut [u8],
+        tlvs: &[Tlv],
+    ) -> Result<usize, Error> {
+        if self.is_empty() {
+            return Ok(0);
+        }
+
+        let max = buf.len().min(self.mtu);
+        let mut buf = BufMut::new(&mut buf[..max]);
+        PktHeader::write(&mut buf, self.seq_num(), tlvs)?;
+
+        let mut written = false;
+        while let Some(i) = self.next_fitting(buf.remaining()) {
+            let q = self.slots[i].0.unwrap();
+            buf.put_bytes(&self.data[q.start..q.start + q.len])?;
+            self.remove(i);
+            written = true;
+        }
+        if !written {
+            return Err(Error::BufferTooSmall);
+        }
+
+        self.seq_num = self.seq_num.map(SeqNum::next);
+        Ok(buf.pos())
+    }
+
+    fn queued(&self) -> impl Iterator<Item = &Queued> {
+        self.slots.iter().filter_map(|s| s.0.as_ref())
+    }
+
+    /// Size of the packet header without packet TLVs.
+    fn header_len(&self) -> usize {
+        if self.seq_num.is_some() {
+            3
+        } else {
+            1
+        }
+    }
+
+    /// Slot of the first message to send of at most `size` bytes.
+    fn next_fitting(&self, size: usize) -> Option<usize> {
+        let mut best: Option<(usize, &Queued)> = None;
+        for (i, slot) in self.slots.iter().enumerate() {
+            if let Some(ref q) = slot.0 {
+                if q.len <= size && best.map_or(true, |(_, b)| q.before(b)) {
+                    best = Some((i, q));
+                }
+            }
+        }
+        best.map(|(i, _)| i)
+    }
+
+    /// Remove the message in slot `i` and close the gap it leaves in the
+    /// data buffer.
+    fn remove(&mut self, i: usize) {
+        let q = match self.slots[i].0.take() {
+            Some(q) => q,
+            None => return,
+        };
+
+        for j in q.start..self.used - q.len {
+            self.data[j] = self.data[j + q.len];
+        }
+        self.used -= q.len;
+        for slot in self.slots.iter_mut() {
+            if let Some(ref mut other) = slot.0 {
+                if other.start > q.start {
+                    other.start -= q.len;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{ManualClock, MessageBuilder, Packet};
+
+    /// Write a message of type `r#type` and `size` bytes.
+    fn message(buf: &mut [u8], r#type: u8, size: usize) -> &[u8] {
+        let hdr = MsgHeader::new(r#type, 4);
+        let mut builder = MessageBuilder::new(buf, &hdr).unwrap();
+        // Header and empty TLV block are 6 bytes, a TLV 4 + value.
+        if size > 6 {
+            let value = [r#type; 64];
+            builder
+                .add_tlv(&Tlv {
+                    r#type: 1,
+                    type_ext: None,
+                    start_index: None,
+                    stop_index: None,
+                    value: Some(&value[..size - 9]),
+                    multi_value: false,
+                })
+                .unwrap();
+        }
+        let len = builder.finish().unwrap();
+        assert_eq!(len, size);
+        &buf[..len]
+    }
+
+    fn types(packet: &[u8]) -> impl Iterator<Item = u8> + '_ {
+        let packet = Packet::read(packet).unwrap();
+        packet.messages.iter().map(|m| m.unwrap().hdr.r#type)
+    }
+
+    #[test]
+    fn test_aggregator() {
+        let clock = ManualClock::new(0);
+        let mut data = [0u8; 128];
+        let mut slots = [Slot::default(); 4];
+        let mut queue = Aggregator::new(&clock, 64, &mut data, &mut slots);
+        queue.set_seq_num(Some(core::u16::MAX));
+
+        let mut buf = [0u8; 64];
+        assert_eq!(queue.push(message(&mut buf, 1, 40), 0, 100), Ok(()));
+        assert_eq!(queue.push(message(&mut buf, 2, 30), 1, 200), Ok(()));
+        assert_eq!(queue.push(message(&mut buf, 3, 20), 0, 50), Ok(()));
+        // Doesn't fit with the 3 bytes of the header.
+        assert_eq!(
+            queue.push(message(&mut buf, 4, 62), 0, 0),
+            Err(Error::BufferTooSmall)
+        );
+        assert_eq!(queue.len(), 3);
+        assert_eq!(queue.next_deadline(), Some(50));
+        // 90 bytes are more than a packet.
+        assert!(queue.is_due());
+
+        let mut out = [0u8; 128];
+        let len = queue.poll(&mut out, &[]).unwrap().unwrap();
+        assert_eq!(len, 3 + 30 + 20);
+        let packet = Packet::read(&out[..len]).unwrap();
+        assert_eq!(packet.hdr.seq_num, Some(core::u16::MAX));
+        assert!(types(&out[..len]).eq([2, 3].iter().cloned()));
+
+        assert!(!queue.is_due());
+        assert_eq!(queue.poll(&mut out, &[]), Ok(None));
+        clock.set(100);
+        let tlvs = [Tlv {
+            r#type: 1,
+            type_ext: None,
+            start_index: None,
+            stop_index: None,
+            value: Some(&[0; 20]),
+            multi_value: false,
+        }];
+        // The message doesn't fit with the packet TLVs.
+        assert_eq!(queue.poll(&mut out, &tlvs), Err(Error::BufferTooSmall));
+        let len = queue.poll(&mut out, &[]).unwrap().unwrap();
+        let packet = Packet::read(&out[..len]).unwrap();
+        assert_eq!(packet.hdr.seq_num, Some(0));
+        assert!(types(&out[..len]).eq([1].iter().cloned()));
+        assert!(queue.is_empty());
+        assert_eq!(queue.packet(&mut out, &[]), Ok(0));
+
+        // Queue full.
+        for i in 0..4 {
+            assert_eq!(queue.push(message(&mut buf, i, 6), 0, 500), Ok(()));
+        }
+        assert_eq!(
+            queue.push(message(&mut buf, 4, 6), 0, 500),
+            Err(Error::BufferTooSmall)
+        );
+        queue.set_seq_num(None);
+        let len = queue.packet(&mut out, &tlvs).unwrap();
+        assert_eq!(len, 1 + 2 + 23 + 4 * 6);
+        let packet = Packet::read(&out[..len]).unwrap();
+        assert_eq!(packet.hdr.seq_num, None);
+        assert!(packet.hdr.tlv_block.is_some());
+        assert!(types(&out[..len]).eq(0..4));
+    }
+}
@@ -0,0 +1,233 @@
+// Copyright 2020 Jean Pierre Dudey. See the LICENSE-MIT and
+// LICENSE-APACHE files at the top-level directory of this
+// distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Ad Hoc On-demand Distance Vector version 2 (AODVv2) routing,
+//! draft-ietf-manet-aodvv2-16.
+//!
+//! The draft leaves the message and TLV types to be assigned by IANA, the
+//! values used here don't clash with those of NHDP and OLSRv2. The route
+//! discovery and maintenance ([`Aodv`](struct.Aodv.html)) need the
+//! `use_std` feature.
+
+use crate::nhdp::set_once;
+use crate::{Address, AddressTlvs, Error, MessageBuilder};
+
+mod rerr;
+mod rrep;
+mod rreq;
+#[cfg(feature = "use_std")]
+mod state;
+
+pub use self::rerr::{Rerr, RerrAddresses, Unreachable};
+pub use self::rrep::{Rrep, RrepAck};
+pub use self::rreq::Rreq;
+#[cfg(feature = "use_std")]
+pub use self::state::{
+    Aodv, AodvConfig, Event, LocalRoute, McMsg, Neighbor, NeighborState,
+    RouteState, Routing, Send,
+};
+
+/// RREQ message type.
+pub const MSG_TYPE_RREQ: u8 = 10;
+/// RREP message type.
+pub const MSG_TYPE_RREP: u8 = 11;
+/// RERR message type.
+pub const MSG_TYPE_RERR: u8 = 12;
+/// RREP_Ack message type.
+pub const MSG_TYPE_RREP_ACK: u8 = 13;
+
+/// AckReq message TLV type, an RREP with it asks for an RREP_Ack.
+pub const MSG_TLV_ACK_REQ: u8 = 10;
+
+/// PATH_METRIC address TLV type, the type extension is the metric type.
+pub const ADDR_TLV_PATH_METRIC: u8 = 11;
+/// SEQ_NUM address TLV type.
+pub const ADDR_TLV_SEQ_NUM: u8 = 12;
+/// ADDRESS_TYPE address TLV type.
+pub const ADDR_TLV_ADDRESS_TYPE: u8 = 13;
+
+/// ADDRESS_TYPE of the originator prefix of an RREQ or RREP.
+pub const ADDRTYPE_ORIGPREFIX: u8 = 0;
+/// ADDRESS_TYPE of the target prefix of an RREQ or RREP.
+pub const ADDRTYPE_TARGPREFIX: u8 = 1;
+/// ADDRESS_TYPE of an unreachable address of a RERR.
+pub const ADDRTYPE_UNREACHABLE: u8 = 2;
+/// ADDRESS_TYPE of the source of the packet that made a router send a
+/// RERR.
+pub const ADDRTYPE_PKTSOURCE: u8 = 3;
+
+/// Hop count metric type (RFC 6551), the default of AODVv2.
+pub const METRIC_TYPE_HOP_COUNT: u8 = 3;
+/// Maximum hop count metric.
+pub const MAX_HOP_COUNT: u32 = 255;
+
+/// An address of an AODVv2 message with its TLVs.
+#[derive(Debug, Clone, Copy, Default)]
+struct AodvAddress {
+    addr: Option<Address>,
+    prefix_length: Option<u8>,
+    addr_type: Option<u8>,
+    seq_num: Option<u16>,
+    /// Metric type and metric, if PATH_METRIC has a value.
+    metric: Option<(u8, Option<u32>)>,
+}
+
+/// Call `f` with every distinct address of `address_tlv` and its TLVs.
+fn for_each_address<F>(address_tlv: &AddressTlvs, mut f: F) -> Result<(), Error>
+where
+    F: FnMut(AodvAddress) -> Result<(), Error>,
+{
+    for (addr, prefix_length) in address_tlv.unique() {
+        f(read_address(address_tlv, addr, prefix_length)?)?;
+    }
+
+    Ok(())
+}
+
+/// Merge the TLVs of every appearance of `addr/prefix_length`.
+fn read_address(
+    address_tlv: &AddressTlvs,
+    addr: Address,
+    prefix_length: Option<u8>,
+) -> Result<AodvAddress, Error> {
+    let mut a = AodvAddress {
+        addr: Some(addr),
+        prefix_length,
+        ..AodvAddress::default()
+    };
+    address_tlv.for_each_tlv(&addr, prefix_length, |tlv, value| {
+        match tlv.r#type {
+            ADDR_TLV_ADDRESS_TYPE if tlv.type_ext() == 0 => {
+                if value.len() != 1 {
+                    return Err(Error::InvalidTlvValue);
+                }
+                set_once(&mut a.addr_type, value[0])
+            }
+            ADDR_TLV_SEQ_NUM if tlv.type_ext() == 0 => {
+                if value.len() != 2 {
+                    return Err(Error::InvalidTlvValue);
+                }
+                let seq_num = u16::from_be_bytes([value[0], value[1]]);
+                if seq_num == 0 {
+                    return Err(Error::InvalidTlvValue);
+                }
+                set_once(&mut a.seq_num, seq_num)
+            }
+            ADDR_TLV_PATH_METRIC => {
+                let metric = decode_metric(tlv.type_ext(), value)?;
+                set_once(&mut a.metric, (tlv.type_ext(), metric))
+            }
+            _ => Ok(()),
+        }
+    })?;
+
+    Ok(a)
+}
+
+/// Decode the value of a PATH_METRIC TLV of type `metric_type`, 1 to 4
+/// bytes in network byte order or nothing. Hop counts can't be larger than
+/// `MAX_HOP_COUNT`.
+fn decode_metric(metric_type: u8, value: &[u8]) -> Result<Option<u32>, Error> {
+    if value.len() > 4 {
+        return Err(Error::InvalidTlvValue);
+    }
+    if value.is_empty() {
+        return Ok(None);
+    }
+
+    let metric = value.iter().fold(0, |m, b| (m << 8) | u32::from(*b));
+    if metric_type == METRIC_TYPE_HOP_COUNT && metric > MAX_HOP_COUNT {
+        return Err(Error::InvalidTlvValue);
+    }
+    Ok(Some(metric))
+}
+
+/// Encode `metric` of type `metric_type` in `buf`, a byte for hop counts and
+/// 4 bytes for the other types. Returns the bytes used.
+fn encode_metric(
+    metric_type: u8,
+    metric: u32,
+    buf: &mut [u8; 4],
+) -> Result<&[u8], Error> {
+    *buf = metric.to_be_bytes();
+    if metric_type != METRIC_TYPE_HOP_COUNT {
+        return Ok(&buf[..]);
+    }
+    if metric > MAX_HOP_COUNT {
+        return Err(Error::InvalidTlvValue);
+    }
+    Ok(&buf[3..])
+}
+
+/// Originator and target of an RREQ or RREP, with the TLVs of the address
+/// that carries the sequence number and metric.
+#[derive(Debug, Clone, Copy)]
+struct Route {
+    orig: AodvAddress,
+    targ: AodvAddress,
+}
+
+impl Route {
+    /// Read the OrigPrefix and TargPrefix addresses, exactly one of each.
+    fn read(address_tlv: &AddressTlvs) -> Result<Route, Error> {
+        let mut orig = None;
+        let mut targ = None;
+        for_each_address(address_tlv, |a| match a.addr_type {
+            Some(ADDRTYPE_ORIGPREFIX) => set_once(&mut orig, a),
+            Some(ADDRTYPE_TARGPREFIX) => set_once(&mut targ, a),
+            _ => Ok(()),
+        })?;
+
+        Ok(Route {
+            orig: orig.ok_or(Error::InvalidMessage)?,
+            targ: targ.ok_or(Error::InvalidMessage)?,
+        })
+    }
+
+    /// Write the address block of the originator and target, and its
+    /// TLVs: ADDRESS_TYPE, SEQ_NUM and PATH_METRIC.
+    fn write(
+        builder: &mut MessageBuilder,
+        addrs: [(Address, Option<u8>); 2],
+        seq_nums: [Option<u16>; 2],
+        metric_type: u8,
+        metric: (usize, u32),
+    ) -> Result<(), Error> {
+        if seq_nums.contains(&Some(0)) {
+            return Err(Error::InvalidTlvValue);
+        }
+
+        let max = |a: &Address| (8 * a.len()) as u8;
+        let prefix_lengths = [
+            addrs[0].1.unwrap_or_else(|| max(&addrs[0].0)),
+            addrs[1].1.unwrap_or_else(|| max(&addrs[1].0)),
+        ];
+        let prefix_lengths = Some(&prefix_lengths[..])
+            .filter(|_| addrs.iter().any(|(_, p)| p.is_some()));
+        builder.add_address_block(&[addrs[0].0, addrs[1].0], prefix_lengths)?;
+
+        let types = [ADDRTYPE_ORIGPREFIX, ADDRTYPE_TARGPREFIX];
+        builder.add_address_tlvs_u8(ADDR_TLV_ADDRESS_TYPE, None, |i| {
+            Some(types[i])
+        })?;
+        let seq_nums = [
+            seq_nums[0].map(u16::to_be_bytes),
+            seq_nums[1].map(u16::to_be_bytes),
+        ];
+        builder.add_address_tlvs(ADDR_TLV_SEQ_NUM, None, |i| {
+            seq_nums[i].as_ref().map(|s| &s[..])
+        })?;
+        let mut value = [0u8; 4];
+        let value = encode_metric(metric_type, metric.1, &mut value)?;
+        builder.add_address_tlvs(ADDR_TLV_PATH_METRIC, Some(metric_type), |i| {
+            Some(value).filter(|_| i == metric.0)
+        })
+    }
+}
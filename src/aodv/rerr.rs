@@ -0,0 +1,275 @@
+// Copyright 2020 Jean Pierre Dudey. See the LICENSE-MIT and
+// LICENSE-APACHE files at the top-level directory of this
+// distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use crate::addrtlv::UniqueAddresses;
+use crate::aodv::*;
+use crate::{Address, AddressTlvs, Error, Message, MessageBuilder, MsgHeader};
+
+/// RERR (Route Error) message.
+#[derive(Debug, Clone)]
+pub struct Rerr<'a> {
+    /// `<msg-addr-length>`
+    pub address_length: usize,
+    /// `<msg-hop-limit>`
+    pub hop_limit: u8,
+    /// PktSource, the source of the packet that couldn't be forwarded.
+    pub pkt_source: Option<Address>,
+    address_tlv: Option<AddressTlvs<'a>>,
+}
+
+impl<'a> Rerr<'a> {
+    /// Create a RERR without PktSource.
+    pub fn new(address_length: usize, hop_limit: u8) -> Rerr<'a> {
+        Rerr {
+            address_length,
+            hop_limit,
+            pkt_source: None,
+            address_tlv: None,
+        }
+    }
+
+    /// Read a RERR from a message.
+    ///
+    /// Fails with
+    /// [`Error::InvalidMessage`](../enum.Error.html#variant.InvalidMessage)
+    /// without `<msg-hop-limit>`, without unreachable addresses, with more
+    /// than one PktSource, or with an unreachable address without a metric
+    /// type, given by a PATH_METRIC TLV without value.
+    ///
+    /// A hop count metric larger than
+    /// [`MAX_HOP_COUNT`](constant.MAX_HOP_COUNT.html) fails with
+    /// [`Error::InvalidTlvValue`](../enum.Error.html#variant.InvalidTlvValue).
+    pub fn from_message(msg: &Message<'a>) -> Result<Rerr<'a>, Error> {
+        let hdr = &msg.hdr;
+        if hdr.r#type != MSG_TYPE_RERR {
+            return Err(Error::InvalidMessage);
+        }
+        let hop_limit = hdr.hop_limit.ok_or(Error::InvalidMessage)?;
+
+        let mut pkt_source = None;
+        let mut unreachable = false;
+        for_each_address(&msg.address_tlv, |a| match a.addr_type {
+            Some(ADDRTYPE_PKTSOURCE) => set_once(&mut pkt_source, a.addr),
+            Some(ADDRTYPE_UNREACHABLE) => {
+                Unreachable::from_address(a)?;
+                unreachable = true;
+                Ok(())
+            }
+            _ => Ok(()),
+        })?;
+        if !unreachable {
+            return Err(Error::InvalidMessage);
+        }
+
+        Ok(Rerr {
+            address_length: hdr.address_length,
+            hop_limit,
+            pkt_source: pkt_source.and_then(|a| a),
+            address_tlv: Some(msg.address_tlv.clone()),
+        })
+    }
+
+    /// The unreachable addresses.
+    pub fn unreachable(&self) -> RerrAddresses<'a> {
+        RerrAddresses {
+            address_tlv: self.address_tlv.clone(),
+            unique: self.address_tlv.as_ref().map(|a| a.unique()),
+        }
+    }
+
+    /// Write the RERR with the unreachable addresses `addrs` to `buf`,
+    /// returns the size of the message.
+    pub fn write(
+        &self,
+        addrs: &[Unreachable],
+        buf: &mut [u8],
+    ) -> Result<usize, Error> {
+        if addrs.is_empty() {
+            return Err(Error::InvalidMessage);
+        }
+        if addrs.iter().any(|a| a.seq_num == Some(0)) {
+            return Err(Error::InvalidTlvValue);
+        }
+
+        let mut hdr = MsgHeader::new(MSG_TYPE_RERR, self.address_length);
+        hdr.hop_limit = Some(self.hop_limit);
+        let mut builder = MessageBuilder::new(buf, &hdr)?;
+
+        if let Some(pkt_source) = self.pkt_source {
+            builder.add_address_block(&[pkt_source], None)?;
+            builder.add_address_tlvs_u8(ADDR_TLV_ADDRESS_TYPE, None, |_| {
+                Some(ADDRTYPE_PKTSOURCE)
+            })?;
+        }
+
+        let max_prefix = (8 * self.address_length) as u8;
+        for block in addrs.chunks(usize::from(core::u8::MAX)) {
+            let mut prefix_lengths = [0u8; core::u8::MAX as usize];
+            for (p, a) in prefix_lengths.iter_mut().zip(block) {
+                *p = a.prefix_length.unwrap_or(max_prefix);
+            }
+            let prefix_lengths = Some(&prefix_lengths[..block.len()])
+                .filter(|_| block.iter().any(|a| a.prefix_length.is_some()));
+            builder.add_address_block(block, prefix_lengths)?;
+
+            builder.add_address_tlvs_u8(ADDR_TLV_ADDRESS_TYPE, None, |_| {
+                Some(ADDRTYPE_UNREACHABLE)
+            })?;
+            let mut values = [[0u8; 2]; core::u8::MAX as usize];
+            for (v, a) in values.iter_mut().zip(block) {
+                *v = a.seq_num.unwrap_or(0).to_be_bytes();
+            }
+            builder.add_address_tlvs(ADDR_TLV_SEQ_NUM, None, |i| {
+                block[i].seq_num.map(|_| &values[i][..])
+            })?;
+            for (i, a) in block.iter().enumerate() {
+                // Once for each metric type.
+                if block[..i].iter().any(|b| b.metric_type == a.metric_type) {
+                    continue;
+                }
+                let t = a.metric_type;
+                builder.add_address_tlvs(
+                    ADDR_TLV_PATH_METRIC,
+                    Some(t),
+                    |i| Some(&[][..]).filter(|_| block[i].metric_type == t),
+                )?;
+            }
+        }
+
+        builder.finish()
+    }
+}
+
+/// An unreachable address of a RERR.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct Unreachable {
+    /// The address.
+    pub addr: Address,
+    /// Prefix length, `None` if it's the maximum.
+    pub prefix_length: Option<u8>,
+    /// Sequence number of the route to the address.
+    pub seq_num: Option<u16>,
+    /// Metric type of the route.
+    pub metric_type: u8,
+}
+
+impl Unreachable {
+    /// Create an unreachable address with the hop count metric type.
+    pub fn new(addr: Address) -> Unreachable {
+        Unreachable {
+            addr,
+            prefix_length: None,
+            seq_num: None,
+            metric_type: METRIC_TYPE_HOP_COUNT,
+        }
+    }
+
+    fn from_address(a: AodvAddress) -> Result<Unreachable, Error> {
+        let metric_type = match a.metric {
+            Some((t, None)) => t,
+            _ => return Err(Error::InvalidMessage),
+        };
+
+        Ok(Unreachable {
+            addr: a.addr.ok_or(Error::InvalidMessage)?,
+            prefix_length: a.prefix_length,
+            seq_num: a.seq_num,
+            metric_type,
+        })
+    }
+}
+
+impl AsRef<Address> for Unreachable {
+    fn as_ref(&self) -> &Address {
+        &self.addr
+    }
+}
+
+/// Iterator over the unreachable addresses of a RERR.
+#[derive(Debug)]
+pub struct RerrAddresses<'a> {
+    address_tlv: Option<AddressTlvs<'a>>,
+    unique: Option<UniqueAddresses<'a>>,
+}
+
+impl<'a> Iterator for RerrAddresses<'a> {
+    type Item = Unreachable;
+
+    fn next(&mut self) -> Option<Unreachable> {
+        let address_tlv = self.address_tlv.as_ref()?;
+        loop {
+            let (addr, prefix_length) = self.unique.as_mut()?.next()?;
+            let a = match read_address(address_tlv, addr, prefix_length) {
+                Ok(a) if a.addr_type == Some(ADDRTYPE_UNREACHABLE) => a,
+                _ => continue,
+            };
+            if let Ok(u) = Unreachable::from_address(a) {
+                return Some(u);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::Buf;
+
+    fn addr(bytes: &[u8]) -> Address {
+        Address::from_bytes(bytes).unwrap()
+    }
+
+    #[test]
+    fn test_rerr() {
+        let mut rerr = Rerr::new(4, 255);
+        rerr.pkt_source = Some(addr(&[10, 0, 0, 9]));
+        let mut addrs = [Unreachable::new(addr(&[10, 0, 0, 0])); 3];
+        addrs[0].prefix_length = Some(24);
+        addrs[1].addr = addr(&[10, 1, 0, 1]);
+        addrs[1].seq_num = Some(7);
+        addrs[2].addr = addr(&[10, 2, 0, 1]);
+        addrs[2].metric_type = 5;
+
+        let mut buf = [0u8; 128];
+        let len = rerr.write(&addrs, &mut buf).unwrap();
+        let msg = Message::read(&mut Buf::new(&buf[..len])).unwrap();
+        let parsed = Rerr::from_message(&msg).unwrap();
+        assert_eq!(parsed.hop_limit, 255);
+        assert_eq!(parsed.pkt_source, rerr.pkt_source);
+        assert!(parsed.unreachable().eq(addrs.iter().cloned()));
+
+        // Without PktSource.
+        rerr.pkt_source = None;
+        let len = rerr.write(&addrs[..1], &mut buf).unwrap();
+        let msg = Message::read(&mut Buf::new(&buf[..len])).unwrap();
+        let parsed = Rerr::from_message(&msg).unwrap();
+        assert_eq!(parsed.pkt_source, None);
+        assert!(parsed.unreachable().eq(addrs[..1].iter().cloned()));
+
+        assert_eq!(rerr.write(&[], &mut buf), Err(Error::InvalidMessage));
+
+        // Unreachable address without metric type.
+        let mut hdr = MsgHeader::new(MSG_TYPE_RERR, 4);
+        hdr.hop_limit = Some(1);
+        let mut builder = MessageBuilder::new(&mut buf, &hdr).unwrap();
+        builder.add_address_block(&[addrs[1].addr], None).unwrap();
+        builder
+            .add_address_tlvs_u8(ADDR_TLV_ADDRESS_TYPE, None, |_| {
+                Some(ADDRTYPE_UNREACHABLE)
+            })
+            .unwrap();
+        let len = builder.finish().unwrap();
+        let msg = Message::read(&mut Buf::new(&buf[..len])).unwrap();
+        assert_eq!(
+            Rerr::from_message(&msg).map(|_| ()),
+            Err(Error::InvalidMessage)
+        );
+    }
+}
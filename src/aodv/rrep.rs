@@ -0,0 +1,204 @@
+// Copyright 2020 Jean Pierre Dudey. See the LICENSE-MIT and
+// LICENSE-APACHE files at the top-level directory of this
+// distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use crate::aodv::*;
+use crate::{Address, Error, Message, MessageBuilder, MsgHeader, Tlv};
+
+/// RREP (Route Reply) message.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct Rrep {
+    /// `<msg-hop-limit>`
+    pub hop_limit: u8,
+    /// AckReq, the next hop must answer with an RREP_Ack.
+    pub ack_req: bool,
+    /// OrigAddr, the address of the originator of the RREQ.
+    pub orig_addr: Address,
+    /// TargPrefix, the address of the target or a prefix it routes.
+    pub targ_prefix: Address,
+    /// Prefix length of TargPrefix, `None` if it's the maximum.
+    pub targ_prefix_length: Option<u8>,
+    /// TargSeqNum, sequence number of the target.
+    pub targ_seq_num: u16,
+    /// Metric type of `targ_metric`.
+    pub metric_type: u8,
+    /// TargMetric, metric of the route to the target.
+    pub targ_metric: u32,
+}
+
+impl Rrep {
+    /// Create a RREP with a hop count metric of 0.
+    pub fn new(
+        hop_limit: u8,
+        orig_addr: Address,
+        targ_prefix: Address,
+        targ_seq_num: u16,
+    ) -> Rrep {
+        Rrep {
+            hop_limit,
+            ack_req: false,
+            orig_addr,
+            targ_prefix,
+            targ_prefix_length: None,
+            targ_seq_num,
+            metric_type: METRIC_TYPE_HOP_COUNT,
+            targ_metric: 0,
+        }
+    }
+
+    /// Read a RREP from a message.
+    ///
+    /// Fails with
+    /// [`Error::InvalidMessage`](../enum.Error.html#variant.InvalidMessage)
+    /// when one of these is missing: `<msg-hop-limit>`, OrigAddr with the
+    /// maximum prefix length, or TargPrefix with SEQ_NUM and PATH_METRIC.
+    ///
+    /// A hop count metric larger than
+    /// [`MAX_HOP_COUNT`](constant.MAX_HOP_COUNT.html) fails with
+    /// [`Error::InvalidTlvValue`](../enum.Error.html#variant.InvalidTlvValue).
+    pub fn from_message(msg: &Message) -> Result<Rrep, Error> {
+        let hdr = &msg.hdr;
+        if hdr.r#type != MSG_TYPE_RREP {
+            return Err(Error::InvalidMessage);
+        }
+        let hop_limit = hdr.hop_limit.ok_or(Error::InvalidMessage)?;
+
+        let mut ack_req = false;
+        for tlv in msg.tlv_block.iter() {
+            let tlv = tlv?;
+            if tlv.r#type == MSG_TLV_ACK_REQ && tlv.type_ext() == 0 {
+                ack_req = true;
+            }
+        }
+
+        let Route { orig, targ } = Route::read(&msg.address_tlv)?;
+        let (metric_type, targ_metric) = match targ.metric {
+            Some((t, Some(m))) => (t, m),
+            _ => return Err(Error::InvalidMessage),
+        };
+        if orig.prefix_length.is_some() {
+            return Err(Error::InvalidMessage);
+        }
+
+        Ok(Rrep {
+            hop_limit,
+            ack_req,
+            orig_addr: orig.addr.ok_or(Error::InvalidMessage)?,
+            targ_prefix: targ.addr.ok_or(Error::InvalidMessage)?,
+            targ_prefix_length: targ.prefix_length,
+            targ_seq_num: targ.seq_num.ok_or(Error::InvalidMessage)?,
+            metric_type,
+            targ_metric,
+        })
+    }
+
+    /// Write the RREP to `buf`, returns the size of the message.
+    pub fn write(&self, buf: &mut [u8]) -> Result<usize, Error> {
+        if self.orig_addr.len() != self.targ_prefix.len() {
+            return Err(Error::InvalidAddressBlock);
+        }
+        let mut hdr = MsgHeader::new(MSG_TYPE_RREP, self.orig_addr.len());
+        hdr.hop_limit = Some(self.hop_limit);
+
+        let mut builder = MessageBuilder::new(buf, &hdr)?;
+        if self.ack_req {
+            builder.add_tlv(&Tlv {
+                r#type: MSG_TLV_ACK_REQ,
+                type_ext: None,
+                start_index: None,
+                stop_index: None,
+                value: None,
+                multi_value: false,
+            })?;
+        }
+        Route::write(
+            &mut builder,
+            [
+                (self.orig_addr, None),
+                (self.targ_prefix, self.targ_prefix_length),
+            ],
+            [None, Some(self.targ_seq_num)],
+            self.metric_type,
+            (1, self.targ_metric),
+        )?;
+        builder.finish()
+    }
+}
+
+/// RREP_Ack message, the answer to an RREP with AckReq.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct RrepAck;
+
+impl RrepAck {
+    /// Read a RREP_Ack from a message.
+    pub fn from_message(msg: &Message) -> Result<RrepAck, Error> {
+        if msg.hdr.r#type != MSG_TYPE_RREP_ACK {
+            return Err(Error::InvalidMessage);
+        }
+
+        Ok(RrepAck)
+    }
+
+    /// Write the RREP_Ack to `buf` for addresses of `address_length` bytes,
+    /// returns the size of the message. It's only sent to the neighbor, with
+    /// a hop limit of 1.
+    pub fn write(
+        &self,
+        address_length: usize,
+        buf: &mut [u8],
+    ) -> Result<usize, Error> {
+        let mut hdr = MsgHeader::new(MSG_TYPE_RREP_ACK, address_length);
+        hdr.hop_limit = Some(1);
+        MessageBuilder::new(buf, &hdr)?.finish()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::Buf;
+
+    fn addr(bytes: &[u8]) -> Address {
+        Address::from_bytes(bytes).unwrap()
+    }
+
+    fn message(buf: &[u8]) -> Message<'_> {
+        Message::read(&mut Buf::new(buf)).unwrap()
+    }
+
+    #[test]
+    fn test_rrep() {
+        let orig =
+            addr(&[0xfe, 0x80, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1]);
+        let targ = addr(&[0xfd, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+        let mut rrep = Rrep::new(255, orig, targ, 42);
+        rrep.targ_prefix_length = Some(64);
+        rrep.targ_metric = 4;
+
+        let mut buf = [0u8; 128];
+        let len = rrep.write(&mut buf).unwrap();
+        assert_eq!(Rrep::from_message(&message(&buf[..len])), Ok(rrep));
+
+        rrep.ack_req = true;
+        let len = rrep.write(&mut buf).unwrap();
+        let msg = message(&buf[..len]);
+        assert_eq!(Rrep::from_message(&msg), Ok(rrep));
+        assert_eq!(Rreq::from_message(&msg), Err(Error::InvalidMessage));
+
+        // Addresses of different lengths.
+        rrep.orig_addr = addr(&[10, 0, 0, 1]);
+        assert_eq!(rrep.write(&mut buf), Err(Error::InvalidAddressBlock));
+
+        let len = RrepAck.write(4, &mut buf).unwrap();
+        let msg = message(&buf[..len]);
+        assert_eq!(msg.hdr.hop_limit, Some(1));
+        assert_eq!(RrepAck::from_message(&msg), Ok(RrepAck));
+        assert_eq!(Rrep::from_message(&msg), Err(Error::InvalidMessage));
+    }
+}
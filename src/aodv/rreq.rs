@@ -0,0 +1,211 @@
+// Copyright 2020 Jean Pierre Dudey. See the LICENSE-MIT and
+// LICENSE-APACHE files at the top-level directory of this
+// distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use crate::aodv::*;
+use crate::{Address, Error, Message, MessageBuilder, MsgHeader};
+
+/// RREQ (Route Request) message.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct Rreq {
+    /// `<msg-hop-limit>`
+    pub hop_limit: u8,
+    /// OrigPrefix, the address of the originator or a prefix it routes.
+    pub orig_prefix: Address,
+    /// Prefix length of OrigPrefix, `None` if it's the maximum.
+    pub orig_prefix_length: Option<u8>,
+    /// TargAddr, the address a route is searched for.
+    pub targ_addr: Address,
+    /// OrigSeqNum, sequence number of the originator.
+    pub orig_seq_num: u16,
+    /// TargSeqNum, last known sequence number of the target.
+    pub targ_seq_num: Option<u16>,
+    /// Metric type of `orig_metric`.
+    pub metric_type: u8,
+    /// OrigMetric, metric of the route to the originator.
+    pub orig_metric: u32,
+}
+
+impl Rreq {
+    /// Create a RREQ with a hop count metric of 0.
+    pub fn new(
+        hop_limit: u8,
+        orig_prefix: Address,
+        targ_addr: Address,
+        orig_seq_num: u16,
+    ) -> Rreq {
+        Rreq {
+            hop_limit,
+            orig_prefix,
+            orig_prefix_length: None,
+            targ_addr,
+            orig_seq_num,
+            targ_seq_num: None,
+            metric_type: METRIC_TYPE_HOP_COUNT,
+            orig_metric: 0,
+        }
+    }
+
+    /// Read a RREQ from a message.
+    ///
+    /// Fails with
+    /// [`Error::InvalidMessage`](../enum.Error.html#variant.InvalidMessage)
+    /// when one of these is missing: `<msg-hop-limit>`, OrigPrefix with
+    /// SEQ_NUM and PATH_METRIC, or TargAddr with the maximum prefix length.
+    ///
+    /// A hop count metric larger than
+    /// [`MAX_HOP_COUNT`](constant.MAX_HOP_COUNT.html) fails with
+    /// [`Error::InvalidTlvValue`](../enum.Error.html#variant.InvalidTlvValue).
+    pub fn from_message(msg: &Message) -> Result<Rreq, Error> {
+        let hdr = &msg.hdr;
+        if hdr.r#type != MSG_TYPE_RREQ {
+            return Err(Error::InvalidMessage);
+        }
+        let hop_limit = hdr.hop_limit.ok_or(Error::InvalidMessage)?;
+
+        let Route { orig, targ } = Route::read(&msg.address_tlv)?;
+        let (metric_type, orig_metric) = match orig.metric {
+            Some((t, Some(m))) => (t, m),
+            _ => return Err(Error::InvalidMessage),
+        };
+        if targ.prefix_length.is_some() {
+            return Err(Error::InvalidMessage);
+        }
+
+        Ok(Rreq {
+            hop_limit,
+            orig_prefix: orig.addr.ok_or(Error::InvalidMessage)?,
+            orig_prefix_length: orig.prefix_length,
+            targ_addr: targ.addr.ok_or(Error::InvalidMessage)?,
+            orig_seq_num: orig.seq_num.ok_or(Error::InvalidMessage)?,
+            targ_seq_num: targ.seq_num,
+            metric_type,
+            orig_metric,
+        })
+    }
+
+    /// Write the RREQ to `buf`, returns the size of the message.
+    pub fn write(&self, buf: &mut [u8]) -> Result<usize, Error> {
+        if self.orig_prefix.len() != self.targ_addr.len() {
+            return Err(Error::InvalidAddressBlock);
+        }
+        let mut hdr = MsgHeader::new(MSG_TYPE_RREQ, self.orig_prefix.len());
+        hdr.hop_limit = Some(self.hop_limit);
+
+        let mut builder = MessageBuilder::new(buf, &hdr)?;
+        Route::write(
+            &mut builder,
+            [
+                (self.orig_prefix, self.orig_prefix_length),
+                (self.targ_addr, None),
+            ],
+            [Some(self.orig_seq_num), self.targ_seq_num],
+            self.metric_type,
+            (0, self.orig_metric),
+        )?;
+        builder.finish()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{Buf, Tlv};
+
+    fn addr(bytes: &[u8]) -> Address {
+        Address::from_bytes(bytes).unwrap()
+    }
+
+    fn read(buf: &[u8]) -> Result<Rreq, Error> {
+        Rreq::from_message(&Message::read(&mut Buf::new(buf)).unwrap())
+    }
+
+    #[test]
+    fn test_rreq() {
+        let mut rreq =
+            Rreq::new(10, addr(&[10, 0, 0, 0]), addr(&[10, 9, 0, 1]), 7);
+        rreq.orig_prefix_length = Some(24);
+        rreq.orig_metric = 3;
+
+        let mut buf = [0u8; 64];
+        let len = rreq.write(&mut buf).unwrap();
+        assert_eq!(read(&buf[..len]), Ok(rreq));
+
+        rreq.orig_prefix_length = None;
+        rreq.targ_seq_num = Some(core::u16::MAX);
+        rreq.metric_type = 7;
+        rreq.orig_metric = 0x1234_5678;
+        let len = rreq.write(&mut buf).unwrap();
+        assert_eq!(read(&buf[..len]), Ok(rreq));
+
+        // A hop count larger than 255, or a sequence number of 0.
+        rreq.metric_type = METRIC_TYPE_HOP_COUNT;
+        assert_eq!(rreq.write(&mut buf), Err(Error::InvalidTlvValue));
+        rreq.orig_metric = 1;
+        rreq.orig_seq_num = 0;
+        assert_eq!(rreq.write(&mut buf), Err(Error::InvalidTlvValue));
+
+        // Without a PATH_METRIC.
+        let mut hdr = MsgHeader::new(MSG_TYPE_RREQ, 4);
+        hdr.hop_limit = Some(1);
+        let mut builder = MessageBuilder::new(&mut buf, &hdr).unwrap();
+        let addrs = [addr(&[10, 0, 0, 1]), addr(&[10, 0, 0, 2])];
+        builder.add_address_block(&addrs, None).unwrap();
+        builder
+            .add_address_tlvs_u8(ADDR_TLV_ADDRESS_TYPE, None, |i| Some(i as u8))
+            .unwrap();
+        builder
+            .add_address_tlvs(ADDR_TLV_SEQ_NUM, None, |i| {
+                Some(&[0, 1][..]).filter(|_| i == 0)
+            })
+            .unwrap();
+        let len = builder.finish().unwrap();
+        assert_eq!(read(&buf[..len]), Err(Error::InvalidMessage));
+
+        // A hop count larger than 255 on the wire.
+        let mut builder = MessageBuilder::new(&mut buf, &hdr).unwrap();
+        builder.add_address_block(&addrs, None).unwrap();
+        builder
+            .add_address_tlvs_u8(ADDR_TLV_ADDRESS_TYPE, None, |i| Some(i as u8))
+            .unwrap();
+        builder
+            .add_address_tlvs(ADDR_TLV_SEQ_NUM, None, |i| {
+                Some(&[0, 1][..]).filter(|_| i == 0)
+            })
+            .unwrap();
+        builder
+            .add_address_tlvs(
+                ADDR_TLV_PATH_METRIC,
+                Some(METRIC_TYPE_HOP_COUNT),
+                |i| Some(&[0xff; 4][..]).filter(|_| i == 0),
+            )
+            .unwrap();
+        let len = builder.finish().unwrap();
+        assert_eq!(read(&buf[..len]), Err(Error::InvalidTlvValue));
+
+        // Two OrigPrefix.
+        let mut builder = MessageBuilder::new(&mut buf, &hdr).unwrap();
+        builder.add_address_block(&addrs, None).unwrap();
+        builder
+            .add_address_tlvs_u8(ADDR_TLV_ADDRESS_TYPE, None, |_| Some(0))
+            .unwrap();
+        builder
+            .add_tlv(&Tlv {
+                r#type: ADDR_TLV_PATH_METRIC,
+                type_ext: Some(METRIC_TYPE_HOP_COUNT),
+                start_index: Some(0),
+                stop_index: None,
+                value: Some(&[1]),
+                multi_value: false,
+            })
+            .unwrap();
+        let len = builder.finish().unwrap();
+        assert_eq!(read(&buf[..len]), Err(Error::InvalidMessage));
+    }
+}
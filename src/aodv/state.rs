@@ -0,0 +1,1163 @@
+// Copyright 2020 Jean Pierre Dudey. See the LICENSE-MIT and
+// LICENSE-APACHE files at the top-level directory of this
+// distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use std::collections::VecDeque;
+use std::vec::Vec;
+
+use crate::aodv::*;
+use crate::{Address, Clock, Error, Message, SeqNum};
+
+/// Size of the buffer messages are written to.
+const MAX_MESSAGE_SIZE: usize = core::u16::MAX as usize;
+
+/// AODVv2 parameters, times are in milliseconds.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct AodvConfig {
+    /// ACTIVE_INTERVAL, how long a route stays Active after being used.
+    pub active_interval: u64,
+    /// MAX_IDLETIME, how long an Idle route is kept valid.
+    pub max_idle_time: u64,
+    /// MAX_BLACKLIST_TIME, how long a neighbor that didn't answer an
+    /// AckReq is ignored.
+    pub max_blacklist_time: u64,
+    /// MAX_SEQNUM_LIFETIME, how long sequence numbers are remembered.
+    pub max_seq_num_lifetime: u64,
+    /// RREQ_WAIT_TIME, time to wait for a RREP before the first retry,
+    /// doubled on each retry.
+    pub rreq_wait_time: u64,
+    /// RREP_Ack_SENT_TIMEOUT, time to wait for a RREP_Ack.
+    pub rrep_ack_sent_timeout: u64,
+    /// RREQ_HOLDDOWN_TIME, time without discoveries for a target after a
+    /// failed one.
+    pub rreq_holddown_time: u64,
+    /// DISCOVERY_ATTEMPTS_MAX, number of RREQs sent for a discovery.
+    pub discovery_attempts_max: u32,
+    /// MAX_HOPCOUNT, hop limit of the messages originated.
+    pub max_hop_count: u8,
+    /// CONTROL_TRAFFIC_LIMIT, messages sent per second, RREQs are dropped
+    /// beyond it.
+    pub control_traffic_limit: usize,
+}
+
+impl Default for AodvConfig {
+    fn default() -> AodvConfig {
+        AodvConfig {
+            active_interval: 5_000,
+            max_idle_time: 200_000,
+            max_blacklist_time: 200_000,
+            max_seq_num_lifetime: 300_000,
+            rreq_wait_time: 2_000,
+            rrep_ack_sent_timeout: 1_000,
+            rreq_holddown_time: 10_000,
+            discovery_attempts_max: 3,
+            max_hop_count: 20,
+            control_traffic_limit: 50,
+        }
+    }
+}
+
+/// State of a route.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum RouteState {
+    /// The next hop isn't known to be a bidirectional neighbor yet.
+    Unconfirmed,
+    /// Valid but not used recently.
+    Idle,
+    /// Valid and used recently.
+    Active,
+    /// Broken, kept to remember the sequence number.
+    Invalid,
+}
+
+/// Local Route.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct LocalRoute {
+    /// LocalRoute.Address
+    pub addr: Address,
+    /// LocalRoute.PrefixLength
+    pub prefix_length: u8,
+    /// LocalRoute.SeqNum
+    pub seq_num: u16,
+    /// LocalRoute.NextHop
+    pub next_hop: Address,
+    /// LocalRoute.MetricType
+    pub metric_type: u8,
+    /// LocalRoute.Metric
+    pub metric: u32,
+    /// LocalRoute.State
+    pub state: RouteState,
+    /// LocalRoute.LastUsed
+    pub last_used: u64,
+    /// LocalRoute.LastSeqNumUpdate
+    pub last_seq_num_update: u64,
+}
+
+impl LocalRoute {
+    /// Can the route be used to forward packets?
+    pub fn is_valid(&self) -> bool {
+        self.state == RouteState::Idle || self.state == RouteState::Active
+    }
+}
+
+/// State of a neighbor.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum NeighborState {
+    /// A message was received from it.
+    Heard,
+    /// The link is known to be bidirectional.
+    Confirmed,
+    /// It didn't answer an AckReq, its RREQs are ignored.
+    Blacklisted,
+}
+
+/// Neighbor.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct Neighbor {
+    /// Neighbor.IPAddress
+    pub addr: Address,
+    /// Neighbor.State
+    pub state: NeighborState,
+    /// Neighbor.Timeout, the end of the blacklisting or of the wait for a
+    /// RREP_Ack.
+    pub timeout: Option<u64>,
+}
+
+/// Multicast Route Message, a RREQ recently received or sent.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct McMsg {
+    /// McMsg.OrigPrefix
+    pub orig_prefix: Address,
+    /// McMsg.OrigPrefixLen
+    pub orig_prefix_length: u8,
+    /// McMsg.TargPrefix
+    pub targ_addr: Address,
+    /// McMsg.OrigSeqNum
+    pub orig_seq_num: u16,
+    /// McMsg.TargSeqNum
+    pub targ_seq_num: Option<u16>,
+    /// McMsg.MetricType
+    pub metric_type: u8,
+    /// McMsg.Metric
+    pub metric: u32,
+    /// McMsg.Timestamp
+    pub timestamp: u64,
+    /// McMsg.RemoveTime
+    pub remove_time: u64,
+}
+
+/// A message to send, encoded.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Send {
+    /// Neighbor to send it to, `None` to send it to every neighbor.
+    pub to: Option<Address>,
+    /// The message.
+    pub msg: Vec<u8>,
+}
+
+/// Change in the routes.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Event {
+    /// A route discovery found the target.
+    RouteFound(Address),
+    /// A route discovery failed after all the attempts.
+    DiscoveryFailed(Address),
+    /// A route was invalidated by a broken link or a RERR.
+    RouteLost {
+        /// Address of the route.
+        addr: Address,
+        /// Prefix length of the route.
+        prefix_length: u8,
+    },
+}
+
+/// What to do with a data packet.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Routing {
+    /// Forward it to the next hop.
+    Forward(Address),
+    /// Hold it, a route discovery is in progress.
+    Discovering,
+    /// Drop it, there is no route.
+    Unreachable,
+}
+
+/// Route discovery in progress for a target.
+#[derive(Debug, Clone, Copy)]
+struct Discovery {
+    target: Address,
+    /// Router client that originates the RREQs.
+    orig: Address,
+    attempts: u32,
+    /// Time of the next attempt, or the end of the hold down.
+    time: u64,
+    hold_down: bool,
+}
+
+/// AODVv2 router.
+///
+/// Keeps the Local Route Set, the Neighbor Set, the Multicast Route Message
+/// table and the route discoveries in progress. It doesn't do any I/O:
+/// messages are given to [`receive`](#method.receive) and the ones to send
+/// are retrieved with [`poll_send`](#method.poll_send), data packets ask
+/// for a route with [`route`](#method.route). [`expire`](#method.expire)
+/// must be called at [`next_expiry`](#method.next_expiry) for timeouts and
+/// retries.
+///
+/// Only the hop count metric is supported, messages with other metric
+/// types are ignored. The router has a single interface.
+#[derive(Debug)]
+pub struct Aodv<C> {
+    clock: C,
+    config: AodvConfig,
+    address_length: usize,
+    /// Router Client Table, `(address, prefix length)`.
+    clients: Vec<(Address, u8)>,
+    seq_num: SeqNum,
+    routes: Vec<LocalRoute>,
+    neighbors: Vec<Neighbor>,
+    mcmsgs: Vec<McMsg>,
+    discoveries: Vec<Discovery>,
+    /// Times of the messages sent in the last second.
+    sent: VecDeque<u64>,
+    /// Scratch buffer the messages are written to, allocated on the first
+    /// message.
+    buf: Vec<u8>,
+    outbox: VecDeque<Send>,
+    events: VecDeque<Event>,
+}
+
+impl<C: Clock> Aodv<C> {
+    /// Create a router using addresses of `address_length` bytes, without
+    /// clients.
+    pub fn new(clock: C, address_length: usize, config: AodvConfig) -> Self {
+        Aodv {
+            clock,
+            config,
+            address_length,
+            clients: Vec::new(),
+            seq_num: SeqNum(1),
+            routes: Vec::new(),
+            neighbors: Vec::new(),
+            mcmsgs: Vec::new(),
+            discoveries: Vec::new(),
+            sent: VecDeque::new(),
+            buf: Vec::new(),
+            outbox: VecDeque::new(),
+            events: VecDeque::new(),
+        }
+    }
+
+    /// The clock.
+    pub fn clock(&self) -> &C {
+        &self.clock
+    }
+
+    /// The parameters.
+    pub fn config(&self) -> &AodvConfig {
+        &self.config
+    }
+
+    /// Add a router client, an address or prefix the router is the
+    /// destination of. `None` is the maximum prefix length.
+    pub fn add_client(&mut self, addr: Address, prefix_length: Option<u8>) {
+        let prefix_length = prefix_length.unwrap_or(self.max_prefix());
+        if !self.clients.contains(&(addr, prefix_length)) {
+            self.clients.push((addr, prefix_length));
+        }
+    }
+
+    /// Is `addr` one of the router clients?
+    pub fn is_client(&self, addr: &Address) -> bool {
+        self.clients.iter().any(|(a, p)| addr.in_prefix(a, *p))
+    }
+
+    /// SeqNum of the router, the one of the next message it originates.
+    pub fn seq_num(&self) -> u16 {
+        self.seq_num.0
+    }
+
+    /// Local Route Set.
+    pub fn routes(&self) -> &[LocalRoute] {
+        &self.routes
+    }
+
+    /// Neighbor Set.
+    pub fn neighbors(&self) -> &[Neighbor] {
+        &self.neighbors
+    }
+
+    /// Multicast Route Message table.
+    pub fn mcmsgs(&self) -> &[McMsg] {
+        &self.mcmsgs
+    }
+
+    /// Retrieve the next message to send.
+    pub fn poll_send(&mut self) -> Option<Send> {
+        self.outbox.pop_front()
+    }
+
+    /// Retrieve the next change in the routes.
+    pub fn poll_event(&mut self) -> Option<Event> {
+        self.events.pop_front()
+    }
+
+    /// Handle timeouts and send the RREQs of the discoveries to retry.
+    pub fn expire(&mut self) {
+        let now = self.clock.now();
+        self.refresh(now);
+    }
+
+    /// Next time the state changes by itself, when
+    /// [`expire`](#method.expire) must be called.
+    pub fn next_expiry(&self) -> Option<u64> {
+        let c = &self.config;
+        let routes = self.routes.iter().map(|r| match r.state {
+            RouteState::Active => r.last_used.saturating_add(c.active_interval),
+            RouteState::Idle => r.last_used.saturating_add(c.max_idle_time),
+            _ => r.last_seq_num_update.saturating_add(c.max_seq_num_lifetime),
+        });
+        let neighbors = self.neighbors.iter().filter_map(|n| n.timeout);
+        let mcmsgs = self.mcmsgs.iter().map(|m| m.remove_time);
+        let discoveries = self.discoveries.iter().map(|d| d.time);
+
+        routes
+            .chain(neighbors)
+            .chain(mcmsgs)
+            .chain(discoveries)
+            .min()
+    }
+
+    /// Route a data packet from `src` to `dest`.
+    ///
+    /// With a valid route the route becomes Active and the packet is
+    /// forwarded. Otherwise, if `src` is a router client a route discovery
+    /// is started, else a RERR is sent to `src`.
+    pub fn route(&mut self, src: &Address, dest: &Address) -> Routing {
+        let now = self.clock.now();
+        self.refresh(now);
+
+        if let Some(i) = self.find_route(dest, LocalRoute::is_valid) {
+            let route = &mut self.routes[i];
+            route.state = RouteState::Active;
+            route.last_used = now;
+            return Routing::Forward(route.next_hop);
+        }
+
+        if !self.is_client(src) {
+            let mut rerr = Rerr::new(self.address_length, 1);
+            rerr.pkt_source = Some(*src);
+            let to = self
+                .find_route(src, LocalRoute::is_valid)
+                .map(|i| self.routes[i].next_hop);
+            if to.is_some() {
+                rerr.hop_limit = self.config.max_hop_count;
+            }
+            let mut unreachable = Unreachable::new(*dest);
+            unreachable.seq_num = self
+                .find_route(dest, |_| true)
+                .map(|i| self.routes[i].seq_num);
+            let _ = self
+                .send(now, to, false, |buf| rerr.write(&[unreachable], buf));
+            return Routing::Unreachable;
+        }
+
+        match self.discoveries.iter().find(|d| d.target == *dest) {
+            Some(d) if d.hold_down => Routing::Unreachable,
+            Some(_) => Routing::Discovering,
+            None => {
+                self.discoveries.push(Discovery {
+                    target: *dest,
+                    orig: *src,
+                    attempts: 0,
+                    time: now,
+                    hold_down: false,
+                });
+                self.refresh(now);
+                Routing::Discovering
+            }
+        }
+    }
+
+    /// The link to the neighbor `next_hop` broke, its routes are
+    /// invalidated and a RERR is sent for the Active ones.
+    pub fn link_broken(&mut self, next_hop: &Address) {
+        let now = self.clock.now();
+        self.refresh(now);
+
+        self.neighbors.retain(|n| n.addr != *next_hop);
+        let lost = self.invalidate(|r| r.next_hop == *next_hop, None);
+        self.send_rerr(now, None, lost);
+    }
+
+    /// Process a message received from the neighbor `from`.
+    ///
+    /// Messages of other types are ignored. Fails with
+    /// [`Error::InvalidMessage`](../enum.Error.html#variant.InvalidMessage)
+    /// if the address length isn't the one of the router, or if the message
+    /// is invalid.
+    pub fn receive(
+        &mut self,
+        from: &Address,
+        msg: &Message,
+    ) -> Result<(), Error> {
+        let now = self.clock.now();
+        self.refresh(now);
+
+        if msg.hdr.address_length != self.address_length {
+            return Err(Error::InvalidMessage);
+        }
+
+        match msg.hdr.r#type {
+            MSG_TYPE_RREQ => {
+                self.process_rreq(now, from, Rreq::from_message(msg)?)
+            }
+            MSG_TYPE_RREP => {
+                self.process_rrep(now, from, Rrep::from_message(msg)?)
+            }
+            MSG_TYPE_RERR => {
+                let rerr = Rerr::from_message(msg)?;
+                let unreachable: Vec<_> = rerr.unreachable().collect();
+                self.process_rerr(now, from, &rerr, &unreachable)
+            }
+            MSG_TYPE_RREP_ACK => {
+                RrepAck::from_message(msg)?;
+                if self.neighbor(now, from).state == NeighborState::Heard {
+                    self.confirm(from);
+                }
+                Ok(())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn process_rreq(
+        &mut self,
+        now: u64,
+        from: &Address,
+        mut rreq: Rreq,
+    ) -> Result<(), Error> {
+        if self.neighbor(now, from).state == NeighborState::Blacklisted
+            || self.is_client(&rreq.orig_prefix)
+            || rreq.metric_type != METRIC_TYPE_HOP_COUNT
+        {
+            return Ok(());
+        }
+        let metric = match rreq.orig_metric.checked_add(1) {
+            Some(m) if m <= MAX_HOP_COUNT => m,
+            _ => return Ok(()),
+        };
+        if self.is_redundant(now, &rreq) {
+            return Ok(());
+        }
+
+        let orig_prefix_length = rreq.orig_prefix_length;
+        self.update_route(
+            now,
+            rreq.orig_prefix,
+            orig_prefix_length,
+            rreq.orig_seq_num,
+            from,
+            metric,
+        );
+
+        if self.is_client(&rreq.targ_addr) {
+            self.seq_num = next_seq_num(self.seq_num);
+            let rrep = Rrep::new(
+                self.config.max_hop_count,
+                rreq.orig_prefix,
+                rreq.targ_addr,
+                self.seq_num.0,
+            );
+            return self.send_rrep(now, from, rrep);
+        }
+
+        if rreq.hop_limit > 1 {
+            rreq.hop_limit -= 1;
+            rreq.orig_metric = metric;
+            self.send(now, None, true, |buf| rreq.write(buf))?;
+        }
+
+        Ok(())
+    }
+
+    fn process_rrep(
+        &mut self,
+        now: u64,
+        from: &Address,
+        mut rrep: Rrep,
+    ) -> Result<(), Error> {
+        // A RREP answers a RREQ, the link is bidirectional.
+        self.neighbor(now, from);
+        self.confirm(from);
+        if rrep.ack_req {
+            self.send(now, Some(*from), false, |buf| {
+                RrepAck.write(rrep.orig_addr.len(), buf)
+            })?;
+        }
+
+        if rrep.metric_type != METRIC_TYPE_HOP_COUNT {
+            return Ok(());
+        }
+        let metric = match rrep.targ_metric.checked_add(1) {
+            Some(m) if m <= MAX_HOP_COUNT => m,
+            _ => return Ok(()),
+        };
+
+        let targ_prefix_length = rrep.targ_prefix_length;
+        self.update_route(
+            now,
+            rrep.targ_prefix,
+            targ_prefix_length,
+            rrep.targ_seq_num,
+            from,
+            metric,
+        );
+
+        if self.is_client(&rrep.orig_addr) {
+            let plen = targ_prefix_length.unwrap_or(self.max_prefix());
+            let found = |d: &Discovery| {
+                !d.hold_down && d.target.in_prefix(&rrep.targ_prefix, plen)
+            };
+            if let Some(i) = self.discoveries.iter().position(found) {
+                let d = self.discoveries.remove(i);
+                self.events.push_back(Event::RouteFound(d.target));
+            }
+            return Ok(());
+        }
+
+        let route = self
+            .find_route(&rrep.orig_addr, |r| r.state != RouteState::Invalid);
+        if let (Some(i), true) = (route, rrep.hop_limit > 1) {
+            let next_hop = self.routes[i].next_hop;
+            rrep.hop_limit -= 1;
+            rrep.targ_metric = metric;
+            self.send_rrep(now, &next_hop, rrep)?;
+        }
+
+        Ok(())
+    }
+
+    fn process_rerr(
+        &mut self,
+        now: u64,
+        from: &Address,
+        rerr: &Rerr,
+        unreachable: &[Unreachable],
+    ) -> Result<(), Error> {
+        let max_prefix = self.max_prefix();
+        let mut lost = Vec::new();
+        for u in unreachable {
+            if u.metric_type != METRIC_TYPE_HOP_COUNT {
+                continue;
+            }
+            let plen = u.prefix_length.unwrap_or(max_prefix);
+            lost.extend(self.invalidate(
+                |r| {
+                    r.addr == u.addr
+                        && r.prefix_length == plen
+                        && r.next_hop == *from
+                },
+                u.seq_num,
+            ));
+        }
+
+        if rerr.hop_limit <= 1 {
+            return Ok(());
+        }
+        match rerr.pkt_source {
+            Some(ref pkt_source) if self.is_client(pkt_source) => Ok(()),
+            Some(ref pkt_source) => {
+                // Forward it towards the source of the packet.
+                let i = self.find_route(pkt_source, LocalRoute::is_valid);
+                let next_hop = match i {
+                    Some(i) => self.routes[i].next_hop,
+                    None => return Ok(()),
+                };
+                let mut rerr = rerr.clone();
+                rerr.hop_limit -= 1;
+                self.send(now, Some(next_hop), false, |buf| {
+                    rerr.write(unreachable, buf)
+                })
+            }
+            None => {
+                let mut hop_limit = rerr.hop_limit - 1;
+                if lost.is_empty() {
+                    hop_limit = 0;
+                }
+                self.send_rerr(now, Some(hop_limit), lost);
+                Ok(())
+            }
+        }
+    }
+
+    /// Send a RERR to every neighbor for the routes `lost`, with the
+    /// maximum hop limit if `hop_limit` is `None`.
+    fn send_rerr(
+        &mut self,
+        now: u64,
+        hop_limit: Option<u8>,
+        lost: Vec<Unreachable>,
+    ) {
+        let hop_limit = hop_limit.unwrap_or(self.config.max_hop_count);
+        if lost.is_empty() || hop_limit == 0 {
+            return;
+        }
+
+        let rerr = Rerr::new(self.address_length, hop_limit);
+        let _ = self.send(now, None, false, |buf| rerr.write(&lost, buf));
+    }
+
+    /// Send a RREP to `next_hop`, asking for a RREP_Ack if it isn't
+    /// confirmed.
+    fn send_rrep(
+        &mut self,
+        now: u64,
+        next_hop: &Address,
+        mut rrep: Rrep,
+    ) -> Result<(), Error> {
+        let timeout = now.saturating_add(self.config.rrep_ack_sent_timeout);
+        let neighbor = self.neighbor(now, next_hop);
+        rrep.ack_req = neighbor.state != NeighborState::Confirmed;
+        if rrep.ack_req && neighbor.timeout.is_none() {
+            neighbor.timeout = Some(timeout);
+        }
+
+        self.send(now, Some(*next_hop), false, |buf| rrep.write(buf))
+    }
+
+    /// Write a message with `write` and queue it, RREQs over the control
+    /// traffic limit are dropped.
+    fn send<F>(
+        &mut self,
+        now: u64,
+        to: Option<Address>,
+        rreq: bool,
+        write: F,
+    ) -> Result<(), Error>
+    where
+        F: FnOnce(&mut [u8]) -> Result<usize, Error>,
+    {
+        while self
+            .sent
+            .front()
+            .map_or(false, |t| t.saturating_add(1_000) <= now)
+        {
+            self.sent.pop_front();
+        }
+        if rreq && self.sent.len() >= self.config.control_traffic_limit {
+            return Ok(());
+        }
+
+        if self.buf.is_empty() {
+            self.buf = vec![0u8; MAX_MESSAGE_SIZE];
+        }
+        let len = write(&mut self.buf)?;
+        let msg = self.buf[..len].to_vec();
+        self.sent.push_back(now);
+        self.outbox.push_back(Send { to, msg });
+        Ok(())
+    }
+
+    /// Check a RREQ against the Multicast Route Message table, and add it
+    /// if it isn't redundant.
+    fn is_redundant(&mut self, now: u64, rreq: &Rreq) -> bool {
+        let remove_time = now.saturating_add(self.config.max_seq_num_lifetime);
+        let mcmsg = McMsg {
+            orig_prefix: rreq.orig_prefix,
+            orig_prefix_length: rreq
+                .orig_prefix_length
+                .unwrap_or(self.max_prefix()),
+            targ_addr: rreq.targ_addr,
+            orig_seq_num: rreq.orig_seq_num,
+            targ_seq_num: rreq.targ_seq_num,
+            metric_type: rreq.metric_type,
+            metric: rreq.orig_metric,
+            timestamp: now,
+            remove_time,
+        };
+
+        let existing = self.mcmsgs.iter_mut().find(|m| {
+            m.orig_prefix == mcmsg.orig_prefix
+                && m.orig_prefix_length == mcmsg.orig_prefix_length
+                && m.targ_addr == mcmsg.targ_addr
+                && m.metric_type == mcmsg.metric_type
+        });
+        let m = match existing {
+            Some(m) => m,
+            None => {
+                self.mcmsgs.push(mcmsg);
+                return false;
+            }
+        };
+
+        let seq_num = SeqNum(mcmsg.orig_seq_num);
+        let redundant = SeqNum(m.orig_seq_num).is_newer_than(seq_num)
+            || (m.orig_seq_num == mcmsg.orig_seq_num
+                && mcmsg.metric >= m.metric);
+        if !redundant {
+            *m = mcmsg;
+        }
+        redundant
+    }
+
+    /// Update the route to `addr/prefix_length` with the one advertised by
+    /// a message from `next_hop`, if it's an improvement.
+    fn update_route(
+        &mut self,
+        now: u64,
+        addr: Address,
+        prefix_length: Option<u8>,
+        seq_num: u16,
+        next_hop: &Address,
+        metric: u32,
+    ) {
+        let prefix_length = prefix_length.unwrap_or(self.max_prefix());
+        let confirmed = self.neighbors.iter().any(|n| {
+            n.addr == *next_hop && n.state == NeighborState::Confirmed
+        });
+        let state = if confirmed {
+            RouteState::Idle
+        } else {
+            RouteState::Unconfirmed
+        };
+
+        let route = LocalRoute {
+            addr,
+            prefix_length,
+            seq_num,
+            next_hop: *next_hop,
+            metric_type: METRIC_TYPE_HOP_COUNT,
+            metric,
+            state,
+            last_used: now,
+            last_seq_num_update: now,
+        };
+        let existing = self.routes.iter_mut().find(|r| {
+            r.addr == addr
+                && r.prefix_length == prefix_length
+                && r.metric_type == METRIC_TYPE_HOP_COUNT
+        });
+        let r = match existing {
+            Some(r) => r,
+            None => {
+                self.routes.push(route);
+                return;
+            }
+        };
+
+        let newer = SeqNum(seq_num).is_newer_than(SeqNum(r.seq_num));
+        let better = r.seq_num == seq_num
+            && (r.state == RouteState::Invalid || metric < r.metric);
+        // A valid route isn't replaced by one through an unconfirmed
+        // neighbor.
+        if !(newer || better) || (r.is_valid() && !confirmed) {
+            return;
+        }
+
+        let active = r.state == RouteState::Active;
+        *r = route;
+        if active {
+            r.state = RouteState::Active;
+        }
+    }
+
+    /// Invalidate the routes matching `f`, unless their sequence number is
+    /// newer than `seq_num`. Returns the Active ones, to send in a RERR.
+    fn invalidate<F>(&mut self, f: F, seq_num: Option<u16>) -> Vec<Unreachable>
+    where
+        F: Fn(&LocalRoute) -> bool,
+    {
+        let max_prefix = self.max_prefix();
+        let mut lost = Vec::new();
+        for r in self.routes.iter_mut() {
+            let newer = seq_num
+                .map_or(false, |s| SeqNum(r.seq_num).is_newer_than(SeqNum(s)));
+            if r.state == RouteState::Invalid || newer || !f(r) {
+                continue;
+            }
+
+            if r.state == RouteState::Active {
+                lost.push(Unreachable {
+                    addr: r.addr,
+                    prefix_length: Some(r.prefix_length)
+                        .filter(|p| *p != max_prefix),
+                    seq_num: Some(r.seq_num),
+                    metric_type: r.metric_type,
+                });
+            }
+            if r.is_valid() {
+                self.events.push_back(Event::RouteLost {
+                    addr: r.addr,
+                    prefix_length: r.prefix_length,
+                });
+            }
+            r.state = RouteState::Invalid;
+        }
+        // Unconfirmed routes are just forgotten.
+        self.routes
+            .retain(|r| r.state != RouteState::Unconfirmed || !f(r));
+
+        lost
+    }
+
+    /// The neighbor `addr`, added as Heard if unknown.
+    fn neighbor(&mut self, now: u64, addr: &Address) -> &mut Neighbor {
+        let i = match self.neighbors.iter().position(|n| n.addr == *addr) {
+            Some(i) => i,
+            None => {
+                self.neighbors.push(Neighbor {
+                    addr: *addr,
+                    state: NeighborState::Heard,
+                    timeout: None,
+                });
+                self.neighbors.len() - 1
+            }
+        };
+
+        let n = &mut self.neighbors[i];
+        if n.state == NeighborState::Blacklisted
+            && n.timeout.map_or(false, |t| t <= now)
+        {
+            n.state = NeighborState::Heard;
+            n.timeout = None;
+        }
+        n
+    }
+
+    /// The link with `addr` is bidirectional, its Unconfirmed routes become
+    /// Idle.
+    fn confirm(&mut self, addr: &Address) {
+        if let Some(n) = self.neighbors.iter_mut().find(|n| n.addr == *addr) {
+            n.state = NeighborState::Confirmed;
+            n.timeout = None;
+        }
+        for r in self.routes.iter_mut() {
+            if r.next_hop == *addr && r.state == RouteState::Unconfirmed {
+                r.state = RouteState::Idle;
+            }
+        }
+    }
+
+    /// Longest prefix match for `addr` among the routes matching `f`.
+    fn find_route<F>(&self, addr: &Address, f: F) -> Option<usize>
+    where
+        F: Fn(&LocalRoute) -> bool,
+    {
+        self.routes
+            .iter()
+            .enumerate()
+            .filter(|(_, r)| f(r) && addr.in_prefix(&r.addr, r.prefix_length))
+            .max_by_key(|(_, r)| (r.prefix_length, core::u32::MAX - r.metric))
+            .map(|(i, _)| i)
+    }
+
+    fn max_prefix(&self) -> u8 {
+        (8 * self.address_length) as u8
+    }
+
+    /// Update the state for time `now`.
+    fn refresh(&mut self, now: u64) {
+        let c = self.config;
+        for r in self.routes.iter_mut() {
+            if r.state == RouteState::Active
+                && r.last_used.saturating_add(c.active_interval) <= now
+            {
+                r.state = RouteState::Idle;
+            }
+            if r.state == RouteState::Idle
+                && r.last_used.saturating_add(c.max_idle_time) <= now
+            {
+                r.state = RouteState::Invalid;
+            }
+        }
+        self.routes.retain(|r| {
+            r.is_valid()
+                || r.last_seq_num_update.saturating_add(c.max_seq_num_lifetime)
+                    > now
+        });
+        self.mcmsgs.retain(|m| m.remove_time > now);
+
+        // Neighbors that didn't answer an AckReq.
+        let mut blacklisted = Vec::new();
+        for n in self.neighbors.iter_mut() {
+            match (n.state, n.timeout) {
+                (NeighborState::Heard, Some(t)) if t <= now => {
+                    n.state = NeighborState::Blacklisted;
+                    n.timeout = Some(now.saturating_add(c.max_blacklist_time));
+                    blacklisted.push(n.addr);
+                }
+                (NeighborState::Blacklisted, Some(t)) if t <= now => {
+                    n.state = NeighborState::Heard;
+                    n.timeout = None;
+                }
+                _ => (),
+            }
+        }
+        self.routes.retain(|r| {
+            r.state != RouteState::Unconfirmed
+                || !blacklisted.contains(&r.next_hop)
+        });
+
+        // Route discoveries.
+        let mut i = 0;
+        while i < self.discoveries.len() {
+            let d = self.discoveries[i];
+            if d.time > now {
+                i += 1;
+                continue;
+            }
+
+            if d.hold_down {
+                self.discoveries.remove(i);
+                continue;
+            }
+            if d.attempts >= c.discovery_attempts_max {
+                self.events.push_back(Event::DiscoveryFailed(d.target));
+                let d = &mut self.discoveries[i];
+                d.hold_down = true;
+                d.time = now.saturating_add(c.rreq_holddown_time);
+                i += 1;
+                continue;
+            }
+
+            self.seq_num = next_seq_num(self.seq_num);
+            let mut rreq =
+                Rreq::new(c.max_hop_count, d.orig, d.target, self.seq_num.0);
+            rreq.targ_seq_num = self
+                .find_route(&d.target, |_| true)
+                .map(|i| self.routes[i].seq_num);
+            self.is_redundant(now, &rreq);
+            let _ = self.send(now, None, true, |buf| rreq.write(buf));
+
+            let d = &mut self.discoveries[i];
+            // The wait time doubles with each attempt.
+            let wait = c
+                .rreq_wait_time
+                .checked_shl(d.attempts)
+                .filter(|w| w >> d.attempts == c.rreq_wait_time)
+                .unwrap_or(core::u64::MAX);
+            d.time = now.saturating_add(wait);
+            d.attempts += 1;
+            i += 1;
+        }
+    }
+}
+
+/// Next sequence number, 0 is skipped.
+fn next_seq_num(seq_num: SeqNum) -> SeqNum {
+    match seq_num.next() {
+        SeqNum(0) => SeqNum(1),
+        s => s,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{Buf, ManualClock, MessageBuilder, MsgHeader};
+
+    fn addr(bytes: &[u8]) -> Address {
+        Address::from_bytes(bytes).unwrap()
+    }
+
+    fn receive<C: Clock>(aodv: &mut Aodv<C>, from: &Address, msg: &[u8]) {
+        let msg = Message::read(&mut Buf::new(msg)).unwrap();
+        aodv.receive(from, &msg).unwrap();
+    }
+
+    #[test]
+    fn test_discovery_retries() {
+        let clock = ManualClock::new(0);
+        let mut aodv = Aodv::new(&clock, 4, AodvConfig::default());
+        let a = addr(&[10, 0, 0, 1]);
+        let d = addr(&[10, 0, 0, 4]);
+        aodv.add_client(a, None);
+
+        assert_eq!(aodv.route(&a, &d), Routing::Discovering);
+        assert_eq!(aodv.route(&a, &d), Routing::Discovering);
+        let send = aodv.poll_send().unwrap();
+        assert_eq!(send.to, None);
+        // Only the message is kept, not the scratch buffer.
+        assert_eq!(send.msg.capacity(), send.msg.len());
+        assert!(aodv.poll_send().is_none());
+
+        // Retries after 2 and 4 seconds, fails 8 seconds later.
+        let mut times = Vec::new();
+        let mut seq_nums = Vec::new();
+        while let Some(t) = aodv.next_expiry() {
+            if t > 14_000 {
+                break;
+            }
+            clock.set(t);
+            aodv.expire();
+            while let Some(send) = aodv.poll_send() {
+                let msg = Message::read(&mut Buf::new(&send.msg)).unwrap();
+                seq_nums.push(Rreq::from_message(&msg).unwrap().orig_seq_num);
+                times.push(t);
+            }
+        }
+        assert_eq!(times, [2_000, 6_000]);
+        assert_eq!(seq_nums, [3, 4]);
+        assert_eq!(aodv.poll_event(), Some(Event::DiscoveryFailed(d)));
+        assert_eq!(aodv.poll_event(), None);
+
+        // No discovery during the hold down.
+        clock.set(14_000 + 9_999);
+        assert_eq!(aodv.route(&a, &d), Routing::Unreachable);
+        clock.set(14_000 + 10_000);
+        assert_eq!(aodv.route(&a, &d), Routing::Discovering);
+    }
+
+    #[test]
+    fn test_discovery_long_backoff() {
+        let clock = ManualClock::new(0);
+        let config = AodvConfig {
+            discovery_attempts_max: 100,
+            ..AodvConfig::default()
+        };
+        let mut aodv = Aodv::new(&clock, 4, config);
+        let a = addr(&[10, 0, 0, 1]);
+        let d = addr(&[10, 0, 0, 4]);
+        aodv.add_client(a, None);
+
+        // The backoff saturates when the shift drops bits or overflows.
+        assert_eq!(aodv.route(&a, &d), Routing::Discovering);
+        for &attempts in &[63, 64] {
+            aodv.discoveries[0].attempts = attempts;
+            aodv.discoveries[0].time = 0;
+            aodv.expire();
+            assert_eq!(aodv.discoveries[0].time, core::u64::MAX);
+        }
+    }
+
+    #[test]
+    fn test_rreq_rate_limit_and_redundant() {
+        let clock = ManualClock::new(0);
+        let config = AodvConfig {
+            control_traffic_limit: 2,
+            ..AodvConfig::default()
+        };
+        let mut aodv = Aodv::new(&clock, 4, config);
+        aodv.add_client(addr(&[10, 0, 0, 2]), None);
+        let from = addr(&[10, 0, 0, 1]);
+
+        let mut buf = [0u8; 64];
+        let mut rreq = Rreq::new(10, from, addr(&[10, 0, 0, 9]), 1);
+        for _ in 0..2 {
+            let len = rreq.write(&mut buf).unwrap();
+            receive(&mut aodv, &from, &buf[..len]);
+        }
+        // The second one is redundant.
+        assert!(aodv.poll_send().is_some());
+        assert!(aodv.poll_send().is_none());
+        assert_eq!(aodv.mcmsgs().len(), 1);
+        assert_eq!(aodv.routes()[0].state, RouteState::Unconfirmed);
+
+        for s in 2..5 {
+            rreq.orig_seq_num = s;
+            let len = rreq.write(&mut buf).unwrap();
+            receive(&mut aodv, &from, &buf[..len]);
+        }
+        // Only one more fits in the limit.
+        assert!(aodv.poll_send().is_some());
+        assert!(aodv.poll_send().is_none());
+        clock.set(1_000);
+        rreq.orig_seq_num = 5;
+        let len = rreq.write(&mut buf).unwrap();
+        receive(&mut aodv, &from, &buf[..len]);
+        assert!(aodv.poll_send().is_some());
+    }
+
+    #[test]
+    fn test_rrep_ack() {
+        let clock = ManualClock::new(0);
+        let mut aodv = Aodv::new(&clock, 4, AodvConfig::default());
+        let b = addr(&[10, 0, 0, 2]);
+        aodv.add_client(b, None);
+        let a = addr(&[10, 0, 0, 1]);
+        let c = addr(&[10, 0, 0, 3]);
+
+        let mut buf = [0u8; 64];
+        let len = Rreq::new(10, a, b, 1).write(&mut buf).unwrap();
+        receive(&mut aodv, &a, &buf[..len]);
+        let send = aodv.poll_send().unwrap();
+        assert_eq!(send.to, Some(a));
+        let msg = Message::read(&mut Buf::new(&send.msg)).unwrap();
+        assert!(Rrep::from_message(&msg).unwrap().ack_req);
+        assert_eq!(aodv.neighbors()[0].state, NeighborState::Heard);
+
+        let len = RrepAck.write(4, &mut buf).unwrap();
+        receive(&mut aodv, &a, &buf[..len]);
+        assert_eq!(aodv.neighbors()[0].state, NeighborState::Confirmed);
+        assert_eq!(aodv.routes()[0].state, RouteState::Idle);
+        assert_eq!(aodv.route(&b, &a), Routing::Forward(a));
+
+        // Without an answer the neighbor is blacklisted.
+        let len = Rreq::new(10, c, b, 1).write(&mut buf).unwrap();
+        receive(&mut aodv, &c, &buf[..len]);
+        assert_eq!(aodv.poll_send().unwrap().to, Some(c));
+        clock.set(1_000);
+        aodv.expire();
+        assert_eq!(aodv.neighbors()[1].state, NeighborState::Blacklisted);
+        assert!(aodv.routes().iter().all(|r| r.next_hop != c));
+        let len = Rreq::new(10, c, b, 2).write(&mut buf).unwrap();
+        receive(&mut aodv, &c, &buf[..len]);
+        assert!(aodv.poll_send().is_none());
+        clock.set(201_000);
+        receive(&mut aodv, &c, &buf[..len]);
+        assert_eq!(aodv.poll_send().unwrap().to, Some(c));
+    }
+
+    #[test]
+    fn test_oversized_metric() {
+        let clock = ManualClock::new(0);
+        let mut aodv = Aodv::new(&clock, 4, AodvConfig::default());
+        aodv.add_client(addr(&[10, 0, 0, 2]), None);
+        let from = addr(&[10, 0, 0, 1]);
+
+        // A hop count PATH_METRIC of ff ff ff ff is rejected.
+        let mut buf = [0u8; 64];
+        let mut hdr = MsgHeader::new(MSG_TYPE_RREQ, 4);
+        hdr.hop_limit = Some(10);
+        let mut builder = MessageBuilder::new(&mut buf, &hdr).unwrap();
+        builder
+            .add_address_block(&[from, addr(&[10, 0, 0, 9])], None)
+            .unwrap();
+        builder
+            .add_address_tlvs_u8(ADDR_TLV_ADDRESS_TYPE, None, |i| Some(i as u8))
+            .unwrap();
+        builder
+            .add_address_tlvs(ADDR_TLV_SEQ_NUM, None, |i| {
+                Some(&[0, 1][..]).filter(|_| i == 0)
+            })
+            .unwrap();
+        builder
+            .add_address_tlvs(
+                ADDR_TLV_PATH_METRIC,
+                Some(METRIC_TYPE_HOP_COUNT),
+                |i| Some(&[0xff; 4][..]).filter(|_| i == 0),
+            )
+            .unwrap();
+        let len = builder.finish().unwrap();
+        let msg = Message::read(&mut Buf::new(&buf[..len])).unwrap();
+        assert_eq!(aodv.receive(&from, &msg), Err(Error::InvalidTlvValue));
+
+        // The largest hop count can't be increased.
+        let mut rreq = Rreq::new(10, from, addr(&[10, 0, 0, 9]), 1);
+        rreq.orig_metric = MAX_HOP_COUNT;
+        let len = rreq.write(&mut buf).unwrap();
+        receive(&mut aodv, &from, &buf[..len]);
+        let mut rrep = Rrep::new(10, addr(&[10, 0, 0, 2]), from, 1);
+        rrep.targ_metric = MAX_HOP_COUNT;
+        let len = rrep.write(&mut buf).unwrap();
+        receive(&mut aodv, &from, &buf[..len]);
+        assert!(aodv.routes().is_empty());
+    }
+}
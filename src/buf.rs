@@ -96,9 +96,105 @@ impl<'a> Buf<'a> {
     }
 }
 
+/// Writer buffer.
+#[derive(Debug, Eq, PartialEq)]
+pub struct BufMut<'a> {
+    /// Internal buffer.
+    buf: &'a mut [u8],
+    /// Current offset.
+    off: usize,
+}
+
+impl<'a> BufMut<'a> {
+    /// Create a new `BufMut`
+    #[inline(always)]
+    pub fn new(buf: &'a mut [u8]) -> BufMut<'a> {
+        BufMut { buf, off: 0 }
+    }
+
+    /// Check if we have sufficient space available to write. Returns an
+    /// error if the buffer is too small.
+    #[inline(always)]
+    fn err_on_full(&self, needed: usize) -> Result<(), Error> {
+        if self.buf.len() - self.off < needed {
+            return Err(Error::BufferTooSmall);
+        }
+        Ok(())
+    }
+
+    /// Current position in the buffer
+    #[inline(always)]
+    pub fn pos(&self) -> usize {
+        self.off
+    }
+
+    /// Number of bytes that can still be written.
+    #[inline(always)]
+    pub fn remaining(&self) -> usize {
+        self.buf.len() - self.off
+    }
+
+    /// Discard everything written after `pos`.
+    #[inline(always)]
+    pub fn truncate(&mut self, pos: usize) {
+        if pos < self.off {
+            self.off = pos;
+        }
+    }
+
+    /// The bytes written so far.
+    #[inline(always)]
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.buf[..self.off]
+    }
+
+    /// The bytes written so far, mutable.
+    #[inline(always)]
+    pub fn as_bytes_mut(&mut self) -> &mut [u8] {
+        &mut self.buf[..self.off]
+    }
+
+    /// Write an `u8` to the buffer.
+    #[inline(always)]
+    pub fn put_u8(&mut self, v: u8) -> Result<(), Error> {
+        self.err_on_full(1)?;
+
+        self.buf[self.off] = v;
+        self.off += 1;
+        Ok(())
+    }
+
+    /// Write an `u16` in network-endian to the buffer.
+    #[inline(always)]
+    pub fn put_ne_u16(&mut self, v: u16) -> Result<(), Error> {
+        self.put_bytes(&v.to_be_bytes())
+    }
+
+    /// Write a byte slice.
+    #[inline(always)]
+    pub fn put_bytes(&mut self, b: &[u8]) -> Result<(), Error> {
+        self.err_on_full(b.len())?;
+
+        self.buf[self.off..self.off + b.len()].copy_from_slice(b);
+        self.off += b.len();
+        Ok(())
+    }
+
+    /// Overwrite an already written `u16` in network-endian at `pos`.
+    ///
+    /// # Panics
+    ///
+    /// If `pos + 2` is past the bytes written so far.
+    #[inline(always)]
+    pub fn set_ne_u16(&mut self, pos: usize, v: u16) {
+        self.as_bytes_mut()[pos..pos + 2].copy_from_slice(&v.to_be_bytes());
+    }
+}
+
 #[cfg(test)]
 pub mod test {
-    use crate::buf::Buf;
+    use crate::buf::{Buf, BufMut};
+    use crate::Error;
 
     const BUF: &[u8] = &[0xde, 0xad, 0xbe, 0xef, 0xba, 0xbe, 0xca, 0xfe];
 
@@ -128,6 +224,22 @@ pub mod test {
         }
     }
 
+    #[test]
+    fn test_buf_mut() {
+        let mut out = [0u8; 6];
+        let mut buf = BufMut::new(&mut out);
+        buf.put_u8(0xde).unwrap();
+        buf.put_ne_u16(0x0000).unwrap();
+        buf.put_bytes(&[0xef, 0xba]).unwrap();
+        buf.set_ne_u16(1, 0xadbe);
+        assert_eq!(buf.pos(), 5);
+        assert_eq!(buf.as_bytes(), &BUF[..5]);
+        assert_eq!(buf.put_ne_u16(0xbeca), Err(Error::BufferTooSmall));
+        buf.truncate(1);
+        assert_eq!(buf.remaining(), 5);
+        assert_eq!(buf.as_bytes(), &BUF[..1]);
+    }
+
     #[test]
     fn test_buf_is_eof() {
         let mut buf = Buf::new(BUF);
@@ -0,0 +1,765 @@
+// Copyright 2020 Jean Pierre Dudey. See the LICENSE-MIT and
+// LICENSE-APACHE files at the top-level directory of this
+// distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use core::ops::Range;
+
+use crate::{Address, AddressBlock, BufMut, Error, MsgHeader, Tlv};
+
+/// Message writer.
+///
+/// Writes a `<message>` in order: the header when created, then the message
+/// TLVs, then each address block followed by its address TLVs. The
+/// `<msg-size>` and `<tlvs-length>` fields are filled in as the message is
+/// written.
+///
+/// Every method either writes all of its input or nothing, so on
+/// [`Error::BufferTooSmall`](enum.Error.html#variant.BufferTooSmall) the
+/// message can still be finished without the last element.
+#[derive(Debug)]
+pub struct MessageBuilder<'a> {
+    buf: BufMut<'a>,
+    address_length: usize,
+    /// Position of the `<tlvs-length>` of the TLV block being written.
+    tlv_block: usize,
+    /// Number of addresses of the address block being written, `None` while
+    /// writing the message TLV block.
+    num_addr: Option<usize>,
+}
+
+impl<'a> MessageBuilder<'a> {
+    /// Start writing a message with header `hdr` to `buf`.
+    pub fn new(
+        buf: &'a mut [u8],
+        hdr: &MsgHeader,
+    ) -> Result<MessageBuilder<'a>, Error> {
+        // <msg-size> is 16 bits.
+        let max = buf.len().min(usize::from(core::u16::MAX));
+        let mut buf = BufMut::new(&mut buf[..max]);
+
+        hdr.write(&mut buf)?;
+        let tlv_block = buf.pos();
+        buf.put_ne_u16(0)?;
+
+        Ok(MessageBuilder {
+            buf,
+            address_length: hdr.address_length,
+            tlv_block,
+            num_addr: None,
+        })
+    }
+
+    /// Size in bytes of the message written so far.
+    pub fn size(&self) -> usize {
+        self.buf.pos()
+    }
+
+    /// Number of bytes that can still be written.
+    pub fn remaining(&self) -> usize {
+        self.buf.remaining()
+    }
+
+    /// Add a TLV to the TLV block being written, the message TLV block or
+    /// the TLV block of the last address block.
+    pub fn add_tlv(&mut self, tlv: &Tlv) -> Result<(), Error> {
+        match self.num_addr {
+            Some(num_addr) => {
+                tlv.index_range(num_addr)?;
+            }
+            None => {
+                // Message TLVs don't have indexes.
+                if tlv.start_index.is_some() || tlv.stop_index.is_some() {
+                    return Err(Error::InvalidTlvIndex);
+                }
+            }
+        }
+
+        let pos = self.buf.pos();
+        match tlv.write(&mut self.buf) {
+            Ok(()) => Ok(()),
+            Err(e) => {
+                self.buf.truncate(pos);
+                Err(e)
+            }
+        }
+    }
+
+    /// Add an address block, see
+    /// [`AddressBlock::write`](struct.AddressBlock.html#method.write).
+    ///
+    /// The address TLVs added after it apply to this address block.
+    pub fn add_address_block<A: AsRef<Address>>(
+        &mut self,
+        addrs: &[A],
+        prefix_lengths: Option<&[u8]>,
+    ) -> Result<(), Error> {
+        self.start_address_block(addrs.len(), |buf, address_length| {
+            AddressBlock::write(buf, address_length, addrs, prefix_lengths)
+        })
+    }
+
+    /// Add an already encoded `<address-block>` with `num_addr` addresses.
+    pub(crate) fn add_raw_address_block(
+        &mut self,
+        raw: &[u8],
+        num_addr: usize,
+    ) -> Result<(), Error> {
+        self.start_address_block(num_addr, |buf, _| buf.put_bytes(raw))
+    }
+
+    /// Add an already encoded `<tlv>` to the TLV block being written.
+    pub(crate) fn add_raw_tlv(&mut self, raw: &[u8]) -> Result<(), Error> {
+        self.buf.put_bytes(raw)
+    }
+
+    /// Close the TLV block being written and start an address block of
+    /// `num_addr` addresses written by `write`.
+    fn start_address_block<F>(
+        &mut self,
+        num_addr: usize,
+        write: F,
+    ) -> Result<(), Error>
+    where
+        F: FnOnce(&mut BufMut, usize) -> Result<(), Error>,
+    {
+        let pos = self.buf.pos();
+        let res = self.close_tlv_block().and_then(|_| {
+            write(&mut self.buf, self.address_length)?;
+            let tlv_block = self.buf.pos();
+            self.buf.put_ne_u16(0)?;
+            Ok(tlv_block)
+        });
+
+        match res {
+            Ok(tlv_block) => {
+                self.tlv_block = tlv_block;
+                self.num_addr = Some(num_addr);
+                Ok(())
+            }
+            Err(e) => {
+                self.buf.truncate(pos);
+                Err(e)
+            }
+        }
+    }
+
+    /// Add the TLVs of type `r#type` for the last address block, with the
+    /// fewest bytes.
+    ///
+    /// `value` gives the value of the TLV for the address at an index,
+    /// `None` if the TLV doesn't apply to it, and an empty value for a TLV
+    /// without value.
+    pub fn add_address_tlvs<'v, F>(
+        &mut self,
+        r#type: u8,
+        type_ext: Option<u8>,
+        mut value: F,
+    ) -> Result<(), Error>
+    where
+        F: FnMut(usize) -> Option<&'v [u8]>,
+    {
+        let num_addr = self.num_addr.ok_or(Error::InvalidTlvIndex)?;
+        let pos = self.buf.pos();
+
+        let mut start = 0;
+        while start < num_addr {
+            if value(start).is_none() {
+                start += 1;
+                continue;
+            }
+
+            // Run of addresses with the TLV.
+            let mut stop = start;
+            while stop + 1 < num_addr && value(stop + 1).is_some() {
+                stop += 1;
+            }
+
+            let res = self.write_run(r#type, type_ext, start, stop, &mut value);
+            if let Err(e) = res {
+                self.buf.truncate(pos);
+                return Err(e);
+            }
+            start = stop + 1;
+        }
+
+        Ok(())
+    }
+
+    /// Same as [`add_address_tlvs`](#method.add_address_tlvs), for TLVs with
+    /// a single byte value.
+    pub fn add_address_tlvs_u8<F>(
+        &mut self,
+        r#type: u8,
+        type_ext: Option<u8>,
+        mut value: F,
+    ) -> Result<(), Error>
+    where
+        F: FnMut(usize) -> Option<u8>,
+    {
+        let num_addr = self.num_addr.ok_or(Error::InvalidTlvIndex)?;
+        let mut values = [None; core::u8::MAX as usize];
+        for (i, v) in values[..num_addr].iter_mut().enumerate() {
+            *v = value(i);
+        }
+
+        let values = &values;
+        self.add_address_tlvs(r#type, type_ext, |i| {
+            values[i].as_ref().map(core::slice::from_ref)
+        })
+    }
+
+    /// Write the TLVs of a run of addresses that have all a value, either
+    /// as a multi-value TLV or a TLV for each range of equal values.
+    fn write_run<'v, F>(
+        &mut self,
+        r#type: u8,
+        type_ext: Option<u8>,
+        start: usize,
+        stop: usize,
+        value: &mut F,
+    ) -> Result<(), Error>
+    where
+        F: FnMut(usize) -> Option<&'v [u8]>,
+    {
+        let num_addr = self.num_addr.unwrap_or(0);
+        let whole_block = start == 0 && stop + 1 == num_addr;
+        let ext_len = if type_ext.is_some() { 1 } else { 0 };
+        let tlv_len = |count: usize, value_len: usize| {
+            let index_len = match count {
+                _ if count == num_addr => 0,
+                1 => 1,
+                _ => 2,
+            };
+            let length_len = match value_len {
+                0 => 0,
+                1..=255 => 1,
+                _ => 2,
+            };
+            2 + ext_len + index_len + length_len + value_len
+        };
+
+        // Cost of a TLV for each range of equal values.
+        let mut ranges_len = 0;
+        let mut i = start;
+        while i <= stop {
+            let v = value(i).unwrap_or(&[]);
+            let mut j = i;
+            while j < stop && value(j + 1) == Some(v) {
+                j += 1;
+            }
+            ranges_len += tlv_len(j - i + 1, v.len());
+            i = j + 1;
+        }
+
+        // Cost of a multi-value TLV, the values must have the same size.
+        let first_len = value(start).map(|v| v.len()).unwrap_or(0);
+        let count = stop - start + 1;
+        let same_len = (start..=stop)
+            .all(|i| value(i).map(|v| v.len()) == Some(first_len));
+        let multi_len = if same_len && first_len != 0 && count > 1 {
+            tlv_len(count, count * first_len)
+        } else {
+            core::usize::MAX
+        };
+
+        let index = |i: usize, j: usize| -> (Option<u8>, Option<u8>) {
+            if i == 0 && j + 1 == num_addr {
+                (None, None)
+            } else if i == j {
+                (Some(i as u8), None)
+            } else {
+                (Some(i as u8), Some(j as u8))
+            }
+        };
+
+        if multi_len < ranges_len {
+            let (start_index, stop_index) = if whole_block {
+                (None, None)
+            } else {
+                index(start, stop)
+            };
+            let tlv = Tlv {
+                r#type,
+                type_ext,
+                start_index,
+                stop_index,
+                value: None,
+                multi_value: true,
+            };
+            tlv.write_head(&mut self.buf, Some(count * first_len))?;
+            for i in start..=stop {
+                self.buf.put_bytes(value(i).unwrap_or(&[]))?;
+            }
+            return Ok(());
+        }
+
+        let mut i = start;
+        while i <= stop {
+            let v = value(i).unwrap_or(&[]);
+            let mut j = i;
+            while j < stop && value(j + 1) == Some(v) {
+                j += 1;
+            }
+
+            let (start_index, stop_index) = index(i, j);
+            let tlv = Tlv {
+                r#type,
+                type_ext,
+                start_index,
+                stop_index,
+                value: if v.is_empty() { None } else { Some(v) },
+                multi_value: false,
+            };
+            tlv.write(&mut self.buf)?;
+            i = j + 1;
+        }
+
+        Ok(())
+    }
+
+    /// Finish the message, returns its size.
+    pub fn finish(mut self) -> Result<usize, Error> {
+        self.close_tlv_block()?;
+
+        let size = self.buf.pos();
+        self.buf.set_ne_u16(2, size as u16);
+        Ok(size)
+    }
+
+    /// Fill in the `<tlvs-length>` of the TLV block being written.
+    fn close_tlv_block(&mut self) -> Result<(), Error> {
+        let length = self.buf.pos() - self.tlv_block - 2;
+        self.buf.set_ne_u16(self.tlv_block, length as u16);
+        Ok(())
+    }
+}
+
+/// A message with more addresses than fit in a single `<message>`, written
+/// as several messages each with a part of the addresses.
+///
+/// Every message has the same header and message TLVs, the addresses keep
+/// their order and are split in address blocks of at most 255 addresses.
+///
+/// [`write`](#method.write) keeps the header as given, so the messages share
+/// the `<msg-seq-num>` and a receiver puts them back together by
+/// `<msg-type>`, `<msg-orig-addr>` and `<msg-seq-num>`. Messages going
+/// through a duplicate set need different sequence numbers, given by
+/// [`write_with_seq_nums`](#method.write_with_seq_nums), and a key of their
+/// own to be put back together.
+#[derive(Debug, Clone)]
+pub struct MessageSplitter<'a, A> {
+    /// Header of the messages.
+    pub hdr: MsgHeader<'a>,
+    /// Message TLVs, repeated in every message.
+    pub tlvs: &'a [Tlv<'a>],
+    /// The addresses.
+    pub addrs: &'a [A],
+    /// Prefix lengths of `addrs`, `None` if all have the maximum.
+    pub prefix_lengths: Option<&'a [u8]>,
+}
+
+impl<'a, A: AsRef<Address>> MessageSplitter<'a, A> {
+    /// Create a message with header `hdr` and the addresses `addrs`.
+    pub fn new(hdr: MsgHeader<'a>, addrs: &'a [A]) -> Self {
+        MessageSplitter {
+            hdr,
+            tlvs: &[],
+            addrs,
+            prefix_lengths: None,
+        }
+    }
+
+    /// Write the fewest messages that fit in `buf`, calling `emit` with each
+    /// of them. Returns the number of messages.
+    ///
+    /// `addr_tlvs` adds the address TLVs of each address block, it's called
+    /// with the range of `addrs` in the block, the index of a TLV in the
+    /// block is relative to the start of the range.
+    ///
+    /// Fails with [`Error::BufferTooSmall`] if `buf` can't hold a message
+    /// with a single address.
+    ///
+    /// [`Error::BufferTooSmall`]: enum.Error.html#variant.BufferTooSmall
+    pub fn write<T, E>(
+        &self,
+        buf: &mut [u8],
+        addr_tlvs: T,
+        emit: E,
+    ) -> Result<usize, Error>
+    where
+        T: FnMut(&mut MessageBuilder, Range<usize>) -> Result<(), Error>,
+        E: FnMut(&[u8]) -> Result<(), Error>,
+    {
+        let seq_num = self.hdr.seq_num;
+        self.write_parts(buf, addr_tlvs, |_| seq_num, emit)
+    }
+
+    /// Same as [`write`](#method.write), with the `<msg-seq-num>` of each
+    /// message given by `seq_num`, called once with the number of the
+    /// message, starting at 0.
+    pub fn write_with_seq_nums<T, S, E>(
+        &self,
+        buf: &mut [u8],
+        addr_tlvs: T,
+        mut seq_num: S,
+        emit: E,
+    ) -> Result<usize, Error>
+    where
+        T: FnMut(&mut MessageBuilder, Range<usize>) -> Result<(), Error>,
+        S: FnMut(usize) -> u16,
+        E: FnMut(&[u8]) -> Result<(), Error>,
+    {
+        self.write_parts(buf, addr_tlvs, |part| Some(seq_num(part)), emit)
+    }
+
+    /// Write the messages with the `<msg-seq-num>` given by `seq_num`.
+    fn write_parts<T, S, E>(
+        &self,
+        buf: &mut [u8],
+        mut addr_tlvs: T,
+        mut seq_num: S,
+        emit: E,
+    ) -> Result<usize, Error>
+    where
+        T: FnMut(&mut MessageBuilder, Range<usize>) -> Result<(), Error>,
+        S: FnMut(usize) -> Option<u16>,
+        E: FnMut(&[u8]) -> Result<(), Error>,
+    {
+        if self
+            .prefix_lengths
+            .map_or(false, |p| p.len() != self.addrs.len())
+        {
+            return Err(Error::InvalidAddressBlock);
+        }
+
+        // A part is written several times to find how many addresses fit.
+        let mut current: Option<(usize, Option<u16>)> = None;
+        split(self.addrs.len(), buf, emit, |addrs, part, buf| {
+            let seq_num = match current {
+                Some((p, s)) if p == part => s,
+                _ => {
+                    let s = seq_num(part);
+                    current = Some((part, s));
+                    s
+                }
+            };
+            let mut hdr = self.hdr.clone();
+            hdr.seq_num = seq_num;
+
+            let mut builder = MessageBuilder::new(buf, &hdr)?;
+            for tlv in self.tlvs {
+                builder.add_tlv(tlv)?;
+            }
+
+            let mut start = addrs.start;
+            while start < addrs.end {
+                let block = start..addrs.end.min(start + MAX_NUM_ADDR);
+                let prefix_lengths =
+                    self.prefix_lengths.map(|p| &p[block.clone()]);
+                builder.add_address_block(
+                    &self.addrs[block.clone()],
+                    prefix_lengths,
+                )?;
+                addr_tlvs(&mut builder, block.clone())?;
+                start = block.end;
+            }
+
+            builder.finish()
+        })
+    }
+}
+
+/// Maximum number of addresses of an address block, `<num-addr>` is 8 bits.
+const MAX_NUM_ADDR: usize = core::u8::MAX as usize;
+
+/// Split `count` addresses in the fewest messages that fit in `buf`, calling
+/// `emit` with each of them. Returns the number of messages.
+///
+/// `write` writes a message with a range of the addresses, the number of
+/// the message is given too. Each message takes as many addresses as fit, so
+/// the number of messages is minimal as long as adding an address never
+/// makes a message smaller.
+pub(crate) fn split<E, W>(
+    count: usize,
+    buf: &mut [u8],
+    mut emit: E,
+    mut write: W,
+) -> Result<usize, Error>
+where
+    E: FnMut(&[u8]) -> Result<(), Error>,
+    W: FnMut(Range<usize>, usize, &mut [u8]) -> Result<usize, Error>,
+{
+    let mut parts = 0;
+    let mut start = 0;
+    while start < count || parts == 0 {
+        // Largest number of addresses that fit.
+        let (mut lo, mut hi) = (0, count - start);
+        while lo < hi {
+            let mid = lo + (hi - lo + 1) / 2;
+            match write(start..start + mid, parts, buf) {
+                Ok(_) => lo = mid,
+                Err(Error::BufferTooSmall) => hi = mid - 1,
+                Err(e) => return Err(e),
+            }
+        }
+        if lo == 0 && start < count {
+            return Err(Error::BufferTooSmall);
+        }
+
+        let size = write(start..start + lo, parts, buf)?;
+        emit(&buf[..size])?;
+        parts += 1;
+        start += lo;
+    }
+
+    Ok(parts)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{Buf, Message};
+
+    fn addr(bytes: &[u8]) -> Address {
+        Address::from_bytes(bytes).unwrap()
+    }
+
+    #[test]
+    fn test_message_builder() {
+        let orig = [10, 0, 0, 1];
+        let mut hdr = MsgHeader::new(1, 4);
+        hdr.orig_addr = Some(&orig);
+        hdr.hop_limit = Some(255);
+        hdr.seq_num = Some(0xbeef);
+
+        let mut out = [0u8; 128];
+        let mut builder = MessageBuilder::new(&mut out, &hdr).unwrap();
+        builder
+            .add_tlv(&Tlv {
+                r#type: 1,
+                type_ext: None,
+                start_index: None,
+                stop_index: None,
+                value: Some(&[0x42]),
+                multi_value: false,
+            })
+            .unwrap();
+
+        let addrs = [
+            addr(&[10, 0, 0, 2]),
+            addr(&[10, 0, 0, 3]),
+            addr(&[10, 0, 0, 4]),
+            addr(&[10, 0, 0, 5]),
+        ];
+        let values: [&[u8]; 4] = [&[1], &[1], &[2], &[3]];
+        builder.add_address_block(&addrs, None).unwrap();
+        // Same value for all.
+        builder.add_address_tlvs(2, None, |_| Some(&[7])).unwrap();
+        // Different values.
+        builder
+            .add_address_tlvs(3, None, |i| Some(values[i]))
+            .unwrap();
+        // Without value, only for some addresses.
+        builder
+            .add_address_tlvs(
+                4,
+                Some(1),
+                |i| if i == 1 { Some(&[]) } else { None },
+            )
+            .unwrap();
+        let size = builder.finish().unwrap();
+
+        let msg = Message::read(&mut Buf::new(&out[..size])).unwrap();
+        assert_eq!(msg.hdr.size(), size);
+        assert_eq!(msg.hdr.orig_addr, Some(&orig[..]));
+        assert_eq!(msg.hdr.hop_limit, Some(255));
+        assert_eq!(msg.hdr.hop_count, None);
+        assert_eq!(msg.hdr.seq_num, Some(0xbeef));
+
+        let tlv = msg.tlv_block.iter().next().unwrap().unwrap();
+        assert_eq!(tlv.value, Some(&[0x42][..]));
+
+        let mut blocks = msg.address_tlv.iter();
+        let (block, tlvs) = blocks.next().unwrap().unwrap();
+        assert!(blocks.next().is_none());
+        assert_eq!(block.num_addr, 4);
+        for (i, a) in addrs.iter().enumerate() {
+            assert_eq!(&block.get_addr(i), a);
+        }
+
+        let mut seen = [[None; 3]; 4];
+        for tlv in tlvs.iter() {
+            let tlv = tlv.unwrap();
+            for (i, seen) in seen.iter_mut().enumerate() {
+                if let Some(v) = tlv.value_at(i, 4).unwrap() {
+                    assert!(seen[usize::from(tlv.r#type) - 2].is_none());
+                    seen[usize::from(tlv.r#type) - 2] = Some(v);
+                }
+            }
+        }
+        for i in 0..4 {
+            assert_eq!(seen[i][0], Some(&[7][..]));
+            assert_eq!(seen[i][1], Some(values[i]));
+            assert_eq!(seen[i][2], if i == 1 { Some(&[][..]) } else { None });
+        }
+    }
+
+    #[test]
+    fn test_message_builder_multi_value() {
+        let hdr = MsgHeader::new(1, 4);
+        let mut out = [0u8; 64];
+        let mut builder = MessageBuilder::new(&mut out, &hdr).unwrap();
+        let addrs = [addr(&[10, 0, 0, 2]), addr(&[10, 0, 0, 3])];
+        let values: [&[u8]; 2] = [&[1, 2], &[3, 4]];
+        builder.add_address_block(&addrs, None).unwrap();
+        builder
+            .add_address_tlvs(7, None, |i| Some(values[i]))
+            .unwrap();
+        let size = builder.finish().unwrap();
+
+        let msg = Message::read(&mut Buf::new(&out[..size])).unwrap();
+        let (_, tlvs) = msg.address_tlv.iter().next().unwrap().unwrap();
+        let mut tlvs = tlvs.iter();
+        let tlv = tlvs.next().unwrap().unwrap();
+        assert!(tlvs.next().is_none());
+        assert!(tlv.multi_value);
+        assert_eq!(tlv.value_at(0, 2).unwrap(), Some(values[0]));
+        assert_eq!(tlv.value_at(1, 2).unwrap(), Some(values[1]));
+    }
+
+    #[test]
+    fn test_message_builder_buffer_too_small() {
+        let hdr = MsgHeader::new(1, 4);
+        let mut out = [0u8; 12];
+        let mut builder = MessageBuilder::new(&mut out, &hdr).unwrap();
+        let addrs = [addr(&[10, 0, 0, 2]), addr(&[10, 0, 0, 3])];
+        assert_eq!(
+            builder.add_address_block(&addrs, None),
+            Err(Error::BufferTooSmall)
+        );
+
+        // Nothing was written, the message is still valid.
+        assert_eq!(builder.size(), 6);
+        let size = builder.finish().unwrap();
+        let msg = Message::read(&mut Buf::new(&out[..size])).unwrap();
+        assert!(msg.address_tlv.iter().next().is_none());
+    }
+
+    #[test]
+    fn test_message_splitter() {
+        let mut addrs = [addr(&[0, 0, 0, 0]); 600];
+        for (i, a) in addrs.iter_mut().enumerate() {
+            *a = addr(&[10, 0, (i / 256) as u8, i as u8]);
+        }
+        let orig = [10, 0, 0, 1];
+        let mut hdr = MsgHeader::new(1, 4);
+        hdr.orig_addr = Some(&orig);
+        hdr.seq_num = Some(core::u16::MAX);
+        let tlvs = [Tlv {
+            r#type: 1,
+            type_ext: None,
+            start_index: None,
+            stop_index: None,
+            value: Some(&[0x42]),
+            multi_value: false,
+        }];
+        let splitter = MessageSplitter {
+            tlvs: &tlvs,
+            ..MessageSplitter::new(hdr, &addrs)
+        };
+        let addr_tlvs = |builder: &mut MessageBuilder, r: Range<usize>| {
+            builder.add_address_tlvs_u8(2, None, |i| Some((r.start + i) as u8))
+        };
+
+        // A single message with 3 address blocks.
+        let mut buf = [0u8; 4096];
+        let mut found = 0;
+        let n = splitter.write(&mut buf, addr_tlvs, |msg| {
+            check_part(&addrs, msg, Some(core::u16::MAX), &mut found);
+            Ok(())
+        });
+        assert_eq!(n, Ok(1));
+        assert_eq!(found, addrs.len());
+
+        // Each message takes as many addresses as fit, about 110 with 1
+        // byte of address and 1 of TLV value per address. Less when the
+        // addresses of a block have different third bytes and take 2 bytes.
+        let mut buf = [0u8; 256];
+        let mut parts = 0;
+        found = 0;
+        let n = splitter
+            .write(&mut buf, addr_tlvs, |msg| {
+                assert!(msg.len() <= 256);
+                check_part(&addrs, msg, Some(core::u16::MAX), &mut found);
+                parts += 1;
+                Ok(())
+            })
+            .unwrap();
+        assert_eq!(found, addrs.len());
+        assert_eq!((parts, n), (6, 6));
+
+        // The sequence numbers given by the caller, asked once per message.
+        let mut seq_nums = [0usize; 6];
+        let mut asked = 0;
+        parts = 0;
+        found = 0;
+        let n = splitter
+            .write_with_seq_nums(
+                &mut buf,
+                addr_tlvs,
+                |part| {
+                    seq_nums[asked] = part;
+                    asked += 1;
+                    100 + part as u16
+                },
+                |msg| {
+                    check_part(&addrs, msg, Some(100 + parts), &mut found);
+                    parts += 1;
+                    Ok(())
+                },
+            )
+            .unwrap();
+        assert_eq!(found, addrs.len());
+        assert_eq!((parts, n), (6, 6));
+        assert_eq!((asked, seq_nums), (6, [0, 1, 2, 3, 4, 5]));
+
+        let mut buf = [0u8; 20];
+        let res = splitter.write(&mut buf, addr_tlvs, |_| Ok(()));
+        assert_eq!(res, Err(Error::BufferTooSmall));
+    }
+
+    /// Check a message with the `<msg-seq-num>` `seq_num` written by a
+    /// `MessageSplitter`, `found` is the number of addresses found before it.
+    fn check_part(
+        addrs: &[Address],
+        msg: &[u8],
+        seq_num: Option<u16>,
+        found: &mut usize,
+    ) {
+        let msg = Message::read(&mut Buf::new(msg)).unwrap();
+        assert_eq!(msg.hdr.size(), msg.as_bytes().len());
+        assert_eq!(msg.hdr.seq_num, seq_num);
+        let mut msg_tlvs = msg.tlv_block.iter();
+        let tlv = msg_tlvs.next().unwrap().unwrap();
+        assert_eq!(tlv.value, Some(&[0x42][..]));
+        assert!(msg_tlvs.next().is_none());
+
+        for block in msg.address_tlv.iter() {
+            let (block, tlvs) = block.unwrap();
+            assert!(block.num_addr <= 255);
+            let tlv = tlvs.iter().next().unwrap().unwrap();
+            for i in 0..block.num_addr {
+                assert_eq!(block.get_addr(i), addrs[*found]);
+                let value = tlv.value_at(i, block.num_addr).unwrap();
+                assert_eq!(value, Some(&[*found as u8][..]));
+                *found += 1;
+            }
+        }
+    }
+}
@@ -0,0 +1,68 @@
+// Copyright 2020 Jean Pierre Dudey. See the LICENSE-MIT and
+// LICENSE-APACHE files at the top-level directory of this
+// distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use core::cell::Cell;
+
+/// A source of time.
+///
+/// The crate never reads the system time by itself, every component that
+/// needs to know the time takes a `Clock`, so it can be driven by a hardware
+/// timer on a microcontroller or by a simulated clock on tests.
+pub trait Clock {
+    /// Monotonic time in milliseconds since an arbitrary epoch.
+    fn now(&self) -> u64;
+
+    /// Wall clock time in seconds since the POSIX epoch, `None` if the
+    /// clock doesn't know it.
+    fn posix_time(&self) -> Option<u64> {
+        None
+    }
+}
+
+impl<C: Clock + ?Sized> Clock for &C {
+    fn now(&self) -> u64 {
+        (**self).now()
+    }
+
+    fn posix_time(&self) -> Option<u64> {
+        (**self).posix_time()
+    }
+}
+
+/// A clock that only moves when told to, for simulations and tests.
+#[derive(Debug, Default)]
+pub struct ManualClock {
+    now: Cell<u64>,
+}
+
+impl ManualClock {
+    /// Create a clock at time `now`.
+    pub fn new(now: u64) -> ManualClock {
+        ManualClock {
+            now: Cell::new(now),
+        }
+    }
+
+    /// Set the time.
+    pub fn set(&self, now: u64) {
+        self.now.set(now);
+    }
+
+    /// Move the time forward by `ms` milliseconds.
+    pub fn advance(&self, ms: u64) {
+        self.now.set(self.now.get() + ms);
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> u64 {
+        self.now.get()
+    }
+}
@@ -0,0 +1,226 @@
+// Copyright 2020 Jean Pierre Dudey. See the LICENSE-MIT and
+// LICENSE-APACHE files at the top-level directory of this
+// distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use std::collections::BTreeMap;
+
+use crate::{Address, Clock, MsgHeader, SeqNum};
+
+/// Number of sequence numbers remembered for each originator.
+const WINDOW: u16 = 64;
+
+bitflags! {
+    /// What to do with a received message, drop it if empty.
+    pub struct Decision: u8 {
+        /// Process the message, it wasn't processed before.
+        const PROCESS = 1;
+        /// Forward the message, it wasn't forwarded before.
+        const FORWARD = 2;
+    }
+}
+
+/// Messages received from an originator.
+#[derive(Debug, Clone, Copy)]
+struct Entry {
+    /// Newest sequence number.
+    newest: SeqNum,
+    /// Processed messages, bit `n` is the sequence number `newest - n`.
+    processed: u64,
+    /// Forwarded messages, same as `processed`.
+    forwarded: u64,
+    time: u64,
+}
+
+/// Processed and Forwarded Sets (RFC 7181 section 16.1), to detect duplicate
+/// messages.
+///
+/// Messages are identified by their type, `<msg-orig-addr>` and
+/// `<msg-seq-num>`. The last 64 sequence numbers of each type and
+/// originator are remembered, older messages are dropped as duplicates. An
+/// originator is forgotten after `hold_time` milliseconds without new
+/// messages.
+#[derive(Debug)]
+pub struct DuplicateSet<C> {
+    clock: C,
+    hold_time: u64,
+    entries: BTreeMap<(u8, Address), Entry>,
+}
+
+impl<C: Clock> DuplicateSet<C> {
+    /// Create an empty set keeping originators for `hold_time`
+    /// milliseconds.
+    pub fn new(clock: C, hold_time: u64) -> Self {
+        DuplicateSet {
+            clock,
+            hold_time,
+            entries: BTreeMap::new(),
+        }
+    }
+
+    /// The clock.
+    pub fn clock(&self) -> &C {
+        &self.clock
+    }
+
+    /// Number of originators remembered.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Is the set empty?
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Decide what to do with a received message with header `hdr`, and
+    /// remember the decision.
+    ///
+    /// `relay` tells if this router relays the messages of the sender, for
+    /// example if it was selected as flooding MPR by it. A message is only
+    /// forwarded once, if it has a `<msg-hop-limit>` larger than 1 and a
+    /// `<msg-hop-count>` smaller than 255. Messages without
+    /// `<msg-orig-addr>` or `<msg-seq-num>` can't be identified, they're
+    /// always processed and never forwarded.
+    pub fn receive(&mut self, hdr: &MsgHeader, relay: bool) -> Decision {
+        let orig = hdr.orig_addr.and_then(Address::from_bytes);
+        let (orig, seq_num) = match (orig, hdr.seq_num) {
+            (Some(orig), Some(seq_num)) => (orig, SeqNum(seq_num)),
+            _ => return Decision::PROCESS,
+        };
+
+        let time = self.clock.now().saturating_add(self.hold_time);
+        let entry = self.entries.entry((hdr.r#type, orig)).or_insert(Entry {
+            newest: seq_num,
+            processed: 0,
+            forwarded: 0,
+            time,
+        });
+
+        let bit = if let Some(d) = seq_num.distance(entry.newest) {
+            // Slide the window to the new sequence number.
+            if d >= WINDOW {
+                entry.processed = 0;
+                entry.forwarded = 0;
+            } else {
+                entry.processed <<= d;
+                entry.forwarded <<= d;
+            }
+            entry.newest = seq_num;
+            0
+        } else {
+            match entry.newest.distance(seq_num) {
+                Some(d) if d < WINDOW => d,
+                Some(_) => return Decision::empty(),
+                None => 0,
+            }
+        };
+
+        let mask = 1u64 << bit;
+        let mut decision = Decision::empty();
+        if entry.processed & mask == 0 {
+            entry.processed |= mask;
+            entry.time = time;
+            decision |= Decision::PROCESS;
+        }
+
+        let forward = relay
+            && hdr.hop_limit.map_or(false, |h| h > 1)
+            && hdr.hop_count.map_or(true, |h| h < core::u8::MAX);
+        if forward && entry.forwarded & mask == 0 {
+            entry.forwarded |= mask;
+            decision |= Decision::FORWARD;
+        }
+
+        decision
+    }
+
+    /// Forget the originators without messages for the hold time.
+    pub fn expire(&mut self) {
+        let now = self.clock.now();
+        let expired = self
+            .entries
+            .iter()
+            .filter(|(_, e)| e.time <= now)
+            .map(|(k, _)| *k)
+            .collect::<Vec<_>>();
+        for k in expired.iter() {
+            self.entries.remove(k);
+        }
+    }
+
+    /// Next time an originator is forgotten, when
+    /// [`expire`](#method.expire) must be called.
+    pub fn next_expiry(&self) -> Option<u64> {
+        self.entries.values().map(|e| e.time).min()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::ManualClock;
+
+    #[test]
+    fn test_duplicate_set() {
+        let clock = ManualClock::new(0);
+        let mut set = DuplicateSet::new(&clock, 1000);
+        let orig = [10, 0, 0, 1];
+        let mut hdr = MsgHeader::new(1, 4);
+        hdr.orig_addr = Some(&orig);
+        hdr.seq_num = Some(core::u16::MAX);
+        hdr.hop_limit = Some(2);
+        hdr.hop_count = Some(0);
+
+        let both = Decision::PROCESS | Decision::FORWARD;
+        assert_eq!(set.receive(&hdr, false), Decision::PROCESS);
+        assert_eq!(set.receive(&hdr, false), Decision::empty());
+        // Forwarded when received from an MPR selector.
+        assert_eq!(set.receive(&hdr, true), Decision::FORWARD);
+        assert_eq!(set.receive(&hdr, true), Decision::empty());
+
+        // Wraps around, and older messages are still detected.
+        hdr.seq_num = Some(1);
+        assert_eq!(set.receive(&hdr, true), both);
+        hdr.seq_num = Some(0);
+        assert_eq!(set.receive(&hdr, true), both);
+        assert_eq!(set.receive(&hdr, true), Decision::empty());
+        hdr.seq_num = Some(1);
+        assert_eq!(set.receive(&hdr, true), Decision::empty());
+
+        // Too old.
+        hdr.seq_num = Some(1 + WINDOW);
+        assert_eq!(set.receive(&hdr, true), both);
+        hdr.seq_num = Some(1);
+        assert_eq!(set.receive(&hdr, true), Decision::empty());
+
+        // Another type is another message.
+        hdr.r#type = 2;
+        assert_eq!(set.receive(&hdr, true), both);
+
+        // Hop limit and hop count.
+        hdr.seq_num = Some(10);
+        hdr.hop_limit = Some(1);
+        assert_eq!(set.receive(&hdr, true), Decision::PROCESS);
+        hdr.seq_num = Some(11);
+        hdr.hop_limit = Some(10);
+        hdr.hop_count = Some(core::u8::MAX);
+        assert_eq!(set.receive(&hdr, true), Decision::PROCESS);
+
+        // Without an originator or sequence number.
+        hdr.orig_addr = None;
+        assert_eq!(set.receive(&hdr, true), Decision::PROCESS);
+        assert_eq!(set.receive(&hdr, true), Decision::PROCESS);
+
+        assert_eq!(set.len(), 2);
+        assert_eq!(set.next_expiry(), Some(1000));
+        clock.set(1000);
+        set.expire();
+        assert!(set.is_empty());
+    }
+}
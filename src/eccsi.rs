@@ -0,0 +1,660 @@
+// Copyright 2020 Jean Pierre Dudey. See the LICENSE-MIT and
+// LICENSE-APACHE files at the top-level directory of this
+// distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! RFC 7859 identity-based signatures.
+//!
+//! Messages are signed with ECCSI (RFC 6507) over NIST P-256 and SHA-256,
+//! the signature is carried in an ICV message TLV (see the
+//! [`icv`](../icv/index.html) module) and covers the same content as any
+//! other RFC 7182 message ICV.
+//!
+//! The identity of the signer is its originator address, optionally
+//! followed by a 32-bit expiry time chosen by the KMS, which is carried as
+//! the `<key-id>` of the ICV TLV.
+//!
+//! Requires the `eccsi` feature.
+
+use p256::elliptic_curve::ff::{Field, PrimeField};
+use p256::elliptic_curve::ops::Reduce;
+use p256::elliptic_curve::sec1::{FromEncodedPoint, ToEncodedPoint};
+use p256::{
+    AffinePoint, EncodedPoint, FieldBytes, ProjectivePoint, Scalar, U256,
+};
+use sha2::{Digest, Sha256};
+
+use crate::icv::{
+    self, IcvValue, CRYPTO_ECCSI, HASH_IDENTITY, HASH_SHA256, MSG_TLV_ICV,
+};
+use crate::{Error, Message, MAX_ADDR_LEN};
+
+/// Size in bytes of a scalar or field element.
+pub const SCALAR_LEN: usize = 32;
+/// Size in bytes of an uncompressed point.
+pub const POINT_LEN: usize = 2 * SCALAR_LEN + 1;
+/// Size in bytes of a signature, `r || s || PVT`.
+pub const SIGNATURE_LEN: usize = 2 * SCALAR_LEN + POINT_LEN;
+/// Size in bytes of the value of an ECCSI ICV TLV, with an expiry time.
+pub const MAX_ICV_VALUE_LEN: usize = 3 + 4 + SIGNATURE_LEN;
+
+/// Identity of a signer, its originator address and an optional expiry
+/// time.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct Identity {
+    buf: [u8; MAX_ADDR_LEN + 4],
+    addr_len: usize,
+    expiry: Option<u32>,
+}
+
+impl Identity {
+    /// Create a new `Identity`, returns `None` if `addr` is larger than
+    /// [`MAX_ADDR_LEN`](../constant.MAX_ADDR_LEN.html).
+    pub fn new(addr: &[u8], expiry: Option<u32>) -> Option<Identity> {
+        if addr.len() > MAX_ADDR_LEN {
+            return None;
+        }
+
+        let mut buf = [0u8; MAX_ADDR_LEN + 4];
+        buf[..addr.len()].copy_from_slice(addr);
+        if let Some(expiry) = expiry {
+            buf[addr.len()..addr.len() + 4]
+                .copy_from_slice(&expiry.to_be_bytes());
+        }
+
+        Some(Identity {
+            buf,
+            addr_len: addr.len(),
+            expiry,
+        })
+    }
+
+    /// Originator address.
+    pub fn addr(&self) -> &[u8] {
+        &self.buf[..self.addr_len]
+    }
+
+    /// Expiry time.
+    pub fn expiry(&self) -> Option<u32> {
+        self.expiry
+    }
+
+    /// The identity as signed by ECCSI, the address followed by the expiry
+    /// time in network-endian.
+    pub fn as_bytes(&self) -> &[u8] {
+        match self.expiry {
+            Some(_) => &self.buf[..self.addr_len + 4],
+            None => &self.buf[..self.addr_len],
+        }
+    }
+
+    /// The `<key-id>` of the ICV TLV.
+    fn key_id(&self) -> &[u8] {
+        &self.as_bytes()[self.addr_len..]
+    }
+}
+
+/// KMS Public Authentication Key (KPAK).
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct PublicKey {
+    kpak: AffinePoint,
+}
+
+impl PublicKey {
+    /// Read the KPAK from an uncompressed point.
+    pub fn from_bytes(bytes: &[u8]) -> Result<PublicKey, Error> {
+        read_point(bytes).map(|kpak| PublicKey { kpak })
+    }
+
+    /// The KPAK as an uncompressed point.
+    pub fn to_bytes(&self) -> [u8; POINT_LEN] {
+        point_bytes(&self.kpak)
+    }
+}
+
+/// Key Management Service, issues signing keys to the routers.
+///
+/// Routers don't need this, it's here to provision keys and for tests.
+#[derive(Clone)]
+pub struct Kms {
+    ksak: Scalar,
+    public_key: PublicKey,
+}
+
+impl Kms {
+    /// Create a KMS from its KMS Secret Authentication Key (KSAK).
+    pub fn new(ksak: &[u8; SCALAR_LEN]) -> Result<Kms, Error> {
+        let ksak = read_nonzero_scalar(ksak).ok_or(Error::InvalidKey)?;
+        let kpak = (ProjectivePoint::GENERATOR * ksak).to_affine();
+
+        Ok(Kms {
+            ksak,
+            public_key: PublicKey { kpak },
+        })
+    }
+
+    /// The KMS Public Authentication Key.
+    pub fn public_key(&self) -> PublicKey {
+        self.public_key
+    }
+
+    /// Issue a signing key for `id`, with `v` a random ephemeral value that
+    /// must be erased afterwards.
+    pub fn issue(
+        &self,
+        id: &[u8],
+        v: &[u8; SCALAR_LEN],
+    ) -> Result<SigningKey, Error> {
+        let v = read_nonzero_scalar(v).ok_or(Error::InvalidKey)?;
+        let pvt = (ProjectivePoint::GENERATOR * v).to_affine();
+        let hs = hash_hs(&self.public_key, id, &pvt);
+        let ssk = self.ksak + reduce(&hs) * v;
+
+        SigningKey::validate(&self.public_key, ssk, pvt, hs)
+    }
+}
+
+/// Secret Signing Key (SSK) and Public Validation Token (PVT) of a signer.
+#[derive(Clone)]
+pub struct SigningKey {
+    ssk: Scalar,
+    pvt: AffinePoint,
+    hs: [u8; SCALAR_LEN],
+}
+
+impl SigningKey {
+    /// Create a signing key from the SSK and PVT issued by the KMS for
+    /// `id`, validating them against the KPAK.
+    pub fn new(
+        public_key: &PublicKey,
+        id: &[u8],
+        ssk: &[u8; SCALAR_LEN],
+        pvt: &[u8],
+    ) -> Result<SigningKey, Error> {
+        let ssk = read_nonzero_scalar(ssk).ok_or(Error::InvalidKey)?;
+        let pvt = read_point(pvt)?;
+        let hs = hash_hs(public_key, id, &pvt);
+
+        SigningKey::validate(public_key, ssk, pvt, hs)
+    }
+
+    /// RFC 6507 section 5.1.2, `KPAK = [SSK]G - [HS]PVT`.
+    fn validate(
+        public_key: &PublicKey,
+        ssk: Scalar,
+        pvt: AffinePoint,
+        hs: [u8; SCALAR_LEN],
+    ) -> Result<SigningKey, Error> {
+        let kpak = ProjectivePoint::GENERATOR * ssk
+            - ProjectivePoint::from(pvt) * reduce(&hs);
+        if kpak.to_affine() != public_key.kpak {
+            return Err(Error::InvalidKey);
+        }
+
+        Ok(SigningKey { ssk, pvt, hs })
+    }
+
+    /// The SSK.
+    pub fn ssk(&self) -> [u8; SCALAR_LEN] {
+        self.ssk.to_bytes().into()
+    }
+
+    /// The PVT as an uncompressed point.
+    pub fn pvt(&self) -> [u8; POINT_LEN] {
+        point_bytes(&self.pvt)
+    }
+
+    /// Sign `msg`, where `j` is a random ephemeral value that must never be
+    /// reused and must be erased afterwards.
+    pub fn sign(
+        &self,
+        msg: &[u8],
+        j: &[u8; SCALAR_LEN],
+    ) -> Result<[u8; SIGNATURE_LEN], Error> {
+        self.sign_with(
+            |hasher| {
+                hasher.update(msg);
+                Ok(())
+            },
+            j,
+        )
+    }
+
+    /// Sign the message fed to the hasher by `msg`.
+    fn sign_with<F>(
+        &self,
+        msg: F,
+        j: &[u8; SCALAR_LEN],
+    ) -> Result<[u8; SIGNATURE_LEN], Error>
+    where
+        F: FnOnce(&mut Sha256) -> Result<(), Error>,
+    {
+        let j = read_nonzero_scalar(j).ok_or(Error::InvalidKey)?;
+        let r = x_bytes(&(ProjectivePoint::GENERATOR * j).to_affine());
+        let he = hash_he(&self.hs, &r, msg)?;
+
+        let t = reduce(&he) + reduce(&r) * self.ssk;
+        let t_inv: Option<Scalar> = t.invert().into();
+        let s = t_inv.ok_or(Error::InvalidKey)? * j;
+
+        let mut sig = [0u8; SIGNATURE_LEN];
+        sig[..SCALAR_LEN].copy_from_slice(&r);
+        sig[SCALAR_LEN..2 * SCALAR_LEN].copy_from_slice(&s.to_bytes());
+        sig[2 * SCALAR_LEN..].copy_from_slice(&self.pvt());
+        Ok(sig)
+    }
+}
+
+/// Verify the signature of `msg` by `id`.
+pub fn verify(
+    public_key: &PublicKey,
+    id: &[u8],
+    msg: &[u8],
+    sig: &[u8],
+) -> Result<(), Error> {
+    verify_with(
+        public_key,
+        id,
+        |hasher| {
+            hasher.update(msg);
+            Ok(())
+        },
+        sig,
+    )
+}
+
+/// Verify the signature of the message fed to the hasher by `msg`.
+fn verify_with<F>(
+    public_key: &PublicKey,
+    id: &[u8],
+    msg: F,
+    sig: &[u8],
+) -> Result<(), Error>
+where
+    F: FnOnce(&mut Sha256) -> Result<(), Error>,
+{
+    if sig.len() != SIGNATURE_LEN {
+        return Err(Error::InvalidSignature);
+    }
+
+    let r = &sig[..SCALAR_LEN];
+    let s = &sig[SCALAR_LEN..2 * SCALAR_LEN];
+    let pvt = read_point(&sig[2 * SCALAR_LEN..])
+        .map_err(|_| Error::InvalidSignature)?;
+
+    let mut r_bytes = [0u8; SCALAR_LEN];
+    r_bytes.copy_from_slice(r);
+    let mut s_bytes = [0u8; SCALAR_LEN];
+    s_bytes.copy_from_slice(s);
+    let s = read_nonzero_scalar(&s_bytes).ok_or(Error::InvalidSignature)?;
+
+    let hs = hash_hs(public_key, id, &pvt);
+    let he = hash_he(&hs, &r_bytes, msg)?;
+
+    // Y = [HS]PVT + KPAK
+    let y = ProjectivePoint::from(pvt) * reduce(&hs)
+        + ProjectivePoint::from(public_key.kpak);
+    // J = [s]([HE]G + [r]Y)
+    let j =
+        (ProjectivePoint::GENERATOR * reduce(&he) + y * reduce(&r_bytes)) * s;
+
+    let j = j.to_affine();
+    if j == AffinePoint::IDENTITY || r_bytes == [0u8; SCALAR_LEN] {
+        return Err(Error::InvalidSignature);
+    }
+
+    if x_bytes(&j) != r_bytes {
+        return Err(Error::InvalidSignature);
+    }
+
+    Ok(())
+}
+
+/// Sign a message, returning the value of the ICV message TLV in `out`.
+///
+/// The message must be the one to send, with any ICV TLVs absent or not,
+/// since they aren't covered, and its originator address must be the one of
+/// `id`. Returns the number of bytes written, at most
+/// [`MAX_ICV_VALUE_LEN`](constant.MAX_ICV_VALUE_LEN.html).
+pub fn sign_message(
+    key: &SigningKey,
+    id: &Identity,
+    msg: &Message<'_>,
+    j: &[u8; SCALAR_LEN],
+    out: &mut [u8],
+) -> Result<usize, Error> {
+    if msg.hdr.orig_addr != Some(id.addr()) {
+        return Err(Error::InvalidKey);
+    }
+
+    let mut value = IcvValue {
+        hash_function: HASH_SHA256,
+        crypto_function: CRYPTO_ECCSI,
+        key_id: id.key_id(),
+        icv: &[],
+    };
+    let digest = coverage_digest(&value, msg)?;
+    let sig = key.sign_with(
+        |hasher| {
+            hasher.update(digest);
+            Ok(())
+        },
+        j,
+    )?;
+    value.icv = &sig;
+    value.write(out)
+}
+
+/// Verify the ECCSI ICV message TLVs of a message.
+///
+/// The message is valid if any of its ECCSI ICVs verifies, the identity of
+/// the signer is returned so the caller can check the expiry time.
+pub fn verify_message(
+    public_key: &PublicKey,
+    msg: &Message<'_>,
+) -> Result<Identity, Error> {
+    let orig_addr = msg.hdr.orig_addr.ok_or(Error::InvalidSignature)?;
+
+    for tlv in msg.tlv_block.iter() {
+        let tlv = tlv?;
+        if tlv.r#type != MSG_TLV_ICV {
+            continue;
+        }
+
+        let value = match IcvValue::from_tlv(&tlv)? {
+            Some(v) if v.crypto_function == CRYPTO_ECCSI => v,
+            _ => continue,
+        };
+
+        let expiry = match value.key_id.len() {
+            0 => None,
+            4 => {
+                let mut b = [0u8; 4];
+                b.copy_from_slice(value.key_id);
+                Some(u32::from_be_bytes(b))
+            }
+            _ => continue,
+        };
+        let id = match Identity::new(orig_addr, expiry) {
+            Some(id) => id,
+            None => continue,
+        };
+
+        let verified = match value.hash_function {
+            HASH_SHA256 => {
+                let digest = coverage_digest(&value, msg)?;
+                let feed = |hasher: &mut Sha256| {
+                    hasher.update(digest);
+                    Ok(())
+                };
+                verify_with(public_key, id.as_bytes(), feed, value.icv)
+            }
+            HASH_IDENTITY => {
+                let feed = |hasher: &mut Sha256| {
+                    icv::message_coverage(&value, msg, |b| hasher.update(b))
+                };
+                verify_with(public_key, id.as_bytes(), feed, value.icv)
+            }
+            _ => continue,
+        };
+
+        if verified.is_ok() {
+            return Ok(id);
+        }
+    }
+
+    Err(Error::InvalidSignature)
+}
+
+/// SHA-256 of the content covered by the message ICV `value`.
+fn coverage_digest(
+    value: &IcvValue<'_>,
+    msg: &Message<'_>,
+) -> Result<[u8; SCALAR_LEN], Error> {
+    let mut hasher = Sha256::new();
+    icv::message_coverage(value, msg, |b| hasher.update(b))?;
+    Ok(hasher.finalize().into())
+}
+
+/// `HS = hash(G || KPAK || ID || PVT)`
+fn hash_hs(
+    public_key: &PublicKey,
+    id: &[u8],
+    pvt: &AffinePoint,
+) -> [u8; SCALAR_LEN] {
+    let mut hasher = Sha256::new();
+    hasher.update(point_bytes(&AffinePoint::GENERATOR));
+    hasher.update(public_key.to_bytes());
+    hasher.update(id);
+    hasher.update(point_bytes(pvt));
+    hasher.finalize().into()
+}
+
+/// `HE = hash(HS || r || M)`, where `M` is fed to the hasher by `msg`.
+fn hash_he<F>(
+    hs: &[u8; SCALAR_LEN],
+    r: &[u8; SCALAR_LEN],
+    msg: F,
+) -> Result<[u8; SCALAR_LEN], Error>
+where
+    F: FnOnce(&mut Sha256) -> Result<(), Error>,
+{
+    let mut hasher = Sha256::new();
+    hasher.update(hs);
+    hasher.update(r);
+    msg(&mut hasher)?;
+    Ok(hasher.finalize().into())
+}
+
+fn reduce(bytes: &[u8; SCALAR_LEN]) -> Scalar {
+    <Scalar as Reduce<U256>>::reduce_bytes(&FieldBytes::from(*bytes))
+}
+
+fn read_nonzero_scalar(bytes: &[u8; SCALAR_LEN]) -> Option<Scalar> {
+    let scalar: Option<Scalar> =
+        Scalar::from_repr(FieldBytes::from(*bytes)).into();
+    scalar.filter(|s| !bool::from(s.is_zero()))
+}
+
+fn read_point(bytes: &[u8]) -> Result<AffinePoint, Error> {
+    if bytes.len() != POINT_LEN || bytes[0] != 0x04 {
+        return Err(Error::InvalidKey);
+    }
+
+    let point =
+        EncodedPoint::from_bytes(bytes).map_err(|_| Error::InvalidKey)?;
+    let point: Option<AffinePoint> =
+        AffinePoint::from_encoded_point(&point).into();
+    point.ok_or(Error::InvalidKey)
+}
+
+fn point_bytes(point: &AffinePoint) -> [u8; POINT_LEN] {
+    let mut bytes = [0u8; POINT_LEN];
+    bytes.copy_from_slice(point.to_encoded_point(false).as_bytes());
+    bytes
+}
+
+fn x_bytes(point: &AffinePoint) -> [u8; SCALAR_LEN] {
+    let mut bytes = [0u8; SCALAR_LEN];
+    bytes.copy_from_slice(&point_bytes(point)[1..1 + SCALAR_LEN]);
+    bytes
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::Buf;
+
+    // Inputs of the RFC 6507 Appendix A example.
+    const KSAK: [u8; SCALAR_LEN] = [
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x01, 0x23, 0x45,
+    ];
+    const V: [u8; SCALAR_LEN] = [
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x02, 0x34, 0x56,
+    ];
+    const J: [u8; SCALAR_LEN] = [
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x03, 0x45, 0x67,
+    ];
+    const ID: &[u8] = b"2011-02\0tel:+447700900123\0";
+    const M: &[u8] = b"message\0";
+
+    // Outputs of the RFC 6507 Appendix A example.
+    const KPAK: [u8; POINT_LEN] = [
+        0x04, 0x50, 0xd4, 0x67, 0x0b, 0xde, 0x75, 0x24, 0x4f, 0x28, 0xd2, 0x83,
+        0x8a, 0x0d, 0x25, 0x55, 0x8a, 0x7a, 0x72, 0x68, 0x6d, 0x45, 0x22, 0xd4,
+        0xc8, 0x27, 0x3f, 0xb6, 0x44, 0x2a, 0xeb, 0xfa, 0x93, 0xdb, 0xdd, 0x37,
+        0x55, 0x1a, 0xfd, 0x26, 0x3b, 0x5d, 0xfd, 0x61, 0x7f, 0x39, 0x60, 0xc6,
+        0x5a, 0x8c, 0x29, 0x88, 0x50, 0xff, 0x99, 0xf2, 0x03, 0x66, 0xdc, 0xe7,
+        0xd4, 0x36, 0x72, 0x17, 0xf4,
+    ];
+    const PVT: [u8; POINT_LEN] = [
+        0x04, 0x75, 0x8a, 0x14, 0x27, 0x79, 0xbe, 0x89, 0xe8, 0x29, 0xe7, 0x19,
+        0x84, 0xcb, 0x40, 0xef, 0x75, 0x8c, 0xc4, 0xad, 0x77, 0x5f, 0xc5, 0xb9,
+        0xa3, 0xe1, 0xc8, 0xed, 0x52, 0xf6, 0xfa, 0x36, 0xd9, 0xa7, 0x9d, 0x24,
+        0x76, 0x92, 0xf4, 0xed, 0xa3, 0xa6, 0xbd, 0xab, 0x77, 0xd6, 0xaa, 0x64,
+        0x74, 0xa4, 0x64, 0xae, 0x49, 0x34, 0x66, 0x3c, 0x52, 0x65, 0xba, 0x70,
+        0x18, 0xba, 0x09, 0x1f, 0x79,
+    ];
+    const HS: [u8; SCALAR_LEN] = [
+        0x49, 0x0f, 0x3f, 0xeb, 0xbc, 0x1c, 0x90, 0x2f, 0x62, 0x89, 0x72, 0x3d,
+        0x7f, 0x8c, 0xbf, 0x79, 0xdb, 0x88, 0x93, 0x08, 0x49, 0xd1, 0x9f, 0x38,
+        0xf0, 0x29, 0x5b, 0x5c, 0x27, 0x6c, 0x14, 0xd1,
+    ];
+    const SSK: [u8; SCALAR_LEN] = [
+        0x23, 0xf3, 0x74, 0xae, 0x1f, 0x40, 0x33, 0xf3, 0xe9, 0xdb, 0xdd, 0xaa,
+        0xef, 0x20, 0xf4, 0xcf, 0x0b, 0x86, 0xbb, 0xd5, 0xa1, 0x38, 0xa5, 0xae,
+        0x9e, 0x7e, 0x00, 0x6b, 0x34, 0x48, 0x9a, 0x0d,
+    ];
+    const R: [u8; SCALAR_LEN] = [
+        0x26, 0x9d, 0x4c, 0x8f, 0xde, 0xb6, 0x6a, 0x74, 0xe4, 0xef, 0x8c, 0x0d,
+        0x5d, 0xcc, 0x59, 0x7d, 0xdf, 0xe6, 0x02, 0x9c, 0x2a, 0xff, 0xc4, 0x93,
+        0x60, 0x08, 0xcd, 0x2c, 0xc1, 0x04, 0x5d, 0x81,
+    ];
+    const S: [u8; SCALAR_LEN] = [
+        0xe0, 0x9b, 0x52, 0x8d, 0x0e, 0xf8, 0xd6, 0xdf, 0x1a, 0xa3, 0xec, 0xbf,
+        0x80, 0x11, 0x0c, 0xfc, 0xec, 0x9f, 0xc6, 0x82, 0x52, 0xce, 0xbb, 0x67,
+        0x9f, 0x41, 0x34, 0x84, 0x69, 0x40, 0xcc, 0xfd,
+    ];
+
+    #[test]
+    fn test_eccsi_rfc6507() {
+        let kms = Kms::new(&KSAK).unwrap();
+        let kpak = kms.public_key();
+        assert_eq!(&kpak.to_bytes()[..], &KPAK[..]);
+        assert_eq!(PublicKey::from_bytes(&KPAK), Ok(kpak));
+
+        let key = kms.issue(ID, &V).unwrap();
+        assert_eq!(&key.pvt()[..], &PVT[..]);
+        assert_eq!(key.hs, HS);
+        assert_eq!(key.ssk(), SSK);
+
+        // The SSK and PVT validate when provisioned on the signer.
+        let provisioned = SigningKey::new(&kpak, ID, &SSK, &PVT).unwrap();
+        assert!(SigningKey::new(&kpak, b"tel:+0", &SSK, &PVT).is_err());
+
+        let sig = provisioned.sign(M, &J).unwrap();
+        assert_eq!(&sig[..SCALAR_LEN], &R[..]);
+        assert_eq!(&sig[SCALAR_LEN..2 * SCALAR_LEN], &S[..]);
+        assert_eq!(&sig[2 * SCALAR_LEN..], &PVT[..]);
+        assert_eq!(verify(&kpak, ID, M, &sig), Ok(()));
+
+        // Signing is deterministic for the same `j`.
+        assert_eq!(&key.sign(M, &J).unwrap()[..], &sig[..]);
+
+        assert_eq!(
+            verify(&kpak, ID, b"massage\0", &sig),
+            Err(Error::InvalidSignature)
+        );
+        assert_eq!(
+            verify(&kpak, b"2011-03\0tel:+447700900123\0", M, &sig),
+            Err(Error::InvalidSignature)
+        );
+
+        let mut bad = sig;
+        bad[SCALAR_LEN] ^= 0x01;
+        assert_eq!(verify(&kpak, ID, M, &bad), Err(Error::InvalidSignature));
+
+        let other = Kms::new(&V).unwrap().public_key();
+        assert_eq!(verify(&other, ID, M, &sig), Err(Error::InvalidSignature));
+    }
+
+    #[test]
+    fn test_eccsi_message() {
+        // Message from 10.0.0.1 with hop limit 3, hop count 1, an empty
+        // TLV block and an address block with 10.0.0.2.
+        const BIN: &[u8] = &[
+            0x01, 0xe3, 0x00, 0x14, 0x0a, 0x00, 0x00, 0x01, 0x03, 0x01, 0x00,
+            0x00, 0x01, 0x00, 0x0a, 0x00, 0x00, 0x02, 0x00, 0x00,
+        ];
+
+        let kms = Kms::new(&KSAK).unwrap();
+        let id = Identity::new(&[10, 0, 0, 1], Some(0x5f5e_1000)).unwrap();
+        let key = kms.issue(id.as_bytes(), &V).unwrap();
+
+        let msg = Message::read(&mut Buf::new(BIN)).unwrap();
+        let mut value = [0u8; MAX_ICV_VALUE_LEN];
+        let len = sign_message(&key, &id, &msg, &J, &mut value).unwrap();
+        assert_eq!(len, MAX_ICV_VALUE_LEN);
+
+        // Insert the ICV TLV in the message, and forward it once.
+        let mut signed = [0u8; 256];
+        let tlv_len = 5 + len;
+        let size = BIN.len() + tlv_len;
+        signed[..BIN.len()].copy_from_slice(BIN);
+        signed[2..4].copy_from_slice(&(size as u16).to_be_bytes());
+        signed[8] = 2;
+        signed[9] = 2;
+        signed[10..12].copy_from_slice(&(tlv_len as u16).to_be_bytes());
+        signed[12..17].copy_from_slice(&[
+            MSG_TLV_ICV,
+            0x98,
+            icv::ICV_EXT_CRYPTO_HASH,
+            0x00,
+            len as u8,
+        ]);
+        signed[17..17 + len].copy_from_slice(&value[..len]);
+        signed[17 + len..size].copy_from_slice(&BIN[12..]);
+
+        let msg = Message::read(&mut Buf::new(&signed[..size])).unwrap();
+        assert_eq!(verify_message(&kms.public_key(), &msg), Ok(id));
+
+        // Tampering with the address block breaks the signature.
+        signed[size - 3] = 3;
+        let msg = Message::read(&mut Buf::new(&signed[..size])).unwrap();
+        assert_eq!(
+            verify_message(&kms.public_key(), &msg),
+            Err(Error::InvalidSignature)
+        );
+
+        // So does changing the key id, it's covered too.
+        signed[size - 3] = 2;
+        signed[20] ^= 0x01;
+        let msg = Message::read(&mut Buf::new(&signed[..size])).unwrap();
+        assert_eq!(
+            verify_message(&kms.public_key(), &msg),
+            Err(Error::InvalidSignature)
+        );
+        signed[20] ^= 0x01;
+        let msg = Message::read(&mut Buf::new(&signed[..size])).unwrap();
+        assert_eq!(verify_message(&kms.public_key(), &msg), Ok(id));
+
+        // The originator must be the one of the identity.
+        let other = Identity::new(&[10, 0, 0, 9], None).unwrap();
+        let msg = Message::read(&mut Buf::new(BIN)).unwrap();
+        assert_eq!(
+            sign_message(&key, &other, &msg, &J, &mut value),
+            Err(Error::InvalidKey)
+        );
+    }
+}
@@ -9,7 +9,7 @@
 // except according to those terms.
 
 /// RFC 5444 error
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
 pub enum Error {
     /// Unexpected End-Of-File.
     UnexpectedEof,
@@ -17,6 +17,20 @@ pub enum Error {
     PrefixTooLarge,
     /// Invalid version
     InvalidVersion,
+    /// A TLV value doesn't have the format mandated by its type.
+    InvalidTlvValue,
+    /// The output buffer is too small.
+    BufferTooSmall,
+    /// A cryptographic key is malformed or doesn't validate.
+    InvalidKey,
+    /// A signature or ICV doesn't verify.
+    InvalidSignature,
+    /// The indexes of an address block TLV are out of range.
+    InvalidTlvIndex,
+    /// An address block is malformed, or its addresses can't be encoded.
+    InvalidAddressBlock,
+    /// The message breaks the rules of its protocol.
+    InvalidMessage,
 }
 
 #[cfg(feature = "use_std")]
@@ -28,6 +42,13 @@ impl std::fmt::Display for Error {
             Error::InvalidVersion => {
                 write!(f, "Version is invalid, not supported")
             }
+            Error::InvalidTlvValue => write!(f, "Invalid TLV value"),
+            Error::BufferTooSmall => write!(f, "Buffer is too small"),
+            Error::InvalidKey => write!(f, "Invalid key"),
+            Error::InvalidSignature => write!(f, "Invalid signature"),
+            Error::InvalidTlvIndex => write!(f, "TLV index out of range"),
+            Error::InvalidAddressBlock => write!(f, "Invalid address block"),
+            Error::InvalidMessage => write!(f, "Invalid message"),
         }
     }
 }
@@ -0,0 +1,136 @@
+// Copyright 2020 Jean Pierre Dudey. See the LICENSE-MIT and
+// LICENSE-APACHE files at the top-level directory of this
+// distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Message forwarding.
+//!
+//! A forwarded message is copied byte for byte, unknown TLVs included, with
+//! `<msg-hop-limit>` decremented and `<msg-hop-count>` incremented when
+//! present. Deciding if a message must be forwarded is up to the protocol,
+//! see [`DuplicateSet`](../struct.DuplicateSet.html).
+
+use crate::{Buf, BufMut, Error, Message, MsgHeader};
+
+/// Copy `msg` to the outgoing packet being written in `out` as a forwarded
+/// message, see [`forward_bytes`](fn.forward_bytes.html).
+pub fn forward(msg: &Message, out: &mut BufMut) -> Result<bool, Error> {
+    forward_bytes(msg.as_bytes(), out)
+}
+
+/// Copy the message starting at `msg` to the outgoing packet being written
+/// in `out` as a forwarded message.
+///
+/// Returns `false` and writes nothing if the message must be dropped, when
+/// its hop limit would reach zero or its hop count is already 255. On
+/// error nothing is written either.
+pub fn forward_bytes(msg: &[u8], out: &mut BufMut) -> Result<bool, Error> {
+    let hdr = MsgHeader::read(&mut Buf::new(msg))?;
+    if hdr.size() < hdr.header_len() {
+        return Err(Error::InvalidMessage);
+    }
+    let msg = msg.get(..hdr.size()).ok_or(Error::UnexpectedEof)?;
+
+    if hdr.hop_limit.map_or(false, |h| h <= 1)
+        || hdr.hop_count == Some(core::u8::MAX)
+    {
+        return Ok(false);
+    }
+
+    let pos = out.pos();
+    out.put_bytes(msg)?;
+
+    // <msg-hop-limit> and <msg-hop-count> follow <msg-orig-addr>.
+    let bytes = &mut out.as_bytes_mut()[pos..];
+    let mut off = 4 + hdr.orig_addr.map_or(0, |a| a.len());
+    if let Some(hop_limit) = hdr.hop_limit {
+        bytes[off] = hop_limit - 1;
+        off += 1;
+    }
+    if let Some(hop_count) = hdr.hop_count {
+        bytes[off] = hop_count + 1;
+    }
+
+    Ok(true)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{MessageBuilder, Tlv};
+
+    fn message(buf: &mut [u8], hop_limit: u8, hop_count: u8) -> usize {
+        let orig = [10, 0, 0, 1];
+        let mut hdr = MsgHeader::new(1, 4);
+        hdr.orig_addr = Some(&orig);
+        hdr.hop_limit = Some(hop_limit);
+        hdr.hop_count = Some(hop_count);
+        hdr.seq_num = Some(42);
+        let mut builder = MessageBuilder::new(buf, &hdr).unwrap();
+        // An unknown TLV is kept as is.
+        builder
+            .add_tlv(&Tlv {
+                r#type: 200,
+                type_ext: Some(3),
+                start_index: None,
+                stop_index: None,
+                value: Some(&[1, 2, 3]),
+                multi_value: false,
+            })
+            .unwrap();
+        builder.finish().unwrap()
+    }
+
+    #[test]
+    fn test_forward() {
+        let mut msg = [0u8; 64];
+        let size = message(&mut msg, 3, 1);
+
+        // After a packet header.
+        let mut buf = [0u8; 64];
+        let mut out = BufMut::new(&mut buf);
+        out.put_u8(0).unwrap();
+        let parsed = Message::read(&mut Buf::new(&msg[..size])).unwrap();
+        assert_eq!(forward(&parsed, &mut out), Ok(true));
+        assert_eq!(out.pos(), 1 + size);
+
+        let fwd = Message::read(&mut Buf::new(&out.as_bytes()[1..])).unwrap();
+        assert_eq!(fwd.hdr.hop_limit, Some(2));
+        assert_eq!(fwd.hdr.hop_count, Some(2));
+        assert_eq!(fwd.hdr.seq_num, Some(42));
+        assert_eq!(&fwd.as_bytes()[..8], &msg[..8]);
+        assert_eq!(&fwd.as_bytes()[10..], &msg[10..size]);
+
+        // Trailing bytes are not part of the message.
+        let mut out = BufMut::new(&mut buf);
+        assert_eq!(forward_bytes(&msg, &mut out), Ok(true));
+        assert_eq!(out.pos(), size);
+
+        // Dropped.
+        let size = message(&mut msg, 1, 1);
+        let mut out = BufMut::new(&mut buf);
+        assert_eq!(forward_bytes(&msg[..size], &mut out), Ok(false));
+        let size = message(&mut msg, 10, core::u8::MAX);
+        assert_eq!(forward_bytes(&msg[..size], &mut out), Ok(false));
+        assert_eq!(out.pos(), 0);
+
+        // Errors.
+        let size = message(&mut msg, 10, 0);
+        assert_eq!(
+            forward_bytes(&msg[..size - 1], &mut out),
+            Err(Error::UnexpectedEof)
+        );
+        let mut small = [0u8; 8];
+        let mut out = BufMut::new(&mut small);
+        assert_eq!(
+            forward_bytes(&msg[..size], &mut out),
+            Err(Error::BufferTooSmall)
+        );
+        assert_eq!(out.pos(), 0);
+    }
+}
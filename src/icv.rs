@@ -0,0 +1,279 @@
+// Copyright 2020 Jean Pierre Dudey. See the LICENSE-MIT and
+// LICENSE-APACHE files at the top-level directory of this
+// distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! RFC 7182 Integrity Check Value TLVs.
+//!
+//! This module only knows the format of the ICV TLVs and what content they
+//! cover, the cryptography lives in the modules implementing each
+//! cryptographic function.
+
+use crate::tlv::for_each_raw_tlv;
+use crate::{Buf, Error, Message, Tlv};
+
+/// ICV packet TLV type.
+pub const PKT_TLV_ICV: u8 = 5;
+/// ICV message TLV type.
+pub const MSG_TLV_ICV: u8 = 5;
+/// ICV address block TLV type.
+pub const ADDR_TLV_ICV: u8 = 5;
+
+/// ICV type extension with a value of unspecified format.
+pub const ICV_EXT_GENERIC: u8 = 0;
+/// ICV type extension of a cryptographic function applied to a hash.
+pub const ICV_EXT_CRYPTO_HASH: u8 = 1;
+
+/// Hash function "identity", the content isn't hashed.
+pub const HASH_IDENTITY: u8 = 0;
+/// Hash function SHA-256.
+pub const HASH_SHA256: u8 = 3;
+
+/// Cryptographic function "identity".
+pub const CRYPTO_IDENTITY: u8 = 0;
+/// Cryptographic function ECCSI (RFC 7859).
+pub const CRYPTO_ECCSI: u8 = 7;
+
+/// Value of an ICV TLV with type extension
+/// [`ICV_EXT_CRYPTO_HASH`](constant.ICV_EXT_CRYPTO_HASH.html).
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct IcvValue<'a> {
+    /// `<hash-function>`
+    pub hash_function: u8,
+    /// `<cryptographic-function>`
+    pub crypto_function: u8,
+    /// `<key-id>`
+    pub key_id: &'a [u8],
+    /// `<ICV-data>`
+    pub icv: &'a [u8],
+}
+
+impl<'a> IcvValue<'a> {
+    /// Read the value of an ICV TLV, the TLV type isn't checked.
+    ///
+    /// Returns `None` if the type extension isn't
+    /// [`ICV_EXT_CRYPTO_HASH`](constant.ICV_EXT_CRYPTO_HASH.html).
+    pub fn from_tlv(tlv: &Tlv<'a>) -> Result<Option<IcvValue<'a>>, Error> {
+        if tlv.type_ext != Some(ICV_EXT_CRYPTO_HASH) {
+            return Ok(None);
+        }
+
+        let value = tlv.value.ok_or(Error::InvalidTlvValue)?;
+        let mut buf = Buf::new(value);
+        let read = |buf: &mut Buf<'a>| -> Result<IcvValue<'a>, Error> {
+            let hash_function = buf.get_u8()?;
+            let crypto_function = buf.get_u8()?;
+            let key_id_length = buf.get_u8().map(usize::from)?;
+            let key_id = buf.get_bytes(key_id_length)?;
+            let icv = buf.get_bytes(value.len() - buf.pos())?;
+
+            Ok(IcvValue {
+                hash_function,
+                crypto_function,
+                key_id,
+                icv,
+            })
+        };
+
+        read(&mut buf).map(Some).map_err(|_| Error::InvalidTlvValue)
+    }
+
+    /// Length in bytes of the TLV value.
+    pub fn len(&self) -> usize {
+        3 + self.key_id.len() + self.icv.len()
+    }
+
+    /// Is the TLV value empty? It never is.
+    pub fn is_empty(&self) -> bool {
+        false
+    }
+
+    /// Write the TLV value to `out`, returns the number of bytes written.
+    pub fn write(&self, out: &mut [u8]) -> Result<usize, Error> {
+        if self.key_id.len() > usize::from(core::u8::MAX) {
+            return Err(Error::InvalidTlvValue);
+        }
+
+        let len = self.len();
+        if out.len() < len {
+            return Err(Error::BufferTooSmall);
+        }
+
+        out[0] = self.hash_function;
+        out[1] = self.crypto_function;
+        out[2] = self.key_id.len() as u8;
+        out[3..3 + self.key_id.len()].copy_from_slice(self.key_id);
+        out[3 + self.key_id.len()..len].copy_from_slice(self.icv);
+        Ok(len)
+    }
+}
+
+/// Feed to `f` the content covered by the message ICV `value`, its
+/// `<ICV-data>` isn't used.
+///
+/// As specified by RFC 7182 section 12.1, that's the `<hash-function>`,
+/// `<cryptographic-function>`, `<key-id-length>` and `<key-id>` fields of
+/// `value`, followed by the whole message with `<msg-hop-limit>` and
+/// `<msg-hop-count>` (if present) set to 0, and every ICV message TLV
+/// removed from the message TLV block, with `<msg-size>` and
+/// `<tlvs-length>` adjusted.
+///
+/// The content is given in several pieces, so it can be hashed without a
+/// copy of the message.
+pub fn message_coverage<F>(
+    value: &IcvValue<'_>,
+    msg: &Message<'_>,
+    mut f: F,
+) -> Result<(), Error>
+where
+    F: FnMut(&[u8]),
+{
+    if value.key_id.len() > usize::from(core::u8::MAX) {
+        return Err(Error::InvalidTlvValue);
+    }
+
+    // <hash-function>, <cryptographic-function>, <key-id-length>, <key-id>
+    f(&[
+        value.hash_function,
+        value.crypto_function,
+        value.key_id.len() as u8,
+    ]);
+    f(value.key_id);
+
+    let bytes = msg.as_bytes();
+    let tlvs = msg.tlv_block.as_bytes();
+
+    // Size of the ICV TLVs to remove.
+    let mut removed = 0;
+    for_each_raw_tlv(tlvs, |tlv, raw| {
+        if tlv.r#type == MSG_TLV_ICV {
+            removed += raw.len();
+        }
+        Ok(())
+    })?;
+
+    // <msg-type>, <msg-flags>, <msg-addr-length>, <msg-size>
+    f(&bytes[..2]);
+    f(&((msg.hdr.size() - removed) as u16).to_be_bytes());
+
+    // <msg-orig-addr>, <msg-hop-limit>, <msg-hop-count>, <msg-seq-num>
+    if let Some(orig_addr) = msg.hdr.orig_addr {
+        f(orig_addr);
+    }
+    if msg.hdr.hop_limit.is_some() {
+        f(&[0]);
+    }
+    if msg.hdr.hop_count.is_some() {
+        f(&[0]);
+    }
+    if let Some(seq_num) = msg.hdr.seq_num {
+        f(&seq_num.to_be_bytes());
+    }
+
+    // <tlv-block>
+    f(&((tlvs.len() - removed) as u16).to_be_bytes());
+    for_each_raw_tlv(tlvs, |tlv, raw| {
+        if tlv.r#type != MSG_TLV_ICV {
+            f(raw);
+        }
+        Ok(())
+    })?;
+
+    // (<addr-block><tlv-block>)*
+    f(&bytes[msg.hdr.header_len() + 2 + tlvs.len()..]);
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const VALUE: IcvValue<'static> = IcvValue {
+        hash_function: HASH_SHA256,
+        crypto_function: CRYPTO_ECCSI,
+        key_id: &[0xab, 0xcd],
+        icv: &[],
+    };
+
+    fn collect(value: &IcvValue, msg: &Message, out: &mut [u8]) -> usize {
+        let mut len = 0;
+        message_coverage(value, msg, |b| {
+            out[len..len + b.len()].copy_from_slice(b);
+            len += b.len();
+        })
+        .unwrap();
+        len
+    }
+
+    #[test]
+    fn test_icv_value() {
+        // ICV TLV, SHA-256 + ECCSI with a 2 byte key id.
+        const BIN: &[u8] = &[
+            0x05, 0x90, 0x01, 0x07, 0x03, 0x07, 0x02, 0xab, 0xcd, 0x01, 0x02,
+        ];
+        let tlv = Tlv::read(&mut Buf::new(BIN)).unwrap();
+        let icv = IcvValue::from_tlv(&tlv).unwrap().unwrap();
+        assert_eq!(icv.hash_function, HASH_SHA256);
+        assert_eq!(icv.crypto_function, CRYPTO_ECCSI);
+        assert_eq!(icv.key_id, &[0xab, 0xcd]);
+        assert_eq!(icv.icv, &[0x01, 0x02]);
+
+        let mut out = [0u8; 7];
+        assert_eq!(icv.write(&mut out), Ok(7));
+        assert_eq!(&out[..], &BIN[4..]);
+        assert_eq!(icv.write(&mut out[..6]), Err(Error::BufferTooSmall));
+
+        // Truncated key id.
+        const BIN_SHORT: &[u8] =
+            &[0x05, 0x90, 0x01, 0x04, 0x03, 0x07, 0x02, 0xab];
+        let tlv = Tlv::read(&mut Buf::new(BIN_SHORT)).unwrap();
+        assert_eq!(IcvValue::from_tlv(&tlv), Err(Error::InvalidTlvValue));
+    }
+
+    #[test]
+    fn test_message_coverage() {
+        // Message with originator, hop limit 3, hop count 1, sequence number
+        // 2, an ICV TLV between two other TLVs and an address block.
+        const BIN: &[u8] = &[
+            0x01, 0xf3, 0x00, 0x21, 0x0a, 0x00, 0x00, 0x01, 0x03, 0x01, 0x00,
+            0x02, 0x00, 0x0b, 0x01, 0x00, 0x05, 0x90, 0x01, 0x03, 0x00, 0x00,
+            0x00, 0x02, 0x00, 0x01, 0x00, 0x0a, 0x00, 0x00, 0x02, 0x00, 0x00,
+        ];
+        // Same message, as covered by the ICV.
+        const COVERED: &[u8] = &[
+            0x01, 0xf3, 0x00, 0x1a, 0x0a, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00,
+            0x02, 0x00, 0x04, 0x01, 0x00, 0x02, 0x00, 0x01, 0x00, 0x0a, 0x00,
+            0x00, 0x02, 0x00, 0x00,
+        ];
+
+        // The fields of the ICV value come first.
+        const PREFIX: &[u8] = &[0x03, 0x07, 0x02, 0xab, 0xcd];
+
+        let msg = Message::read(&mut Buf::new(BIN)).unwrap();
+        let mut out = [0u8; 64];
+        let len = collect(&VALUE, &msg, &mut out);
+        assert_eq!(&out[..PREFIX.len()], PREFIX);
+        assert_eq!(&out[PREFIX.len()..len], COVERED);
+
+        // The covered message is a valid message too.
+        let covered = Message::read(&mut Buf::new(COVERED)).unwrap();
+        assert_eq!(covered.hdr.size(), COVERED.len());
+        let len = collect(&VALUE, &covered, &mut out);
+        assert_eq!(&out[PREFIX.len()..len], COVERED);
+
+        // Without a key id.
+        let value = IcvValue {
+            key_id: &[],
+            ..VALUE
+        };
+        let len = collect(&value, &msg, &mut out);
+        assert_eq!(&out[..3], &[0x03, 0x07, 0x00]);
+        assert_eq!(&out[3..len], COVERED);
+    }
+}
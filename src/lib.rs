@@ -38,7 +38,7 @@
 //! logic in the packets, that's up to you, here the hard part is done to leave
 //! the other things more simple such as handling sequence numbers, we don't
 //! touch sequence numbers, nor we increment/decrement hop count, hop limits,
-//! etc.
+//! etc. The only exception is the opt-in `forward` module.
 //!
 //! # Minimum Supported Rust Version
 //!
@@ -55,7 +55,10 @@
 //! # Features
 //!
 //! - `use_std`: (default) enables usage of `std`, disable it to be compatible
-//! with `no_std`.
+//!   with `no_std`.
+//! - `eccsi`: RFC 7859 identity-based signatures, in the `eccsi` module.
+//!   Its dependencies need Rust 1.65, it isn't covered by the MSRV.
+//! - `forward`: forwarding of messages, in the `forward` module.
 
 #![warn(missing_docs)]
 #![cfg_attr(not(feature = "use_std"), no_std)]
@@ -64,17 +67,54 @@
 extern crate bitflags;
 
 mod addrtlv;
+mod aggregate;
 mod buf;
+mod builder;
+mod clock;
+#[cfg(feature = "use_std")]
+mod duplicate;
 mod error;
 mod msg;
 mod packet;
+#[cfg(feature = "use_std")]
+mod reassembly;
+mod schedule;
+mod seqnum;
 mod tlv;
 
-pub use addrtlv::{AddressBlock, AddressTlvIter, AddressTlvs, MAX_ADDR_LEN};
-pub use buf::Buf;
+pub mod aodv;
+#[cfg(feature = "eccsi")]
+pub mod eccsi;
+#[cfg(feature = "forward")]
+pub mod forward;
+pub mod icv;
+pub mod linkloss;
+pub mod loadng;
+pub mod nhdp;
+pub mod olsr;
+pub mod rewrite;
+pub mod smf;
+pub mod time;
+pub mod timestamp;
+
+pub use addrtlv::{
+    Address, AddressBlock, AddressTlvIter, AddressTlvs, MAX_ADDR_LEN,
+};
+pub use aggregate::{Aggregator, Slot};
+pub use buf::{Buf, BufMut};
+pub use builder::{MessageBuilder, MessageSplitter};
+pub use clock::{Clock, ManualClock};
+#[cfg(feature = "use_std")]
+pub use duplicate::{Decision, DuplicateSet};
 pub use error::Error;
-pub use msg::{Message, MessageIter, Messages, MsgHeader};
-pub use packet::{Packet, PktHeader};
+pub use msg::{
+    Message, MessageIter, MessageMut, Messages, MessagesMut, MsgHeader,
+};
+pub use packet::{Packet, PacketMut, PktHeader};
+#[cfg(feature = "use_std")]
+pub use reassembly::{Part, Reassembled, Reassembly};
+pub use schedule::{Emission, Reason, Rng, Scheduler, Timer};
+pub use seqnum::SeqNum;
 pub use tlv::{Tlv, TlvBlock};
 
 /// Supported version of RFC 5444.
@@ -0,0 +1,544 @@
+// Copyright 2020 Jean Pierre Dudey. See the LICENSE-MIT and
+// LICENSE-APACHE files at the top-level directory of this
+// distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Link loss estimation from packet sequence numbers.
+//!
+//! A [`LossTracker`] follows the `<pkt-seq-num>` of the packets received
+//! from a neighbor interface: the gaps are lost packets, packets filling a
+//! gap are reordered, packets seen twice are duplicates and big jumps are
+//! restarts of the neighbor. The counts are kept in buckets of
+//! [`LossConfig::interval`] milliseconds, the statistics of the last `n`
+//! buckets give a sliding window of `n` intervals, so a short and a long
+//! window can be read from the same tracker.
+//!
+//! Metrics like ETX (`expected / received`) and link quality hysteresis
+//! are calculated from [`LossStats`]. [`LossTracker`] doesn't use the heap,
+//! [`LinkLoss`] (with `use_std`) keeps the trackers of every neighbor.
+//!
+//! [`LossTracker`]: struct.LossTracker.html
+//! [`LossConfig::interval`]: struct.LossConfig.html#structfield.interval
+//! [`LossStats`]: struct.LossStats.html
+//! [`LinkLoss`]: struct.LinkLoss.html
+
+use core::fmt;
+#[cfg(feature = "use_std")]
+use std::collections::BTreeMap;
+#[cfg(feature = "use_std")]
+use std::mem;
+
+#[cfg(feature = "use_std")]
+use crate::{Address, Clock, PktHeader};
+
+/// Maximum number of buckets of a [`LossTracker`](struct.LossTracker.html).
+pub const MAX_LOSS_BUCKETS: usize = 64;
+
+/// Number of sequence numbers before the newest one whose reception is
+/// remembered, to tell reordered packets from duplicates.
+const HISTORY: u16 = 64;
+
+/// Consecutive stale packets, behind the newest one and already received,
+/// that make a restart of the neighbor.
+const RESTART_STALE_PACKETS: u32 = 3;
+
+/// Configuration of a [`LossTracker`](struct.LossTracker.html).
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct LossConfig {
+    /// Duration of a bucket in milliseconds.
+    pub interval: u64,
+    /// Number of buckets kept, the longest window. At most
+    /// [`MAX_LOSS_BUCKETS`](constant.MAX_LOSS_BUCKETS.html).
+    pub buckets: usize,
+    /// A sequence number further ahead or back than this is a restart of
+    /// the neighbor. Must be less than 2^15.
+    pub restart_threshold: u16,
+}
+
+impl Default for LossConfig {
+    /// 32 buckets of a second, restart after 256 sequence numbers.
+    fn default() -> LossConfig {
+        LossConfig {
+            interval: 1000,
+            buckets: 32,
+            restart_threshold: 256,
+        }
+    }
+}
+
+/// Packet counts of a window.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Default)]
+pub struct LossStats {
+    /// Packets received, without duplicates.
+    pub received: u32,
+    /// Packets skipped and not received later.
+    pub lost: u32,
+    /// Packets received after a newer one.
+    pub reordered: u32,
+    /// Packets received twice.
+    pub duplicates: u32,
+    /// Restarts of the neighbor.
+    pub resets: u32,
+}
+
+impl LossStats {
+    /// Packets sent by the neighbor, received or lost.
+    pub fn expected(&self) -> u32 {
+        self.received.saturating_add(self.lost)
+    }
+
+    /// Lost packets over the expected ones, multiplied by `scale` (as 1000
+    /// for a ratio in per mille). `None` without packets.
+    pub fn loss_ratio(&self, scale: u32) -> Option<u32> {
+        let expected = u64::from(self.expected());
+        if expected == 0 {
+            return None;
+        }
+        Some((u64::from(self.lost) * u64::from(scale) / expected) as u32)
+    }
+
+    fn add(&mut self, other: &LossStats) {
+        self.received = self.received.saturating_add(other.received);
+        self.lost = self.lost.saturating_add(other.lost);
+        self.reordered = self.reordered.saturating_add(other.reordered);
+        self.duplicates = self.duplicates.saturating_add(other.duplicates);
+        self.resets = self.resets.saturating_add(other.resets);
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct Bucket {
+    /// Number of the interval of the bucket, `now / interval`.
+    epoch: u64,
+    /// Counts of the interval, `lost` isn't reduced by the reordered
+    /// packets.
+    stats: LossStats,
+}
+
+/// Packet loss of the link to a neighbor interface.
+///
+/// Sequence numbers wrap around, a packet is newer than the newest one
+/// if it's at most `restart_threshold` ahead. A reordered packet cancels a
+/// loss of the window, if the loss was counted in a bucket that left the
+/// window the loss is underestimated.
+///
+/// A neighbor restarting at a sequence number behind the newest one is
+/// detected by a packet older than the remembered history, or by
+/// consecutive stale packets: the first ones are counted as duplicates.
+#[derive(Clone)]
+pub struct LossTracker {
+    config: LossConfig,
+    buckets: [Bucket; MAX_LOSS_BUCKETS],
+    newest: Option<u16>,
+    /// Received packets, bit `n` is the sequence number `newest - n`.
+    received: u64,
+    /// Consecutive stale packets.
+    stale: u32,
+    /// Time of the last packet.
+    last: Option<u64>,
+}
+
+// Arrays of more than 32 elements only implement Debug since Rust 1.47.
+impl fmt::Debug for LossTracker {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("LossTracker")
+            .field("config", &self.config)
+            .field("buckets", &&self.buckets[..])
+            .field("newest", &self.newest)
+            .field("received", &self.received)
+            .field("stale", &self.stale)
+            .field("last", &self.last)
+            .finish()
+    }
+}
+
+impl LossTracker {
+    /// Create a tracker without packets.
+    ///
+    /// # Panics
+    ///
+    /// If `config` has no bucket, more than
+    /// [`MAX_LOSS_BUCKETS`](constant.MAX_LOSS_BUCKETS.html), an interval
+    /// of 0 or a restart threshold of 2^15 or more.
+    pub fn new(config: LossConfig) -> LossTracker {
+        assert!(config.buckets >= 1 && config.buckets <= MAX_LOSS_BUCKETS);
+        assert!(config.interval > 0);
+        assert!(config.restart_threshold < 0x8000);
+        LossTracker {
+            config,
+            buckets: [Bucket::default(); MAX_LOSS_BUCKETS],
+            newest: None,
+            received: 0,
+            stale: 0,
+            last: None,
+        }
+    }
+
+    /// The configuration.
+    pub fn config(&self) -> &LossConfig {
+        &self.config
+    }
+
+    /// Newest sequence number received.
+    pub fn newest(&self) -> Option<u16> {
+        self.newest
+    }
+
+    /// Time of the last packet received.
+    pub fn last(&self) -> Option<u64> {
+        self.last
+    }
+
+    /// Count a packet with the `<pkt-seq-num>` `seq_num` received at `now`.
+    pub fn receive(&mut self, now: u64, seq_num: u16) {
+        self.last = Some(now);
+        let restart = self.config.restart_threshold;
+        let newest = match self.newest {
+            Some(newest) => newest,
+            None => {
+                self.newest = Some(seq_num);
+                self.received = 1;
+                let stats = self.bucket(now);
+                stats.received = stats.received.saturating_add(1);
+                return;
+            }
+        };
+
+        let ahead = seq_num.wrapping_sub(newest);
+        let back = newest.wrapping_sub(seq_num);
+        if ahead == 0 {
+            let stats = self.bucket(now);
+            stats.duplicates = stats.duplicates.saturating_add(1);
+            return;
+        }
+
+        if back <= restart && back < HISTORY {
+            let bit = 1u64 << back;
+            if self.received & bit == 0 {
+                self.stale = 0;
+                self.received |= bit;
+                let stats = self.bucket(now);
+                stats.received = stats.received.saturating_add(1);
+                stats.reordered = stats.reordered.saturating_add(1);
+                return;
+            }
+
+            self.stale += 1;
+            if self.stale < RESTART_STALE_PACKETS {
+                let stats = self.bucket(now);
+                stats.duplicates = stats.duplicates.saturating_add(1);
+                return;
+            }
+        }
+
+        // A newer packet, or a restart: as the threshold is below 2^15 a
+        // packet behind the newest one is more than the threshold ahead.
+        self.stale = 0;
+        let stats = self.bucket(now);
+        stats.received = stats.received.saturating_add(1);
+        if ahead <= restart {
+            stats.lost = stats.lost.saturating_add(u32::from(ahead) - 1);
+            let shifted = self.received.checked_shl(u32::from(ahead));
+            self.received = shifted.unwrap_or(0) | 1;
+        } else {
+            stats.resets = stats.resets.saturating_add(1);
+            self.received = 1;
+        }
+        self.newest = Some(seq_num);
+    }
+
+    /// Counts of the last `n` buckets up to `now`, the current one
+    /// included. `n` is limited to the number of buckets.
+    pub fn stats(&self, now: u64, n: usize) -> LossStats {
+        let epoch = now / self.config.interval;
+        let n = n.min(self.config.buckets) as u64;
+        let mut stats = LossStats::default();
+        for b in &self.buckets[..self.config.buckets] {
+            if b.epoch <= epoch && epoch - b.epoch < n {
+                stats.add(&b.stats);
+            }
+        }
+        stats.lost = stats.lost.saturating_sub(stats.reordered);
+        stats
+    }
+
+    /// Counts of all the buckets up to `now`.
+    pub fn window(&self, now: u64) -> LossStats {
+        self.stats(now, self.config.buckets)
+    }
+
+    /// Time when every bucket with packets is out of the window.
+    pub fn expiry(&self) -> Option<u64> {
+        let epoch = self.last? / self.config.interval;
+        let buckets = self.config.buckets as u64;
+        Some(
+            epoch
+                .saturating_add(buckets)
+                .saturating_mul(self.config.interval),
+        )
+    }
+
+    /// Forget every packet.
+    pub fn reset(&mut self) {
+        *self = LossTracker::new(self.config);
+    }
+
+    /// Counts of the bucket of `now`, emptied if it's from an older
+    /// interval.
+    fn bucket(&mut self, now: u64) -> &mut LossStats {
+        let epoch = now / self.config.interval;
+        let i = (epoch % self.config.buckets as u64) as usize;
+        let bucket = &mut self.buckets[i];
+        if bucket.epoch != epoch {
+            *bucket = Bucket {
+                epoch,
+                stats: LossStats::default(),
+            };
+        }
+        &mut bucket.stats
+    }
+}
+
+impl Default for LossTracker {
+    fn default() -> LossTracker {
+        LossTracker::new(LossConfig::default())
+    }
+}
+
+/// Packet loss of the links to the neighbor interfaces, identified by the
+/// source address of their packets.
+///
+/// A neighbor is forgotten when it sent no packet during the whole window,
+/// by [`expire`](#method.expire).
+#[cfg(feature = "use_std")]
+#[derive(Debug)]
+pub struct LinkLoss<C> {
+    clock: C,
+    config: LossConfig,
+    trackers: BTreeMap<Address, LossTracker>,
+}
+
+#[cfg(feature = "use_std")]
+impl<C: Clock> LinkLoss<C> {
+    /// Create an empty set of trackers configured with `config`.
+    ///
+    /// # Panics
+    ///
+    /// As [`LossTracker::new`](struct.LossTracker.html#method.new).
+    pub fn new(clock: C, config: LossConfig) -> Self {
+        LossTracker::new(config);
+        LinkLoss {
+            clock,
+            config,
+            trackers: BTreeMap::new(),
+        }
+    }
+
+    /// The clock.
+    pub fn clock(&self) -> &C {
+        &self.clock
+    }
+
+    /// Count a packet with the header `hdr` received from `source`, packets
+    /// without `<pkt-seq-num>` are ignored.
+    pub fn receive_packet(&mut self, source: &Address, hdr: &PktHeader) {
+        if let Some(seq_num) = hdr.seq_num {
+            let now = self.clock.now();
+            let config = self.config;
+            self.trackers
+                .entry(*source)
+                .or_insert_with(|| LossTracker::new(config))
+                .receive(now, seq_num);
+        }
+    }
+
+    /// Tracker of the link to `addr`.
+    pub fn tracker(&self, addr: &Address) -> Option<&LossTracker> {
+        self.trackers.get(addr)
+    }
+
+    /// Counts of the link to `addr` over the last `n` buckets.
+    pub fn stats(&self, addr: &Address, n: usize) -> Option<LossStats> {
+        let now = self.clock.now();
+        Some(self.trackers.get(addr)?.stats(now, n))
+    }
+
+    /// Neighbor interfaces with a tracker.
+    pub fn addrs(&self) -> impl Iterator<Item = &Address> + '_ {
+        self.trackers.keys()
+    }
+
+    /// Forget the link to `addr`.
+    pub fn remove(&mut self, addr: &Address) {
+        self.trackers.remove(addr);
+    }
+
+    /// Forget the neighbors without packets in the window.
+    pub fn expire(&mut self) {
+        let now = self.clock.now();
+        let trackers = mem::replace(&mut self.trackers, BTreeMap::new());
+        self.trackers = trackers
+            .into_iter()
+            .filter(|(_, t)| t.expiry().map_or(false, |time| time > now))
+            .collect();
+    }
+
+    /// Next time a neighbor is forgotten, when [`expire`](#method.expire)
+    /// must be called.
+    pub fn next_expiry(&self) -> Option<u64> {
+        self.trackers.values().filter_map(LossTracker::expiry).min()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn tracker() -> LossTracker {
+        LossTracker::new(LossConfig {
+            interval: 1000,
+            buckets: 4,
+            restart_threshold: 256,
+        })
+    }
+
+    #[test]
+    fn test_loss_tracker() {
+        let mut t = tracker();
+        assert_eq!(t.window(0).loss_ratio(1000), None);
+
+        // 0, 2, 3, 5, then 1 arrives late and 3 twice.
+        for s in [0, 2, 3, 5, 1, 3].iter() {
+            t.receive(100, *s);
+        }
+        let stats = t.window(100);
+        assert_eq!(
+            stats,
+            LossStats {
+                received: 5,
+                lost: 1,
+                reordered: 1,
+                duplicates: 1,
+                resets: 0,
+            }
+        );
+        assert_eq!(stats.expected(), 6);
+        assert_eq!(stats.loss_ratio(1000), Some(166));
+        assert_eq!(t.newest(), Some(5));
+
+        // Sliding windows.
+        t.receive(1500, 8);
+        assert_eq!(t.stats(1500, 1).lost, 2);
+        assert_eq!(t.stats(1500, 2).lost, 3);
+        assert_eq!(t.window(3999).received, 6);
+        assert_eq!(t.window(4000).received, 1);
+        assert_eq!(t.window(5000), LossStats::default());
+        assert_eq!(t.expiry(), Some(5000));
+
+        // A bucket is reused for a later interval.
+        t.receive(4100, 9);
+        assert_eq!(t.window(4100).received, 2);
+
+        t.reset();
+        assert_eq!(t.newest(), None);
+        assert_eq!(t.window(4100), LossStats::default());
+    }
+
+    #[test]
+    fn test_loss_tracker_wrap_around() {
+        let mut t = tracker();
+        t.receive(0, core::u16::MAX - 1);
+        t.receive(0, 1);
+        t.receive(0, core::u16::MAX);
+        t.receive(0, 1);
+        let stats = t.window(0);
+        assert_eq!((stats.received, stats.lost), (3, 1));
+        assert_eq!((stats.reordered, stats.duplicates), (1, 1));
+
+        // Restarts ahead and back aren't losses.
+        t.receive(0, 1 + 257);
+        t.receive(0, 0);
+        let stats = t.window(0);
+        assert_eq!((stats.received, stats.lost, stats.resets), (5, 1, 2));
+        assert_eq!(t.newest(), Some(0));
+        t.receive(0, 1);
+        assert_eq!(t.window(0).lost, 1);
+    }
+
+    #[test]
+    fn test_loss_tracker_restart_behind() {
+        let mut t = tracker();
+        for s in 0..40 {
+            t.receive(0, s);
+        }
+
+        // Restart at 10, the third stale packet is a reset.
+        for s in 10..13 {
+            t.receive(0, s);
+        }
+        let stats = t.window(0);
+        assert_eq!((stats.received, stats.lost), (41, 0));
+        assert_eq!((stats.duplicates, stats.resets), (2, 1));
+        assert_eq!(t.newest(), Some(12));
+        t.receive(0, 13);
+        assert_eq!(t.window(0).lost, 0);
+
+        // A reordered packet ends the stale packets.
+        t.receive(0, 15);
+        t.receive(0, 13);
+        t.receive(0, 14);
+        t.receive(0, 13);
+        t.receive(0, 14);
+        let stats = t.window(0);
+        assert_eq!((stats.lost, stats.reordered), (0, 1));
+        assert_eq!((stats.duplicates, stats.resets), (5, 1));
+
+        // Older than the remembered history.
+        t.receive(0, 15u16.wrapping_sub(HISTORY));
+        assert_eq!(t.window(0).resets, 2);
+        assert_eq!(t.newest(), Some(15u16.wrapping_sub(HISTORY)));
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_loss_tracker_restart_threshold() {
+        LossTracker::new(LossConfig {
+            restart_threshold: 0x8000,
+            ..LossConfig::default()
+        });
+    }
+
+    #[cfg(feature = "use_std")]
+    #[test]
+    fn test_link_loss() {
+        use crate::{ManualClock, Packet};
+
+        let clock = ManualClock::new(0);
+        let mut loss = LinkLoss::new(&clock, LossConfig::default());
+        let a = Address::from_bytes(&[10, 0, 0, 1]).unwrap();
+        let b = Address::from_bytes(&[10, 0, 0, 2]).unwrap();
+        for s in [1u16, 3].iter() {
+            let mut buf = [0x08, 0, 0];
+            buf[1..].copy_from_slice(&s.to_be_bytes());
+            let pkt = Packet::read(&buf).unwrap();
+            loss.receive_packet(&a, &pkt.hdr);
+        }
+        let pkt = Packet::read(&[0]).unwrap();
+        loss.receive_packet(&b, &pkt.hdr);
+
+        assert!(loss.tracker(&b).is_none());
+        assert_eq!(loss.stats(&a, 1).unwrap().loss_ratio(100), Some(33));
+        assert_eq!(loss.addrs().count(), 1);
+        assert_eq!(loss.next_expiry(), Some(32_000));
+
+        clock.set(32_000);
+        loss.expire();
+        assert!(loss.tracker(&a).is_none());
+        assert_eq!(loss.next_expiry(), None);
+    }
+}
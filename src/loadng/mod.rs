@@ -0,0 +1,201 @@
+// Copyright 2020 Jean Pierre Dudey. See the LICENSE-MIT and
+// LICENSE-APACHE files at the top-level directory of this
+// distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Lightweight On-demand Ad hoc Distance-vector Routing - Next Generation
+//! (LOADng), draft-clausen-lln-loadng-15.
+//!
+//! LOADng is meant for constrained networks, so the messages work with
+//! addresses of any length, including 1 or 2 bytes, and the route
+//! discovery core ([`Loadng`](struct.Loadng.html)) doesn't use the heap:
+//! its tables are slices given by the caller.
+//!
+//! The draft leaves the message and TLV types to be assigned by IANA, the
+//! values used here don't clash with those of NHDP, OLSRv2 and AODVv2.
+//!
+//! # Encoding
+//!
+//! | Message  | `<msg-orig-addr>` | `<msg-seq-num>` | Addresses               |
+//! |----------|-------------------|-----------------|-------------------------|
+//! | RREQ     | originator        | seq-num         | destination             |
+//! | RREP     | originator        | seq-num         | destination             |
+//! | RREP_ACK |                   | seq-num         | destination             |
+//! | RERR     | originator        |                 | unreachable, destination |
+//!
+//! RREQs and RREPs also have `<msg-hop-limit>` and `<msg-hop-count>`, and a
+//! METRIC message TLV unless the metric is the hop count. An RREP asking
+//! for an RREP_ACK has an ACK_REQUIRED message TLV, and the unreachable
+//! address of a RERR has an UNREACHABLE address TLV with the error code.
+
+use crate::nhdp::set_once;
+use crate::{Address, Error, Message, MessageBuilder, MsgHeader, Tlv};
+
+mod rerr;
+mod router;
+mod rrep;
+mod rreq;
+
+pub use self::rerr::Rerr;
+pub use self::router::{
+    DiscoverySlot, Event, Loadng, LoadngConfig, NeighborSlot, RouteSlot,
+    Routing, RoutingTuple, Send,
+};
+pub use self::rrep::{Rrep, RrepAck};
+pub use self::rreq::Rreq;
+
+/// RREQ message type.
+pub const MSG_TYPE_RREQ: u8 = 20;
+/// RREP message type.
+pub const MSG_TYPE_RREP: u8 = 21;
+/// RREP_ACK message type.
+pub const MSG_TYPE_RREP_ACK: u8 = 22;
+/// RERR message type.
+pub const MSG_TYPE_RERR: u8 = 23;
+
+/// METRIC message TLV type, the type extension is the metric type and the
+/// value the route metric in 4 bytes.
+pub const MSG_TLV_METRIC: u8 = 20;
+/// ACK_REQUIRED message TLV type, an RREP with it asks for an RREP_ACK.
+pub const MSG_TLV_ACK_REQUIRED: u8 = 21;
+
+/// UNREACHABLE address TLV type, its value is the error code.
+pub const ADDR_TLV_UNREACHABLE: u8 = 20;
+
+/// Hop count metric type, the default. It isn't encoded as the hop count
+/// is in the message header.
+pub const METRIC_TYPE_HOP_COUNT: u8 = 0;
+
+/// RERR error code: no available route.
+pub const ERROR_NO_AVAILABLE_ROUTE: u8 = 0;
+
+/// Fields of a RREQ or RREP, they have the same encoding.
+#[derive(Debug, Clone, Copy)]
+struct RouteMsg {
+    hop_limit: u8,
+    hop_count: u8,
+    originator: Address,
+    destination: Address,
+    seq_num: u16,
+    metric_type: u8,
+    route_metric: u32,
+    ack_required: bool,
+}
+
+impl RouteMsg {
+    /// Read a RREQ or RREP of type `r#type`.
+    fn read(msg: &Message, r#type: u8) -> Result<RouteMsg, Error> {
+        let hdr = &msg.hdr;
+        if hdr.r#type != r#type {
+            return Err(Error::InvalidMessage);
+        }
+        let hop_limit = hdr.hop_limit.ok_or(Error::InvalidMessage)?;
+        let hop_count = hdr.hop_count.ok_or(Error::InvalidMessage)?;
+        let seq_num = hdr.seq_num.ok_or(Error::InvalidMessage)?;
+        let originator = hdr
+            .orig_addr
+            .and_then(Address::from_bytes)
+            .ok_or(Error::InvalidMessage)?;
+
+        let mut metric = None;
+        let mut ack_required = false;
+        for tlv in msg.tlv_block.iter() {
+            let tlv = tlv?;
+            match tlv.r#type {
+                MSG_TLV_METRIC => {
+                    let value = tlv.value.unwrap_or(&[]);
+                    if value.len() != 4 {
+                        return Err(Error::InvalidTlvValue);
+                    }
+                    let m = [value[0], value[1], value[2], value[3]];
+                    set_once(
+                        &mut metric,
+                        (tlv.type_ext(), u32::from_be_bytes(m)),
+                    )?;
+                }
+                MSG_TLV_ACK_REQUIRED if tlv.type_ext() == 0 => {
+                    ack_required = true;
+                }
+                _ => (),
+            }
+        }
+        let (metric_type, route_metric) =
+            metric.unwrap_or((METRIC_TYPE_HOP_COUNT, u32::from(hop_count)));
+
+        Ok(RouteMsg {
+            hop_limit,
+            hop_count,
+            originator,
+            destination: read_destination(msg)?,
+            seq_num,
+            metric_type,
+            route_metric,
+            ack_required,
+        })
+    }
+
+    /// Write a RREQ or RREP of type `r#type`.
+    fn write(&self, r#type: u8, buf: &mut [u8]) -> Result<usize, Error> {
+        if self.originator.len() != self.destination.len() {
+            return Err(Error::InvalidAddressBlock);
+        }
+        let mut hdr = MsgHeader::new(r#type, self.originator.len());
+        hdr.orig_addr = Some(self.originator.as_bytes());
+        hdr.hop_limit = Some(self.hop_limit);
+        hdr.hop_count = Some(self.hop_count);
+        hdr.seq_num = Some(self.seq_num);
+
+        let mut builder = MessageBuilder::new(buf, &hdr)?;
+        if self.metric_type != METRIC_TYPE_HOP_COUNT {
+            builder.add_tlv(&Tlv {
+                r#type: MSG_TLV_METRIC,
+                type_ext: Some(self.metric_type),
+                start_index: None,
+                stop_index: None,
+                value: Some(&self.route_metric.to_be_bytes()),
+                multi_value: false,
+            })?;
+        }
+        if self.ack_required {
+            builder.add_tlv(&Tlv {
+                r#type: MSG_TLV_ACK_REQUIRED,
+                type_ext: None,
+                start_index: None,
+                stop_index: None,
+                value: None,
+                multi_value: false,
+            })?;
+        }
+        builder.add_address_block(&[self.destination], None)?;
+        builder.finish()
+    }
+}
+
+/// Read the only address of a message, without a prefix length.
+fn read_destination(msg: &Message) -> Result<Address, Error> {
+    let mut destination = None;
+    for (addr, prefix_length) in msg.address_tlv.unique() {
+        check_prefix_length(&addr, prefix_length)?;
+        set_once(&mut destination, addr)?;
+    }
+
+    destination.ok_or(Error::InvalidMessage)
+}
+
+/// LOADng routes addresses, not prefixes.
+fn check_prefix_length(
+    addr: &Address,
+    prefix_length: Option<u8>,
+) -> Result<(), Error> {
+    match prefix_length {
+        Some(p) if usize::from(p) != 8 * addr.len() => {
+            Err(Error::InvalidMessage)
+        }
+        _ => Ok(()),
+    }
+}
@@ -0,0 +1,161 @@
+// Copyright 2020 Jean Pierre Dudey. See the LICENSE-MIT and
+// LICENSE-APACHE files at the top-level directory of this
+// distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use crate::loadng::*;
+use crate::{Address, Error, Message, MessageBuilder, MsgHeader};
+
+/// RERR (Route Error) message, unicast to the source of a data packet that
+/// couldn't be forwarded.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct Rerr {
+    /// `<msg-hop-limit>`
+    pub hop_limit: u8,
+    /// RERR.error-code, the reason the route broke.
+    pub error_code: u8,
+    /// RERR.originator, the router that couldn't forward the packet.
+    pub originator: Address,
+    /// RERR.destination, the source of the packet.
+    pub destination: Address,
+    /// RERR.unreachable-address, the destination of the packet.
+    pub unreachable: Address,
+}
+
+impl Rerr {
+    /// Read a RERR from a message.
+    ///
+    /// Fails with
+    /// [`Error::InvalidMessage`](../enum.Error.html#variant.InvalidMessage)
+    /// without `<msg-orig-addr>` or `<msg-hop-limit>`, or if the message
+    /// hasn't one unreachable address and one destination.
+    pub fn from_message(msg: &Message) -> Result<Rerr, Error> {
+        let hdr = &msg.hdr;
+        if hdr.r#type != MSG_TYPE_RERR {
+            return Err(Error::InvalidMessage);
+        }
+        let hop_limit = hdr.hop_limit.ok_or(Error::InvalidMessage)?;
+        let originator = hdr
+            .orig_addr
+            .and_then(Address::from_bytes)
+            .ok_or(Error::InvalidMessage)?;
+
+        let mut unreachable = None;
+        let mut destination = None;
+        for (addr, prefix_length) in msg.address_tlv.unique() {
+            check_prefix_length(&addr, prefix_length)?;
+
+            let mut error_code = None;
+            msg.address_tlv.for_each_tlv(
+                &addr,
+                prefix_length,
+                |tlv, value| {
+                    if tlv.r#type != ADDR_TLV_UNREACHABLE || tlv.type_ext() != 0
+                    {
+                        return Ok(());
+                    }
+                    if value.len() != 1 {
+                        return Err(Error::InvalidTlvValue);
+                    }
+                    set_once(&mut error_code, value[0])
+                },
+            )?;
+            match error_code {
+                Some(code) => set_once(&mut unreachable, (addr, code))?,
+                None => set_once(&mut destination, addr)?,
+            }
+        }
+        let (unreachable, error_code) =
+            unreachable.ok_or(Error::InvalidMessage)?;
+
+        Ok(Rerr {
+            hop_limit,
+            error_code,
+            originator,
+            destination: destination.ok_or(Error::InvalidMessage)?,
+            unreachable,
+        })
+    }
+
+    /// Write the RERR to `buf`, returns the size of the message.
+    ///
+    /// Fails with
+    /// [`Error::InvalidAddressBlock`](../enum.Error.html#variant.InvalidAddressBlock)
+    /// if the addresses have different lengths, or if the destination is
+    /// the unreachable address.
+    pub fn write(&self, buf: &mut [u8]) -> Result<usize, Error> {
+        let len = self.originator.len();
+        if self.destination.len() != len
+            || self.unreachable.len() != len
+            || self.destination == self.unreachable
+        {
+            return Err(Error::InvalidAddressBlock);
+        }
+        let mut hdr = MsgHeader::new(MSG_TYPE_RERR, len);
+        hdr.orig_addr = Some(self.originator.as_bytes());
+        hdr.hop_limit = Some(self.hop_limit);
+
+        let mut builder = MessageBuilder::new(buf, &hdr)?;
+        builder
+            .add_address_block(&[self.unreachable, self.destination], None)?;
+        builder.add_address_tlvs_u8(ADDR_TLV_UNREACHABLE, None, |i| {
+            Some(self.error_code).filter(|_| i == 0)
+        })?;
+        builder.finish()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::Buf;
+
+    fn addr(bytes: &[u8]) -> Address {
+        Address::from_bytes(bytes).unwrap()
+    }
+
+    fn read(buf: &[u8]) -> Result<Rerr, Error> {
+        Rerr::from_message(&Message::read(&mut Buf::new(buf)).unwrap())
+    }
+
+    #[test]
+    fn test_rerr() {
+        let mut rerr = Rerr {
+            hop_limit: 16,
+            error_code: ERROR_NO_AVAILABLE_ROUTE,
+            originator: addr(&[0, 2]),
+            destination: addr(&[0, 1]),
+            unreachable: addr(&[0, 9]),
+        };
+        let mut buf = [0u8; 32];
+        let len = rerr.write(&mut buf).unwrap();
+        assert_eq!(read(&buf[..len]), Ok(rerr));
+
+        rerr.error_code = 7;
+        rerr.unreachable = addr(&[1, 9]);
+        let len = rerr.write(&mut buf).unwrap();
+        assert_eq!(read(&buf[..len]), Ok(rerr));
+
+        rerr.unreachable = rerr.destination;
+        assert_eq!(rerr.write(&mut buf), Err(Error::InvalidAddressBlock));
+        rerr.unreachable = addr(&[9]);
+        assert_eq!(rerr.write(&mut buf), Err(Error::InvalidAddressBlock));
+
+        // Without the destination.
+        let mut hdr = MsgHeader::new(MSG_TYPE_RERR, 2);
+        hdr.orig_addr = Some(&[0, 2]);
+        hdr.hop_limit = Some(1);
+        let mut builder = MessageBuilder::new(&mut buf, &hdr).unwrap();
+        builder.add_address_block(&[addr(&[0, 9])], None).unwrap();
+        builder
+            .add_address_tlvs_u8(ADDR_TLV_UNREACHABLE, None, |_| Some(0))
+            .unwrap();
+        let len = builder.finish().unwrap();
+        assert_eq!(read(&buf[..len]), Err(Error::InvalidMessage));
+    }
+}
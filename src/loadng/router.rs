@@ -0,0 +1,913 @@
+// Copyright 2020 Jean Pierre Dudey. See the LICENSE-MIT and
+// LICENSE-APACHE files at the top-level directory of this
+// distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use crate::loadng::*;
+use crate::{Address, Clock, Error, Message, SeqNum};
+
+/// Number of messages waiting to be sent.
+const QUEUE_LEN: usize = 4;
+
+/// LOADng parameters, times are in milliseconds.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct LoadngConfig {
+    /// R_HOLD_TIME, how long a route is kept after it was last used or
+    /// updated.
+    pub route_hold_time: u64,
+    /// NET_TRAVERSAL_TIME, time to wait for a RREP before sending the RREQ
+    /// again.
+    pub net_traversal_time: u64,
+    /// RREQ_MAX_TRIES, number of RREQs sent for a route discovery.
+    pub rreq_max_tries: u32,
+    /// Time to wait for a RREP_ACK before blacklisting the neighbor.
+    pub rrep_ack_timeout: u64,
+    /// B_HOLD_TIME, how long a blacklisted neighbor is ignored.
+    pub blacklist_hold_time: u64,
+    /// MAX_DIST, hop limit of the messages originated.
+    pub hop_limit: u8,
+}
+
+impl Default for LoadngConfig {
+    fn default() -> LoadngConfig {
+        LoadngConfig {
+            route_hold_time: 300_000,
+            net_traversal_time: 2_000,
+            rreq_max_tries: 3,
+            rrep_ack_timeout: 1_000,
+            blacklist_hold_time: 10_000,
+            hop_limit: 16,
+        }
+    }
+}
+
+/// Routing Tuple, a route to a destination.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct RoutingTuple {
+    /// R_dest_addr
+    pub dest: Address,
+    /// R_next_addr
+    pub next_hop: Address,
+    /// R_metric_type
+    pub metric_type: u8,
+    /// R_metric
+    pub metric: u32,
+    /// R_hop_count
+    pub hop_count: u8,
+    /// R_seq_num
+    pub seq_num: u16,
+    /// R_bidirectional, the link to the next hop is known to work both
+    /// ways.
+    pub bidirectional: bool,
+    /// R_valid_time
+    pub valid_time: u64,
+}
+
+/// A place in the Routing Set of a [`Loadng`](struct.Loadng.html).
+#[derive(Debug, Default, Clone, Copy)]
+pub struct RouteSlot(Option<RoutingTuple>);
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+enum NeighborState {
+    /// Waiting for the RREP_ACK of the RREP with this sequence number and
+    /// originator.
+    AckPending(u16, Address),
+    /// Didn't send a RREP_ACK, its RREQs are ignored.
+    Blacklisted,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Neighbor {
+    addr: Address,
+    state: NeighborState,
+    timeout: u64,
+}
+
+/// A place in the Pending Acknowledgment and Blacklisted Neighbor Sets of
+/// a [`Loadng`](struct.Loadng.html).
+#[derive(Debug, Default, Clone, Copy)]
+pub struct NeighborSlot(Option<Neighbor>);
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+enum DiscoveryState {
+    /// Next RREQ to send and time to send it.
+    Pending {
+        tries: u32,
+        next: u64,
+    },
+    Found,
+    Failed,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Discovery {
+    dest: Address,
+    state: DiscoveryState,
+}
+
+/// A place for a route discovery of a [`Loadng`](struct.Loadng.html).
+#[derive(Debug, Default, Clone, Copy)]
+pub struct DiscoverySlot(Option<Discovery>);
+
+/// A message to send, with its next hop.
+#[derive(Debug, Clone, Copy)]
+enum Output {
+    Rreq(Rreq),
+    Rrep(Address, Rrep),
+    RrepAck(Address, RrepAck),
+    Rerr(Address, Rerr),
+}
+
+/// A message written by [`Loadng::poll_send`](struct.Loadng.html#method.poll_send).
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct Send {
+    /// Neighbor to send it to, `None` to send it to every neighbor.
+    pub to: Option<Address>,
+    /// Size of the message.
+    pub len: usize,
+}
+
+/// End of a route discovery.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Event {
+    /// A route to the destination was found.
+    RouteFound(Address),
+    /// No RREP was received after all the RREQs.
+    DiscoveryFailed(Address),
+}
+
+/// What to do with a data packet.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Routing {
+    /// Forward it to the next hop.
+    Forward(Address),
+    /// Hold it, a route discovery is in progress.
+    Discovering,
+    /// Drop it, there is no route.
+    Unreachable,
+}
+
+/// LOADng router, without heap allocations.
+///
+/// The Routing Set, the neighbors waiting for an RREP_ACK or blacklisted,
+/// and the route discoveries in progress are kept in slices given by the
+/// caller. When one is full the entry expiring first is replaced, and no
+/// new route discovery is started.
+///
+/// It doesn't do any I/O: messages are given to
+/// [`receive`](#method.receive), data packets ask for a route with
+/// [`route`](#method.route), and [`poll_send`](#method.poll_send) writes the
+/// messages to send in a buffer. A few messages can wait to be sent, so
+/// `poll_send` must be called until it returns `None` after each of them.
+/// [`expire`](#method.expire) must be called at
+/// [`next_expiry`](#method.next_expiry), then `poll_send` for the RREQs
+/// to send again and [`poll_event`](#method.poll_event) for the result of
+/// route discoveries.
+///
+/// Only the hop count metric is supported, messages with other metric
+/// types are ignored.
+#[derive(Debug)]
+pub struct Loadng<'a, C> {
+    clock: C,
+    config: LoadngConfig,
+    addr: Address,
+    seq_num: SeqNum,
+    routes: &'a mut [RouteSlot],
+    neighbors: &'a mut [NeighborSlot],
+    discoveries: &'a mut [DiscoverySlot],
+    queue: [Option<Output>; QUEUE_LEN],
+}
+
+impl<'a, C: Clock> Loadng<'a, C> {
+    /// Create a router with address `addr` and empty tables.
+    pub fn new(
+        clock: C,
+        addr: Address,
+        config: LoadngConfig,
+        routes: &'a mut [RouteSlot],
+        neighbors: &'a mut [NeighborSlot],
+        discoveries: &'a mut [DiscoverySlot],
+    ) -> Self {
+        for slot in routes.iter_mut() {
+            *slot = RouteSlot(None);
+        }
+        for slot in neighbors.iter_mut() {
+            *slot = NeighborSlot(None);
+        }
+        for slot in discoveries.iter_mut() {
+            *slot = DiscoverySlot(None);
+        }
+
+        Loadng {
+            clock,
+            config,
+            addr,
+            seq_num: SeqNum(0),
+            routes,
+            neighbors,
+            discoveries,
+            queue: [None; QUEUE_LEN],
+        }
+    }
+
+    /// The clock.
+    pub fn clock(&self) -> &C {
+        &self.clock
+    }
+
+    /// The parameters.
+    pub fn config(&self) -> &LoadngConfig {
+        &self.config
+    }
+
+    /// Address of the router.
+    pub fn addr(&self) -> &Address {
+        &self.addr
+    }
+
+    /// Sequence number of the last RREQ or RREP originated.
+    pub fn seq_num(&self) -> u16 {
+        self.seq_num.0
+    }
+
+    /// The Routing Set.
+    pub fn routes(&self) -> impl Iterator<Item = &RoutingTuple> {
+        self.routes.iter().filter_map(|s| s.0.as_ref())
+    }
+
+    /// Is the neighbor `addr` blacklisted?
+    pub fn is_blacklisted(&self, addr: &Address) -> bool {
+        self.neighbors
+            .iter()
+            .filter_map(|s| s.0.as_ref())
+            .any(|n| n.addr == *addr && n.state == NeighborState::Blacklisted)
+    }
+
+    /// Route a data packet from `src` to `dest`.
+    ///
+    /// With a route the packet is forwarded and the route kept for
+    /// R_HOLD_TIME. Otherwise, if the packet comes from this router a route
+    /// discovery is started, else a RERR is sent to `src`.
+    pub fn route(&mut self, src: &Address, dest: &Address) -> Routing {
+        let now = self.clock.now();
+        let valid_time = now.saturating_add(self.config.route_hold_time);
+        if let Some(r) = self.find_route(now, dest) {
+            r.valid_time = r.valid_time.max(valid_time);
+            return Routing::Forward(r.next_hop);
+        }
+
+        if *src != self.addr {
+            if let Some(next_hop) =
+                self.find_route(now, src).map(|r| r.next_hop)
+            {
+                let rerr = Rerr {
+                    hop_limit: self.config.hop_limit,
+                    error_code: ERROR_NO_AVAILABLE_ROUTE,
+                    originator: self.addr,
+                    destination: *src,
+                    unreachable: *dest,
+                };
+                self.push(Output::Rerr(next_hop, rerr));
+            }
+            return Routing::Unreachable;
+        }
+
+        let existing = self
+            .discoveries
+            .iter()
+            .filter_map(|s| s.0.as_ref())
+            .find(|d| d.dest == *dest);
+        match existing.map(|d| d.state) {
+            Some(DiscoveryState::Pending { .. }) => {
+                return Routing::Discovering
+            }
+            Some(_) => return Routing::Unreachable,
+            None => (),
+        }
+        match self.discoveries.iter_mut().find(|s| s.0.is_none()) {
+            Some(slot) => {
+                *slot = DiscoverySlot(Some(Discovery {
+                    dest: *dest,
+                    state: DiscoveryState::Pending {
+                        tries: 0,
+                        next: now,
+                    },
+                }));
+                Routing::Discovering
+            }
+            None => Routing::Unreachable,
+        }
+    }
+
+    /// The link to the neighbor `next_hop` broke, its routes are removed.
+    pub fn link_broken(&mut self, next_hop: &Address) {
+        for slot in self.routes.iter_mut() {
+            if slot.0.map_or(false, |r| r.next_hop == *next_hop) {
+                slot.0 = None;
+            }
+        }
+    }
+
+    /// Process a message received from the neighbor `from`.
+    ///
+    /// Messages of other types are ignored. Fails with
+    /// [`Error::InvalidMessage`](../enum.Error.html#variant.InvalidMessage)
+    /// if the address length isn't the one of the router, or if the message
+    /// is invalid.
+    pub fn receive(
+        &mut self,
+        from: &Address,
+        msg: &Message,
+    ) -> Result<(), Error> {
+        if msg.hdr.address_length != self.addr.len() {
+            return Err(Error::InvalidMessage);
+        }
+
+        let now = self.clock.now();
+        match msg.hdr.r#type {
+            MSG_TYPE_RREQ => {
+                self.process_rreq(now, from, Rreq::from_message(msg)?)
+            }
+            MSG_TYPE_RREP => {
+                self.process_rrep(now, from, Rrep::from_message(msg)?)
+            }
+            MSG_TYPE_RREP_ACK => {
+                self.process_rrep_ack(from, RrepAck::from_message(msg)?)
+            }
+            MSG_TYPE_RERR => {
+                self.process_rerr(now, from, Rerr::from_message(msg)?)
+            }
+            _ => (),
+        }
+
+        Ok(())
+    }
+
+    /// Write the next message to send in `buf`. RREQs of route discoveries
+    /// are sent after the other messages.
+    ///
+    /// The message is dropped if it doesn't fit in `buf`.
+    pub fn poll_send(&mut self, buf: &mut [u8]) -> Result<Option<Send>, Error> {
+        let output = match self.pop() {
+            Some(output) => output,
+            None => match self.next_rreq() {
+                Some(rreq) => Output::Rreq(rreq),
+                None => return Ok(None),
+            },
+        };
+
+        let (to, len) = match output {
+            Output::Rreq(rreq) => (None, rreq.write(buf)?),
+            Output::Rrep(to, rrep) => (Some(to), rrep.write(buf)?),
+            Output::RrepAck(to, ack) => (Some(to), ack.write(buf)?),
+            Output::Rerr(to, rerr) => (Some(to), rerr.write(buf)?),
+        };
+        Ok(Some(Send { to, len }))
+    }
+
+    /// Retrieve the result of the next route discovery that ended.
+    pub fn poll_event(&mut self) -> Option<Event> {
+        for slot in self.discoveries.iter_mut() {
+            let event = match slot.0 {
+                Some(Discovery {
+                    dest,
+                    state: DiscoveryState::Found,
+                }) => Event::RouteFound(dest),
+                Some(Discovery {
+                    dest,
+                    state: DiscoveryState::Failed,
+                }) => Event::DiscoveryFailed(dest),
+                _ => continue,
+            };
+            slot.0 = None;
+            return Some(event);
+        }
+
+        None
+    }
+
+    /// Remove the expired routes, blacklist the neighbors that didn't send
+    /// a RREP_ACK in time and end the route discoveries without an answer
+    /// to the last RREQ.
+    pub fn expire(&mut self) {
+        let now = self.clock.now();
+        let c = self.config;
+        for slot in self.routes.iter_mut() {
+            if slot.0.map_or(false, |r| r.valid_time <= now) {
+                slot.0 = None;
+            }
+        }
+
+        for slot in self.neighbors.iter_mut() {
+            let n = match slot.0 {
+                Some(ref mut n) if n.timeout <= now => n,
+                _ => continue,
+            };
+            match n.state {
+                NeighborState::AckPending(..) => {
+                    n.state = NeighborState::Blacklisted;
+                    n.timeout = now.saturating_add(c.blacklist_hold_time);
+                }
+                NeighborState::Blacklisted => slot.0 = None,
+            }
+        }
+
+        for d in self.discoveries.iter_mut().filter_map(|s| s.0.as_mut()) {
+            let failed = match d.state {
+                DiscoveryState::Pending { tries, next } => {
+                    next <= now && tries >= c.rreq_max_tries
+                }
+                _ => false,
+            };
+            if failed {
+                d.state = DiscoveryState::Failed;
+            }
+        }
+    }
+
+    /// Next time the state changes by itself, when
+    /// [`expire`](#method.expire) must be called.
+    pub fn next_expiry(&self) -> Option<u64> {
+        let routes = self.routes().map(|r| r.valid_time);
+        let neighbors = self
+            .neighbors
+            .iter()
+            .filter_map(|s| s.0.as_ref())
+            .map(|n| n.timeout);
+        let discoveries = self
+            .discoveries
+            .iter()
+            .filter_map(|s| s.0.as_ref())
+            .filter_map(|d| match d.state {
+                DiscoveryState::Pending { next, .. } => Some(next),
+                _ => None,
+            });
+
+        routes.chain(neighbors).chain(discoveries).min()
+    }
+
+    fn process_rreq(&mut self, now: u64, from: &Address, rreq: Rreq) {
+        if self.is_blacklisted(from)
+            || rreq.originator == self.addr
+            || rreq.metric_type != METRIC_TYPE_HOP_COUNT
+        {
+            return;
+        }
+        let hop_count = match rreq.hop_count.checked_add(1) {
+            Some(h) => h,
+            None => return,
+        };
+        let r = (rreq.originator, rreq.seq_num, hop_count);
+        if !self.update_route(now, from, r, false) {
+            return;
+        }
+
+        if rreq.destination == self.addr {
+            self.seq_num = self.seq_num.next();
+            let rrep = Rrep::new(
+                self.config.hop_limit,
+                self.addr,
+                rreq.originator,
+                self.seq_num.0,
+            );
+            self.send_rrep(now, from, rrep);
+        } else if rreq.hop_limit > 1 {
+            self.push(Output::Rreq(Rreq {
+                hop_limit: rreq.hop_limit - 1,
+                hop_count,
+                route_metric: u32::from(hop_count),
+                ..rreq
+            }));
+        }
+    }
+
+    fn process_rrep(&mut self, now: u64, from: &Address, rrep: Rrep) {
+        if rrep.ack_required {
+            let ack = RrepAck {
+                seq_num: rrep.seq_num,
+                destination: rrep.originator,
+            };
+            self.push(Output::RrepAck(*from, ack));
+        }
+        if rrep.originator == self.addr
+            || rrep.metric_type != METRIC_TYPE_HOP_COUNT
+        {
+            return;
+        }
+        let hop_count = match rrep.hop_count.checked_add(1) {
+            Some(h) => h,
+            None => return,
+        };
+
+        // The neighbor got the RREQ from this router, the link works both
+        // ways.
+        self.confirm(from);
+        let r = (rrep.originator, rrep.seq_num, hop_count);
+        if !self.update_route(now, from, r, true) {
+            return;
+        }
+
+        if rrep.destination == self.addr {
+            for d in self.discoveries.iter_mut().filter_map(|s| s.0.as_mut()) {
+                let pending = match d.state {
+                    DiscoveryState::Pending { .. } => true,
+                    _ => false,
+                };
+                if pending && d.dest == rrep.originator {
+                    d.state = DiscoveryState::Found;
+                }
+            }
+        } else if rrep.hop_limit > 1 {
+            if let Some(r) = self.find_route(now, &rrep.destination) {
+                let next_hop = r.next_hop;
+                let rrep = Rrep {
+                    hop_limit: rrep.hop_limit - 1,
+                    hop_count,
+                    ack_required: false,
+                    route_metric: u32::from(hop_count),
+                    ..rrep
+                };
+                self.send_rrep(now, &next_hop, rrep);
+            }
+        }
+    }
+
+    fn process_rrep_ack(&mut self, from: &Address, ack: RrepAck) {
+        let slot = self.neighbors.iter_mut().find(|s| match s.0 {
+            Some(Neighbor {
+                addr,
+                state: NeighborState::AckPending(seq_num, originator),
+                ..
+            }) => {
+                addr == *from
+                    && seq_num == ack.seq_num
+                    && originator == ack.destination
+            }
+            _ => false,
+        });
+        if let Some(slot) = slot {
+            slot.0 = None;
+            self.confirm(from);
+        }
+    }
+
+    fn process_rerr(&mut self, now: u64, from: &Address, rerr: Rerr) {
+        for slot in self.routes.iter_mut() {
+            if slot.0.map_or(false, |r| {
+                r.dest == rerr.unreachable && r.next_hop == *from
+            }) {
+                slot.0 = None;
+            }
+        }
+
+        if rerr.destination == self.addr || rerr.hop_limit <= 1 {
+            return;
+        }
+        if let Some(r) = self.find_route(now, &rerr.destination) {
+            let next_hop = r.next_hop;
+            let rerr = Rerr {
+                hop_limit: rerr.hop_limit - 1,
+                ..rerr
+            };
+            self.push(Output::Rerr(next_hop, rerr));
+        }
+    }
+
+    /// Send a RREP to `next_hop`, asking for a RREP_ACK if the link isn't
+    /// known to be bidirectional.
+    fn send_rrep(&mut self, now: u64, next_hop: &Address, mut rrep: Rrep) {
+        rrep.ack_required = !self.is_bidirectional(next_hop);
+        if rrep.ack_required {
+            let state =
+                NeighborState::AckPending(rrep.seq_num, rrep.originator);
+            self.add_neighbor(Neighbor {
+                addr: *next_hop,
+                state,
+                timeout: now.saturating_add(self.config.rrep_ack_timeout),
+            });
+        }
+
+        self.push(Output::Rrep(*next_hop, rrep));
+    }
+
+    /// The RREQ of the next route discovery to try again.
+    fn next_rreq(&mut self) -> Option<Rreq> {
+        let now = self.clock.now();
+        let c = self.config;
+        let d = self
+            .discoveries
+            .iter_mut()
+            .filter_map(|s| s.0.as_mut())
+            .find(|d| match d.state {
+                DiscoveryState::Pending { tries, next } => {
+                    next <= now && tries < c.rreq_max_tries
+                }
+                _ => false,
+            })?;
+
+        if let DiscoveryState::Pending { tries, .. } = d.state {
+            d.state = DiscoveryState::Pending {
+                tries: tries + 1,
+                next: now.saturating_add(c.net_traversal_time),
+            };
+        }
+        let dest = d.dest;
+        self.seq_num = self.seq_num.next();
+        Some(Rreq::new(c.hop_limit, self.addr, dest, self.seq_num.0))
+    }
+
+    /// Update the route to a destination with the one `(dest, seq_num,
+    /// hop_count)` through `next_hop`. Returns `false` if it isn't newer or
+    /// shorter, then the message advertising it is dropped.
+    fn update_route(
+        &mut self,
+        now: u64,
+        next_hop: &Address,
+        (dest, seq_num, hop_count): (Address, u16, u8),
+        bidirectional: bool,
+    ) -> bool {
+        let bidirectional = bidirectional || self.is_bidirectional(next_hop);
+        let route = RoutingTuple {
+            dest,
+            next_hop: *next_hop,
+            metric_type: METRIC_TYPE_HOP_COUNT,
+            metric: u32::from(hop_count),
+            hop_count,
+            seq_num,
+            bidirectional,
+            valid_time: now.saturating_add(self.config.route_hold_time),
+        };
+
+        let existing = self
+            .routes
+            .iter_mut()
+            .filter_map(|s| s.0.as_mut())
+            .find(|r| r.dest == dest);
+        if let Some(r) = existing {
+            let newer = SeqNum(seq_num).is_newer_than(SeqNum(r.seq_num));
+            let better = seq_num == r.seq_num && route.metric < r.metric;
+            if newer || better {
+                *r = route;
+            }
+            return newer || better;
+        }
+
+        let slot = self
+            .routes
+            .iter_mut()
+            .min_by_key(|s| s.0.map(|r| r.valid_time));
+        if let Some(slot) = slot {
+            slot.0 = Some(route);
+        }
+        true
+    }
+
+    /// Add or replace the state of a neighbor.
+    fn add_neighbor(&mut self, neighbor: Neighbor) {
+        let slot = match self
+            .neighbors
+            .iter()
+            .position(|s| s.0.map_or(false, |n| n.addr == neighbor.addr))
+        {
+            Some(i) => self.neighbors.get_mut(i),
+            None => self
+                .neighbors
+                .iter_mut()
+                .min_by_key(|s| s.0.map(|n| n.timeout)),
+        };
+        if let Some(slot) = slot {
+            slot.0 = Some(neighbor);
+        }
+    }
+
+    /// The link with `addr` works both ways.
+    fn confirm(&mut self, addr: &Address) {
+        for r in self.routes.iter_mut().filter_map(|s| s.0.as_mut()) {
+            if r.next_hop == *addr {
+                r.bidirectional = true;
+            }
+        }
+        for slot in self.neighbors.iter_mut() {
+            if slot.0.map_or(false, |n| {
+                n.addr == *addr && n.state == NeighborState::Blacklisted
+            }) {
+                slot.0 = None;
+            }
+        }
+    }
+
+    fn is_bidirectional(&self, addr: &Address) -> bool {
+        self.routes()
+            .any(|r| r.next_hop == *addr && r.bidirectional)
+    }
+
+    /// The valid route to `dest`.
+    fn find_route(
+        &mut self,
+        now: u64,
+        dest: &Address,
+    ) -> Option<&mut RoutingTuple> {
+        self.routes
+            .iter_mut()
+            .filter_map(|s| s.0.as_mut())
+            .find(|r| r.dest == *dest && r.valid_time > now)
+    }
+
+    /// Queue a message, dropped if the queue is full.
+    fn push(&mut self, output: Output) {
+        if let Some(slot) = self.queue.iter_mut().find(|o| o.is_none()) {
+            *slot = Some(output);
+        }
+    }
+
+    fn pop(&mut self) -> Option<Output> {
+        let output = self.queue[0].take();
+        self.queue.rotate_left(1);
+        output
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{Buf, ManualClock};
+
+    fn addr(n: u8) -> Address {
+        Address::from_bytes(&[0xfe, n]).unwrap()
+    }
+
+    /// Deliver the messages between routers on a line, until there are none
+    /// left. `dropped` is the type of the messages lost.
+    fn run<C: Clock>(nodes: &mut [Loadng<C>], dropped: Option<u8>) {
+        let mut buf = [0u8; 64];
+        loop {
+            let mut sent = false;
+            for i in 0..nodes.len() {
+                while let Some(send) = nodes[i].poll_send(&mut buf).unwrap() {
+                    sent = true;
+                    let msg =
+                        Message::read(&mut Buf::new(&buf[..send.len])).unwrap();
+                    if Some(msg.hdr.r#type) == dropped {
+                        continue;
+                    }
+                    let from = *nodes[i].addr();
+                    for &j in [i.wrapping_sub(1), i + 1].iter() {
+                        let node = match nodes.get_mut(j) {
+                            Some(n) => n,
+                            None => continue,
+                        };
+                        if send.to.map_or(true, |to| to == *node.addr()) {
+                            node.receive(&from, &msg).unwrap();
+                        }
+                    }
+                }
+            }
+            if !sent {
+                return;
+            }
+        }
+    }
+
+    #[test]
+    fn test_discovery() {
+        let clock = ManualClock::new(0);
+        let config = LoadngConfig::default();
+        let mut routes = [[RouteSlot::default(); 4]; 3];
+        let mut neighbors = [[NeighborSlot::default(); 2]; 3];
+        let mut discoveries = [[DiscoverySlot::default(); 2]; 3];
+        let [r0, r1, r2] = &mut routes;
+        let [n0, n1, n2] = &mut neighbors;
+        let [d0, d1, d2] = &mut discoveries;
+        let mut nodes = [
+            Loadng::new(&clock, addr(1), config, r0, n0, d0),
+            Loadng::new(&clock, addr(2), config, r1, n1, d1),
+            Loadng::new(&clock, addr(3), config, r2, n2, d2),
+        ];
+        let (a, b, c) = (addr(1), addr(2), addr(3));
+
+        assert_eq!(nodes[0].route(&a, &c), Routing::Discovering);
+        assert_eq!(nodes[0].route(&a, &c), Routing::Discovering);
+        run(&mut nodes, None);
+        assert_eq!(nodes[0].poll_event(), Some(Event::RouteFound(c)));
+        assert_eq!(nodes[0].poll_event(), None);
+
+        let route = nodes[0].routes().find(|r| r.dest == c).cloned().unwrap();
+        assert_eq!((route.next_hop, route.hop_count), (b, 2));
+        assert!(route.bidirectional);
+        // The RREP_ACKs were received.
+        for node in nodes.iter() {
+            assert!(node.routes().all(|r| r.bidirectional));
+            assert!(node.neighbors.iter().all(|s| s.0.is_none()));
+        }
+
+        assert_eq!(nodes[0].route(&a, &c), Routing::Forward(b));
+        assert_eq!(nodes[1].route(&a, &c), Routing::Forward(c));
+        assert_eq!(nodes[2].route(&c, &a), Routing::Forward(b));
+        assert_eq!(nodes[1].route(&c, &a), Routing::Forward(a));
+
+        // The link breaks, B sends a RERR to A.
+        nodes[1].link_broken(&c);
+        assert_eq!(nodes[1].route(&a, &c), Routing::Unreachable);
+        run(&mut nodes, None);
+        assert!(nodes[0].routes().all(|r| r.dest != c));
+        assert_eq!(nodes[0].route(&a, &c), Routing::Discovering);
+
+        // Routes expire.
+        clock.set(300_000);
+        nodes[2].expire();
+        assert_eq!(nodes[2].routes().count(), 0);
+    }
+
+    #[test]
+    fn test_never_expiring_routes() {
+        let clock = ManualClock::new(0);
+        let config = LoadngConfig {
+            route_hold_time: core::u64::MAX,
+            ..LoadngConfig::default()
+        };
+        let mut routes = [[RouteSlot::default(); 2]; 2];
+        let mut neighbors = [[NeighborSlot::default(); 2]; 2];
+        let mut discoveries = [[DiscoverySlot::default(); 2]; 2];
+        let [r0, r1] = &mut routes;
+        let [n0, n1] = &mut neighbors;
+        let [d0, d1] = &mut discoveries;
+        let mut nodes = [
+            Loadng::new(&clock, addr(1), config, r0, n0, d0),
+            Loadng::new(&clock, addr(2), config, r1, n1, d1),
+        ];
+        let (a, b) = (addr(1), addr(2));
+
+        assert_eq!(nodes[0].route(&a, &b), Routing::Discovering);
+        run(&mut nodes, None);
+        clock.set(1_000);
+        assert_eq!(nodes[0].route(&a, &b), Routing::Forward(b));
+        let route = nodes[0].routes().find(|r| r.dest == b).cloned().unwrap();
+        assert_eq!(route.valid_time, core::u64::MAX);
+    }
+
+    #[test]
+    fn test_discovery_failure_and_blacklist() {
+        let clock = ManualClock::new(0);
+        let config = LoadngConfig::default();
+        let mut routes = [[RouteSlot::default(); 2]; 2];
+        let mut neighbors = [[NeighborSlot::default(); 1]; 2];
+        let mut discoveries = [[DiscoverySlot::default(); 1]; 2];
+        let [r0, r1] = &mut routes;
+        let [n0, n1] = &mut neighbors;
+        let [d0, d1] = &mut discoveries;
+        let mut nodes = [
+            Loadng::new(&clock, addr(1), config, r0, n0, d0),
+            Loadng::new(&clock, addr(2), config, r1, n1, d1),
+        ];
+        let (a, b) = (addr(1), addr(2));
+
+        // No answer, the RREQ is sent three times.
+        let mut times = 0;
+        assert_eq!(nodes[0].route(&a, &addr(9)), Routing::Discovering);
+        assert_eq!(nodes[0].route(&a, &addr(8)), Routing::Unreachable);
+        while let Some(t) = nodes[0].next_expiry() {
+            if t > 6_000 {
+                break;
+            }
+            clock.set(t);
+            nodes[0].expire();
+            while nodes[0].poll_send(&mut [0; 64]).unwrap().is_some() {
+                times += 1;
+            }
+        }
+        assert_eq!(times, 3);
+        assert_eq!(
+            nodes[0].poll_event(),
+            Some(Event::DiscoveryFailed(addr(9)))
+        );
+
+        // B doesn't get the RREP_ACK of A, and blacklists it.
+        assert_eq!(nodes[0].route(&a, &b), Routing::Discovering);
+        run(&mut nodes, Some(MSG_TYPE_RREP_ACK));
+        assert_eq!(nodes[0].poll_event(), Some(Event::RouteFound(b)));
+        clock.set(7_000);
+        nodes[1].expire();
+        assert!(nodes[1].is_blacklisted(&a));
+
+        // Its RREQs are ignored.
+        nodes[0].link_broken(&b);
+        assert_eq!(nodes[0].route(&a, &b), Routing::Discovering);
+        run(&mut nodes, None);
+        assert_eq!(nodes[0].poll_event(), None);
+
+        // They are processed again after B_HOLD_TIME.
+        clock.set(17_000);
+        nodes[0].expire();
+        nodes[1].expire();
+        assert!(!nodes[1].is_blacklisted(&a));
+        run(&mut nodes, None);
+        assert_eq!(nodes[0].poll_event(), Some(Event::RouteFound(b)));
+        assert_eq!(nodes[0].route(&a, &b), Routing::Forward(b));
+        assert_eq!(nodes[1].route(&b, &a), Routing::Forward(a));
+    }
+}
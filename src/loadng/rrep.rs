@@ -0,0 +1,188 @@
+// Copyright 2020 Jean Pierre Dudey. See the LICENSE-MIT and
+// LICENSE-APACHE files at the top-level directory of this
+// distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use crate::loadng::*;
+use crate::{Address, Error, Message, MessageBuilder, MsgHeader};
+
+/// RREP (Route Reply) message, the answer of the destination of a RREQ
+/// unicast back to its originator.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct Rrep {
+    /// `<msg-hop-limit>`
+    pub hop_limit: u8,
+    /// `<msg-hop-count>`
+    pub hop_count: u8,
+    /// RREP.ackrequired, the next hop must answer with an RREP_ACK.
+    pub ack_required: bool,
+    /// RREP.originator, the destination of the RREQ.
+    pub originator: Address,
+    /// RREP.destination, the originator of the RREQ.
+    pub destination: Address,
+    /// RREP.seq-num, sequence number of the originator.
+    pub seq_num: u16,
+    /// RREP.metric-type
+    pub metric_type: u8,
+    /// RREP.route-metric, metric of the route to the originator. It's the
+    /// hop count with [`METRIC_TYPE_HOP_COUNT`].
+    ///
+    /// [`METRIC_TYPE_HOP_COUNT`]: constant.METRIC_TYPE_HOP_COUNT.html
+    pub route_metric: u32,
+}
+
+impl Rrep {
+    /// Create a RREP with the hop count metric and a hop count of 0.
+    pub fn new(
+        hop_limit: u8,
+        originator: Address,
+        destination: Address,
+        seq_num: u16,
+    ) -> Rrep {
+        Rrep {
+            hop_limit,
+            hop_count: 0,
+            ack_required: false,
+            originator,
+            destination,
+            seq_num,
+            metric_type: METRIC_TYPE_HOP_COUNT,
+            route_metric: 0,
+        }
+    }
+
+    /// Read a RREP from a message.
+    ///
+    /// Fails with
+    /// [`Error::InvalidMessage`](../enum.Error.html#variant.InvalidMessage)
+    /// if a header field is missing or there isn't exactly one address.
+    pub fn from_message(msg: &Message) -> Result<Rrep, Error> {
+        let m = RouteMsg::read(msg, MSG_TYPE_RREP)?;
+
+        Ok(Rrep {
+            hop_limit: m.hop_limit,
+            hop_count: m.hop_count,
+            ack_required: m.ack_required,
+            originator: m.originator,
+            destination: m.destination,
+            seq_num: m.seq_num,
+            metric_type: m.metric_type,
+            route_metric: m.route_metric,
+        })
+    }
+
+    /// Write the RREP to `buf`, returns the size of the message.
+    pub fn write(&self, buf: &mut [u8]) -> Result<usize, Error> {
+        let m = RouteMsg {
+            hop_limit: self.hop_limit,
+            hop_count: self.hop_count,
+            originator: self.originator,
+            destination: self.destination,
+            seq_num: self.seq_num,
+            metric_type: self.metric_type,
+            route_metric: self.route_metric,
+            ack_required: self.ack_required,
+        };
+        m.write(MSG_TYPE_RREP, buf)
+    }
+}
+
+/// RREP_ACK message, sent to the neighbor an RREP with ACK_REQUIRED was
+/// received from.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct RrepAck {
+    /// RREP_ACK.seq-num, the sequence number of the RREP.
+    pub seq_num: u16,
+    /// RREP_ACK.destination, the originator of the RREP.
+    pub destination: Address,
+}
+
+impl RrepAck {
+    /// Read a RREP_ACK from a message.
+    ///
+    /// Fails with
+    /// [`Error::InvalidMessage`](../enum.Error.html#variant.InvalidMessage)
+    /// without `<msg-seq-num>` or if there isn't exactly one address.
+    pub fn from_message(msg: &Message) -> Result<RrepAck, Error> {
+        if msg.hdr.r#type != MSG_TYPE_RREP_ACK {
+            return Err(Error::InvalidMessage);
+        }
+
+        Ok(RrepAck {
+            seq_num: msg.hdr.seq_num.ok_or(Error::InvalidMessage)?,
+            destination: read_destination(msg)?,
+        })
+    }
+
+    /// Write the RREP_ACK to `buf`, with a hop limit of 1. Returns the size
+    /// of the message.
+    pub fn write(&self, buf: &mut [u8]) -> Result<usize, Error> {
+        let mut hdr = MsgHeader::new(MSG_TYPE_RREP_ACK, self.destination.len());
+        hdr.hop_limit = Some(1);
+        hdr.seq_num = Some(self.seq_num);
+
+        let mut builder = MessageBuilder::new(buf, &hdr)?;
+        builder.add_address_block(&[self.destination], None)?;
+        builder.finish()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::Buf;
+
+    fn addr(bytes: &[u8]) -> Address {
+        Address::from_bytes(bytes).unwrap()
+    }
+
+    fn message(buf: &[u8]) -> Message<'_> {
+        Message::read(&mut Buf::new(buf)).unwrap()
+    }
+
+    #[test]
+    fn test_rrep() {
+        // 2-byte addresses.
+        let mut rrep = Rrep::new(16, addr(&[0xfe, 1]), addr(&[0xfe, 2]), 9);
+        let mut buf = [0u8; 32];
+        let len = rrep.write(&mut buf).unwrap();
+        assert_eq!(Rrep::from_message(&message(&buf[..len])), Ok(rrep));
+
+        rrep.ack_required = true;
+        rrep.hop_count = 4;
+        rrep.route_metric = 4;
+        let len = rrep.write(&mut buf).unwrap();
+        assert_eq!(Rrep::from_message(&message(&buf[..len])), Ok(rrep));
+        assert_eq!(
+            Rreq::from_message(&message(&buf[..len])),
+            Err(Error::InvalidMessage)
+        );
+
+        rrep.metric_type = 1;
+        rrep.route_metric = 100;
+        let len = rrep.write(&mut buf).unwrap();
+        assert_eq!(Rrep::from_message(&message(&buf[..len])), Ok(rrep));
+    }
+
+    #[test]
+    fn test_rrep_ack() {
+        let ack = RrepAck {
+            seq_num: 0x1234,
+            destination: addr(&[3]),
+        };
+        let mut buf = [0u8; 16];
+        let len = ack.write(&mut buf).unwrap();
+        assert_eq!(
+            &buf[..len],
+            &[22, 0x50, 0, 14, 1, 0x12, 0x34, 0, 0, 1, 0, 3, 0, 0][..]
+        );
+        let msg = message(&buf[..len]);
+        assert_eq!(msg.hdr.hop_limit, Some(1));
+        assert_eq!(RrepAck::from_message(&msg), Ok(ack));
+    }
+}
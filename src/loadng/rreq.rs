@@ -0,0 +1,148 @@
+// Copyright 2020 Jean Pierre Dudey. See the LICENSE-MIT and
+// LICENSE-APACHE files at the top-level directory of this
+// distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use crate::loadng::*;
+use crate::{Address, Error, Message};
+
+/// RREQ (Route Request) message, flooded to find a route to `destination`.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct Rreq {
+    /// `<msg-hop-limit>`
+    pub hop_limit: u8,
+    /// `<msg-hop-count>`
+    pub hop_count: u8,
+    /// RREQ.originator, the router searching for a route.
+    pub originator: Address,
+    /// RREQ.destination, the address a route is searched for.
+    pub destination: Address,
+    /// RREQ.seq-num, sequence number of the originator.
+    pub seq_num: u16,
+    /// RREQ.metric-type
+    pub metric_type: u8,
+    /// RREQ.route-metric, metric of the route to the originator. It's the
+    /// hop count with [`METRIC_TYPE_HOP_COUNT`].
+    ///
+    /// [`METRIC_TYPE_HOP_COUNT`]: constant.METRIC_TYPE_HOP_COUNT.html
+    pub route_metric: u32,
+}
+
+impl Rreq {
+    /// Create a RREQ with the hop count metric and a hop count of 0.
+    pub fn new(
+        hop_limit: u8,
+        originator: Address,
+        destination: Address,
+        seq_num: u16,
+    ) -> Rreq {
+        Rreq {
+            hop_limit,
+            hop_count: 0,
+            originator,
+            destination,
+            seq_num,
+            metric_type: METRIC_TYPE_HOP_COUNT,
+            route_metric: 0,
+        }
+    }
+
+    /// Read a RREQ from a message.
+    ///
+    /// Fails with
+    /// [`Error::InvalidMessage`](../enum.Error.html#variant.InvalidMessage)
+    /// if a header field is missing or there isn't exactly one address.
+    pub fn from_message(msg: &Message) -> Result<Rreq, Error> {
+        let m = RouteMsg::read(msg, MSG_TYPE_RREQ)?;
+
+        Ok(Rreq {
+            hop_limit: m.hop_limit,
+            hop_count: m.hop_count,
+            originator: m.originator,
+            destination: m.destination,
+            seq_num: m.seq_num,
+            metric_type: m.metric_type,
+            route_metric: m.route_metric,
+        })
+    }
+
+    /// Write the RREQ to `buf`, returns the size of the message.
+    pub fn write(&self, buf: &mut [u8]) -> Result<usize, Error> {
+        let m = RouteMsg {
+            hop_limit: self.hop_limit,
+            hop_count: self.hop_count,
+            originator: self.originator,
+            destination: self.destination,
+            seq_num: self.seq_num,
+            metric_type: self.metric_type,
+            route_metric: self.route_metric,
+            ack_required: false,
+        };
+        m.write(MSG_TYPE_RREQ, buf)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{Buf, MessageBuilder, MsgHeader};
+
+    fn addr(bytes: &[u8]) -> Address {
+        Address::from_bytes(bytes).unwrap()
+    }
+
+    fn read(buf: &[u8]) -> Result<Rreq, Error> {
+        Rreq::from_message(&Message::read(&mut Buf::new(buf)).unwrap())
+    }
+
+    #[test]
+    fn test_rreq() {
+        // 1-byte addresses.
+        let mut rreq = Rreq::new(16, addr(&[1]), addr(&[9]), 7);
+        let mut buf = [0u8; 32];
+        let len = rreq.write(&mut buf).unwrap();
+        assert_eq!(
+            &buf[..len],
+            &[20, 0xf0, 0, 16, 1, 16, 0, 0, 7, 0, 0, 1, 0, 9, 0, 0][..]
+        );
+        assert_eq!(read(&buf[..len]), Ok(rreq));
+
+        rreq.hop_count = 3;
+        rreq.route_metric = 3;
+        let len = rreq.write(&mut buf).unwrap();
+        assert_eq!(read(&buf[..len]), Ok(rreq));
+
+        // Another metric type.
+        rreq.metric_type = 2;
+        rreq.route_metric = 0x1234_5678;
+        let len = rreq.write(&mut buf).unwrap();
+        assert_eq!(read(&buf[..len]), Ok(rreq));
+
+        rreq.destination = addr(&[0, 9]);
+        assert_eq!(rreq.write(&mut buf), Err(Error::InvalidAddressBlock));
+
+        // Without an address.
+        let mut hdr = MsgHeader::new(MSG_TYPE_RREQ, 1);
+        hdr.orig_addr = Some(&[1]);
+        hdr.hop_limit = Some(1);
+        hdr.hop_count = Some(0);
+        hdr.seq_num = Some(1);
+        let len = MessageBuilder::new(&mut buf, &hdr)
+            .unwrap()
+            .finish()
+            .unwrap();
+        assert_eq!(read(&buf[..len]), Err(Error::InvalidMessage));
+
+        // Without a sequence number.
+        hdr.seq_num = None;
+        let mut builder = MessageBuilder::new(&mut buf, &hdr).unwrap();
+        builder.add_address_block(&[addr(&[9])], None).unwrap();
+        let len = builder.finish().unwrap();
+        assert_eq!(read(&buf[..len]), Err(Error::InvalidMessage));
+    }
+}
@@ -8,7 +8,7 @@
 // option. This file may not be copied, modified, or distributed
 // except according to those terms.
 
-use crate::{AddressTlvs, Buf, Error, TlvBlock};
+use crate::{Address, AddressTlvs, Buf, BufMut, Error, TlvBlock, MAX_ADDR_LEN};
 
 bitflags! {
     /// Message header flags.
@@ -32,6 +32,8 @@ pub struct Message<'a> {
     pub tlv_block: TlvBlock<'a>,
     /// Address block/TLV block iterator
     pub address_tlv: AddressTlvs<'a>,
+    /// The whole `<message>`
+    bytes: &'a [u8],
 }
 
 impl<'a> Message<'a> {
@@ -43,23 +45,32 @@ impl<'a> Message<'a> {
         let msg_tlv_block = TlvBlock::read(buf)?;
 
         let count = buf.pos() - initial_offset;
-        let restant_bytes = hdr.size - count;
+        let restant_bytes =
+            hdr.size.checked_sub(count).ok_or(Error::InvalidMessage)?;
 
         let address_tlv = AddressTlvs {
             address_length: hdr.address_length,
             buf: Buf::new(buf.get_bytes(restant_bytes)?),
         };
 
+        let bytes = &buf.buf[initial_offset..buf.pos()];
+
         Ok(Message {
             hdr,
             tlv_block: msg_tlv_block,
             address_tlv,
+            bytes,
         })
     }
+
+    /// Get the bytes of the whole message, including the header.
+    pub fn as_bytes(&self) -> &'a [u8] {
+        self.bytes
+    }
 }
 
 /// Message header.
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct MsgHeader<'a> {
     /// Message type.
     pub r#type: u8,
@@ -78,6 +89,42 @@ pub struct MsgHeader<'a> {
 }
 
 impl<'a> MsgHeader<'a> {
+    /// Create a message header without any of the optional fields.
+    pub fn new(r#type: u8, address_length: usize) -> MsgHeader<'a> {
+        MsgHeader {
+            r#type,
+            address_length,
+            size: 0,
+            orig_addr: None,
+            hop_limit: None,
+            hop_count: None,
+            seq_num: None,
+        }
+    }
+
+    /// Total size in bytes of the `<message>` including `<msg-header>`.
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// Size in bytes of the `<msg-header>`.
+    pub fn header_len(&self) -> usize {
+        let mut len = 4;
+        if let Some(orig_addr) = self.orig_addr {
+            len += orig_addr.len();
+        }
+        if self.hop_limit.is_some() {
+            len += 1;
+        }
+        if self.hop_count.is_some() {
+            len += 1;
+        }
+        if self.seq_num.is_some() {
+            len += 2;
+        }
+        len
+    }
+
     /// Read the message header
     pub fn read(buf: &mut Buf<'a>) -> Result<MsgHeader<'a>, Error> {
         // Parse <msg-type>
@@ -140,6 +187,51 @@ impl<'a> MsgHeader<'a> {
             seq_num,
         })
     }
+
+    /// Write the message header.
+    ///
+    /// `<msg-size>` is written as is, usually the header is written by a
+    /// [`MessageBuilder`](struct.MessageBuilder.html) that fills it in.
+    pub fn write(&self, buf: &mut BufMut) -> Result<(), Error> {
+        if self.address_length == 0 || self.address_length > MAX_ADDR_LEN {
+            return Err(Error::InvalidMessage);
+        }
+
+        let mut flags = MsgHeaderFlags::empty();
+        if let Some(orig_addr) = self.orig_addr {
+            if orig_addr.len() != self.address_length {
+                return Err(Error::InvalidMessage);
+            }
+            flags |= MsgHeaderFlags::HAS_ORIG;
+        }
+        if self.hop_limit.is_some() {
+            flags |= MsgHeaderFlags::HAS_HOP_LIMIT;
+        }
+        if self.hop_count.is_some() {
+            flags |= MsgHeaderFlags::HAS_HOP_COUNT;
+        }
+        if self.seq_num.is_some() {
+            flags |= MsgHeaderFlags::HAS_SEQ_NUM;
+        }
+
+        buf.put_u8(self.r#type)?;
+        buf.put_u8(flags.bits() | (self.address_length - 1) as u8)?;
+        buf.put_ne_u16(self.size as u16)?;
+        if let Some(orig_addr) = self.orig_addr {
+            buf.put_bytes(orig_addr)?;
+        }
+        if let Some(hop_limit) = self.hop_limit {
+            buf.put_u8(hop_limit)?;
+        }
+        if let Some(hop_count) = self.hop_count {
+            buf.put_u8(hop_count)?;
+        }
+        if let Some(seq_num) = self.seq_num {
+            buf.put_ne_u16(seq_num)?;
+        }
+
+        Ok(())
+    }
 }
 
 /// Packet messages
@@ -187,3 +279,273 @@ impl<'a> Iterator for MessageIter<'a> {
         }
     }
 }
+
+/// A message in a mutable buffer, to change its fixed-size fields in place.
+///
+/// The message is parsed once to find the fields, nothing is re-serialized.
+#[derive(Debug)]
+pub struct MessageMut<'a> {
+    buf: &'a mut [u8],
+    hop_limit: Option<usize>,
+    hop_count: Option<usize>,
+    seq_num: Option<usize>,
+}
+
+impl<'a> MessageMut<'a> {
+    /// View the message at the start of `buf`, the bytes after it are
+    /// ignored.
+    pub fn new(buf: &'a mut [u8]) -> Result<MessageMut<'a>, Error> {
+        let (size, msg) = MessageMut::locate(buf)?;
+        Ok(MessageMut {
+            buf: &mut buf[..size],
+            ..msg
+        })
+    }
+
+    /// Parse the message at the start of `buf`, returns its size and the
+    /// offsets of the fields.
+    fn locate(buf: &[u8]) -> Result<(usize, MessageMut<'static>), Error> {
+        let msg = Message::read(&mut Buf::new(buf))?;
+        let hdr = &msg.hdr;
+
+        // The fields after <msg-orig-addr> are in order.
+        let mut off = 4 + hdr.orig_addr.map_or(0, |a| a.len());
+        let mut field = |present: bool, len: usize| {
+            let pos = Some(off).filter(|_| present);
+            if present {
+                off += len;
+            }
+            pos
+        };
+        let hop_limit = field(hdr.hop_limit.is_some(), 1);
+        let hop_count = field(hdr.hop_count.is_some(), 1);
+        let seq_num = field(hdr.seq_num.is_some(), 2);
+
+        Ok((
+            hdr.size,
+            MessageMut {
+                buf: &mut [],
+                hop_limit,
+                hop_count,
+                seq_num,
+            },
+        ))
+    }
+
+    /// Parse the message.
+    pub fn message(&self) -> Result<Message<'_>, Error> {
+        Message::read(&mut Buf::new(self.buf))
+    }
+
+    /// Get the bytes of the whole message.
+    pub fn as_bytes(&self) -> &[u8] {
+        self.buf
+    }
+
+    /// `<msg-hop-limit>`
+    pub fn hop_limit(&self) -> Option<u8> {
+        self.hop_limit.map(|i| self.buf[i])
+    }
+
+    /// `<msg-hop-count>`
+    pub fn hop_count(&self) -> Option<u8> {
+        self.hop_count.map(|i| self.buf[i])
+    }
+
+    /// `<msg-seq-num>`
+    pub fn seq_num(&self) -> Option<u16> {
+        self.seq_num
+            .map(|i| u16::from_be_bytes([self.buf[i], self.buf[i + 1]]))
+    }
+
+    /// Set `<msg-hop-limit>`, fails with
+    /// [`Error::InvalidMessage`](enum.Error.html#variant.InvalidMessage) if
+    /// the message doesn't have it, as for the other setters.
+    pub fn set_hop_limit(&mut self, hop_limit: u8) -> Result<(), Error> {
+        let i = self.hop_limit.ok_or(Error::InvalidMessage)?;
+        self.buf[i] = hop_limit;
+        Ok(())
+    }
+
+    /// Set `<msg-hop-count>`.
+    pub fn set_hop_count(&mut self, hop_count: u8) -> Result<(), Error> {
+        let i = self.hop_count.ok_or(Error::InvalidMessage)?;
+        self.buf[i] = hop_count;
+        Ok(())
+    }
+
+    /// Set `<msg-seq-num>`.
+    pub fn set_seq_num(&mut self, seq_num: u16) -> Result<(), Error> {
+        let i = self.seq_num.ok_or(Error::InvalidMessage)?;
+        self.buf[i..i + 2].copy_from_slice(&seq_num.to_be_bytes());
+        Ok(())
+    }
+
+    /// Value of the first message TLV with type `r#type` and type extension
+    /// `type_ext`, to be changed in place. `None` if there's no such TLV.
+    pub fn msg_tlv_value_mut(
+        &mut self,
+        r#type: u8,
+        type_ext: u8,
+    ) -> Result<Option<&mut [u8]>, Error> {
+        let mut range = None;
+        {
+            let base = &*self.buf;
+            let msg = Message::read(&mut Buf::new(base))?;
+            for tlv in msg.tlv_block.iter() {
+                let tlv = tlv?;
+                if tlv.r#type == r#type && tlv.type_ext() == type_ext {
+                    range = tlv.value.map(|v| offset(base, v));
+                    break;
+                }
+            }
+        }
+
+        Ok(range.map(move |(start, end)| &mut self.buf[start..end]))
+    }
+
+    /// Value of the first address TLV with type `r#type` and type extension
+    /// `type_ext` for `addr` (with the maximum prefix length), to be changed
+    /// in place. For a multivalue TLV only the value of `addr` is given.
+    /// `None` if there's no such TLV.
+    pub fn addr_tlv_value_mut(
+        &mut self,
+        addr: &Address,
+        r#type: u8,
+        type_ext: u8,
+    ) -> Result<Option<&mut [u8]>, Error> {
+        let mut range = None;
+        {
+            let base = &*self.buf;
+            let msg = Message::read(&mut Buf::new(base))?;
+            msg.address_tlv.for_each_tlv(addr, None, |tlv, value| {
+                let found = tlv.r#type == r#type && tlv.type_ext() == type_ext;
+                if found && range.is_none() {
+                    range = Some(offset(base, value));
+                }
+                Ok(())
+            })?;
+        }
+
+        Ok(range.map(move |(start, end)| &mut self.buf[start..end]))
+    }
+}
+
+/// Messages of a packet in a mutable buffer.
+#[derive(Debug)]
+pub struct MessagesMut<'a> {
+    buf: &'a mut [u8],
+}
+
+impl<'a> MessagesMut<'a> {
+    /// View the messages in `buf`.
+    pub fn new(buf: &'a mut [u8]) -> MessagesMut<'a> {
+        MessagesMut { buf }
+    }
+}
+
+impl<'a> Iterator for MessagesMut<'a> {
+    type Item = Result<MessageMut<'a>, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.buf.is_empty() {
+            return None;
+        }
+
+        let buf = core::mem::replace(&mut self.buf, &mut []);
+        match MessageMut::locate(buf) {
+            Ok((size, msg)) => {
+                let (buf, rest) = buf.split_at_mut(size);
+                self.buf = rest;
+                Some(Ok(MessageMut { buf, ..msg }))
+            }
+            // Stop after an error, the next message can't be found.
+            Err(e) => Some(Err(e)),
+        }
+    }
+}
+
+/// Range of `part` inside of `base`.
+fn offset(base: &[u8], part: &[u8]) -> (usize, usize) {
+    let start = part.as_ptr() as usize - base.as_ptr() as usize;
+    (start, start + part.len())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::nhdp::{
+        LinkMetric, LinkMetricFlags, ADDR_TLV_LINK_METRIC, MSG_TLV_MPR_WILLING,
+    };
+    use crate::{MessageBuilder, Tlv};
+
+    fn addr(bytes: &[u8]) -> Address {
+        Address::from_bytes(bytes).unwrap()
+    }
+
+    #[test]
+    fn test_message_mut() {
+        let orig = [10, 0, 0, 1];
+        let mut hdr = MsgHeader::new(0, 4);
+        hdr.orig_addr = Some(&orig);
+        hdr.hop_count = Some(1);
+        hdr.seq_num = Some(7);
+
+        let mut buf = [0u8; 64];
+        let mut builder = MessageBuilder::new(&mut buf, &hdr).unwrap();
+        let willingness = Tlv {
+            r#type: MSG_TLV_MPR_WILLING,
+            type_ext: None,
+            start_index: None,
+            stop_index: None,
+            value: Some(&[0x77]),
+            multi_value: false,
+        };
+        builder.add_tlv(&willingness).unwrap();
+        let addrs = [addr(&[10, 0, 0, 2]), addr(&[10, 0, 0, 3])];
+        builder.add_address_block(&addrs, None).unwrap();
+        let metric = |m| {
+            let flags = LinkMetricFlags::INCOMING_LINK;
+            LinkMetric::from_metric(0, flags, m).unwrap().value()
+        };
+        let values = [metric(10).to_be_bytes(), metric(20).to_be_bytes()];
+        builder
+            .add_address_tlvs(ADDR_TLV_LINK_METRIC, None, |i| Some(&values[i]))
+            .unwrap();
+        let size = builder.finish().unwrap();
+
+        let mut msg = MessageMut::new(&mut buf).unwrap();
+        assert_eq!(msg.as_bytes().len(), size);
+        assert_eq!(msg.hop_limit(), None);
+        assert_eq!(msg.hop_count(), Some(1));
+        assert_eq!(msg.seq_num(), Some(7));
+        assert_eq!(msg.set_hop_limit(1), Err(Error::InvalidMessage));
+        msg.set_hop_count(2).unwrap();
+        msg.set_seq_num(0x1234).unwrap();
+
+        let value = msg.msg_tlv_value_mut(MSG_TLV_MPR_WILLING, 0).unwrap();
+        value.unwrap()[0] = 0x33;
+        assert_eq!(msg.msg_tlv_value_mut(MSG_TLV_MPR_WILLING, 1), Ok(None));
+        let value = msg
+            .addr_tlv_value_mut(&addrs[1], ADDR_TLV_LINK_METRIC, 0)
+            .unwrap()
+            .unwrap();
+        assert_eq!(value, &values[1]);
+        value.copy_from_slice(&metric(30).to_be_bytes());
+
+        let parsed = msg.message().unwrap();
+        assert_eq!(parsed.hdr.hop_count, Some(2));
+        assert_eq!(parsed.hdr.seq_num, Some(0x1234));
+        let tlv = parsed.tlv_block.iter().next().unwrap().unwrap();
+        assert_eq!(tlv.value, Some(&[0x33][..]));
+        let mut found = None;
+        parsed
+            .address_tlv
+            .for_each_tlv(&addrs[1], None, |_, v| {
+                found = Some(u16::from_be_bytes([v[0], v[1]]));
+                Ok(())
+            })
+            .unwrap();
+        assert_eq!(found, Some(metric(30)));
+    }
+}
@@ -0,0 +1,716 @@
+// Copyright 2020 Jean Pierre Dudey. See the LICENSE-MIT and
+// LICENSE-APACHE files at the top-level directory of this
+// distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use crate::addrtlv::UniqueAddresses;
+use crate::nhdp::*;
+use crate::olsr::{
+    MprTypes, MAX_MPR_TYPES, MSG_TLV_MPR_TYPES, MSG_TLV_MP_OLSRV2,
+};
+use crate::smf::{Rssa, SmfType, ADDR_TLV_SMF_NBR_TYPE, MSG_TLV_SMF_TYPE};
+use crate::{
+    time, Address, AddressTlvs, Error, Message, MessageBuilder, MsgHeader,
+};
+
+/// HELLO message.
+#[derive(Debug, Clone)]
+pub struct Hello<'a> {
+    /// `<msg-addr-length>`
+    pub address_length: usize,
+    /// `<msg-orig-addr>`
+    pub orig_addr: Option<&'a [u8]>,
+    /// `<msg-seq-num>`
+    pub seq_num: Option<u16>,
+    /// VALIDITY_TIME in milliseconds.
+    pub validity_time: u64,
+    /// INTERVAL_TIME in milliseconds.
+    pub interval_time: Option<u64>,
+    /// MPR_WILLING, absent when the router doesn't run OLSRv2.
+    pub willingness: Option<Willingness>,
+    /// MPR_TYPES, absent when the router only takes part in the topology
+    /// of its first metric type.
+    pub mpr_types: Option<MprTypes>,
+    /// SMF_TYPE, absent when the router doesn't run SMF.
+    pub smf_type: Option<SmfType>,
+    /// Does the HELLO have an MP_OLSRv2 TLV?
+    pub mp_olsrv2: bool,
+    address_tlv: Option<AddressTlvs<'a>>,
+}
+
+impl<'a> Hello<'a> {
+    /// Create a HELLO without addresses.
+    pub fn new(address_length: usize, validity_time: u64) -> Hello<'a> {
+        Hello {
+            address_length,
+            orig_addr: None,
+            seq_num: None,
+            validity_time,
+            interval_time: None,
+            willingness: None,
+            mpr_types: None,
+            smf_type: None,
+            mp_olsrv2: false,
+            address_tlv: None,
+        }
+    }
+
+    /// Read a HELLO from a message.
+    ///
+    /// Messages breaking the RFC 6130 section 12.1 rules that don't depend
+    /// on the state of the router are rejected with
+    /// [`Error::InvalidMessage`](../enum.Error.html#variant.InvalidMessage):
+    ///
+    /// - A `<msg-hop-limit>` other than 1 or a `<msg-hop-count>` other
+    ///   than 0.
+    /// - Not exactly one VALIDITY_TIME TLV, or more than one INTERVAL_TIME,
+    ///   MPR_WILLING, MPR_TYPES, SMF_TYPE or MP_OLSRv2 TLV.
+    /// - An address with a LOCAL_IF TLV and a LINK_STATUS or OTHER_NEIGHB
+    ///   TLV.
+    /// - An address with different values for the same TLV type, including
+    ///   LINK_METRIC TLVs of the same metric type and direction.
+    /// - An address with a LOCAL_IF, LINK_STATUS or OTHER_NEIGHB TLV and a
+    ///   prefix length other than the maximum.
+    pub fn from_message(msg: &Message<'a>) -> Result<Hello<'a>, Error> {
+        let hdr = &msg.hdr;
+        if hdr.r#type != MSG_TYPE_HELLO
+            || hdr.hop_limit.map_or(false, |h| h != 1)
+            || hdr.hop_count.map_or(false, |h| h != 0)
+        {
+            return Err(Error::InvalidMessage);
+        }
+
+        // HELLOs are never forwarded, they're received with a hop count of 0.
+        let hop_count = hdr.hop_count.unwrap_or(0);
+        let mut validity_time = None;
+        let mut interval_time = None;
+        let mut willingness = None;
+        let mut mpr_types = None;
+        let mut smf_type = None;
+        let mut mp_olsrv2 = None;
+        for tlv in msg.tlv_block.iter() {
+            let tlv = tlv?;
+            if tlv.type_ext() != 0 {
+                continue;
+            }
+
+            let value = tlv.value.unwrap_or(&[]);
+            match tlv.r#type {
+                MSG_TLV_VALIDITY_TIME => {
+                    let t = time::decode_tlv_value(value, hop_count)?;
+                    set_once(&mut validity_time, t)?;
+                }
+                MSG_TLV_INTERVAL_TIME => {
+                    let t = time::decode_tlv_value(value, hop_count)?;
+                    set_once(&mut interval_time, t)?;
+                }
+                MSG_TLV_MPR_WILLING => {
+                    if value.len() != 1 {
+                        return Err(Error::InvalidTlvValue);
+                    }
+                    let w = Willingness::from_value(value[0]);
+                    set_once(&mut willingness, w)?;
+                }
+                MSG_TLV_MPR_TYPES => MprTypes::read(&mut mpr_types, &tlv)?,
+                MSG_TLV_SMF_TYPE => {
+                    let t = SmfType::from_value(value)?;
+                    set_once(&mut smf_type, t)?;
+                }
+                MSG_TLV_MP_OLSRV2 => set_once(&mut mp_olsrv2, ())?,
+                _ => (),
+            }
+        }
+
+        let hello = Hello {
+            address_length: hdr.address_length,
+            orig_addr: hdr.orig_addr,
+            seq_num: hdr.seq_num,
+            validity_time: validity_time.ok_or(Error::InvalidMessage)?,
+            interval_time,
+            willingness,
+            mpr_types,
+            // Unknown RSSAs are ignored.
+            smf_type: smf_type.and_then(|t| t),
+            mp_olsrv2: mp_olsrv2.is_some(),
+            address_tlv: Some(msg.address_tlv.clone()),
+        };
+        hello.check_addresses()?;
+
+        Ok(hello)
+    }
+
+    /// Addresses of the HELLO with their TLVs.
+    ///
+    /// Each address is given once, with the TLVs of every address block it
+    /// appears in.
+    pub fn addresses(&self) -> HelloAddresses<'a> {
+        HelloAddresses {
+            address_tlv: self.address_tlv.clone(),
+            unique: self.address_tlv.as_ref().map(|a| a.unique()),
+            mpr_parts: self.mpr_parts(),
+        }
+    }
+
+    /// Number of parts of an MPR value, one for each metric type of
+    /// MPR_TYPES.
+    fn mpr_parts(&self) -> usize {
+        self.mpr_types.map_or(1, |m| m.len().max(1))
+    }
+
+    /// Write the HELLO with the addresses `addrs` to `buf`, returns the size
+    /// of the message.
+    ///
+    /// The message doesn't have `<msg-hop-limit>` nor `<msg-hop-count>`,
+    /// and the TLVs are written in the most compact form. Addresses sharing
+    /// a head or a tail compress better when they're next to each other.
+    ///
+    /// The MPR TLVs have a value for each metric type of MPR_TYPES, the
+    /// values of an address past them are left out.
+    pub fn write(
+        &self,
+        addrs: &[HelloAddress],
+        buf: &mut [u8],
+    ) -> Result<usize, Error> {
+        for (i, a) in addrs.iter().enumerate() {
+            if a.local_if.is_some()
+                && (a.link_status.is_some() || a.other_neighb.is_some())
+            {
+                return Err(Error::InvalidMessage);
+            }
+            if addrs[..i].iter().any(|b| b.addr == a.addr) {
+                return Err(Error::InvalidMessage);
+            }
+        }
+
+        let mut hdr = MsgHeader::new(MSG_TYPE_HELLO, self.address_length);
+        hdr.orig_addr = self.orig_addr;
+        hdr.seq_num = self.seq_num;
+
+        let mut builder = MessageBuilder::new(buf, &hdr)?;
+        let validity_time = [time::encode(self.validity_time)];
+        builder.add_tlv(&msg_tlv(MSG_TLV_VALIDITY_TIME, &validity_time))?;
+        if let Some(interval_time) = self.interval_time {
+            let interval_time = [time::encode(interval_time)];
+            builder.add_tlv(&msg_tlv(MSG_TLV_INTERVAL_TIME, &interval_time))?;
+        }
+        if let Some(willingness) = self.willingness {
+            let willingness = [willingness.value()];
+            builder.add_tlv(&msg_tlv(MSG_TLV_MPR_WILLING, &willingness))?;
+        }
+        if let Some(tlv) = self.mpr_types.as_ref().and_then(MprTypes::tlv) {
+            builder.add_tlv(&tlv)?;
+        }
+        if let Some(smf_type) = self.smf_type {
+            let mut value = [0; 2];
+            let value = smf_type.value(&mut value);
+            builder.add_tlv(&msg_tlv(MSG_TLV_SMF_TYPE, value))?;
+        }
+        if self.mp_olsrv2 {
+            builder.add_tlv(&msg_tlv(MSG_TLV_MP_OLSRV2, &[]))?;
+        }
+
+        for block in addrs.chunks(usize::from(core::u8::MAX)) {
+            builder.add_address_block(block, None)?;
+            write_address_tlvs(&mut builder, block, self.mpr_parts())?;
+        }
+
+        builder.finish()
+    }
+
+    /// Check the rules on the address TLVs.
+    fn check_addresses(&self) -> Result<(), Error> {
+        let address_tlv = match self.address_tlv {
+            Some(ref a) => a,
+            None => return Ok(()),
+        };
+
+        for (addr, prefix_length) in address_tlv.unique() {
+            if prefix_length.is_some() {
+                address_tlv.for_each_tlv(&addr, prefix_length, |tlv, _| {
+                    let is_nhdp = tlv.type_ext() == 0
+                        && (tlv.r#type == ADDR_TLV_LOCAL_IF
+                            || tlv.r#type == ADDR_TLV_LINK_STATUS
+                            || tlv.r#type == ADDR_TLV_OTHER_NEIGHB);
+                    if is_nhdp {
+                        return Err(Error::InvalidMessage);
+                    }
+                    Ok(())
+                })?;
+                continue;
+            }
+
+            let a = collect(address_tlv, addr, self.mpr_parts())?;
+            if a.local_if.is_some()
+                && (a.link_status.is_some() || a.other_neighb.is_some())
+            {
+                return Err(Error::InvalidMessage);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// An address of a HELLO and its TLVs.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct HelloAddress {
+    /// The address.
+    pub addr: Address,
+    /// LOCAL_IF
+    pub local_if: Option<LocalIf>,
+    /// LINK_STATUS
+    pub link_status: Option<LinkStatus>,
+    /// OTHER_NEIGHB
+    pub other_neighb: Option<OtherNeighb>,
+    /// MPR, empty if absent. With MPR_TYPES, the value for the first metric
+    /// type, see [`mpr_for`](#method.mpr_for) for the others.
+    pub mpr: Mpr,
+    /// SMF_NBR_TYPE, the RSSA of the neighbor.
+    pub smf_nbr_type: Option<Rssa>,
+    link_metrics: LinkMetrics,
+    more_mprs: [Mpr; MAX_MPR_TYPES - 1],
+}
+
+impl HelloAddress {
+    /// Create an address without TLVs.
+    pub fn new(addr: Address) -> HelloAddress {
+        HelloAddress {
+            addr,
+            local_if: None,
+            link_status: None,
+            other_neighb: None,
+            mpr: Mpr::empty(),
+            smf_nbr_type: None,
+            link_metrics: LinkMetrics::default(),
+            more_mprs: [Mpr::empty(); MAX_MPR_TYPES - 1],
+        }
+    }
+
+    /// MPR value for the metric type at `index` in the MPR_TYPES of the
+    /// HELLO, [`mpr`](#structfield.mpr) for the first one. Empty if absent.
+    pub fn mpr_for(&self, index: usize) -> Mpr {
+        match index {
+            0 => self.mpr,
+            i => self.more_mprs.get(i - 1).cloned().unwrap_or(Mpr::empty()),
+        }
+    }
+
+    /// Set the MPR value for the metric type at `index` in the MPR_TYPES of
+    /// the HELLO.
+    ///
+    /// Fails with [`Error::BufferTooSmall`] if `index` isn't below
+    /// [`MAX_MPR_TYPES`](../olsr/constant.MAX_MPR_TYPES.html).
+    ///
+    /// [`Error::BufferTooSmall`]: ../enum.Error.html#variant.BufferTooSmall
+    pub fn set_mpr_for(&mut self, index: usize, mpr: Mpr) -> Result<(), Error> {
+        match index {
+            0 => self.mpr = mpr,
+            i => {
+                let m = self
+                    .more_mprs
+                    .get_mut(i - 1)
+                    .ok_or(Error::BufferTooSmall)?;
+                *m = mpr;
+            }
+        }
+        Ok(())
+    }
+
+    /// LINK_METRIC TLVs of the address.
+    pub fn link_metrics(&self) -> impl Iterator<Item = LinkMetric> + '_ {
+        self.link_metrics.iter()
+    }
+
+    /// Add a LINK_METRIC TLV.
+    ///
+    /// Returns [`Error::InvalidTlvValue`] if another metric of the same type
+    /// has a different value for one of the directions, and
+    /// [`Error::BufferTooSmall`] if there are already
+    /// [`MAX_LINK_METRICS`](constant.MAX_LINK_METRICS.html) metrics.
+    ///
+    /// [`Error::InvalidTlvValue`]: ../enum.Error.html#variant.InvalidTlvValue
+    /// [`Error::BufferTooSmall`]: ../enum.Error.html#variant.BufferTooSmall
+    pub fn add_link_metric(&mut self, metric: LinkMetric) -> Result<(), Error> {
+        self.link_metrics.add(metric)
+    }
+
+    /// Metric for the direction `flags` of type `metric_type`.
+    pub fn link_metric(
+        &self,
+        metric_type: u8,
+        flags: LinkMetricFlags,
+    ) -> Option<u16> {
+        self.link_metrics.get(metric_type, flags)
+    }
+}
+
+impl AsRef<Address> for HelloAddress {
+    fn as_ref(&self) -> &Address {
+        &self.addr
+    }
+}
+
+/// Iterator over the addresses of a HELLO.
+#[derive(Debug)]
+pub struct HelloAddresses<'a> {
+    address_tlv: Option<AddressTlvs<'a>>,
+    unique: Option<UniqueAddresses<'a>>,
+    mpr_parts: usize,
+}
+
+impl<'a> Iterator for HelloAddresses<'a> {
+    type Item = HelloAddress;
+
+    fn next(&mut self) -> Option<HelloAddress> {
+        let address_tlv = self.address_tlv.as_ref()?;
+        loop {
+            // Addresses without the maximum prefix length can't have NHDP
+            // TLVs.
+            let (addr, prefix_length) = self.unique.as_mut()?.next()?;
+            if prefix_length.is_some() {
+                continue;
+            }
+            if let Ok(a) = collect(address_tlv, addr, self.mpr_parts) {
+                return Some(a);
+            }
+        }
+    }
+}
+
+/// Merge the TLVs of every appearance of `addr` with the maximum prefix
+/// length, the MPR values have `mpr_parts` parts.
+fn collect(
+    address_tlv: &AddressTlvs,
+    addr: Address,
+    mpr_parts: usize,
+) -> Result<HelloAddress, Error> {
+    let mut a = HelloAddress::new(addr);
+    let mut local_if = None;
+    let mut link_status = None;
+    let mut other_neighb = None;
+    let mut mpr = None;
+    let mut smf_nbr_type = None;
+
+    address_tlv.for_each_tlv(&addr, None, |tlv, value| {
+        match (tlv.r#type, tlv.type_ext()) {
+            (ADDR_TLV_LOCAL_IF, 0) => set_value(&mut local_if, value),
+            (ADDR_TLV_LINK_STATUS, 0) => set_value(&mut link_status, value),
+            (ADDR_TLV_OTHER_NEIGHB, 0) => set_value(&mut other_neighb, value),
+            (ADDR_TLV_MPR, 0) => set_mpr(&mut mpr, value, mpr_parts),
+            (ADDR_TLV_SMF_NBR_TYPE, 0) => set_value(&mut smf_nbr_type, value),
+            (ADDR_TLV_LINK_METRIC, _) => a.link_metrics.read(tlv, value),
+            _ => Ok(()),
+        }
+    })?;
+
+    // Unknown values are ignored.
+    a.local_if = local_if.and_then(LocalIf::from_value);
+    a.link_status = link_status.and_then(LinkStatus::from_value);
+    a.other_neighb = other_neighb.and_then(OtherNeighb::from_value);
+    if let Some(mpr) = mpr {
+        for (i, m) in mpr[..mpr_parts].iter().enumerate() {
+            a.set_mpr_for(i, Mpr::from_bits_truncate(*m))?;
+        }
+    }
+    a.smf_nbr_type = smf_nbr_type.and_then(Rssa::from_value);
+    Ok(a)
+}
+
+/// Set `slot` to the MPR value `value` with `parts` parts, fails if it was
+/// already set to a different value.
+fn set_mpr(
+    slot: &mut Option<[u8; MAX_MPR_TYPES]>,
+    value: &[u8],
+    parts: usize,
+) -> Result<(), Error> {
+    if value.len() != parts {
+        return Err(Error::InvalidTlvValue);
+    }
+
+    let mut mpr = [0; MAX_MPR_TYPES];
+    mpr[..parts].copy_from_slice(value);
+    match *slot {
+        Some(m) if m != mpr => Err(Error::InvalidMessage),
+        _ => {
+            *slot = Some(mpr);
+            Ok(())
+        }
+    }
+}
+
+/// Write the TLVs of the last address block, with the addresses `block`
+/// and MPR values of `mpr_parts` parts.
+fn write_address_tlvs(
+    builder: &mut MessageBuilder,
+    block: &[HelloAddress],
+    mpr_parts: usize,
+) -> Result<(), Error> {
+    builder.add_address_tlvs_u8(ADDR_TLV_LOCAL_IF, None, |i| {
+        block[i].local_if.map(LocalIf::value)
+    })?;
+    builder.add_address_tlvs_u8(ADDR_TLV_LINK_STATUS, None, |i| {
+        block[i].link_status.map(LinkStatus::value)
+    })?;
+    builder.add_address_tlvs_u8(ADDR_TLV_OTHER_NEIGHB, None, |i| {
+        block[i].other_neighb.map(OtherNeighb::value)
+    })?;
+    let mut mprs = [0u8; MAX_MPR_TYPES * core::u8::MAX as usize];
+    for (i, a) in block.iter().enumerate() {
+        for j in 0..mpr_parts {
+            mprs[mpr_parts * i + j] = a.mpr_for(j).bits();
+        }
+    }
+    let mprs = &mprs;
+    builder.add_address_tlvs(ADDR_TLV_MPR, None, |i| {
+        Some(&mprs[mpr_parts * i..mpr_parts * (i + 1)])
+            .filter(|m| m.iter().any(|b| *b != 0))
+    })?;
+    builder.add_address_tlvs_u8(ADDR_TLV_SMF_NBR_TYPE, None, |i| {
+        block[i].smf_nbr_type.map(Rssa::value)
+    })?;
+    LinkMetrics::write(builder, block.len(), |i| block[i].link_metrics)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::Buf;
+
+    fn addr(bytes: &[u8]) -> Address {
+        Address::from_bytes(bytes).unwrap()
+    }
+
+    fn read(bin: &[u8]) -> Result<Hello<'_>, Error> {
+        let msg = Message::read(&mut Buf::new(bin)).unwrap();
+        Hello::from_message(&msg)
+    }
+
+    #[test]
+    fn test_hello_roundtrip() {
+        let orig = [10, 0, 0, 1];
+        let mut hello = Hello::new(4, 6000);
+        hello.orig_addr = Some(&orig);
+        hello.seq_num = Some(7);
+        hello.interval_time = Some(2000);
+        hello.willingness = Some(Willingness {
+            flooding: WILL_DEFAULT,
+            routing: WILL_ALWAYS,
+        });
+        hello.mpr_types = Some(MprTypes::from_types(&[1, 0]).unwrap());
+        hello.mp_olsrv2 = true;
+
+        let mut addrs = [HelloAddress::new(addr(&[10, 0, 0, 1])); 5];
+        addrs[0].local_if = Some(LocalIf::ThisIf);
+        addrs[1].addr = addr(&[10, 0, 1, 1]);
+        addrs[1].local_if = Some(LocalIf::OtherIf);
+        addrs[2].addr = addr(&[10, 0, 0, 2]);
+        addrs[2].link_status = Some(LinkStatus::Symmetric);
+        addrs[2].mpr = Mpr::FLOODING | Mpr::ROUTING;
+        addrs[2].set_mpr_for(1, Mpr::ROUTING).unwrap();
+        addrs[3].set_mpr_for(1, Mpr::FLOODING).unwrap();
+        addrs[3].addr = addr(&[10, 0, 0, 3]);
+        addrs[3].link_status = Some(LinkStatus::Heard);
+        addrs[4].addr = addr(&[10, 0, 0, 4]);
+        addrs[4].other_neighb = Some(OtherNeighb::Symmetric);
+        let metric = |flags, metric| LinkMetric {
+            metric_type: 0,
+            flags,
+            metric,
+        };
+        for (i, a) in addrs[2..].iter_mut().enumerate() {
+            let m = metric(LinkMetricFlags::INCOMING_LINK, 0x100 + i as u16);
+            a.add_link_metric(m).unwrap();
+        }
+        let m = metric(LinkMetricFlags::OUTGOING_LINK, 0x100);
+        addrs[2].add_link_metric(m).unwrap();
+        let m = metric(LinkMetricFlags::OUTGOING_NEIGHBOR, 0x200);
+        addrs[4].add_link_metric(m).unwrap();
+
+        let mut buf = [0u8; 128];
+        let size = hello.write(&addrs, &mut buf).unwrap();
+        let msg = Message::read(&mut Buf::new(&buf[..size])).unwrap();
+        assert_eq!(msg.hdr.size(), size);
+        assert!(msg.hdr.hop_limit.is_none());
+        assert!(msg.hdr.hop_count.is_none());
+
+        let parsed = Hello::from_message(&msg).unwrap();
+        assert_eq!(parsed.orig_addr, Some(&orig[..]));
+        assert_eq!(parsed.seq_num, Some(7));
+        assert!(parsed.validity_time >= 6000);
+        assert_eq!(parsed.interval_time, Some(2000));
+        assert_eq!(parsed.willingness, hello.willingness);
+        assert_eq!(parsed.mpr_types, hello.mpr_types);
+        assert!(parsed.mp_olsrv2);
+
+        let mut n = 0;
+        for (a, b) in parsed.addresses().zip(addrs.iter()) {
+            assert_eq!(&a, b);
+            n += 1;
+        }
+        assert_eq!(n, addrs.len());
+        let a2 = parsed.addresses().nth(2).unwrap();
+        assert_eq!(
+            a2.link_metric(0, LinkMetricFlags::OUTGOING_LINK),
+            Some(0x100)
+        );
+        assert_eq!(
+            a2.link_metric(0, LinkMetricFlags::INCOMING_LINK),
+            Some(0x100)
+        );
+        let a3 = parsed.addresses().nth(3).unwrap();
+        assert_eq!(a3.mpr_for(0), Mpr::empty());
+        assert_eq!(a3.mpr_for(1), Mpr::FLOODING);
+        assert_eq!(a3.mpr_for(MAX_MPR_TYPES), Mpr::empty());
+        assert_eq!(
+            addrs[3].set_mpr_for(MAX_MPR_TYPES, Mpr::FLOODING),
+            Err(Error::BufferTooSmall)
+        );
+
+        // Without MPR_TYPES the MPR values have a single part.
+        hello.mpr_types = None;
+        let size = hello.write(&addrs, &mut buf).unwrap();
+        let parsed = read(&buf[..size]).unwrap();
+        let mut a = parsed.addresses().skip(2);
+        let (a2, a3) = (a.next().unwrap(), a.next().unwrap());
+        assert_eq!(a2.mpr, Mpr::FLOODING | Mpr::ROUTING);
+        assert_eq!(a2.mpr_for(1), Mpr::empty());
+        assert_eq!(a3.mpr, Mpr::empty());
+
+        // With MPR_TYPES an MPR value with a single part is rejected.
+        let hdr = MsgHeader::new(MSG_TYPE_HELLO, 4);
+        let mpr_types = MprTypes::from_types(&[1, 0]).unwrap();
+        let mut builder = MessageBuilder::new(&mut buf, &hdr).unwrap();
+        builder
+            .add_tlv(&msg_tlv(MSG_TLV_VALIDITY_TIME, &[time::encode(6000)]))
+            .unwrap();
+        builder.add_tlv(&mpr_types.tlv().unwrap()).unwrap();
+        builder.add_address_block(&addrs[2..3], None).unwrap();
+        builder
+            .add_address_tlvs_u8(ADDR_TLV_MPR, None, |_| Some(1))
+            .unwrap();
+        let size = builder.finish().unwrap();
+        assert_eq!(read(&buf[..size]).err(), Some(Error::InvalidTlvValue));
+
+        // Duplicated addresses and LOCAL_IF with LINK_STATUS are rejected.
+        let dup = [addrs[2], addrs[2]];
+        assert_eq!(hello.write(&dup, &mut buf), Err(Error::InvalidMessage));
+        let mut bad = addrs[0];
+        bad.link_status = Some(LinkStatus::Heard);
+        assert_eq!(hello.write(&[bad], &mut buf), Err(Error::InvalidMessage));
+    }
+
+    #[test]
+    fn test_hello_smf() {
+        let mut hello = Hello::new(4, 6000);
+        hello.smf_type = Some(SmfType {
+            rssa: Rssa::ECds,
+            router_priority: Some(100),
+        });
+        let mut addrs = [HelloAddress::new(addr(&[10, 0, 0, 2])); 2];
+        addrs[0].link_status = Some(LinkStatus::Symmetric);
+        addrs[0].smf_nbr_type = Some(Rssa::ECds);
+        addrs[1].addr = addr(&[10, 0, 0, 3]);
+        addrs[1].link_status = Some(LinkStatus::Symmetric);
+        addrs[1].smf_nbr_type = Some(Rssa::Cf);
+
+        let mut buf = [0u8; 64];
+        let size = hello.write(&addrs, &mut buf).unwrap();
+        let parsed = read(&buf[..size]).unwrap();
+        assert_eq!(parsed.smf_type, hello.smf_type);
+        assert!(parsed.addresses().eq(addrs.iter().cloned()));
+
+        // Unknown RSSAs are ignored, a second SMF_TYPE is rejected.
+        let mut hdr = MsgHeader::new(MSG_TYPE_HELLO, 4);
+        hdr.hop_limit = Some(1);
+        let mut builder = MessageBuilder::new(&mut buf, &hdr).unwrap();
+        builder
+            .add_tlv(&msg_tlv(MSG_TLV_VALIDITY_TIME, &[time::encode(6000)]))
+            .unwrap();
+        builder.add_tlv(&msg_tlv(MSG_TLV_SMF_TYPE, &[200])).unwrap();
+        let size = builder.finish().unwrap();
+        assert_eq!(read(&buf[..size]).unwrap().smf_type, None);
+
+        let mut builder = MessageBuilder::new(&mut buf, &hdr).unwrap();
+        builder
+            .add_tlv(&msg_tlv(MSG_TLV_VALIDITY_TIME, &[time::encode(6000)]))
+            .unwrap();
+        builder.add_tlv(&msg_tlv(MSG_TLV_SMF_TYPE, &[0])).unwrap();
+        builder.add_tlv(&msg_tlv(MSG_TLV_SMF_TYPE, &[1])).unwrap();
+        let size = builder.finish().unwrap();
+        assert_eq!(read(&buf[..size]).err(), Some(Error::InvalidMessage));
+    }
+
+    #[test]
+    fn test_hello_merge_blocks() {
+        // The same address in two address blocks, LINK_STATUS on the first
+        // and MPR on the second.
+        const BIN: &[u8] = &[
+            0x00, 0x03, 0x00, 0x22, 0x00, 0x04, 0x01, 0x10, 0x01, 0x50, 0x01,
+            0x00, 0x0a, 0x00, 0x00, 0x02, 0x00, 0x04, 0x03, 0x10, 0x01, 0x01,
+            0x01, 0x00, 0x0a, 0x00, 0x00, 0x02, 0x00, 0x04, 0x08, 0x10, 0x01,
+            0x01,
+        ];
+        let hello = read(BIN).unwrap();
+        assert_eq!(hello.validity_time, 1000);
+        let mut addrs = hello.addresses();
+        let a = addrs.next().unwrap();
+        assert!(addrs.next().is_none());
+        assert_eq!(a.addr, addr(&[10, 0, 0, 2]));
+        assert_eq!(a.link_status, Some(LinkStatus::Symmetric));
+        assert_eq!(a.mpr, Mpr::FLOODING);
+    }
+
+    #[test]
+    fn test_hello_invalid() {
+        // Valid, VALIDITY_TIME only.
+        const VALID: &[u8] =
+            &[0x00, 0x03, 0x00, 0x0a, 0x00, 0x04, 0x01, 0x10, 0x01, 0x50];
+        assert!(read(VALID).is_ok());
+
+        // Hop limit 2.
+        const HOP_LIMIT: &[u8] = &[
+            0x00, 0x43, 0x00, 0x0b, 0x02, 0x00, 0x04, 0x01, 0x10, 0x01, 0x50,
+        ];
+        assert_eq!(read(HOP_LIMIT).err(), Some(Error::InvalidMessage));
+
+        // No VALIDITY_TIME.
+        const NO_VALIDITY: &[u8] = &[0x00, 0x03, 0x00, 0x06, 0x00, 0x00];
+        assert_eq!(read(NO_VALIDITY).err(), Some(Error::InvalidMessage));
+
+        // Two VALIDITY_TIME.
+        const TWO_VALIDITY: &[u8] = &[
+            0x00, 0x03, 0x00, 0x0e, 0x00, 0x08, 0x01, 0x10, 0x01, 0x50, 0x01,
+            0x10, 0x01, 0x50,
+        ];
+        assert_eq!(read(TWO_VALIDITY).err(), Some(Error::InvalidMessage));
+
+        // LOCAL_IF and LINK_STATUS on the same address.
+        const LOCAL_IF_LINK: &[u8] = &[
+            0x00, 0x03, 0x00, 0x1a, 0x00, 0x04, 0x01, 0x10, 0x01, 0x50, 0x01,
+            0x00, 0x0a, 0x00, 0x00, 0x02, 0x00, 0x08, 0x02, 0x10, 0x01, 0x00,
+            0x03, 0x10, 0x01, 0x01,
+        ];
+        assert_eq!(read(LOCAL_IF_LINK).err(), Some(Error::InvalidMessage));
+
+        // Two different LINK_STATUS values on the same address.
+        const CONFLICT: &[u8] = &[
+            0x00, 0x03, 0x00, 0x1a, 0x00, 0x04, 0x01, 0x10, 0x01, 0x50, 0x01,
+            0x00, 0x0a, 0x00, 0x00, 0x02, 0x00, 0x08, 0x03, 0x10, 0x01, 0x01,
+            0x03, 0x10, 0x01, 0x02,
+        ];
+        assert_eq!(read(CONFLICT).err(), Some(Error::InvalidMessage));
+
+        // LINK_STATUS on an address with a prefix length of 24.
+        const PREFIX: &[u8] = &[
+            0x00, 0x03, 0x00, 0x17, 0x00, 0x04, 0x01, 0x10, 0x01, 0x50, 0x01,
+            0x10, 0x0a, 0x00, 0x00, 0x02, 0x18, 0x00, 0x04, 0x03, 0x10, 0x01,
+            0x01,
+        ];
+        assert_eq!(read(PREFIX).err(), Some(Error::InvalidMessage));
+    }
+}
@@ -0,0 +1,100 @@
+// Copyright 2020 Jean Pierre Dudey. See the LICENSE-MIT and
+// LICENSE-APACHE files at the top-level directory of this
+// distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use crate::linkloss::LinkLoss;
+use crate::nhdp::LinkTuple;
+use crate::{Address, Clock};
+
+/// Link quality of a perfect link, qualities go from 0 to this value.
+pub const QUALITY_MAX: u16 = 1000;
+
+/// Link quality hysteresis parameters (RFC 6130 appendix B), qualities
+/// are in thousandths.
+///
+/// The quality of a link must reach `accept` for the link to be used, then
+/// it's used until the quality falls below `reject`.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct HysteresisConfig {
+    /// HYST_ACCEPT
+    pub accept: u16,
+    /// HYST_REJECT, at most `accept`.
+    pub reject: u16,
+    /// INITIAL_QUALITY, L_in_quality of a new link.
+    pub initial_quality: u16,
+    /// INITIAL_PENDING, is a new link pending until its quality reaches
+    /// `accept`?
+    pub initial_pending: bool,
+}
+
+impl Default for HysteresisConfig {
+    /// The thresholds of OLSRv1 (RFC 3626), new links are pending.
+    fn default() -> HysteresisConfig {
+        HysteresisConfig {
+            accept: 800,
+            reject: 300,
+            initial_quality: 0,
+            initial_pending: true,
+        }
+    }
+}
+
+/// A source of incoming link qualities.
+pub trait LinkQuality {
+    /// Quality of the link from the neighbor interface `addr`, from 0 to
+    /// [`QUALITY_MAX`](constant.QUALITY_MAX.html). `None` if it's unknown.
+    fn link_quality(&self, addr: &Address) -> Option<u16>;
+}
+
+impl<Q: LinkQuality + ?Sized> LinkQuality for &Q {
+    fn link_quality(&self, addr: &Address) -> Option<u16> {
+        (**self).link_quality(addr)
+    }
+}
+
+/// The ratio of packets received over the whole window.
+impl<C: Clock> LinkQuality for LinkLoss<C> {
+    fn link_quality(&self, addr: &Address) -> Option<u16> {
+        let now = self.clock().now();
+        let stats = self.tracker(addr)?.window(now);
+        let loss = stats.loss_ratio(u32::from(QUALITY_MAX))?;
+        Some(QUALITY_MAX - loss as u16)
+    }
+}
+
+/// Set the quality of the new link `link` (RFC 6130 appendix B.1).
+pub(crate) fn initialize(link: &mut LinkTuple, config: &HysteresisConfig) {
+    link.in_quality = config.initial_quality;
+    link.lost = link.in_quality < config.reject;
+    link.pending = config.initial_pending && link.in_quality < config.accept;
+}
+
+/// Apply the change of quality of `link` at `now` (RFC 6130 appendix
+/// B.2), lost links are kept for `l_hold_time`.
+pub(crate) fn update(
+    link: &mut LinkTuple,
+    now: u64,
+    config: &HysteresisConfig,
+    l_hold_time: u64,
+) {
+    let quality = link.quality();
+    if quality >= config.accept {
+        if link.pending || link.lost {
+            link.pending = false;
+            link.lost = false;
+            let time = link.heard_time.saturating_add(l_hold_time);
+            link.time = link.time.max(time);
+        }
+    } else if quality < config.reject && !link.lost {
+        link.lost = true;
+        if !link.pending {
+            link.time = link.time.max(now.saturating_add(l_hold_time));
+        }
+    }
+}
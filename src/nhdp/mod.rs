@@ -0,0 +1,414 @@
+// Copyright 2020 Jean Pierre Dudey. See the LICENSE-MIT and
+// LICENSE-APACHE files at the top-level directory of this
+// distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! RFC 6130 Neighborhood Discovery Protocol (NHDP).
+//!
+//! Includes the HELLO message TLVs added by RFC 7181 (OLSRv2). The
+//! information bases and HELLO processing ([`Nhdp`](struct.Nhdp.html))
+//! need the `use_std` feature.
+//!
+//! Link quality hysteresis (RFC 6130 appendix B) is enabled with
+//! [`NhdpConfig::hysteresis`](struct.NhdpConfig.html#structfield.hysteresis),
+//! the qualities are given by a [`LinkQuality`](trait.LinkQuality.html)
+//! source or set one link at a time.
+
+use crate::{Error, MessageBuilder, Tlv};
+
+mod hello;
+#[cfg(feature = "use_std")]
+mod hysteresis;
+#[cfg(feature = "use_std")]
+mod state;
+
+pub use self::hello::{Hello, HelloAddress, HelloAddresses};
+#[cfg(feature = "use_std")]
+pub use self::hysteresis::{HysteresisConfig, LinkQuality, QUALITY_MAX};
+#[cfg(feature = "use_std")]
+pub use self::state::{
+    Event, InterfaceId, LinkTuple, LostNeighborTuple, NeighborId,
+    NeighborTuple, Nhdp, NhdpConfig, TwoHopTuple,
+};
+
+/// Maximum number of link metrics of an address.
+pub const MAX_LINK_METRICS: usize = 4;
+
+/// HELLO message type.
+pub const MSG_TYPE_HELLO: u8 = 0;
+
+/// INTERVAL_TIME message TLV type.
+pub const MSG_TLV_INTERVAL_TIME: u8 = crate::time::INTERVAL_TIME;
+/// VALIDITY_TIME message TLV type.
+pub const MSG_TLV_VALIDITY_TIME: u8 = crate::time::VALIDITY_TIME;
+/// MPR_WILLING message TLV type (RFC 7181).
+pub const MSG_TLV_MPR_WILLING: u8 = 7;
+
+/// LOCAL_IF address TLV type.
+pub const ADDR_TLV_LOCAL_IF: u8 = 2;
+/// LINK_STATUS address TLV type.
+pub const ADDR_TLV_LINK_STATUS: u8 = 3;
+/// OTHER_NEIGHB address TLV type.
+pub const ADDR_TLV_OTHER_NEIGHB: u8 = 4;
+/// LINK_METRIC address TLV type (RFC 7181).
+pub const ADDR_TLV_LINK_METRIC: u8 = 7;
+/// MPR address TLV type (RFC 7181).
+pub const ADDR_TLV_MPR: u8 = 8;
+
+/// Willingness of a router that must never be selected as MPR.
+pub const WILL_NEVER: u8 = 0;
+/// Default willingness.
+pub const WILL_DEFAULT: u8 = 7;
+/// Willingness of a router that must always be selected as MPR.
+pub const WILL_ALWAYS: u8 = 15;
+
+/// Value of a LOCAL_IF address TLV.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum LocalIf {
+    /// The address belongs to the interface the HELLO was sent on.
+    ThisIf,
+    /// The address belongs to another interface of the router.
+    OtherIf,
+}
+
+impl LocalIf {
+    /// Value of the TLV.
+    pub fn value(self) -> u8 {
+        match self {
+            LocalIf::ThisIf => 0,
+            LocalIf::OtherIf => 1,
+        }
+    }
+
+    /// Read the value of the TLV, `None` if it's unknown.
+    pub fn from_value(value: u8) -> Option<LocalIf> {
+        match value {
+            0 => Some(LocalIf::ThisIf),
+            1 => Some(LocalIf::OtherIf),
+            _ => None,
+        }
+    }
+}
+
+/// Value of a LINK_STATUS address TLV.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum LinkStatus {
+    /// The link was lost.
+    Lost,
+    /// The link is symmetric.
+    Symmetric,
+    /// The link is heard.
+    Heard,
+}
+
+impl LinkStatus {
+    /// Value of the TLV.
+    pub fn value(self) -> u8 {
+        match self {
+            LinkStatus::Lost => 0,
+            LinkStatus::Symmetric => 1,
+            LinkStatus::Heard => 2,
+        }
+    }
+
+    /// Read the value of the TLV, `None` if it's unknown.
+    pub fn from_value(value: u8) -> Option<LinkStatus> {
+        match value {
+            0 => Some(LinkStatus::Lost),
+            1 => Some(LinkStatus::Symmetric),
+            2 => Some(LinkStatus::Heard),
+            _ => None,
+        }
+    }
+}
+
+/// Value of an OTHER_NEIGHB address TLV.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum OtherNeighb {
+    /// The neighbor was lost.
+    Lost,
+    /// The neighbor is symmetric.
+    Symmetric,
+}
+
+impl OtherNeighb {
+    /// Value of the TLV.
+    pub fn value(self) -> u8 {
+        match self {
+            OtherNeighb::Lost => 0,
+            OtherNeighb::Symmetric => 1,
+        }
+    }
+
+    /// Read the value of the TLV, `None` if it's unknown.
+    pub fn from_value(value: u8) -> Option<OtherNeighb> {
+        match value {
+            0 => Some(OtherNeighb::Lost),
+            1 => Some(OtherNeighb::Symmetric),
+            _ => None,
+        }
+    }
+}
+
+bitflags! {
+    /// Value of an MPR address TLV.
+    pub struct Mpr: u8 {
+        /// Selected as flooding MPR.
+        const FLOODING = 1;
+        /// Selected as routing MPR.
+        const ROUTING = 2;
+    }
+}
+
+/// Value of an MPR_WILLING message TLV.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct Willingness {
+    /// Willingness to be a flooding MPR.
+    pub flooding: u8,
+    /// Willingness to be a routing MPR.
+    pub routing: u8,
+}
+
+impl Willingness {
+    /// Value of the TLV, the willingness values are truncated to 4 bits.
+    pub fn value(self) -> u8 {
+        (self.flooding & 0x0f) << 4 | (self.routing & 0x0f)
+    }
+
+    /// Read the value of the TLV.
+    pub fn from_value(value: u8) -> Willingness {
+        Willingness {
+            flooding: value >> 4,
+            routing: value & 0x0f,
+        }
+    }
+}
+
+impl Default for Willingness {
+    fn default() -> Willingness {
+        Willingness {
+            flooding: WILL_DEFAULT,
+            routing: WILL_DEFAULT,
+        }
+    }
+}
+
+bitflags! {
+    /// Direction flags of a LINK_METRIC address TLV.
+    pub struct LinkMetricFlags: u8 {
+        /// Metric of the link from the neighbor.
+        const INCOMING_LINK = 0x08;
+        /// Metric of the link to the neighbor.
+        const OUTGOING_LINK = 0x04;
+        /// Metric of the best link from the neighbor.
+        const INCOMING_NEIGHBOR = 0x02;
+        /// Metric of the best link to the neighbor.
+        const OUTGOING_NEIGHBOR = 0x01;
+    }
+}
+
+/// Value of a LINK_METRIC address TLV.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct LinkMetric {
+    /// Metric type, the `<tlv-type-ext>` of the TLV.
+    pub metric_type: u8,
+    /// Direction flags, the 4 high bits of the value.
+    pub flags: LinkMetricFlags,
+    /// Compressed metric, the 12 low bits of the value.
+    pub metric: u16,
+}
+
+impl LinkMetric {
+    /// Value of the TLV.
+    pub fn value(self) -> u16 {
+        u16::from(self.flags.bits()) << 12 | (self.metric & 0x0fff)
+    }
+
+    /// Read the value of the TLV.
+    pub fn from_value(metric_type: u8, value: u16) -> LinkMetric {
+        LinkMetric {
+            metric_type,
+            flags: LinkMetricFlags::from_bits_truncate((value >> 12) as u8),
+            metric: value & 0x0fff,
+        }
+    }
+
+    /// Create a link metric from an uncompressed `metric`, rounded up.
+    /// `None` if the metric can't be represented, see
+    /// [`olsr::metric::encode`](../olsr/metric/fn.encode.html).
+    pub fn from_metric(
+        metric_type: u8,
+        flags: LinkMetricFlags,
+        metric: u32,
+    ) -> Option<LinkMetric> {
+        crate::olsr::metric::encode(metric).map(|metric| LinkMetric {
+            metric_type,
+            flags,
+            metric,
+        })
+    }
+
+    /// Uncompressed metric.
+    pub fn to_metric(self) -> u32 {
+        crate::olsr::metric::decode(self.metric)
+    }
+}
+
+/// LINK_METRIC TLVs of an address, with the directions of the same metric
+/// type and value merged.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Default)]
+pub(crate) struct LinkMetrics([Option<LinkMetric>; MAX_LINK_METRICS]);
+
+impl LinkMetrics {
+    pub(crate) fn iter(&self) -> impl Iterator<Item = LinkMetric> + '_ {
+        self.0.iter().filter_map(|m| *m)
+    }
+
+    /// Add a metric, fails with `InvalidTlvValue` if it conflicts with
+    /// another one and `BufferTooSmall` if the set is full.
+    pub(crate) fn add(&mut self, metric: LinkMetric) -> Result<(), Error> {
+        for m in self.iter() {
+            if m.metric_type == metric.metric_type
+                && m.metric != metric.metric
+                && m.flags.intersects(metric.flags)
+            {
+                return Err(Error::InvalidTlvValue);
+            }
+        }
+
+        // Merge the directions with the same metric.
+        let same = self.0.iter_mut().flatten().find(|m| {
+            m.metric_type == metric.metric_type && m.metric == metric.metric
+        });
+        if let Some(m) = same {
+            m.flags |= metric.flags;
+            return Ok(());
+        }
+
+        match self.0.iter_mut().find(|m| m.is_none()) {
+            Some(m) => {
+                *m = Some(metric);
+                Ok(())
+            }
+            None => Err(Error::BufferTooSmall),
+        }
+    }
+
+    pub(crate) fn get(
+        &self,
+        metric_type: u8,
+        flags: LinkMetricFlags,
+    ) -> Option<u16> {
+        self.iter()
+            .find(|m| m.metric_type == metric_type && m.flags.contains(flags))
+            .map(|m| m.metric)
+    }
+
+    /// Add the value of a LINK_METRIC TLV read from a message, conflicts
+    /// make the message invalid and metrics beyond `MAX_LINK_METRICS` are
+    /// ignored.
+    pub(crate) fn read(
+        &mut self,
+        tlv: &Tlv,
+        value: &[u8],
+    ) -> Result<(), Error> {
+        if value.len() != 2 {
+            return Err(Error::InvalidTlvValue);
+        }
+
+        let value = u16::from_be_bytes([value[0], value[1]]);
+        let m = LinkMetric::from_value(tlv.type_ext(), value);
+        match self.add(m) {
+            Err(Error::InvalidTlvValue) => Err(Error::InvalidMessage),
+            _ => Ok(()),
+        }
+    }
+
+    /// Write the LINK_METRIC TLVs of the last address block, with the
+    /// metrics `metrics(i)` for address `i`. A TLV is written for each
+    /// metric type and set of directions.
+    pub(crate) fn write<F>(
+        builder: &mut MessageBuilder,
+        num_addr: usize,
+        metrics: F,
+    ) -> Result<(), Error>
+    where
+        F: Fn(usize) -> LinkMetrics,
+    {
+        let mut values = [0u8; 2 * core::u8::MAX as usize];
+        for i in 0..num_addr {
+            for m in metrics(i).iter() {
+                let same = |x: &LinkMetric| {
+                    x.metric_type == m.metric_type && x.flags == m.flags
+                };
+                let find = |j| metrics(j).iter().find(|x| same(x));
+                if (0..i).any(|j| find(j).is_some()) {
+                    continue;
+                }
+
+                for j in i..num_addr {
+                    if let Some(x) = find(j) {
+                        values[2 * j..2 * j + 2]
+                            .copy_from_slice(&x.value().to_be_bytes());
+                    }
+                }
+
+                let type_ext = Some(m.metric_type).filter(|t| *t != 0);
+                let values = &values;
+                builder.add_address_tlvs(
+                    ADDR_TLV_LINK_METRIC,
+                    type_ext,
+                    |j| find(j).map(|_| &values[2 * j..2 * j + 2]),
+                )?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Set `slot` to `value`, fails if it was already set.
+pub(crate) fn set_once<T>(slot: &mut Option<T>, value: T) -> Result<(), Error> {
+    if slot.is_some() {
+        return Err(Error::InvalidMessage);
+    }
+
+    *slot = Some(value);
+    Ok(())
+}
+
+/// Set `slot` to the single byte `value`, fails if it was already set to a
+/// different value.
+pub(crate) fn set_value(
+    slot: &mut Option<u8>,
+    value: &[u8],
+) -> Result<(), Error> {
+    if value.len() != 1 {
+        return Err(Error::InvalidTlvValue);
+    }
+
+    match *slot {
+        Some(v) if v != value[0] => Err(Error::InvalidMessage),
+        _ => {
+            *slot = Some(value[0]);
+            Ok(())
+        }
+    }
+}
+
+/// Message TLV with a value.
+pub(crate) fn msg_tlv<'v>(r#type: u8, value: &'v [u8]) -> Tlv<'v> {
+    Tlv {
+        r#type,
+        type_ext: None,
+        start_index: None,
+        stop_index: None,
+        value: Some(value),
+        multi_value: false,
+    }
+}
@@ -0,0 +1,1133 @@
+// Copyright 2020 Jean Pierre Dudey. See the LICENSE-MIT and
+// LICENSE-APACHE files at the top-level directory of this
+// distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use std::collections::{BTreeMap, VecDeque};
+use std::iter;
+use std::vec::Vec;
+
+use crate::nhdp::hysteresis::{self, HysteresisConfig, LinkQuality};
+use crate::nhdp::{Hello, HelloAddress, LinkStatus, LocalIf, OtherNeighb};
+use crate::{Address, Clock, Error};
+
+/// NHDP parameters, times are in milliseconds.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct NhdpConfig {
+    /// HELLO_INTERVAL, advertised as INTERVAL_TIME.
+    pub hello_interval: u64,
+    /// H_HOLD_TIME, advertised as VALIDITY_TIME.
+    pub h_hold_time: u64,
+    /// L_HOLD_TIME, how long a lost link is kept.
+    pub l_hold_time: u64,
+    /// N_HOLD_TIME, how long a lost neighbor is advertised.
+    pub n_hold_time: u64,
+    /// I_HOLD_TIME, how long a removed interface address is remembered.
+    pub i_hold_time: u64,
+    /// Link quality hysteresis, `None` to use every link.
+    pub hysteresis: Option<HysteresisConfig>,
+}
+
+impl Default for NhdpConfig {
+    fn default() -> NhdpConfig {
+        NhdpConfig {
+            hello_interval: 2_000,
+            h_hold_time: 6_000,
+            l_hold_time: 6_000,
+            n_hold_time: 6_000,
+            i_hold_time: 6_000,
+            hysteresis: None,
+        }
+    }
+}
+
+/// Identifier of a local interface.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub struct InterfaceId(u32);
+
+/// Identifier of a Neighbor Tuple.
+///
+/// It stays the same while the neighbor changes its addresses, when two
+/// tuples are merged the lowest identifier is kept.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub struct NeighborId(u32);
+
+/// Change in the neighborhood.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Event {
+    /// The neighbor became symmetric.
+    SymmetricNeighbor(NeighborId),
+    /// The neighbor is no longer symmetric, it may not exist anymore.
+    LostNeighbor(NeighborId),
+    /// A symmetric 2-hop neighbor was added.
+    TwoHopAdded {
+        /// Interface it was heard on.
+        interface: InterfaceId,
+        /// 1-hop neighbor advertising it.
+        neighbor: NeighborId,
+        /// Address of the 2-hop neighbor.
+        addr: Address,
+    },
+    /// A symmetric 2-hop neighbor was removed.
+    TwoHopRemoved {
+        /// Interface it was heard on.
+        interface: InterfaceId,
+        /// 1-hop neighbor that advertised it.
+        neighbor: NeighborId,
+        /// Address of the 2-hop neighbor.
+        addr: Address,
+    },
+}
+
+/// Link Tuple.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct LinkTuple {
+    /// L_neighbor_iface_addr_list
+    pub neighbor_iface_addrs: Vec<Address>,
+    /// L_HEARD_time
+    pub heard_time: u64,
+    /// L_SYM_time
+    pub sym_time: u64,
+    /// L_pending
+    pub pending: bool,
+    /// L_lost
+    pub lost: bool,
+    /// L_in_quality (L_quality of RFC 6130), from 0 to
+    /// [`QUALITY_MAX`](constant.QUALITY_MAX.html).
+    pub in_quality: u16,
+    /// L_out_quality, the quality of the link to the neighbor if it's
+    /// known.
+    pub out_quality: Option<u16>,
+    /// L_time
+    pub time: u64,
+    /// Neighbor Tuple of the neighbor.
+    pub neighbor: NeighborId,
+    /// Status on the last update, to detect changes.
+    symmetric: bool,
+}
+
+impl LinkTuple {
+    /// Quality of the link used by the hysteresis, the lowest of
+    /// `in_quality` and `out_quality`.
+    pub fn quality(&self) -> u16 {
+        self.out_quality
+            .map_or(self.in_quality, |q| q.min(self.in_quality))
+    }
+
+    /// L_status at time `now`, `None` while the link is pending.
+    pub fn status(&self, now: u64) -> Option<LinkStatus> {
+        if self.pending {
+            None
+        } else if self.lost {
+            Some(LinkStatus::Lost)
+        } else if self.sym_time > now {
+            Some(LinkStatus::Symmetric)
+        } else if self.heard_time > now {
+            Some(LinkStatus::Heard)
+        } else {
+            Some(LinkStatus::Lost)
+        }
+    }
+}
+
+/// 2-Hop Tuple.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct TwoHopTuple {
+    /// N2_neighbor_iface_addr_list
+    pub neighbor_iface_addrs: Vec<Address>,
+    /// N2_2hop_addr
+    pub two_hop_addr: Address,
+    /// N2_time
+    pub time: u64,
+    /// Neighbor Tuple of the 1-hop neighbor.
+    pub neighbor: NeighborId,
+}
+
+/// Neighbor Tuple.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct NeighborTuple {
+    /// N_neighbor_addr_list
+    pub neighbor_addrs: Vec<Address>,
+    /// N_symmetric
+    pub symmetric: bool,
+}
+
+/// Lost Neighbor Tuple.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct LostNeighborTuple {
+    /// NL_neighbor_addr
+    pub neighbor_addr: Address,
+    /// NL_time
+    pub time: u64,
+}
+
+/// Local interface with its Interface Information Base.
+#[derive(Debug)]
+struct Interface {
+    addrs: Vec<Address>,
+    links: Vec<LinkTuple>,
+    two_hops: Vec<TwoHopTuple>,
+}
+
+/// RFC 6130 neighborhood state of a router.
+///
+/// Holds the Local Information Base, the Interface Information Base of each
+/// interface and the Neighbor Information Base. It doesn't send nor
+/// receive anything by itself: received HELLOs are given to
+/// [`process_hello`](#method.process_hello), the content of the HELLOs to
+/// send is built by [`hello`](#method.hello), and
+/// [`expire`](#method.expire) must be called at
+/// [`next_expiry`](#method.next_expiry).
+///
+/// Changes to the symmetric 1-hop and 2-hop neighborhoods are reported as
+/// [`Event`](enum.Event.html)s, retrieved with
+/// [`poll_event`](#method.poll_event).
+#[derive(Debug)]
+pub struct Nhdp<C> {
+    clock: C,
+    config: NhdpConfig,
+    address_length: usize,
+    interfaces: BTreeMap<InterfaceId, Interface>,
+    /// Removed Interface Address Set, `(IR_local_iface_addr, IR_time)`.
+    removed: Vec<(Address, u64)>,
+    neighbors: BTreeMap<NeighborId, NeighborTuple>,
+    lost: Vec<LostNeighborTuple>,
+    next_interface: u32,
+    next_neighbor: u32,
+    events: VecDeque<Event>,
+}
+
+impl<C: Clock> Nhdp<C> {
+    /// Create the state of a router using addresses of `address_length`
+    /// bytes, without interfaces.
+    pub fn new(clock: C, address_length: usize, config: NhdpConfig) -> Self {
+        Nhdp {
+            clock,
+            config,
+            address_length,
+            interfaces: BTreeMap::new(),
+            removed: Vec::new(),
+            neighbors: BTreeMap::new(),
+            lost: Vec::new(),
+            next_interface: 0,
+            next_neighbor: 0,
+            events: VecDeque::new(),
+        }
+    }
+
+    /// The clock.
+    pub fn clock(&self) -> &C {
+        &self.clock
+    }
+
+    /// The configuration.
+    pub fn config(&self) -> &NhdpConfig {
+        &self.config
+    }
+
+    /// Add a local interface with addresses `addrs`.
+    pub fn add_interface(&mut self, addrs: &[Address]) -> InterfaceId {
+        let id = InterfaceId(self.next_interface);
+        self.next_interface += 1;
+        self.removed.retain(|(a, _)| !addrs.contains(a));
+        self.interfaces.insert(
+            id,
+            Interface {
+                addrs: addrs.to_vec(),
+                links: Vec::new(),
+                two_hops: Vec::new(),
+            },
+        );
+        id
+    }
+
+    /// Remove a local interface, its addresses are kept in the Removed
+    /// Interface Address Set for I_HOLD_TIME.
+    pub fn remove_interface(&mut self, id: InterfaceId) {
+        let now = self.clock.now();
+        let iface = match self.interfaces.get_mut(&id) {
+            Some(i) => i,
+            None => return,
+        };
+
+        // Expire the links first, so their loss is handled as usual.
+        for link in iface.links.iter_mut() {
+            link.time = 0;
+        }
+        self.refresh(now);
+
+        if let Some(iface) = self.interfaces.remove(&id) {
+            let time = now.saturating_add(self.config.i_hold_time);
+            self.removed
+                .extend(iface.addrs.into_iter().map(|a| (a, time)));
+        }
+    }
+
+    /// Interfaces of the router.
+    pub fn interfaces(&self) -> impl Iterator<Item = InterfaceId> + '_ {
+        self.interfaces.keys().cloned()
+    }
+
+    /// Addresses of a local interface.
+    pub fn interface_addrs(&self, id: InterfaceId) -> &[Address] {
+        self.interfaces.get(&id).map_or(&[], |i| &i.addrs)
+    }
+
+    /// Is `addr` one of the current or recently used local addresses?
+    pub fn is_local(&self, addr: &Address) -> bool {
+        self.interfaces.values().any(|i| i.addrs.contains(addr))
+            || self.removed.iter().any(|(a, _)| a == addr)
+    }
+
+    /// Link Set of an interface.
+    pub fn links(&self, id: InterfaceId) -> &[LinkTuple] {
+        self.interfaces.get(&id).map_or(&[], |i| &i.links)
+    }
+
+    /// Set the quality of the link to the neighbor interface `addr` on the
+    /// interface `id`, and update its L_pending and L_lost flags when the
+    /// hysteresis is enabled.
+    pub fn set_link_quality(
+        &mut self,
+        id: InterfaceId,
+        addr: &Address,
+        in_quality: u16,
+        out_quality: Option<u16>,
+    ) {
+        let now = self.clock.now();
+        let l_hold_time = self.config.l_hold_time;
+        let hysteresis = self.config.hysteresis;
+        let link = self.interfaces.get_mut(&id).and_then(|i| {
+            i.links
+                .iter_mut()
+                .find(|l| l.neighbor_iface_addrs.contains(addr))
+        });
+        if let Some(link) = link {
+            link.in_quality = in_quality;
+            link.out_quality = out_quality;
+            if let Some(config) = hysteresis {
+                hysteresis::update(link, now, &config, l_hold_time);
+            }
+        }
+        self.refresh(now);
+    }
+
+    /// Update the incoming quality of every link with `source`, from the
+    /// first neighbor interface address with a known quality. Links
+    /// without one are left unchanged.
+    pub fn update_quality<Q: LinkQuality>(&mut self, source: &Q) {
+        let now = self.clock.now();
+        let l_hold_time = self.config.l_hold_time;
+        let hysteresis = self.config.hysteresis;
+        for iface in self.interfaces.values_mut() {
+            for link in iface.links.iter_mut() {
+                let addrs = &link.neighbor_iface_addrs;
+                let quality = addrs.iter().find_map(|a| source.link_quality(a));
+                if let Some(quality) = quality {
+                    link.in_quality = quality;
+                    if let Some(config) = hysteresis {
+                        hysteresis::update(link, now, &config, l_hold_time);
+                    }
+                }
+            }
+        }
+        self.refresh(now);
+    }
+
+    /// 2-Hop Set of an interface.
+    pub fn two_hops(&self, id: InterfaceId) -> &[TwoHopTuple] {
+        self.interfaces.get(&id).map_or(&[], |i| &i.two_hops)
+    }
+
+    /// Neighbor Set.
+    pub fn neighbors(
+        &self,
+    ) -> impl Iterator<Item = (NeighborId, &NeighborTuple)> + '_ {
+        self.neighbors.iter().map(|(id, n)| (*id, n))
+    }
+
+    /// Neighbor Tuple with identifier `id`.
+    pub fn neighbor(&self, id: NeighborId) -> Option<&NeighborTuple> {
+        self.neighbors.get(&id)
+    }
+
+    /// Neighbor Tuple having `addr` in its address list.
+    pub fn neighbor_by_addr(&self, addr: &Address) -> Option<NeighborId> {
+        self.neighbors
+            .iter()
+            .find(|(_, n)| n.neighbor_addrs.contains(addr))
+            .map(|(id, _)| *id)
+    }
+
+    /// Lost Neighbor Set.
+    pub fn lost_neighbors(&self) -> &[LostNeighborTuple] {
+        &self.lost
+    }
+
+    /// Retrieve the next change in the neighborhood.
+    pub fn poll_event(&mut self) -> Option<Event> {
+        self.events.pop_front()
+    }
+
+    /// Remove the expired tuples.
+    pub fn expire(&mut self) {
+        let now = self.clock.now();
+        self.refresh(now);
+    }
+
+    /// Next time the state changes by itself, when
+    /// [`expire`](#method.expire) must be called.
+    pub fn next_expiry(&self) -> Option<u64> {
+        let now = self.clock.now();
+        let links = self.interfaces.values().flat_map(|i| {
+            i.links.iter().flat_map(|l| {
+                iter::once(l.time)
+                    .chain(iter::once(l.sym_time))
+                    .chain(iter::once(l.heard_time))
+            })
+        });
+        let two_hops = self
+            .interfaces
+            .values()
+            .flat_map(|i| i.two_hops.iter().map(|t| t.time));
+        let lost = self.lost.iter().map(|l| l.time);
+        let removed = self.removed.iter().map(|(_, t)| *t);
+
+        links
+            .chain(two_hops)
+            .chain(lost)
+            .chain(removed)
+            .filter(|t| *t > now)
+            .min()
+    }
+
+    /// Process a HELLO received on interface `id` in an IP datagram from
+    /// `source`.
+    ///
+    /// Fails with
+    /// [`Error::InvalidMessage`](../enum.Error.html#variant.InvalidMessage)
+    /// if the HELLO must be discarded: its address length isn't the one of
+    /// the router, or it has one of the router's addresses in a LOCAL_IF
+    /// TLV. HELLOs received on unknown interfaces are ignored.
+    pub fn process_hello(
+        &mut self,
+        id: InterfaceId,
+        source: &Address,
+        hello: &Hello,
+    ) -> Result<(), Error> {
+        let now = self.clock.now();
+        self.refresh(now);
+
+        if !self.interfaces.contains_key(&id) {
+            return Ok(());
+        }
+        if hello.address_length != self.address_length {
+            return Err(Error::InvalidMessage);
+        }
+
+        let addrs: Vec<HelloAddress> = hello.addresses().collect();
+        if addrs
+            .iter()
+            .any(|a| a.local_if.is_some() && self.is_local(&a.addr))
+        {
+            return Err(Error::InvalidMessage);
+        }
+
+        let mut sending: Vec<Address> = addrs
+            .iter()
+            .filter(|a| a.local_if == Some(LocalIf::ThisIf))
+            .map(|a| a.addr)
+            .collect();
+        if sending.is_empty() {
+            sending.push(*source);
+        }
+        let mut neighbor_addrs: Vec<Address> = addrs
+            .iter()
+            .filter(|a| a.local_if.is_some())
+            .map(|a| a.addr)
+            .collect();
+        for a in &sending {
+            if !neighbor_addrs.contains(a) {
+                neighbor_addrs.push(*a);
+            }
+        }
+        let expiry = now.saturating_add(hello.validity_time);
+
+        let neighbor = self.update_neighbor(now, neighbor_addrs);
+        self.update_link(now, id, neighbor, &sending, &addrs, expiry);
+        self.update_two_hops(id, neighbor, &sending, &addrs, expiry);
+
+        self.refresh(now);
+        Ok(())
+    }
+
+    /// Content of the next HELLO to send on interface `id`.
+    ///
+    /// The addresses are sorted, they can be written with
+    /// [`Hello::write`](struct.Hello.html#method.write). As allowed by
+    /// RFC 7466, the addresses of symmetric neighbors that have a
+    /// LINK_STATUS other than SYMMETRIC on this interface also have an
+    /// OTHER_NEIGHB of SYMMETRIC.
+    pub fn hello(
+        &mut self,
+        id: InterfaceId,
+    ) -> Option<(Hello<'static>, Vec<HelloAddress>)> {
+        let now = self.clock.now();
+        self.refresh(now);
+
+        let iface = self.interfaces.get(&id)?;
+        let mut addrs: Vec<HelloAddress> = Vec::new();
+        let contains =
+            |addrs: &[HelloAddress], a| addrs.iter().any(|h| h.addr == a);
+
+        for (i, other) in self.interfaces.iter() {
+            for a in &other.addrs {
+                if !contains(&addrs, *a) {
+                    let mut h = HelloAddress::new(*a);
+                    h.local_if = Some(if *i == id {
+                        LocalIf::ThisIf
+                    } else {
+                        LocalIf::OtherIf
+                    });
+                    addrs.push(h);
+                }
+            }
+        }
+
+        for link in &iface.links {
+            let status = match link.status(now) {
+                Some(s) => s,
+                None => continue,
+            };
+            let symmetric = self
+                .neighbors
+                .get(&link.neighbor)
+                .map_or(false, |n| n.symmetric);
+            for a in &link.neighbor_iface_addrs {
+                if contains(&addrs, *a) {
+                    continue;
+                }
+                let mut h = HelloAddress::new(*a);
+                h.link_status = Some(status);
+                if symmetric && status != LinkStatus::Symmetric {
+                    h.other_neighb = Some(OtherNeighb::Symmetric);
+                }
+                addrs.push(h);
+            }
+        }
+
+        for n in self.neighbors.values().filter(|n| n.symmetric) {
+            for a in &n.neighbor_addrs {
+                if !contains(&addrs, *a) {
+                    let mut h = HelloAddress::new(*a);
+                    h.other_neighb = Some(OtherNeighb::Symmetric);
+                    addrs.push(h);
+                }
+            }
+        }
+        for l in &self.lost {
+            if !contains(&addrs, l.neighbor_addr) {
+                let mut h = HelloAddress::new(l.neighbor_addr);
+                h.other_neighb = Some(OtherNeighb::Lost);
+                addrs.push(h);
+            }
+        }
+
+        addrs.sort_by_key(|a| a.addr);
+        let mut hello =
+            Hello::new(self.address_length, self.config.h_hold_time);
+        hello.interval_time = Some(self.config.hello_interval);
+        Some((hello, addrs))
+    }
+
+    /// Update the Neighbor Set with the addresses of a HELLO (RFC 6130
+    /// section 12.3), returns the Neighbor Tuple of the sender.
+    fn update_neighbor(
+        &mut self,
+        now: u64,
+        neighbor_addrs: Vec<Address>,
+    ) -> NeighborId {
+        let matching: Vec<NeighborId> = self
+            .neighbors
+            .iter()
+            .filter(|(_, n)| intersects(&n.neighbor_addrs, &neighbor_addrs))
+            .map(|(id, _)| *id)
+            .collect();
+        let id = match matching.first() {
+            Some(id) => *id,
+            None => {
+                let id = NeighborId(self.next_neighbor);
+                self.next_neighbor += 1;
+                id
+            }
+        };
+
+        let mut old_addrs = Vec::new();
+        let mut symmetric = false;
+        for m in &matching {
+            let n = match self.neighbors.remove(m) {
+                Some(n) => n,
+                None => continue,
+            };
+            old_addrs.extend(n.neighbor_addrs);
+            symmetric |= n.symmetric;
+
+            // Merge into the first tuple.
+            if *m != id {
+                for iface in self.interfaces.values_mut() {
+                    for l in iface.links.iter_mut().filter(|l| l.neighbor == *m)
+                    {
+                        l.neighbor = id;
+                    }
+                    for t in
+                        iface.two_hops.iter_mut().filter(|t| t.neighbor == *m)
+                    {
+                        t.neighbor = id;
+                    }
+                }
+                if n.symmetric {
+                    self.events.push_back(Event::LostNeighbor(*m));
+                }
+            }
+        }
+
+        // Addresses the neighbor doesn't have anymore.
+        let removed: Vec<Address> = old_addrs
+            .into_iter()
+            .filter(|a| !neighbor_addrs.contains(a))
+            .collect();
+        if symmetric {
+            let time = now.saturating_add(self.config.n_hold_time);
+            for a in &removed {
+                self.add_lost(*a, time);
+            }
+        }
+        for iface in self.interfaces.values_mut() {
+            for l in iface.links.iter_mut().filter(|l| l.neighbor == id) {
+                l.neighbor_iface_addrs.retain(|a| !removed.contains(a));
+                if l.neighbor_iface_addrs.is_empty() {
+                    l.time = 0;
+                }
+            }
+            for t in iface.two_hops.iter_mut().filter(|t| t.neighbor == id) {
+                t.neighbor_iface_addrs.retain(|a| !removed.contains(a));
+                if t.neighbor_iface_addrs.is_empty() {
+                    t.time = 0;
+                }
+            }
+        }
+
+        self.neighbors.insert(
+            id,
+            NeighborTuple {
+                neighbor_addrs,
+                symmetric,
+            },
+        );
+        id
+    }
+
+    /// Update the Link Set of the interface `id` (RFC 6130 section 12.5).
+    fn update_link(
+        &mut self,
+        now: u64,
+        id: InterfaceId,
+        neighbor: NeighborId,
+        sending: &[Address],
+        addrs: &[HelloAddress],
+        expiry: u64,
+    ) {
+        let l_hold_time = self.config.l_hold_time;
+        let hysteresis = self.config.hysteresis;
+        let iface = match self.interfaces.get_mut(&id) {
+            Some(i) => i,
+            None => return,
+        };
+
+        let index = iface
+            .links
+            .iter()
+            .position(|l| intersects(&l.neighbor_iface_addrs, sending));
+        for (i, l) in iface.links.iter_mut().enumerate() {
+            if Some(i) != index {
+                l.neighbor_iface_addrs.retain(|a| !sending.contains(a));
+                if l.neighbor_iface_addrs.is_empty() {
+                    l.time = 0;
+                }
+            }
+        }
+        let index = match index {
+            Some(i) => i,
+            None => {
+                let mut link = LinkTuple {
+                    neighbor_iface_addrs: Vec::new(),
+                    heard_time: 0,
+                    sym_time: 0,
+                    pending: false,
+                    lost: false,
+                    in_quality: hysteresis::QUALITY_MAX,
+                    out_quality: None,
+                    time: 0,
+                    neighbor,
+                    symmetric: false,
+                };
+                if let Some(config) = hysteresis {
+                    hysteresis::initialize(&mut link, &config);
+                }
+                iface.links.push(link);
+                iface.links.len() - 1
+            }
+        };
+
+        let own = &iface.addrs;
+        let status = addrs
+            .iter()
+            .filter(|a| own.contains(&a.addr))
+            .find_map(|a| a.link_status);
+
+        let link = &mut iface.links[index];
+        link.neighbor_iface_addrs = sending.to_vec();
+        link.neighbor = neighbor;
+        match status {
+            Some(LinkStatus::Heard) | Some(LinkStatus::Symmetric) => {
+                link.sym_time = expiry;
+            }
+            Some(LinkStatus::Lost) if link.sym_time > now => {
+                link.sym_time = 0;
+                if link.heard_time > now {
+                    link.time = now.saturating_add(l_hold_time);
+                }
+            }
+            _ => (),
+        }
+        link.heard_time = expiry.max(link.sym_time);
+        if !link.pending {
+            link.time =
+                link.time.max(link.heard_time.saturating_add(l_hold_time));
+        } else {
+            link.time = link.time.max(link.heard_time);
+        }
+    }
+
+    /// Update the 2-Hop Set of the interface `id` (RFC 6130 section 12.6,
+    /// as updated by RFC 7466).
+    fn update_two_hops(
+        &mut self,
+        id: InterfaceId,
+        neighbor: NeighborId,
+        sending: &[Address],
+        addrs: &[HelloAddress],
+        expiry: u64,
+    ) {
+        let now = self.clock.now();
+        let symmetric = self
+            .interfaces
+            .get(&id)
+            .and_then(|i| {
+                i.links.iter().find(|l| l.neighbor_iface_addrs == sending)
+            })
+            .map_or(false, |l| l.status(now) == Some(LinkStatus::Symmetric));
+        if !symmetric {
+            return;
+        }
+
+        for a in addrs.iter().filter(|a| a.local_if.is_none()) {
+            if self.is_local(&a.addr) {
+                continue;
+            }
+
+            let is_sym = a.link_status == Some(LinkStatus::Symmetric)
+                || a.other_neighb == Some(OtherNeighb::Symmetric);
+            let is_lost = match a.link_status {
+                Some(LinkStatus::Lost) | Some(LinkStatus::Heard) => true,
+                _ => a.other_neighb == Some(OtherNeighb::Lost),
+            };
+
+            let iface = match self.interfaces.get_mut(&id) {
+                Some(i) => i,
+                None => return,
+            };
+            let same = |t: &TwoHopTuple| {
+                t.two_hop_addr == a.addr
+                    && intersects(&t.neighbor_iface_addrs, sending)
+            };
+            if is_sym {
+                match iface.two_hops.iter_mut().find(|t| same(t)) {
+                    Some(t) => {
+                        t.neighbor_iface_addrs = sending.to_vec();
+                        t.neighbor = neighbor;
+                        t.time = expiry;
+                    }
+                    None => {
+                        iface.two_hops.push(TwoHopTuple {
+                            neighbor_iface_addrs: sending.to_vec(),
+                            two_hop_addr: a.addr,
+                            time: expiry,
+                            neighbor,
+                        });
+                        self.events.push_back(Event::TwoHopAdded {
+                            interface: id,
+                            neighbor,
+                            addr: a.addr,
+                        });
+                    }
+                }
+            } else if is_lost {
+                let events = &mut self.events;
+                iface.two_hops.retain(|t| {
+                    if !same(t) {
+                        return true;
+                    }
+                    events.push_back(Event::TwoHopRemoved {
+                        interface: id,
+                        neighbor: t.neighbor,
+                        addr: t.two_hop_addr,
+                    });
+                    false
+                });
+            }
+        }
+    }
+
+    /// Add a Lost Neighbor Tuple, or extend it.
+    fn add_lost(&mut self, addr: Address, time: u64) {
+        match self.lost.iter_mut().find(|l| l.neighbor_addr == addr) {
+            Some(l) => l.time = l.time.max(time),
+            None => self.lost.push(LostNeighborTuple {
+                neighbor_addr: addr,
+                time,
+            }),
+        }
+    }
+
+    /// Remove expired tuples and apply the changes of link status (RFC
+    /// 6130 section 13).
+    fn refresh(&mut self, now: u64) {
+        self.removed.retain(|(_, t)| *t > now);
+        self.lost.retain(|l| l.time > now);
+
+        for (id, iface) in self.interfaces.iter_mut() {
+            let mut lost_links: Vec<Vec<Address>> = Vec::new();
+            for l in iface.links.iter_mut() {
+                let symmetric = l.time > now
+                    && l.status(now) == Some(LinkStatus::Symmetric);
+                if l.symmetric && !symmetric {
+                    lost_links.push(l.neighbor_iface_addrs.clone());
+                }
+                l.symmetric = symmetric;
+            }
+            iface.links.retain(|l| l.time > now);
+
+            let events = &mut self.events;
+            iface.two_hops.retain(|t| {
+                let keep = t.time > now
+                    && !lost_links
+                        .iter()
+                        .any(|a| intersects(a, &t.neighbor_iface_addrs));
+                if !keep {
+                    events.push_back(Event::TwoHopRemoved {
+                        interface: *id,
+                        neighbor: t.neighbor,
+                        addr: t.two_hop_addr,
+                    });
+                }
+                keep
+            });
+        }
+
+        let ids: Vec<NeighborId> = self.neighbors.keys().cloned().collect();
+        for id in ids {
+            let links = self
+                .interfaces
+                .values()
+                .flat_map(|i| i.links.iter())
+                .filter(|l| l.neighbor == id);
+            let mut has_link = false;
+            let mut symmetric = false;
+            for l in links {
+                has_link = true;
+                symmetric |= l.symmetric;
+            }
+
+            let n = match self.neighbors.get_mut(&id) {
+                Some(n) => n,
+                None => continue,
+            };
+            if symmetric && !n.symmetric {
+                n.symmetric = true;
+                let addrs = &n.neighbor_addrs;
+                self.lost.retain(|l| !addrs.contains(&l.neighbor_addr));
+                self.events.push_back(Event::SymmetricNeighbor(id));
+            } else if !symmetric && n.symmetric {
+                n.symmetric = false;
+                let addrs = n.neighbor_addrs.clone();
+                let time = now.saturating_add(self.config.n_hold_time);
+                for a in addrs {
+                    self.add_lost(a, time);
+                }
+                self.events.push_back(Event::LostNeighbor(id));
+            }
+
+            if !has_link {
+                self.neighbors.remove(&id);
+            }
+        }
+    }
+}
+
+/// Do `a` and `b` have an address in common?
+fn intersects(a: &[Address], b: &[Address]) -> bool {
+    a.iter().any(|x| b.contains(x))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{Buf, ManualClock, Message};
+
+    fn addr(bytes: &[u8]) -> Address {
+        Address::from_bytes(bytes).unwrap()
+    }
+
+    /// Send a HELLO from `from` on interface `from_if` to `to` on `to_if`.
+    fn send(
+        from: &mut Nhdp<&ManualClock>,
+        from_if: InterfaceId,
+        to: &mut Nhdp<&ManualClock>,
+        to_if: InterfaceId,
+    ) -> Result<(), Error> {
+        let (hello, addrs) = from.hello(from_if).unwrap();
+        let mut buf = [0u8; 256];
+        let size = hello.write(&addrs, &mut buf).unwrap();
+        let msg = Message::read(&mut Buf::new(&buf[..size])).unwrap();
+        let hello = Hello::from_message(&msg).unwrap();
+        let source = from.interface_addrs(from_if)[0];
+        to.process_hello(to_if, &source, &hello)
+    }
+
+    fn events(nhdp: &mut Nhdp<&ManualClock>) -> Vec<Event> {
+        std::iter::from_fn(|| nhdp.poll_event()).collect()
+    }
+
+    fn router<'c>(
+        clock: &'c ManualClock,
+        addrs: &[&[Address]],
+    ) -> (Nhdp<&'c ManualClock>, Vec<InterfaceId>) {
+        let mut nhdp = Nhdp::new(clock, 4, NhdpConfig::default());
+        let ids = addrs.iter().map(|a| nhdp.add_interface(a)).collect();
+        (nhdp, ids)
+    }
+
+    #[test]
+    fn test_nhdp_symmetric_link() {
+        let clock = ManualClock::new(1000);
+        let (a_addr, b_addr) = (addr(&[10, 0, 0, 1]), addr(&[10, 0, 0, 2]));
+        let (mut a, a_if) = router(&clock, &[&[a_addr]]);
+        let (mut b, b_if) = router(&clock, &[&[b_addr]]);
+
+        // B hears A.
+        send(&mut a, a_if[0], &mut b, b_if[0]).unwrap();
+        let link = &b.links(b_if[0])[0];
+        assert_eq!(link.neighbor_iface_addrs, vec![a_addr]);
+        assert_eq!(link.status(clock.now()), Some(LinkStatus::Heard));
+        assert!(events(&mut b).is_empty());
+
+        // A hears B hearing A, then B hears A hearing B.
+        send(&mut b, b_if[0], &mut a, a_if[0]).unwrap();
+        let n = a.neighbor_by_addr(&b_addr).unwrap();
+        assert_eq!(events(&mut a), vec![Event::SymmetricNeighbor(n)]);
+        send(&mut a, a_if[0], &mut b, b_if[0]).unwrap();
+        let n = b.neighbor_by_addr(&a_addr).unwrap();
+        assert_eq!(events(&mut b), vec![Event::SymmetricNeighbor(n)]);
+        assert!(b.neighbor(n).unwrap().symmetric);
+
+        // A goes silent, B loses the link after H_HOLD_TIME.
+        assert_eq!(b.next_expiry(), Some(clock.now() + 6000));
+        clock.advance(6000);
+        b.expire();
+        assert_eq!(events(&mut b), vec![Event::LostNeighbor(n)]);
+        assert_eq!(b.lost_neighbors()[0].neighbor_addr, a_addr);
+
+        // It's advertised as LOST until the link expires.
+        let (_, addrs) = b.hello(b_if[0]).unwrap();
+        let h = addrs.iter().find(|h| h.addr == a_addr).unwrap();
+        assert_eq!(h.link_status, Some(LinkStatus::Lost));
+        clock.advance(6000);
+        b.expire();
+        assert!(b.links(b_if[0]).is_empty());
+        assert!(b.neighbor(n).is_none());
+        assert!(b.lost_neighbors().is_empty());
+        let (_, addrs) = b.hello(b_if[0]).unwrap();
+        assert!(addrs.iter().all(|h| h.addr != a_addr));
+    }
+
+    #[test]
+    fn test_nhdp_two_hop() {
+        let clock = ManualClock::new(0);
+        let a_addr = addr(&[10, 0, 0, 1]);
+        let b_addr = addr(&[10, 0, 0, 2]);
+        let c_addr = addr(&[10, 0, 0, 3]);
+        let (mut a, a_if) = router(&clock, &[&[a_addr]]);
+        let (mut b, b_if) = router(&clock, &[&[b_addr]]);
+        let (mut c, c_if) = router(&clock, &[&[c_addr]]);
+
+        for _ in 0..2 {
+            send(&mut a, a_if[0], &mut b, b_if[0]).unwrap();
+            send(&mut c, c_if[0], &mut b, b_if[0]).unwrap();
+            send(&mut b, b_if[0], &mut a, a_if[0]).unwrap();
+            send(&mut b, b_if[0], &mut c, c_if[0]).unwrap();
+        }
+
+        let n = a.neighbor_by_addr(&b_addr).unwrap();
+        let two_hops = a.two_hops(a_if[0]);
+        assert_eq!(two_hops.len(), 1);
+        assert_eq!(two_hops[0].two_hop_addr, c_addr);
+        assert_eq!(two_hops[0].neighbor, n);
+        let ev = events(&mut a);
+        assert!(ev.contains(&Event::TwoHopAdded {
+            interface: a_if[0],
+            neighbor: n,
+            addr: c_addr,
+        }));
+
+        // C goes silent, B reports it LOST and A removes the 2-hop tuple.
+        clock.advance(3000);
+        send(&mut a, a_if[0], &mut b, b_if[0]).unwrap();
+        send(&mut b, b_if[0], &mut a, a_if[0]).unwrap();
+        events(&mut a);
+        clock.advance(3000);
+        b.expire();
+        send(&mut b, b_if[0], &mut a, a_if[0]).unwrap();
+        assert!(a.two_hops(a_if[0]).is_empty());
+        assert_eq!(
+            events(&mut a),
+            vec![Event::TwoHopRemoved {
+                interface: a_if[0],
+                neighbor: n,
+                addr: c_addr,
+            }]
+        );
+    }
+
+    #[test]
+    fn test_nhdp_own_address() {
+        let clock = ManualClock::new(0);
+        let a_addr = addr(&[10, 0, 0, 1]);
+        let (mut a, a_if) = router(&clock, &[&[a_addr]]);
+        let (mut b, b_if) = router(&clock, &[&[a_addr]]);
+        assert_eq!(
+            send(&mut a, a_if[0], &mut b, b_if[0]),
+            Err(Error::InvalidMessage)
+        );
+
+        // Also while the address is in the Removed Interface Address Set.
+        let b_addr = addr(&[10, 0, 0, 2]);
+        b.remove_interface(b_if[0]);
+        let b_if = b.add_interface(&[b_addr]);
+        assert!(b.is_local(&a_addr));
+        assert_eq!(
+            send(&mut a, a_if[0], &mut b, b_if),
+            Err(Error::InvalidMessage)
+        );
+        clock.advance(6000);
+        assert!(send(&mut a, a_if[0], &mut b, b_if).is_ok());
+    }
+
+    #[test]
+    fn test_nhdp_rfc7466() {
+        // A and B have two interfaces each, the link between their first
+        // interfaces is symmetric, on the second ones B only hears A.
+        let clock = ManualClock::new(0);
+        let a1 = addr(&[10, 0, 0, 1]);
+        let a2 = addr(&[10, 0, 1, 1]);
+        let b1 = addr(&[10, 0, 0, 2]);
+        let b2 = addr(&[10, 0, 1, 2]);
+        let (mut a, a_if) = router(&clock, &[&[a1], &[a2]]);
+        let (mut b, b_if) = router(&clock, &[&[b1], &[b2]]);
+
+        send(&mut a, a_if[0], &mut b, b_if[0]).unwrap();
+        send(&mut b, b_if[0], &mut a, a_if[0]).unwrap();
+        send(&mut a, a_if[0], &mut b, b_if[0]).unwrap();
+        send(&mut a, a_if[1], &mut b, b_if[1]).unwrap();
+
+        // A single neighbor with both addresses.
+        assert_eq!(b.neighbors().count(), 1);
+        let (_, n) = b.neighbors().next().unwrap();
+        assert!(n.symmetric);
+        assert_eq!(n.neighbor_addrs.len(), 2);
+
+        let (_, addrs) = b.hello(b_if[1]).unwrap();
+        let h = addrs.iter().find(|h| h.addr == a2).unwrap();
+        assert_eq!(h.link_status, Some(LinkStatus::Heard));
+        assert_eq!(h.other_neighb, Some(OtherNeighb::Symmetric));
+        let h = addrs.iter().find(|h| h.addr == b1).unwrap();
+        assert_eq!(h.local_if, Some(LocalIf::OtherIf));
+        let h = addrs.iter().find(|h| h.addr == b2).unwrap();
+        assert_eq!(h.local_if, Some(LocalIf::ThisIf));
+    }
+
+    #[test]
+    fn test_nhdp_hysteresis() {
+        use crate::linkloss::{LinkLoss, LossConfig};
+        use crate::Packet;
+
+        let clock = ManualClock::new(0);
+        let (a_addr, b_addr) = (addr(&[10, 0, 0, 1]), addr(&[10, 0, 0, 2]));
+        let (mut a, a_if) = router(&clock, &[&[a_addr]]);
+        let config = NhdpConfig {
+            hysteresis: Some(HysteresisConfig::default()),
+            ..NhdpConfig::default()
+        };
+        let mut b = Nhdp::new(&clock, 4, config);
+        let b_if = b.add_interface(&[b_addr]);
+        let status = |b: &mut Nhdp<&ManualClock>| {
+            let (_, addrs) = b.hello(b_if).unwrap();
+            addrs
+                .iter()
+                .find(|h| h.addr == a_addr)
+                .map(|h| h.link_status)
+        };
+
+        // The new link is pending, it isn't advertised.
+        send(&mut a, a_if[0], &mut b, b_if).unwrap();
+        let link = &b.links(b_if)[0];
+        assert!(link.pending && link.lost);
+        assert_eq!(link.status(clock.now()), None);
+        assert_eq!(status(&mut b), None);
+
+        // Accepted with a good enough quality.
+        b.set_link_quality(b_if, &a_addr, 900, None);
+        assert_eq!(status(&mut b), Some(Some(LinkStatus::Heard)));
+        send(&mut b, b_if, &mut a, a_if[0]).unwrap();
+        send(&mut a, a_if[0], &mut b, b_if).unwrap();
+        let n = b.neighbor_by_addr(&a_addr).unwrap();
+        assert_eq!(events(&mut b), vec![Event::SymmetricNeighbor(n)]);
+
+        // Rejected below HYST_REJECT, in both directions.
+        b.set_link_quality(b_if, &a_addr, 500, None);
+        assert!(events(&mut b).is_empty());
+        b.set_link_quality(b_if, &a_addr, 900, Some(200));
+        assert_eq!(events(&mut b), vec![Event::LostNeighbor(n)]);
+        assert_eq!(status(&mut b), Some(Some(LinkStatus::Lost)));
+        b.set_link_quality(b_if, &a_addr, 500, None);
+        assert_eq!(status(&mut b), Some(Some(LinkStatus::Lost)));
+
+        // Back with the packets received from A, 9 out of 10.
+        let mut loss = LinkLoss::new(&clock, LossConfig::default());
+        for s in (0u16..10).filter(|s| *s != 4) {
+            let mut buf = [0x08, 0, 0];
+            buf[1..].copy_from_slice(&s.to_be_bytes());
+            loss.receive_packet(&a_addr, &Packet::read(&buf).unwrap().hdr);
+        }
+        assert_eq!(loss.link_quality(&a_addr), Some(900));
+        b.update_quality(&loss);
+        assert_eq!(b.links(b_if)[0].in_quality, 900);
+        assert_eq!(events(&mut b), vec![Event::SymmetricNeighbor(n)]);
+        assert_eq!(status(&mut b), Some(Some(LinkStatus::Symmetric)));
+    }
+}
@@ -0,0 +1,455 @@
+// Copyright 2020 Jean Pierre Dudey. See the LICENSE-MIT and
+// LICENSE-APACHE files at the top-level directory of this
+// distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! RFC 7779 Directional Airtime (DAT) link metric.
+//!
+//! The loss of a link is estimated from the gaps in the `<pkt-seq-num>` of
+//! the packets received from the neighbor interface, counted in
+//! [`DAT_MEMORY_LENGTH`] buckets of [`DAT_REFRESH_INTERVAL`] milliseconds.
+//! The metric is the expected airtime of a transmission: the expected
+//! number of transmissions (ETX) divided by the link speed,
+//!
+//! ```text
+//! metric = total / received * 2^24 / (speed in kbit/s)
+//! ```
+//!
+//! rounded up to the representable metrics with
+//! [`LinkMetric::from_metric`](../../nhdp/struct.LinkMetric.html#method.from_metric).
+//!
+//! When the HELLOs of the neighbor stop, the time without them is counted
+//! as lost. [`DatLink`] keeps the state of a link without the heap,
+//! [`Dat`] (with `use_std`) the links of every neighbor.
+//!
+//! [`DAT_MEMORY_LENGTH`]: constant.DAT_MEMORY_LENGTH.html
+//! [`DAT_REFRESH_INTERVAL`]: constant.DAT_REFRESH_INTERVAL.html
+//! [`DatLink`]: struct.DatLink.html
+//! [`Dat`]: struct.Dat.html
+
+use core::fmt;
+#[cfg(feature = "use_std")]
+use std::collections::BTreeMap;
+
+use crate::nhdp::{LinkMetric, LinkMetricFlags};
+use crate::olsr::metric::{MAXIMUM_METRIC, MINIMUM_METRIC};
+use crate::Address;
+#[cfg(feature = "use_std")]
+use crate::{Clock, PktHeader};
+
+/// Number of buckets of the loss history.
+pub const DAT_MEMORY_LENGTH: usize = 64;
+/// Duration of a bucket in milliseconds, the metric is calculated when it
+/// ends.
+pub const DAT_REFRESH_INTERVAL: u64 = 1000;
+/// HELLOs are missing after this percentage of their interval.
+pub const DAT_HELLO_TIMEOUT_FACTOR: u64 = 120;
+/// A sequence number further ahead or back than this is a restart of the
+/// neighbor, not a loss.
+pub const DAT_SEQNO_RESTART_DETECTION: u16 = 256;
+
+/// A source of link speeds.
+pub trait LinkSpeed {
+    /// Speed in bit/s of the link to the neighbor interface `addr`, `None`
+    /// if it's unknown.
+    fn link_speed(&self, addr: &Address) -> Option<u64>;
+}
+
+impl<S: LinkSpeed + ?Sized> LinkSpeed for &S {
+    fn link_speed(&self, addr: &Address) -> Option<u64> {
+        (**self).link_speed(addr)
+    }
+}
+
+/// The same speed in bit/s for every link.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct FixedSpeed(pub u64);
+
+impl LinkSpeed for FixedSpeed {
+    fn link_speed(&self, _: &Address) -> Option<u64> {
+        Some(self.0)
+    }
+}
+
+/// Packets of a bucket.
+#[derive(Debug, Clone, Copy, Default)]
+struct Bucket {
+    received: u32,
+    total: u32,
+}
+
+/// DAT state of a link: the loss history, the HELLO timeout and the last
+/// metric.
+#[derive(Clone)]
+pub struct DatLink {
+    buckets: [Bucket; DAT_MEMORY_LENGTH],
+    /// Bucket of the current refresh interval.
+    current: usize,
+    last_seq_num: Option<u16>,
+    /// Time of the last HELLO and its INTERVAL_TIME.
+    last_hello: Option<(u64, u64)>,
+    metric: Option<u32>,
+}
+
+impl DatLink {
+    /// Create a link without history.
+    pub fn new() -> DatLink {
+        DatLink {
+            buckets: [Bucket::default(); DAT_MEMORY_LENGTH],
+            current: 0,
+            last_seq_num: None,
+            last_hello: None,
+            metric: None,
+        }
+    }
+
+    /// Count a packet with the `<pkt-seq-num>` `seq_num`, the packets
+    /// skipped since the previous one are lost.
+    ///
+    /// Duplicates and reordered packets are ignored. A jump of more than
+    /// [`DAT_SEQNO_RESTART_DETECTION`](constant.DAT_SEQNO_RESTART_DETECTION.html)
+    /// ahead or back is a restart of the neighbor, it isn't counted as a
+    /// loss.
+    pub fn receive_packet(&mut self, seq_num: u16) {
+        let lost = match self.last_seq_num {
+            Some(last) => {
+                let ahead = seq_num.wrapping_sub(last);
+                let back = last.wrapping_sub(seq_num);
+                if ahead == 0 || back <= DAT_SEQNO_RESTART_DETECTION {
+                    return;
+                }
+                if ahead > DAT_SEQNO_RESTART_DETECTION {
+                    0
+                } else {
+                    u32::from(ahead) - 1
+                }
+            }
+            None => 0,
+        };
+        self.last_seq_num = Some(seq_num);
+
+        let bucket = &mut self.buckets[self.current];
+        bucket.received = bucket.received.saturating_add(1);
+        bucket.total = bucket.total.saturating_add(lost + 1);
+    }
+
+    /// Note a HELLO received at `now` with an INTERVAL_TIME of `interval`
+    /// milliseconds.
+    pub fn receive_hello(&mut self, now: u64, interval: u64) {
+        self.last_hello = Some((now, interval));
+    }
+
+    /// End the current bucket at `now` and calculate the metric with the
+    /// link speed `speed` in bit/s, returns the new metric.
+    ///
+    /// It must be called every
+    /// [`DAT_REFRESH_INTERVAL`](constant.DAT_REFRESH_INTERVAL.html). The
+    /// metric is `None` without packets in the history or without the link
+    /// speed.
+    pub fn refresh(&mut self, now: u64, speed: Option<u64>) -> Option<u32> {
+        let (received, total) = self.loss();
+        let total = total + self.hello_penalty(now, total);
+        self.metric = match speed.map(|s| s / 1000) {
+            Some(kbps) if kbps > 0 && received > 0 => {
+                let m = (total << 24) / (received * kbps);
+                let m =
+                    m.max(u64::from(MINIMUM_METRIC)).min(MAXIMUM_METRIC.into());
+                Some(m as u32)
+            }
+            _ => None,
+        };
+
+        self.current = (self.current + 1) % DAT_MEMORY_LENGTH;
+        self.buckets[self.current] = Bucket::default();
+        self.metric
+    }
+
+    /// Packets received and sent by the neighbor in the history.
+    pub fn loss(&self) -> (u64, u64) {
+        self.buckets.iter().fold((0, 0), |(r, t), b| {
+            (r + u64::from(b.received), t + u64::from(b.total))
+        })
+    }
+
+    /// Packets lost with the missing HELLOs: the part of the history
+    /// without them.
+    fn hello_penalty(&self, now: u64, total: u64) -> u64 {
+        let (last, interval) = match self.last_hello {
+            Some((last, interval)) if interval > 0 => (last, interval),
+            _ => return 0,
+        };
+        let elapsed = now.saturating_sub(last);
+        let timeout = DAT_HELLO_TIMEOUT_FACTOR.saturating_mul(interval);
+        if elapsed.saturating_mul(100) <= timeout {
+            return 0;
+        }
+
+        let missed = elapsed / interval;
+        let window = DAT_MEMORY_LENGTH as u64 * DAT_REFRESH_INTERVAL;
+        total * (missed * interval).min(window) / window
+    }
+
+    /// Last metric calculated by [`refresh`](#method.refresh).
+    pub fn metric(&self) -> Option<u32> {
+        self.metric
+    }
+
+    /// LINK_METRIC of the last metric, of type `metric_type` for the
+    /// directions `flags`.
+    pub fn link_metric(
+        &self,
+        metric_type: u8,
+        flags: LinkMetricFlags,
+    ) -> Option<LinkMetric> {
+        LinkMetric::from_metric(metric_type, flags, self.metric?)
+    }
+}
+
+// Arrays of more than 32 elements only implement Debug since Rust 1.47.
+impl fmt::Debug for DatLink {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("DatLink")
+            .field("buckets", &&self.buckets[..])
+            .field("current", &self.current)
+            .field("last_seq_num", &self.last_seq_num)
+            .field("last_hello", &self.last_hello)
+            .field("metric", &self.metric)
+            .finish()
+    }
+}
+
+impl Default for DatLink {
+    fn default() -> DatLink {
+        DatLink::new()
+    }
+}
+
+/// DAT metrics of the links to the neighbor interfaces, identified by the
+/// source address of their packets.
+///
+/// The metrics are calculated every
+/// [`DAT_REFRESH_INTERVAL`](constant.DAT_REFRESH_INTERVAL.html) by
+/// [`expire`](#method.expire), with the speeds given by `S`.
+#[cfg(feature = "use_std")]
+#[derive(Debug)]
+pub struct Dat<C, S> {
+    clock: C,
+    speed: S,
+    next_refresh: u64,
+    links: BTreeMap<Address, DatLink>,
+}
+
+#[cfg(feature = "use_std")]
+impl<C: Clock, S: LinkSpeed> Dat<C, S> {
+    /// Create an empty set of links.
+    pub fn new(clock: C, speed: S) -> Self {
+        let next_refresh = clock.now().saturating_add(DAT_REFRESH_INTERVAL);
+        Dat {
+            clock,
+            speed,
+            next_refresh,
+            links: BTreeMap::new(),
+        }
+    }
+
+    /// The clock.
+    pub fn clock(&self) -> &C {
+        &self.clock
+    }
+
+    /// Count a packet with the header `hdr` received from `source`, packets
+    /// without `<pkt-seq-num>` are ignored.
+    pub fn receive_packet(&mut self, source: &Address, hdr: &PktHeader) {
+        if let Some(seq_num) = hdr.seq_num {
+            self.links
+                .entry(*source)
+                .or_default()
+                .receive_packet(seq_num);
+        }
+    }
+
+    /// Note a HELLO with an INTERVAL_TIME of `interval` milliseconds
+    /// received from `source`.
+    pub fn receive_hello(&mut self, source: &Address, interval: u64) {
+        let now = self.clock.now();
+        let link = self.links.entry(*source).or_default();
+        link.receive_hello(now, interval);
+    }
+
+    /// Forget the link to `addr`, when it's lost.
+    pub fn remove(&mut self, addr: &Address) {
+        self.links.remove(addr);
+    }
+
+    /// State of the link to `addr`.
+    pub fn link(&self, addr: &Address) -> Option<&DatLink> {
+        self.links.get(addr)
+    }
+
+    /// Metric of the link to `addr`.
+    pub fn metric(&self, addr: &Address) -> Option<u32> {
+        self.links.get(addr)?.metric()
+    }
+
+    /// Calculate the metrics if the refresh interval ended.
+    ///
+    /// The metrics are calculated once for each interval that ended, up to
+    /// [`DAT_MEMORY_LENGTH`](constant.DAT_MEMORY_LENGTH.html) times after a
+    /// jump of the clock.
+    pub fn expire(&mut self) {
+        let now = self.clock.now();
+        let mut refreshes = 0;
+        while self.next_refresh <= now {
+            for (addr, link) in self.links.iter_mut() {
+                link.refresh(now, self.speed.link_speed(addr));
+            }
+            refreshes += 1;
+            if refreshes == DAT_MEMORY_LENGTH {
+                self.next_refresh = now.saturating_add(DAT_REFRESH_INTERVAL);
+            } else {
+                self.next_refresh =
+                    self.next_refresh.saturating_add(DAT_REFRESH_INTERVAL);
+            }
+        }
+    }
+
+    /// Next time the metrics are calculated, when
+    /// [`expire`](#method.expire) must be called.
+    pub fn next_expiry(&self) -> Option<u64> {
+        Some(self.next_refresh)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const MBPS: Option<u64> = Some(1_000_000);
+    /// Metric without loss at 1 Mbit/s.
+    const ONE: u32 = (1 << 24) / 1000;
+
+    #[test]
+    fn test_dat_link() {
+        let mut link = DatLink::new();
+        assert_eq!(link.refresh(0, MBPS), None);
+
+        // No loss at 1 Mbit/s.
+        for s in 0..10 {
+            link.receive_packet(s);
+        }
+        assert_eq!(link.refresh(1000, MBPS), Some(ONE));
+        assert_eq!(link.refresh(1000, None), None);
+        assert_eq!(link.refresh(1000, Some(999)), None);
+
+        // Half of the packets lost, duplicates and older packets ignored.
+        for s in (10..20).step_by(2) {
+            link.receive_packet(s);
+            link.receive_packet(s);
+            link.receive_packet(s - 1);
+        }
+        assert_eq!(link.loss(), (15, 19));
+        assert_eq!(link.refresh(2000, MBPS), Some((19 << 24) / 15_000));
+        let flags = LinkMetricFlags::INCOMING_LINK;
+        let m = link.link_metric(0, flags).unwrap();
+        assert!(m.to_metric() >= link.metric().unwrap());
+
+        // Wrap around, then a restart isn't a loss.
+        let mut link = DatLink::new();
+        link.receive_packet(core::u16::MAX);
+        link.receive_packet(1);
+        assert_eq!(link.loss(), (2, 3));
+        link.receive_packet(1 + DAT_SEQNO_RESTART_DETECTION + 1);
+        assert_eq!(link.loss(), (3, 4));
+        link.receive_packet(0);
+        assert_eq!(link.loss(), (4, 5));
+
+        // The oldest bucket is dropped.
+        for t in 0..DAT_MEMORY_LENGTH as u64 - 1 {
+            link.refresh(t * 1000, MBPS);
+        }
+        assert_eq!(link.loss(), (4, 5));
+        link.refresh(63_000, MBPS);
+        assert_eq!(link.loss(), (0, 0));
+        assert_eq!(link.refresh(64_000, MBPS), None);
+    }
+
+    #[test]
+    fn test_dat_hello_timeout() {
+        let mut link = DatLink::new();
+        for s in 0..10 {
+            link.receive_packet(s);
+        }
+        link.receive_hello(0, 2000);
+        assert_eq!(link.refresh(2400, MBPS), Some(ONE));
+        // 16 s without HELLOs are a quarter of the history, 2.5 of the 10
+        // packets.
+        assert_eq!(link.refresh(16_000, MBPS), Some((12 << 24) / 10_000));
+        // The whole history at most.
+        assert_eq!(link.refresh(1_000_000, MBPS), Some(2 * ONE));
+
+        // Large times and intervals don't overflow.
+        link.receive_hello(0, core::u64::MAX);
+        assert_eq!(link.refresh(core::u64::MAX, MBPS), Some(ONE));
+        link.receive_hello(0, 2000);
+        assert_eq!(link.refresh(core::u64::MAX, MBPS), Some(2 * ONE));
+    }
+
+    #[cfg(feature = "use_std")]
+    #[test]
+    fn test_dat() {
+        use crate::{ManualClock, Packet};
+
+        let clock = ManualClock::new(0);
+        let a = Address::from_bytes(&[10, 0, 0, 1]).unwrap();
+        let b = Address::from_bytes(&[10, 0, 0, 2]).unwrap();
+        let speed = |addr: &Address| Some(if *addr == a { 1 } else { 2 });
+        struct Speeds<F>(F);
+        impl<F: Fn(&Address) -> Option<u64>> LinkSpeed for Speeds<F> {
+            fn link_speed(&self, addr: &Address) -> Option<u64> {
+                (self.0)(addr).map(|s| s * 1_000_000)
+            }
+        }
+        let mut dat = Dat::new(&clock, Speeds(speed));
+
+        // Packets with a sequence number and no messages.
+        let pkt = |s: u16| {
+            let s = s.to_be_bytes();
+            [0x08, s[0], s[1]]
+        };
+        for s in 0..4 {
+            let bin = pkt(s);
+            let hdr = Packet::read(&bin).unwrap().hdr;
+            dat.receive_packet(&a, &hdr);
+            dat.receive_packet(&b, &hdr);
+        }
+        let hdr = Packet::read(&[0]).unwrap().hdr;
+        dat.receive_packet(&a, &hdr);
+        assert_eq!(dat.link(&a).map(DatLink::loss), Some((4, 4)));
+
+        assert_eq!(dat.next_expiry(), Some(1000));
+        dat.expire();
+        assert_eq!(dat.metric(&a), None);
+        clock.set(2500);
+        dat.expire();
+        assert_eq!(dat.next_expiry(), Some(3000));
+        assert_eq!(dat.metric(&a), Some(ONE));
+        assert_eq!(dat.metric(&b), Some((1 << 24) / 2000));
+
+        dat.receive_hello(&b, 2000);
+        dat.remove(&a);
+        assert_eq!(dat.metric(&a), None);
+        assert!(dat.link(&b).is_some());
+
+        // A jump of the clock refreshes the history once, then restarts
+        // the intervals.
+        clock.set(core::u64::MAX - 10);
+        dat.expire();
+        assert_eq!(dat.next_expiry(), Some(core::u64::MAX));
+        assert_eq!(dat.link(&b).map(DatLink::loss), Some((0, 0)));
+    }
+}
@@ -0,0 +1,150 @@
+// Copyright 2020 Jean Pierre Dudey. See the LICENSE-MIT and
+// LICENSE-APACHE files at the top-level directory of this
+// distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! RFC 7181 section 6 representation of link metrics.
+//!
+//! A compressed metric is a 12-bit value holding an exponent `b` in its 4
+//! high bits and a mantissa `a` in its 8 low bits, for a metric of
+//! `(257 + a) * 2^b - 256`. The value of a LINK_METRIC TLV has the
+//! direction flags in its 4 high bits and the compressed metric in the
+//! rest.
+
+use crate::nhdp::LinkMetricFlags;
+use crate::Error;
+
+/// Smallest link metric.
+pub const MINIMUM_METRIC: u32 = 1;
+/// Largest link metric that can be represented.
+pub const MAXIMUM_METRIC: u32 = 16_776_960;
+/// Metric of a link without a known metric, it's never sent.
+pub const UNKNOWN_METRIC: u32 = MAXIMUM_METRIC + 1;
+
+/// Decode a compressed metric, only the 12 low bits of `code` are used.
+pub fn decode(code: u16) -> u32 {
+    let a = u32::from(code & 0xff);
+    let b = u32::from((code >> 8) & 0x0f);
+    ((257 + a) << b) - 256
+}
+
+/// Encode a metric, rounding up to the next metric that can be represented.
+///
+/// Metrics below [`MINIMUM_METRIC`](constant.MINIMUM_METRIC.html) are
+/// encoded as the minimum. Returns `None` for metrics above
+/// [`MAXIMUM_METRIC`](constant.MAXIMUM_METRIC.html), including
+/// [`UNKNOWN_METRIC`](constant.UNKNOWN_METRIC.html).
+pub fn encode(metric: u32) -> Option<u16> {
+    if metric > MAXIMUM_METRIC {
+        return None;
+    }
+
+    // Smallest exponent whose largest metric is large enough.
+    let target = metric + 256;
+    let b = (0..16).find(|b| 512u32 << b >= target)?;
+    let a = ((target + (1 << b) - 1) / (1 << b)).saturating_sub(257);
+    Some((b << 8) as u16 | a as u16)
+}
+
+/// Read the value of a LINK_METRIC TLV for one address, returns an
+/// iterator over the metric of each direction in it.
+///
+/// Fails with [`Error::InvalidTlvValue`] if the value isn't 2 bytes long.
+///
+/// [`Error::InvalidTlvValue`]: ../../enum.Error.html#variant.InvalidTlvValue
+pub fn directions(value: &[u8]) -> Result<Directions, Error> {
+    if value.len() != 2 {
+        return Err(Error::InvalidTlvValue);
+    }
+
+    let value = u16::from_be_bytes([value[0], value[1]]);
+    Ok(Directions {
+        flags: LinkMetricFlags::from_bits_truncate((value >> 12) as u8),
+        metric: decode(value),
+        index: 0,
+    })
+}
+
+/// Iterator over the directions of a LINK_METRIC TLV value, gives each
+/// direction flag with the decoded metric.
+#[derive(Debug, Clone)]
+pub struct Directions {
+    flags: LinkMetricFlags,
+    metric: u32,
+    index: usize,
+}
+
+impl Iterator for Directions {
+    type Item = (LinkMetricFlags, u32);
+
+    fn next(&mut self) -> Option<(LinkMetricFlags, u32)> {
+        const ALL: [LinkMetricFlags; 4] = [
+            LinkMetricFlags::INCOMING_LINK,
+            LinkMetricFlags::OUTGOING_LINK,
+            LinkMetricFlags::INCOMING_NEIGHBOR,
+            LinkMetricFlags::OUTGOING_NEIGHBOR,
+        ];
+
+        while self.index < ALL.len() {
+            let flag = ALL[self.index];
+            self.index += 1;
+            if self.flags.contains(flag) {
+                return Some((flag, self.metric));
+            }
+        }
+
+        None
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_metric_codec() {
+        assert_eq!(decode(0), MINIMUM_METRIC);
+        assert_eq!(decode(0x0fff), MAXIMUM_METRIC);
+        assert_eq!(decode(0xffff), MAXIMUM_METRIC);
+        assert_eq!(decode(0x00ff), 255 + 1);
+        assert_eq!(decode(0x0100), 514 - 256);
+
+        assert_eq!(encode(0), Some(0));
+        assert_eq!(encode(MINIMUM_METRIC), Some(0));
+        assert_eq!(encode(MAXIMUM_METRIC), Some(0x0fff));
+        assert_eq!(encode(MAXIMUM_METRIC + 1), None);
+        assert_eq!(encode(UNKNOWN_METRIC), None);
+        assert_eq!(encode(core::u32::MAX), None);
+
+        // Encoding rounds up.
+        for code in 0..0x1000 {
+            assert_eq!(encode(decode(code)), Some(code));
+            if code > 0 {
+                assert_eq!(encode(decode(code - 1) + 1), Some(code));
+            }
+        }
+    }
+
+    #[test]
+    fn test_directions() {
+        let value = (0xa000 | encode(1000).unwrap()).to_be_bytes();
+        let mut d = directions(&value).unwrap();
+        let metric = decode(encode(1000).unwrap());
+        assert!(metric >= 1000);
+        assert_eq!(d.next(), Some((LinkMetricFlags::INCOMING_LINK, metric)));
+        assert_eq!(
+            d.next(),
+            Some((LinkMetricFlags::INCOMING_NEIGHBOR, metric))
+        );
+        assert_eq!(d.next(), None);
+
+        assert_eq!(directions(&[0, 0]).unwrap().count(), 0);
+        assert_eq!(directions(&[0xf0, 0]).unwrap().count(), 4);
+        assert!(directions(&[0]).is_err());
+    }
+}
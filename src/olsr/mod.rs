@@ -0,0 +1,101 @@
+// Copyright 2020 Jean Pierre Dudey. See the LICENSE-MIT and
+// LICENSE-APACHE files at the top-level directory of this
+// distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! RFC 7181 Optimized Link State Routing Protocol version 2 (OLSRv2).
+//!
+//! The HELLO message TLVs of OLSRv2 are in the [`nhdp`](../nhdp/index.html)
+//! module. MPR selection ([`NeighborGraph`](struct.NeighborGraph.html)),
+//! the Topology Information Base and the routing table calculation
+//! ([`Topology`](struct.Topology.html)) need the `use_std` feature.
+//!
+//! # Multi-topology
+//!
+//! With RFC 7722 a router takes part in one topology per metric type, the
+//! types are listed in the MPR_TYPES TLV ([`MprTypes`](struct.MprTypes.html))
+//! of its HELLO and TC messages, its addresses have the LINK_METRIC TLVs of
+//! each type and the MPR TLVs of its HELLOs a value for each type.
+//! [`MultiTopology`](struct.MultiTopology.html) keeps a Topology Information
+//! Base and calculates a routing table for each one.
+//!
+//! # Multi-path
+//!
+//! With RFC 8218 (MP-OLSRv2) routers advertise their support with the
+//! MP_OLSRv2 TLV of their HELLO and TC messages, and the routers able to
+//! forward source-routed packets add a SOURCE_ROUTE TLV to their TCs.
+//! [`Topology::multipath`](struct.Topology.html#method.multipath) finds
+//! several paths to a destination through these routers, to be given in a
+//! source routing header.
+
+pub mod dat;
+pub mod metric;
+#[cfg(feature = "use_std")]
+mod mpr;
+mod mt;
+#[cfg(feature = "use_std")]
+mod multipath;
+mod tc;
+#[cfg(feature = "use_std")]
+mod topology;
+
+#[cfg(feature = "use_std")]
+pub use self::mpr::{mpr_value, MprType, MprViolation, NeighborGraph};
+pub use self::mt::{MprTypes, MAX_MPR_TYPES};
+#[cfg(feature = "use_std")]
+pub use self::multipath::{MultipathConfig, Path};
+pub use self::tc::{Tc, TcAddress, TcAddresses};
+#[cfg(feature = "use_std")]
+pub use self::topology::{
+    AdvertisingRouterTuple, AttachedNetworkTuple, LocalLink, LocalNeighbor,
+    MultiTopology, RoutableAddressTuple, Route, RouteChange,
+    RouterTopologyTuple, RoutingTable, Topology,
+};
+
+/// TC message type.
+pub const MSG_TYPE_TC: u8 = 1;
+
+/// CONT_SEQ_NUM message TLV type, its value is the ANSN.
+pub const MSG_TLV_CONT_SEQ_NUM: u8 = 8;
+/// CONT_SEQ_NUM type extension of a complete TC message.
+pub const CONT_SEQ_NUM_COMPLETE: u8 = 0;
+/// CONT_SEQ_NUM type extension of a TC message split in several messages.
+pub const CONT_SEQ_NUM_INCOMPLETE: u8 = 1;
+
+/// MPR_TYPES message TLV type (RFC 7722), in HELLO and TC messages.
+pub const MSG_TLV_MPR_TYPES: u8 = 9;
+/// MPR_TYPES type extension with the list of metric types, the only one
+/// defined. Type extensions 1 to 223 are unassigned.
+pub const MPR_TYPES_EXT_LIST: u8 = 0;
+/// First MPR_TYPES type extension for experimental use, up to 255.
+pub const MPR_TYPES_EXT_EXPERIMENTAL: u8 = 224;
+/// First LINK_METRIC type extension (metric type) for experimental use, up
+/// to 255.
+pub const METRIC_TYPE_EXPERIMENTAL: u8 = 224;
+
+/// MP_OLSRv2 message TLV type (RFC 8218), in HELLO and TC messages of the
+/// routers supporting MP-OLSRv2. Its value is empty.
+pub const MSG_TLV_MP_OLSRV2: u8 = 129;
+/// SOURCE_ROUTE message TLV type (RFC 8218), in TC messages of the routers
+/// forwarding source-routed packets. Its value is empty.
+pub const MSG_TLV_SOURCE_ROUTE: u8 = 130;
+
+/// NBR_ADDR_TYPE address TLV type.
+pub const ADDR_TLV_NBR_ADDR_TYPE: u8 = 9;
+/// GATEWAY address TLV type.
+pub const ADDR_TLV_GATEWAY: u8 = 10;
+
+bitflags! {
+    /// Value of a NBR_ADDR_TYPE address TLV, ROUTABLE_ORIG is both flags.
+    pub struct NbrAddrType: u8 {
+        /// Originator address of a neighbor.
+        const ORIGINATOR = 1;
+        /// Routable address of a neighbor.
+        const ROUTABLE = 2;
+    }
+}
@@ -0,0 +1,395 @@
+// Copyright 2020 Jean Pierre Dudey. See the LICENSE-MIT and
+// LICENSE-APACHE files at the top-level directory of this
+// distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::nhdp::{Mpr, WILL_ALWAYS, WILL_NEVER};
+use crate::olsr::metric::UNKNOWN_METRIC;
+
+/// Kind of MPR set.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum MprType {
+    /// Flooding MPRs, selected for each interface with the flooding
+    /// willingness and the outgoing link metrics.
+    Flooding,
+    /// Routing MPRs, selected with the routing willingness and the
+    /// incoming neighbor metrics, with the RFC 7187 optimization.
+    Routing,
+}
+
+/// Reason an MPR set doesn't meet the requirements of RFC 7181 section
+/// 18.3.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum MprViolation<K> {
+    /// The MPR isn't a 1-hop neighbor.
+    NotNeighbor(K),
+    /// The MPR has a willingness of WILL_NEVER.
+    WillNever(K),
+    /// A neighbor with a willingness of WILL_ALWAYS isn't an MPR.
+    MissingAlways(K),
+    /// No MPR is on a shortest path to this 2-hop neighbor.
+    Uncovered(K),
+}
+
+/// 1-hop (N1) and 2-hop (N2) neighbors of a router, the input of MPR
+/// selection.
+///
+/// Routers are identified by `K`, a 2-hop neighbor that is also a 1-hop
+/// neighbor must have the same key in both sets. Metrics of
+/// [`UNKNOWN_METRIC`](metric/constant.UNKNOWN_METRIC.html) or larger
+/// are links that can't be used.
+#[derive(Debug, Clone)]
+pub struct NeighborGraph<K> {
+    /// N1 with the willingness and `d1(y)` of each neighbor.
+    n1: BTreeMap<K, (u8, u32)>,
+    /// N2 with `d2(y, x)` for each neighbor `y` of each 2-hop neighbor `x`.
+    n2: BTreeMap<K, BTreeMap<K, u32>>,
+}
+
+impl<K: Ord + Copy> NeighborGraph<K> {
+    /// Create an empty graph.
+    pub fn new() -> NeighborGraph<K> {
+        NeighborGraph {
+            n1: BTreeMap::new(),
+            n2: BTreeMap::new(),
+        }
+    }
+
+    /// Add the 1-hop neighbor `y` with the willingness `willingness` and
+    /// the metric `metric` of the link to it.
+    pub fn add_neighbor(&mut self, y: K, willingness: u8, metric: u32) {
+        self.n1.insert(y, (willingness, metric));
+    }
+
+    /// Add the 2-hop neighbor `x` reachable through `y` with `metric`, the
+    /// smallest metric is kept if the link is added more than once.
+    pub fn add_two_hop(&mut self, y: K, x: K, metric: u32) {
+        let d2 = self.n2.entry(x).or_default().entry(y).or_insert(metric);
+        *d2 = (*d2).min(metric);
+    }
+
+    /// Select an MPR set with the heuristic of RFC 7181 appendix B.
+    ///
+    /// The neighbors with a willingness of WILL_ALWAYS are selected first,
+    /// then the only neighbors on a shortest path to a 2-hop neighbor, and
+    /// then the neighbors covering most of the remaining 2-hop neighbors,
+    /// preferring a higher willingness. Finally MPRs that aren't needed are
+    /// removed, lowest willingness first.
+    pub fn select(&self, mpr_type: MprType) -> BTreeSet<K> {
+        let required = self.required(mpr_type);
+        let willingness = |y: &K| self.n1[y].0;
+        let mut mprs = self
+            .n1
+            .iter()
+            .filter(|(_, n)| n.0 == WILL_ALWAYS)
+            .map(|(y, _)| *y)
+            .collect::<BTreeSet<_>>();
+
+        for covering in required.values() {
+            if covering.len() == 1 {
+                mprs.extend(covering.iter().cloned());
+            }
+        }
+
+        loop {
+            let uncovered = required
+                .values()
+                .filter(|c| c.is_disjoint(&mprs))
+                .collect::<Vec<_>>();
+            if uncovered.is_empty() {
+                break;
+            }
+
+            // Highest willingness, then most uncovered 2-hop neighbors
+            // (R(y)), then most 2-hop neighbors (D(y)).
+            let best = self
+                .n1
+                .keys()
+                .filter(|y| !mprs.contains(y) && willingness(y) != WILL_NEVER)
+                .map(|y| {
+                    let r = uncovered.iter().filter(|c| c.contains(y)).count();
+                    let d = required.values().filter(|c| c.contains(y)).count();
+                    (willingness(y), r, d, *y)
+                })
+                .filter(|(_, r, _, _)| *r > 0)
+                .fold(
+                    None,
+                    |best: Option<(u8, usize, usize, K)>, c| match best {
+                        Some(b) if (b.0, b.1, b.2) >= (c.0, c.1, c.2) => {
+                            Some(b)
+                        }
+                        _ => Some(c),
+                    },
+                );
+            match best {
+                Some((_, _, _, y)) => mprs.insert(y),
+                None => break,
+            };
+        }
+
+        let mut candidates = mprs
+            .iter()
+            .cloned()
+            .filter(|y| willingness(y) != WILL_ALWAYS)
+            .collect::<Vec<_>>();
+        candidates.sort_by_key(|y| willingness(y));
+        for y in candidates {
+            mprs.remove(&y);
+            if required.values().any(|c| c.is_disjoint(&mprs)) {
+                mprs.insert(y);
+            }
+        }
+
+        mprs
+    }
+
+    /// Check that `mprs` meets the requirements of an MPR set of type
+    /// `mpr_type`.
+    ///
+    /// Any selection strategy can be checked with it, not only
+    /// [`select`](#method.select).
+    pub fn check(
+        &self,
+        mpr_type: MprType,
+        mprs: &BTreeSet<K>,
+    ) -> Result<(), MprViolation<K>> {
+        for y in mprs {
+            match self.n1.get(y) {
+                None => return Err(MprViolation::NotNeighbor(*y)),
+                Some(n) if n.0 == WILL_NEVER => {
+                    return Err(MprViolation::WillNever(*y))
+                }
+                _ => (),
+            }
+        }
+        for (y, n) in self.n1.iter() {
+            if n.0 == WILL_ALWAYS && !mprs.contains(y) {
+                return Err(MprViolation::MissingAlways(*y));
+            }
+        }
+
+        // RFC 7181 section 18.3: every 2-hop neighbor x must be reached
+        // through an MPR with the metric d(x), the smallest d1(y) + d2(y, x)
+        // over the neighbors y that can be MPRs. Unless the link to x is
+        // better, or as good for routing MPRs (RFC 7187).
+        for (x, links) in self.n2.iter() {
+            let mut d = None;
+            let mut through_mprs = None;
+            for (y, d2) in links.iter() {
+                let d1 = match self.n1.get(y) {
+                    Some(n) if y != x && n.0 != WILL_NEVER => n.1,
+                    _ => continue,
+                };
+                let d_yx = match (metric(d1), metric(*d2)) {
+                    (Some(d1), Some(d2)) => d1 + d2,
+                    _ => continue,
+                };
+
+                d = Some(d.map_or(d_yx, |d: u64| d.min(d_yx)));
+                if mprs.contains(y) {
+                    through_mprs =
+                        Some(through_mprs.map_or(d_yx, |m: u64| m.min(d_yx)));
+                }
+            }
+
+            let d = match d {
+                Some(d) => d,
+                None => continue,
+            };
+            let direct = match self.n1.get(x).and_then(|n| metric(n.1)) {
+                Some(d1) => match mpr_type {
+                    MprType::Flooding => d1 < d,
+                    MprType::Routing => d1 <= d,
+                },
+                None => false,
+            };
+            if !direct && through_mprs != Some(d) {
+                return Err(MprViolation::Uncovered(*x));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// 2-hop neighbors that must be covered, with the neighbors `y` such
+    /// that `d(y, x) = d(x)`.
+    fn required(&self, mpr_type: MprType) -> BTreeMap<K, BTreeSet<K>> {
+        let mut required = BTreeMap::new();
+        for (x, links) in self.n2.iter() {
+            // Paths through the neighbors that can be MPRs.
+            let paths = links.iter().filter_map(|(y, d2)| {
+                let (w, d1) = *self.n1.get(y)?;
+                let d = metric(d1)? + metric(*d2)?;
+                if w == WILL_NEVER || y == x {
+                    return None;
+                }
+                Some((*y, d))
+            });
+            let d = match paths.clone().map(|(_, d)| d).min() {
+                Some(d) => d,
+                None => continue,
+            };
+
+            // A 1-hop neighbor doesn't need an MPR if the link to it is
+            // better than any 2-hop path, or as good for routing MPRs
+            // (RFC 7187).
+            let d1 = self.n1.get(x).and_then(|n| metric(n.1));
+            let direct = match (d1, mpr_type) {
+                (Some(d1), MprType::Flooding) => d1 < d,
+                (Some(d1), MprType::Routing) => d1 <= d,
+                (None, _) => false,
+            };
+            if direct {
+                continue;
+            }
+
+            let covering = paths.filter(|p| p.1 == d).map(|p| p.0).collect();
+            required.insert(*x, covering);
+        }
+
+        required
+    }
+}
+
+impl<K: Ord + Copy> Default for NeighborGraph<K> {
+    fn default() -> NeighborGraph<K> {
+        NeighborGraph::new()
+    }
+}
+
+/// Value of the MPR address TLV of the neighbor `y`, empty if it's neither
+/// a flooding nor a routing MPR.
+pub fn mpr_value<K: Ord>(
+    y: &K,
+    flooding: &BTreeSet<K>,
+    routing: &BTreeSet<K>,
+) -> Mpr {
+    let mut mpr = Mpr::empty();
+    if flooding.contains(y) {
+        mpr |= Mpr::FLOODING;
+    }
+    if routing.contains(y) {
+        mpr |= Mpr::ROUTING;
+    }
+    mpr
+}
+
+/// Metric of a usable link.
+fn metric(m: u32) -> Option<u64> {
+    Some(u64::from(m)).filter(|m| *m < u64::from(UNKNOWN_METRIC))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::nhdp::WILL_DEFAULT;
+
+    #[test]
+    fn test_mpr_select() {
+        // 1 and 2 reach 10, only 2 reaches 11, 3 reaches 12 with a better
+        // metric than 4.
+        let mut g = NeighborGraph::new();
+        for y in 1..=4 {
+            g.add_neighbor(y, WILL_DEFAULT, 1);
+        }
+        g.add_two_hop(1, 10, 1);
+        g.add_two_hop(2, 10, 1);
+        g.add_two_hop(2, 11, 1);
+        g.add_two_hop(3, 12, 1);
+        g.add_two_hop(4, 12, 5);
+
+        let mprs = g.select(MprType::Flooding);
+        assert_eq!(mprs, [2, 3].iter().cloned().collect());
+        assert_eq!(g.check(MprType::Flooding, &mprs), Ok(()));
+
+        let only = [2].iter().cloned().collect();
+        let res = g.check(MprType::Flooding, &only);
+        assert_eq!(res, Err(MprViolation::Uncovered(12)));
+        let with_4 = [2, 4].iter().cloned().collect();
+        let res = g.check(MprType::Flooding, &with_4);
+        assert_eq!(res, Err(MprViolation::Uncovered(12)));
+        let other = [2, 3, 9].iter().cloned().collect();
+        let res = g.check(MprType::Flooding, &other);
+        assert_eq!(res, Err(MprViolation::NotNeighbor(9)));
+
+        // Willingness.
+        g.add_neighbor(4, WILL_ALWAYS, 1);
+        g.add_neighbor(3, WILL_NEVER, 1);
+        let mprs = g.select(MprType::Flooding);
+        assert_eq!(mprs, [2, 4].iter().cloned().collect());
+        assert_eq!(g.check(MprType::Flooding, &mprs), Ok(()));
+        let res =
+            g.check(MprType::Flooding, &[2, 3, 4].iter().cloned().collect());
+        assert_eq!(res, Err(MprViolation::WillNever(3)));
+        let res = g.check(MprType::Flooding, &[2].iter().cloned().collect());
+        assert_eq!(res, Err(MprViolation::MissingAlways(4)));
+    }
+
+    #[test]
+    fn test_mpr_rfc7187() {
+        // 2 is a 1-hop neighbor also reachable through 1 with the same
+        // metric.
+        let mut g = NeighborGraph::new();
+        g.add_neighbor(1, WILL_DEFAULT, 1);
+        g.add_neighbor(2, WILL_DEFAULT, 2);
+        g.add_two_hop(1, 2, 1);
+
+        assert_eq!(g.select(MprType::Flooding), [1].iter().cloned().collect());
+        assert!(g.select(MprType::Routing).is_empty());
+        let none = BTreeSet::new();
+        let res = g.check(MprType::Flooding, &none);
+        assert_eq!(res, Err(MprViolation::Uncovered(2)));
+        assert_eq!(g.check(MprType::Routing, &none), Ok(()));
+
+        // A direct link that's better never needs an MPR.
+        g.add_neighbor(2, WILL_DEFAULT, 1);
+        assert!(g.select(MprType::Flooding).is_empty());
+
+        // Unknown metrics can't be used.
+        g.add_neighbor(2, WILL_DEFAULT, UNKNOWN_METRIC);
+        assert_eq!(g.select(MprType::Routing), [1].iter().cloned().collect());
+        g.add_neighbor(1, WILL_DEFAULT, UNKNOWN_METRIC);
+        assert!(g.select(MprType::Routing).is_empty());
+    }
+
+    #[test]
+    fn test_mpr_random() {
+        // Simple LCG, the selection must always pass the checker.
+        let mut seed = 0x2545_f491u32;
+        let mut rand = |n: u32| {
+            seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12345);
+            (seed >> 16) % n
+        };
+
+        for _ in 0..200 {
+            let mut g = NeighborGraph::new();
+            let n1 = 1 + rand(8);
+            for y in 0..n1 {
+                let w = [WILL_NEVER, WILL_DEFAULT, 3, WILL_ALWAYS];
+                g.add_neighbor(y, w[rand(4) as usize], 1 + rand(4));
+            }
+            for _ in 0..rand(30) {
+                g.add_two_hop(rand(n1), rand(16), 1 + rand(4));
+            }
+
+            for t in [MprType::Flooding, MprType::Routing].iter() {
+                let mprs = g.select(*t);
+                assert_eq!(g.check(*t, &mprs), Ok(()));
+
+                // No MPR can be removed.
+                for y in mprs.iter() {
+                    let mut fewer = mprs.clone();
+                    fewer.remove(y);
+                    assert!(g.check(*t, &fewer).is_err());
+                }
+            }
+        }
+    }
+}
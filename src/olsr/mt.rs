@@ -0,0 +1,180 @@
+// Copyright 2020 Jean Pierre Dudey. See the LICENSE-MIT and
+// LICENSE-APACHE files at the top-level directory of this
+// distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use crate::nhdp::MAX_LINK_METRICS;
+use crate::olsr::*;
+use crate::{Error, Tlv};
+
+/// Maximum number of metric types of an
+/// [`MprTypes`](struct.MprTypes.html), an address can't have the
+/// LINK_METRIC TLVs of more types.
+pub const MAX_MPR_TYPES: usize = MAX_LINK_METRICS;
+
+/// Value of an MPR_TYPES message TLV (RFC 7722): the metric types of the
+/// topologies a router takes part in, each one a LINK_METRIC type
+/// extension.
+///
+/// The order of the types is kept, the first one is the topology of the
+/// routers without MPR_TYPES.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Default)]
+pub struct MprTypes {
+    types: [u8; MAX_MPR_TYPES],
+    len: usize,
+}
+
+impl MprTypes {
+    /// Create an empty list.
+    pub fn new() -> MprTypes {
+        MprTypes::default()
+    }
+
+    /// Create a list with the metric types `types`.
+    ///
+    /// Fails with [`Error::InvalidTlvValue`] if a type is repeated, and
+    /// with [`Error::BufferTooSmall`] if there are more than
+    /// [`MAX_MPR_TYPES`](constant.MAX_MPR_TYPES.html) types.
+    ///
+    /// [`Error::InvalidTlvValue`]: ../enum.Error.html#variant.InvalidTlvValue
+    /// [`Error::BufferTooSmall`]: ../enum.Error.html#variant.BufferTooSmall
+    pub fn from_types(types: &[u8]) -> Result<MprTypes, Error> {
+        let mut mpr_types = MprTypes::new();
+        for t in types {
+            mpr_types.add(*t)?;
+        }
+        Ok(mpr_types)
+    }
+
+    /// Read the value of an MPR_TYPES TLV, the same as
+    /// [`from_types`](#method.from_types) but an empty value is also
+    /// rejected with
+    /// [`Error::InvalidTlvValue`](../enum.Error.html#variant.InvalidTlvValue).
+    pub fn from_value(value: &[u8]) -> Result<MprTypes, Error> {
+        if value.is_empty() {
+            return Err(Error::InvalidTlvValue);
+        }
+        MprTypes::from_types(value)
+    }
+
+    /// Add the metric type `metric_type` at the end of the list.
+    ///
+    /// Fails as [`from_types`](#method.from_types).
+    pub fn add(&mut self, metric_type: u8) -> Result<(), Error> {
+        if self.contains(metric_type) {
+            return Err(Error::InvalidTlvValue);
+        }
+        if self.len == MAX_MPR_TYPES {
+            return Err(Error::BufferTooSmall);
+        }
+
+        self.types[self.len] = metric_type;
+        self.len += 1;
+        Ok(())
+    }
+
+    /// The metric types, it's also the value of the TLV.
+    pub fn types(&self) -> &[u8] {
+        &self.types[..self.len]
+    }
+
+    /// Position of `metric_type` in the list.
+    pub fn index(&self, metric_type: u8) -> Option<usize> {
+        self.types().iter().position(|t| *t == metric_type)
+    }
+
+    /// Is `metric_type` in the list?
+    pub fn contains(&self, metric_type: u8) -> bool {
+        self.index(metric_type).is_some()
+    }
+
+    /// Number of metric types.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Is the list empty?
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Message TLV with the list, `None` if it's empty as the TLV can't be
+    /// empty.
+    pub fn tlv(&self) -> Option<Tlv<'_>> {
+        if self.is_empty() {
+            return None;
+        }
+
+        Some(Tlv {
+            r#type: MSG_TLV_MPR_TYPES,
+            type_ext: None,
+            start_index: None,
+            stop_index: None,
+            value: Some(self.types()),
+            multi_value: false,
+        })
+    }
+
+    /// Read the MPR_TYPES TLV `tlv` of a message into `slot`.
+    ///
+    /// Only the [`MPR_TYPES_EXT_LIST`](constant.MPR_TYPES_EXT_LIST.html)
+    /// type extension is read, the others are ignored. A second TLV is
+    /// rejected with
+    /// [`Error::InvalidMessage`](../enum.Error.html#variant.InvalidMessage).
+    pub(crate) fn read(
+        slot: &mut Option<MprTypes>,
+        tlv: &Tlv,
+    ) -> Result<(), Error> {
+        if tlv.type_ext() != MPR_TYPES_EXT_LIST {
+            return Ok(());
+        }
+
+        let mpr_types = MprTypes::from_value(tlv.value.unwrap_or(&[]))?;
+        crate::nhdp::set_once(slot, mpr_types)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_mpr_types() {
+        let mut mpr_types = MprTypes::from_types(&[3, 0]).unwrap();
+        assert_eq!(mpr_types.types(), &[3, 0][..]);
+        assert_eq!(mpr_types.index(0), Some(1));
+        assert!(!mpr_types.contains(1));
+        assert_eq!(mpr_types.add(3), Err(Error::InvalidTlvValue));
+        mpr_types.add(1).unwrap();
+        mpr_types.add(METRIC_TYPE_EXPERIMENTAL).unwrap();
+        assert_eq!(mpr_types.add(2), Err(Error::BufferTooSmall));
+        assert_eq!(mpr_types.len(), MAX_MPR_TYPES);
+
+        let tlv = mpr_types.tlv().unwrap();
+        assert_eq!(tlv.r#type, MSG_TLV_MPR_TYPES);
+        assert_eq!(tlv.value, Some(&[3, 0, 1, 224][..]));
+        assert_eq!(MprTypes::from_value(&[3, 0, 1, 224]), Ok(mpr_types));
+        assert_eq!(MprTypes::from_value(&[]), Err(Error::InvalidTlvValue));
+        assert!(MprTypes::new().tlv().is_none());
+
+        // Other type extensions are ignored, a second list is rejected.
+        let mut slot = None;
+        MprTypes::read(&mut slot, &tlv).unwrap();
+        assert_eq!(slot, Some(mpr_types));
+        let other = Tlv {
+            r#type: MSG_TLV_MPR_TYPES,
+            type_ext: Some(MPR_TYPES_EXT_EXPERIMENTAL),
+            start_index: None,
+            stop_index: None,
+            value: None,
+            multi_value: false,
+        };
+        MprTypes::read(&mut slot, &other).unwrap();
+        assert_eq!(MprTypes::read(&mut slot, &tlv), Err(Error::InvalidMessage));
+    }
+}
@@ -0,0 +1,424 @@
+// Copyright 2020 Jean Pierre Dudey. See the LICENSE-MIT and
+// LICENSE-APACHE files at the top-level directory of this
+// distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, BTreeSet, BinaryHeap};
+use std::convert::TryFrom;
+use std::iter;
+
+use crate::nhdp::{InterfaceId, WILL_NEVER};
+use crate::olsr::{LocalNeighbor, Topology};
+use crate::{Address, Clock};
+
+/// MP-OLSRv2 parameters (RFC 8218).
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct MultipathConfig {
+    /// NUMBER_OF_PATHS, how many times the shortest path is searched.
+    pub number_of_paths: usize,
+    /// MAX_SRH, the maximum number of routers in a source route.
+    pub max_srh: usize,
+    /// The cost of the links of a path is multiplied by this factor before
+    /// the next search, fp(c).
+    pub path_factor: u64,
+    /// The cost of the other links of the routers of a path is multiplied
+    /// by this factor before the next search, fe(c).
+    pub adjacent_factor: u64,
+    /// Remove the routers and links of a path instead of increasing their
+    /// cost, so the paths don't share a router.
+    pub disjoint: bool,
+}
+
+impl Default for MultipathConfig {
+    /// 3 paths, fp(c) = 3c and fe(c) = 2c.
+    fn default() -> MultipathConfig {
+        MultipathConfig {
+            number_of_paths: 3,
+            max_srh: 256,
+            path_factor: 3,
+            adjacent_factor: 2,
+            disjoint: false,
+        }
+    }
+}
+
+/// A path to a router.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Path {
+    /// Originator address of the destination.
+    pub dest_addr: Address,
+    /// Originator addresses of the routers of the path after this one, the
+    /// destination last.
+    pub hops: Vec<Address>,
+    /// Address of the first router on the link.
+    pub next_hop: Address,
+    /// Local interface of the link to the first router.
+    pub interface: InterfaceId,
+    /// Sum of the link metrics.
+    pub metric: u32,
+}
+
+impl Path {
+    /// Routers between this one and the destination.
+    pub fn intermediate(&self) -> &[Address] {
+        self.hops.split_last().map_or(&[][..], |(_, rest)| rest)
+    }
+
+    /// Content of the source routing header: the addresses of the
+    /// intermediate routers, in order. Empty for a neighbor.
+    pub fn source_route(&self) -> Vec<u8> {
+        let addrs = self.intermediate().iter();
+        addrs.flat_map(|a| a.as_bytes().iter().cloned()).collect()
+    }
+}
+
+/// A link of the graph, `from` is `None` for this router.
+#[derive(Debug)]
+struct Edge {
+    from: Option<Address>,
+    to: Address,
+    metric: u32,
+    /// Cost of the next search, `None` if it's removed.
+    cost: Option<u64>,
+    first_hop: Option<(Address, InterfaceId)>,
+}
+
+impl<C: Clock> Topology<C> {
+    /// Find up to `number_of_paths` paths to the router with the
+    /// originator address `dest_addr` (RFC 8218 section 5.4), with the
+    /// symmetric 1-hop neighbors `neighbors` of the router with the
+    /// addresses `local_addrs`.
+    ///
+    /// The shortest path is searched again after increasing the cost of
+    /// the links of the previous one, or removing them. The same path is
+    /// only given once, the shortest first. Intermediate routers must
+    /// forward source-routed packets: either neighbors willing to route
+    /// that advertised MP_OLSRv2 in their HELLOs, or routers that
+    /// advertised SOURCE_ROUTE in their TCs. Paths with more than
+    /// `max_srh` intermediate routers are dropped.
+    pub fn multipath(
+        &self,
+        local_addrs: &[Address],
+        neighbors: &[LocalNeighbor],
+        dest_addr: &Address,
+        config: &MultipathConfig,
+    ) -> Vec<Path> {
+        let mut edges = self.edges(neighbors);
+        let relays = self.relays(neighbors);
+        let mut paths: Vec<Path> = Vec::new();
+        if local_addrs.contains(dest_addr) {
+            return paths;
+        }
+
+        for _ in 0..config.number_of_paths {
+            let path = match shortest(&edges, &relays, local_addrs, dest_addr) {
+                Some(p) => p,
+                None => break,
+            };
+
+            let hops: Vec<Address> =
+                path.iter().map(|i| edges[*i].to).collect();
+            let on_path = &hops[..hops.len() - 1];
+            for (i, e) in edges.iter_mut().enumerate() {
+                let adjacent = e.from.map_or(false, |a| on_path.contains(&a))
+                    || on_path.contains(&e.to);
+                e.cost = match (e.cost, path.contains(&i), config.disjoint) {
+                    (_, true, true) => None,
+                    (Some(c), true, false) => {
+                        Some(c.saturating_mul(config.path_factor))
+                    }
+                    (_, false, true) if adjacent => None,
+                    (Some(c), false, false) if adjacent => {
+                        Some(c.saturating_mul(config.adjacent_factor))
+                    }
+                    (cost, _, _) => cost,
+                };
+            }
+
+            let metric = path.iter().map(|i| u64::from(edges[*i].metric));
+            let metric = metric.sum::<u64>();
+            let (next_hop, interface) = match edges[path[0]].first_hop {
+                Some(h) => h,
+                None => break,
+            };
+            let metric = match u32::try_from(metric) {
+                Ok(m) => m,
+                Err(_) => continue,
+            };
+            if on_path.len() > config.max_srh
+                || paths.iter().any(|p| p.hops == hops)
+            {
+                continue;
+            }
+            paths.push(Path {
+                dest_addr: *dest_addr,
+                hops,
+                next_hop,
+                interface,
+                metric,
+            });
+        }
+
+        paths
+    }
+
+    /// Paths to every router of the topology, see
+    /// [`multipath`](#method.multipath). Unreachable routers aren't
+    /// included.
+    pub fn multipaths(
+        &self,
+        local_addrs: &[Address],
+        neighbors: &[LocalNeighbor],
+        config: &MultipathConfig,
+    ) -> BTreeMap<Address, Vec<Path>> {
+        let neighbors_orig = neighbors.iter().filter_map(|n| n.orig_addr);
+        let dests = self
+            .router_topology()
+            .iter()
+            .flat_map(|t| {
+                iter::once(t.from_orig_addr).chain(iter::once(t.to_orig_addr))
+            })
+            .chain(neighbors_orig)
+            .collect::<BTreeSet<_>>();
+
+        dests
+            .into_iter()
+            .map(|d| (d, self.multipath(local_addrs, neighbors, &d, config)))
+            .filter(|(_, paths)| !paths.is_empty())
+            .collect()
+    }
+
+    /// Links from this router to its neighbors, with the best link to
+    /// each, and between the routers of the topology.
+    fn edges(&self, neighbors: &[LocalNeighbor]) -> Vec<Edge> {
+        let mut edges = Vec::new();
+        for n in neighbors {
+            let best = n
+                .links
+                .iter()
+                .filter(|l| !l.neighbor_iface_addrs.is_empty())
+                .min_by_key(|l| l.metric);
+            if let (Some(orig), Some(l)) = (n.orig_addr, best) {
+                edges.push(Edge {
+                    from: None,
+                    to: orig,
+                    metric: l.metric,
+                    cost: Some(u64::from(l.metric)),
+                    first_hop: Some((l.neighbor_iface_addrs[0], l.interface)),
+                });
+            }
+        }
+        for t in self.router_topology() {
+            edges.push(Edge {
+                from: Some(t.from_orig_addr),
+                to: t.to_orig_addr,
+                metric: t.metric,
+                cost: Some(u64::from(t.metric)),
+                first_hop: None,
+            });
+        }
+        edges
+    }
+
+    /// Routers that can be intermediate routers of a path.
+    fn relays(&self, neighbors: &[LocalNeighbor]) -> BTreeSet<Address> {
+        let neighbors = neighbors
+            .iter()
+            .filter(|n| n.mp_olsrv2 && n.willingness != WILL_NEVER)
+            .filter_map(|n| n.orig_addr);
+        let routers = self
+            .advertising_routers()
+            .iter()
+            .filter(|r| r.source_route)
+            .map(|r| r.orig_addr);
+        neighbors.chain(routers).collect()
+    }
+}
+
+/// Dijkstra's algorithm with the costs of `edges`, returns the indexes of
+/// the edges of the shortest path to `dest_addr`.
+fn shortest(
+    edges: &[Edge],
+    relays: &BTreeSet<Address>,
+    local_addrs: &[Address],
+    dest_addr: &Address,
+) -> Option<Vec<usize>> {
+    let mut heap = BinaryHeap::new();
+    for (i, e) in edges.iter().enumerate().filter(|(_, e)| e.from.is_none()) {
+        if let Some(cost) = e.cost {
+            heap.push(Reverse((cost, 1u8, e.to, i)));
+        }
+    }
+
+    // The edge reaching each router.
+    let mut reached = BTreeMap::new();
+    while let Some(Reverse((cost, hops, to, i))) = heap.pop() {
+        if reached.contains_key(&to) || local_addrs.contains(&to) {
+            continue;
+        }
+        reached.insert(to, i);
+        if to == *dest_addr {
+            break;
+        }
+        if !relays.contains(&to) {
+            continue;
+        }
+
+        for (j, e) in edges.iter().enumerate() {
+            if e.from != Some(to) || reached.contains_key(&e.to) {
+                continue;
+            }
+            if let Some(c) = e.cost {
+                let h = hops.saturating_add(1);
+                heap.push(Reverse((cost.saturating_add(c), h, e.to, j)));
+            }
+        }
+    }
+
+    let mut path = Vec::new();
+    let mut i = *reached.get(dest_addr)?;
+    loop {
+        path.push(i);
+        match edges[i].from {
+            Some(from) => i = reached[&from],
+            None => break,
+        }
+    }
+    path.reverse();
+    Some(path)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::nhdp::{LinkMetric, LinkMetricFlags, Nhdp, NhdpConfig};
+    use crate::nhdp::{WILL_DEFAULT, WILL_NEVER};
+    use crate::olsr::{LocalLink, NbrAddrType, Tc, TcAddress};
+    use crate::{Buf, ManualClock, Message};
+
+    fn addr(last: u8) -> Address {
+        Address::from_bytes(&[10, 0, 0, last]).unwrap()
+    }
+
+    fn advertise(
+        topology: &mut Topology<&ManualClock>,
+        last: u8,
+        ansn: u16,
+        source_route: bool,
+        links: &[(u8, u32)],
+    ) {
+        let addrs = links
+            .iter()
+            .map(|(to, m)| {
+                let mut a = TcAddress::new(addr(*to));
+                a.nbr_addr_type = NbrAddrType::ORIGINATOR;
+                let flags = LinkMetricFlags::OUTGOING_NEIGHBOR;
+                let m = LinkMetric::from_metric(0, flags, *m).unwrap();
+                a.add_link_metric(m).unwrap();
+                a
+            })
+            .collect::<Vec<_>>();
+        let orig = [10, 0, 0, last];
+        let mut tc = Tc::new(4, &orig, 0, ansn, 10_000);
+        tc.mp_olsrv2 = true;
+        tc.source_route = source_route;
+        let mut buf = [0u8; 256];
+        let size = tc.write(&addrs, &mut buf).unwrap();
+        let msg = Message::read(&mut Buf::new(&buf[..size])).unwrap();
+        let tc = Tc::from_message(&msg).unwrap();
+        assert_eq!(tc.source_route, source_route);
+        assert_eq!(topology.process_tc(&tc), Ok(true));
+    }
+
+    #[test]
+    fn test_multipath() {
+        let clock = ManualClock::new(0);
+        let mut nhdp = Nhdp::new(&clock, 4, NhdpConfig::default());
+        let local = addr(1);
+        let iface = nhdp.add_interface(&[local]);
+        let mut topology = Topology::new(&clock, 0);
+
+        // L - B - D - C and L - E - D, D doesn't forward source routes.
+        let mut neighbors = [2, 5]
+            .iter()
+            .map(|last| LocalNeighbor {
+                orig_addr: Some(addr(*last)),
+                willingness: WILL_DEFAULT,
+                mp_olsrv2: false,
+                links: vec![LocalLink {
+                    interface: iface,
+                    neighbor_iface_addrs: vec![addr(*last)],
+                    metric: 1,
+                }],
+                two_hops: Vec::new(),
+            })
+            .collect::<Vec<_>>();
+        advertise(&mut topology, 2, 1, true, &[(4, 1)]);
+        advertise(&mut topology, 5, 1, true, &[(4, 2)]);
+        advertise(&mut topology, 4, 1, false, &[(3, 1)]);
+
+        // The second path avoids B, the third one is B again.
+        let config = MultipathConfig::default();
+        let paths = topology.multipath(&[local], &neighbors, &addr(4), &config);
+        let hops = paths.iter().map(|p| p.hops.clone()).collect::<Vec<_>>();
+        assert_eq!(hops, vec![vec![addr(2), addr(4)], vec![addr(5), addr(4)]]);
+        assert_eq!((paths[0].metric, paths[1].metric), (2, 3));
+        assert_eq!(paths[1].next_hop, addr(5));
+        assert_eq!(paths[1].interface, iface);
+        assert_eq!(paths[0].source_route(), vec![10, 0, 0, 2]);
+
+        // Disjoint paths, or fewer of them.
+        let disjoint = MultipathConfig {
+            disjoint: true,
+            ..config
+        };
+        let paths =
+            topology.multipath(&[local], &neighbors, &addr(4), &disjoint);
+        assert_eq!(paths.len(), 2);
+        let one = MultipathConfig {
+            number_of_paths: 1,
+            ..config
+        };
+        let paths = topology.multipath(&[local], &neighbors, &addr(4), &one);
+        assert_eq!(paths.len(), 1);
+
+        // C is behind D, neighbors are reached without a source route.
+        assert!(topology
+            .multipath(&[local], &neighbors, &addr(3), &config)
+            .is_empty());
+        let paths = topology.multipath(&[local], &neighbors, &addr(2), &config);
+        assert_eq!(paths.len(), 1);
+        assert!(paths[0].source_route().is_empty());
+        let all = topology.multipaths(&[local], &neighbors, &config);
+        assert_eq!(
+            all.keys().cloned().collect::<Vec<_>>(),
+            vec![addr(2), addr(4), addr(5)]
+        );
+
+        // MAX_SRH limits the intermediate routers.
+        let short = MultipathConfig {
+            max_srh: 0,
+            ..config
+        };
+        let paths = topology.multipath(&[local], &neighbors, &addr(4), &short);
+        assert!(paths.is_empty());
+
+        // E stops forwarding source routes, unless it advertises MP_OLSRv2
+        // in its HELLOs and is willing to route.
+        advertise(&mut topology, 5, 2, false, &[(4, 2)]);
+        let paths = topology.multipath(&[local], &neighbors, &addr(4), &config);
+        assert_eq!(paths.len(), 1);
+        neighbors[1].mp_olsrv2 = true;
+        let paths = topology.multipath(&[local], &neighbors, &addr(4), &config);
+        assert_eq!(paths.len(), 2);
+        neighbors[1].willingness = WILL_NEVER;
+        let paths = topology.multipath(&[local], &neighbors, &addr(4), &config);
+        assert_eq!(paths.len(), 1);
+    }
+}
@@ -0,0 +1,660 @@
+// Copyright 2020 Jean Pierre Dudey. See the LICENSE-MIT and
+// LICENSE-APACHE files at the top-level directory of this
+// distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use crate::addrtlv::UniqueAddresses;
+use crate::builder::split;
+use crate::nhdp::{
+    msg_tlv, set_once, set_value, LinkMetric, LinkMetricFlags, LinkMetrics,
+    ADDR_TLV_LINK_METRIC, MSG_TLV_INTERVAL_TIME, MSG_TLV_VALIDITY_TIME,
+};
+use crate::olsr::*;
+use crate::{
+    time, Address, AddressTlvs, Error, Message, MessageBuilder, MsgHeader, Tlv,
+};
+
+/// TC (Topology Control) message.
+#[derive(Debug, Clone)]
+pub struct Tc<'a> {
+    /// `<msg-addr-length>`
+    pub address_length: usize,
+    /// `<msg-orig-addr>`
+    pub orig_addr: &'a [u8],
+    /// `<msg-seq-num>`
+    pub seq_num: u16,
+    /// `<msg-hop-limit>`
+    pub hop_limit: u8,
+    /// `<msg-hop-count>`
+    pub hop_count: u8,
+    /// Advertised Neighbor Sequence Number, the value of the CONT_SEQ_NUM
+    /// TLV.
+    pub ansn: u16,
+    /// Is the TC complete? `false` if it was split in several messages.
+    pub complete: bool,
+    /// VALIDITY_TIME in milliseconds, for the hop count of the message.
+    pub validity_time: u64,
+    /// INTERVAL_TIME in milliseconds, for the hop count of the message.
+    pub interval_time: Option<u64>,
+    /// MPR_TYPES, absent when the originator only takes part in the
+    /// topology of its first metric type.
+    pub mpr_types: Option<MprTypes>,
+    /// Does the TC have an MP_OLSRv2 TLV?
+    pub mp_olsrv2: bool,
+    /// Does the TC have a SOURCE_ROUTE TLV?
+    pub source_route: bool,
+    address_tlv: Option<AddressTlvs<'a>>,
+}
+
+impl<'a> Tc<'a> {
+    /// Create a complete TC without addresses, with a hop limit of 255 and
+    /// a hop count of 0.
+    pub fn new(
+        address_length: usize,
+        orig_addr: &'a [u8],
+        seq_num: u16,
+        ansn: u16,
+        validity_time: u64,
+    ) -> Tc<'a> {
+        Tc {
+            address_length,
+            orig_addr,
+            seq_num,
+            hop_limit: core::u8::MAX,
+            hop_count: 0,
+            ansn,
+            complete: true,
+            validity_time,
+            interval_time: None,
+            mpr_types: None,
+            mp_olsrv2: false,
+            source_route: false,
+            address_tlv: None,
+        }
+    }
+
+    /// Read a TC from a message.
+    ///
+    /// Messages breaking the RFC 7181 section 16.3.1 rules that don't
+    /// depend on the state of the router are rejected with
+    /// [`Error::InvalidMessage`](../enum.Error.html#variant.InvalidMessage):
+    ///
+    /// - A missing `<msg-orig-addr>`, `<msg-seq-num>`, `<msg-hop-limit>` or
+    ///   `<msg-hop-count>`.
+    /// - Not exactly one VALIDITY_TIME TLV, or more than one INTERVAL_TIME,
+    ///   MPR_TYPES, MP_OLSRv2 or SOURCE_ROUTE TLV.
+    /// - Not exactly one CONT_SEQ_NUM TLV with the COMPLETE or INCOMPLETE
+    ///   type extension.
+    /// - An address with a NBR_ADDR_TYPE TLV and a prefix length other than
+    ///   the maximum.
+    /// - An address with both a NBR_ADDR_TYPE and a GATEWAY TLV.
+    /// - An address with different values for the GATEWAY TLV, or for
+    ///   LINK_METRIC TLVs of the same metric type and direction.
+    pub fn from_message(msg: &Message<'a>) -> Result<Tc<'a>, Error> {
+        let hdr = &msg.hdr;
+        if hdr.r#type != MSG_TYPE_TC {
+            return Err(Error::InvalidMessage);
+        }
+        let orig_addr = hdr.orig_addr.ok_or(Error::InvalidMessage)?;
+        let seq_num = hdr.seq_num.ok_or(Error::InvalidMessage)?;
+        let hop_limit = hdr.hop_limit.ok_or(Error::InvalidMessage)?;
+        let hop_count = hdr.hop_count.ok_or(Error::InvalidMessage)?;
+
+        let mut validity_time = None;
+        let mut interval_time = None;
+        let mut cont_seq_num = None;
+        let mut mpr_types = None;
+        let mut mp_olsrv2 = None;
+        let mut source_route = None;
+        for tlv in msg.tlv_block.iter() {
+            let tlv = tlv?;
+            let value = tlv.value.unwrap_or(&[]);
+            match (tlv.r#type, tlv.type_ext()) {
+                (MSG_TLV_VALIDITY_TIME, 0) => {
+                    let t = time::decode_tlv_value(value, hop_count)?;
+                    set_once(&mut validity_time, t)?;
+                }
+                (MSG_TLV_INTERVAL_TIME, 0) => {
+                    let t = time::decode_tlv_value(value, hop_count)?;
+                    set_once(&mut interval_time, t)?;
+                }
+                (MSG_TLV_CONT_SEQ_NUM, ext)
+                    if ext == CONT_SEQ_NUM_COMPLETE
+                        || ext == CONT_SEQ_NUM_INCOMPLETE =>
+                {
+                    if value.len() != 2 {
+                        return Err(Error::InvalidTlvValue);
+                    }
+                    let ansn = u16::from_be_bytes([value[0], value[1]]);
+                    let complete = ext == CONT_SEQ_NUM_COMPLETE;
+                    set_once(&mut cont_seq_num, (ansn, complete))?;
+                }
+                (MSG_TLV_MPR_TYPES, _) => MprTypes::read(&mut mpr_types, &tlv)?,
+                (MSG_TLV_MP_OLSRV2, 0) => set_once(&mut mp_olsrv2, ())?,
+                (MSG_TLV_SOURCE_ROUTE, 0) => set_once(&mut source_route, ())?,
+                _ => (),
+            }
+        }
+
+        let (ansn, complete) = cont_seq_num.ok_or(Error::InvalidMessage)?;
+        let address_tlv = msg.address_tlv.clone();
+        for (addr, prefix_length) in address_tlv.unique() {
+            collect(&address_tlv, addr, prefix_length)?;
+        }
+
+        Ok(Tc {
+            address_length: hdr.address_length,
+            orig_addr,
+            seq_num,
+            hop_limit,
+            hop_count,
+            ansn,
+            complete,
+            validity_time: validity_time.ok_or(Error::InvalidMessage)?,
+            interval_time,
+            mpr_types,
+            mp_olsrv2: mp_olsrv2.is_some(),
+            source_route: source_route.is_some(),
+            address_tlv: Some(address_tlv),
+        })
+    }
+
+    /// Addresses of the TC with their TLVs.
+    ///
+    /// Each address and prefix length is given once, with the TLVs of every
+    /// address block it appears in.
+    pub fn addresses(&self) -> TcAddresses<'a> {
+        TcAddresses {
+            address_tlv: self.address_tlv.clone(),
+            unique: self.address_tlv.as_ref().map(|a| a.unique()),
+        }
+    }
+
+    /// Write the TC with the addresses `addrs` to `buf` as a single message,
+    /// returns the size of the message.
+    ///
+    /// Fails with [`Error::BufferTooSmall`] if it doesn't fit, see
+    /// [`write_split`](#method.write_split) to split it instead.
+    ///
+    /// [`Error::BufferTooSmall`]: ../enum.Error.html#variant.BufferTooSmall
+    pub fn write(
+        &self,
+        addrs: &[TcAddress],
+        buf: &mut [u8],
+    ) -> Result<usize, Error> {
+        check_addresses(addrs)?;
+        self.write_part(addrs, self.seq_num, self.complete, buf)
+    }
+
+    /// Write the TC with the addresses `addrs` as the fewest messages that
+    /// fit in `buf`, calling `emit` with each of them. Returns the number of
+    /// messages.
+    ///
+    /// When the TC doesn't fit in a single message every part has the
+    /// INCOMPLETE type extension, and the sequence numbers following
+    /// `seq_num`. An address is never split across messages.
+    pub fn write_split<F>(
+        &self,
+        addrs: &[TcAddress],
+        buf: &mut [u8],
+        mut emit: F,
+    ) -> Result<usize, Error>
+    where
+        F: FnMut(&[u8]) -> Result<(), Error>,
+    {
+        check_addresses(addrs)?;
+        match self.write_part(addrs, self.seq_num, self.complete, buf) {
+            Ok(size) => {
+                emit(&buf[..size])?;
+                return Ok(1);
+            }
+            Err(Error::BufferTooSmall) if addrs.len() > 1 => (),
+            Err(e) => return Err(e),
+        }
+
+        split(addrs.len(), buf, emit, |part, n, buf| {
+            let seq_num = self.seq_num.wrapping_add(n as u16);
+            self.write_part(&addrs[part], seq_num, false, buf)
+        })
+    }
+
+    /// Write a message with the addresses `addrs`.
+    fn write_part(
+        &self,
+        addrs: &[TcAddress],
+        seq_num: u16,
+        complete: bool,
+        buf: &mut [u8],
+    ) -> Result<usize, Error> {
+        let mut hdr = MsgHeader::new(MSG_TYPE_TC, self.address_length);
+        hdr.orig_addr = Some(self.orig_addr);
+        hdr.seq_num = Some(seq_num);
+        hdr.hop_limit = Some(self.hop_limit);
+        hdr.hop_count = Some(self.hop_count);
+
+        let mut builder = MessageBuilder::new(buf, &hdr)?;
+        let ansn = self.ansn.to_be_bytes();
+        let cont_seq_num = Tlv {
+            type_ext: Some(CONT_SEQ_NUM_INCOMPLETE).filter(|_| !complete),
+            ..msg_tlv(MSG_TLV_CONT_SEQ_NUM, &ansn)
+        };
+        builder.add_tlv(&cont_seq_num)?;
+        let validity_time = [time::encode(self.validity_time)];
+        builder.add_tlv(&msg_tlv(MSG_TLV_VALIDITY_TIME, &validity_time))?;
+        if let Some(interval_time) = self.interval_time {
+            let interval_time = [time::encode(interval_time)];
+            builder.add_tlv(&msg_tlv(MSG_TLV_INTERVAL_TIME, &interval_time))?;
+        }
+        if let Some(tlv) = self.mpr_types.as_ref().and_then(MprTypes::tlv) {
+            builder.add_tlv(&tlv)?;
+        }
+        if self.mp_olsrv2 {
+            builder.add_tlv(&msg_tlv(MSG_TLV_MP_OLSRV2, &[]))?;
+        }
+        if self.source_route {
+            builder.add_tlv(&msg_tlv(MSG_TLV_SOURCE_ROUTE, &[]))?;
+        }
+
+        let max_prefix = (8 * self.address_length) as u8;
+        for block in addrs.chunks(usize::from(core::u8::MAX)) {
+            let mut prefix_lengths = [0u8; core::u8::MAX as usize];
+            for (p, a) in prefix_lengths.iter_mut().zip(block) {
+                *p = a.prefix_length.unwrap_or(max_prefix);
+            }
+            let prefix_lengths = if block.iter().all(|a| a.is_host()) {
+                None
+            } else {
+                Some(&prefix_lengths[..block.len()])
+            };
+
+            builder.add_address_block(block, prefix_lengths)?;
+            write_address_tlvs(&mut builder, block)?;
+        }
+
+        builder.finish()
+    }
+}
+
+/// An address of a TC and its TLVs, either an advertised neighbor address
+/// (with NBR_ADDR_TYPE) or an attached network (with GATEWAY).
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct TcAddress {
+    /// The address.
+    pub addr: Address,
+    /// Prefix length, `None` if it's the maximum.
+    pub prefix_length: Option<u8>,
+    /// NBR_ADDR_TYPE, empty if absent.
+    pub nbr_addr_type: NbrAddrType,
+    /// GATEWAY, the number of hops from the originator to the attached
+    /// network.
+    pub gateway: Option<u8>,
+    link_metrics: LinkMetrics,
+}
+
+impl TcAddress {
+    /// Create an address with the maximum prefix length and without TLVs.
+    pub fn new(addr: Address) -> TcAddress {
+        TcAddress {
+            addr,
+            prefix_length: None,
+            nbr_addr_type: NbrAddrType::empty(),
+            gateway: None,
+            link_metrics: LinkMetrics::default(),
+        }
+    }
+
+    /// Create an attached network `addr/prefix_length` at `gateway` hops.
+    pub fn network(addr: Address, prefix_length: u8, gateway: u8) -> TcAddress {
+        TcAddress {
+            prefix_length: Some(prefix_length)
+                .filter(|p| usize::from(*p) != 8 * addr.len()),
+            gateway: Some(gateway),
+            ..TcAddress::new(addr)
+        }
+    }
+
+    /// LINK_METRIC TLVs of the address.
+    pub fn link_metrics(&self) -> impl Iterator<Item = LinkMetric> + '_ {
+        self.link_metrics.iter()
+    }
+
+    /// Add a LINK_METRIC TLV, see
+    /// [`HelloAddress::add_link_metric`](../nhdp/struct.HelloAddress.html#method.add_link_metric).
+    pub fn add_link_metric(&mut self, metric: LinkMetric) -> Result<(), Error> {
+        self.link_metrics.add(metric)
+    }
+
+    /// Metric for the direction `flags` of type `metric_type`.
+    pub fn link_metric(
+        &self,
+        metric_type: u8,
+        flags: LinkMetricFlags,
+    ) -> Option<u16> {
+        self.link_metrics.get(metric_type, flags)
+    }
+
+    /// Prefix length, `None` if it's the maximum.
+    fn prefix(&self) -> Option<u8> {
+        self.prefix_length
+            .filter(|p| usize::from(*p) != 8 * self.addr.len())
+    }
+
+    /// Does the address have the maximum prefix length?
+    fn is_host(&self) -> bool {
+        self.prefix().is_none()
+    }
+
+    /// Check the rules of section 16.3.1 that apply to a single address.
+    fn check(&self) -> Result<(), Error> {
+        if !self.nbr_addr_type.is_empty()
+            && (self.gateway.is_some() || !self.is_host())
+        {
+            return Err(Error::InvalidMessage);
+        }
+
+        Ok(())
+    }
+}
+
+impl AsRef<Address> for TcAddress {
+    fn as_ref(&self) -> &Address {
+        &self.addr
+    }
+}
+
+/// Iterator over the addresses of a TC.
+#[derive(Debug)]
+pub struct TcAddresses<'a> {
+    address_tlv: Option<AddressTlvs<'a>>,
+    unique: Option<UniqueAddresses<'a>>,
+}
+
+impl<'a> Iterator for TcAddresses<'a> {
+    type Item = TcAddress;
+
+    fn next(&mut self) -> Option<TcAddress> {
+        let address_tlv = self.address_tlv.as_ref()?;
+        loop {
+            let (addr, prefix_length) = self.unique.as_mut()?.next()?;
+            if let Ok(a) = collect(address_tlv, addr, prefix_length) {
+                return Some(a);
+            }
+        }
+    }
+}
+
+/// Merge the TLVs of every appearance of `addr/prefix_length`.
+fn collect(
+    address_tlv: &AddressTlvs,
+    addr: Address,
+    prefix_length: Option<u8>,
+) -> Result<TcAddress, Error> {
+    let mut a = TcAddress::new(addr);
+    a.prefix_length = prefix_length;
+    let mut gateway = None;
+
+    address_tlv.for_each_tlv(&addr, prefix_length, |tlv, value| {
+        match (tlv.r#type, tlv.type_ext()) {
+            (ADDR_TLV_NBR_ADDR_TYPE, 0) => {
+                if value.len() != 1 {
+                    return Err(Error::InvalidTlvValue);
+                }
+                // An address can be both an originator and routable
+                // address, in one or two TLVs.
+                a.nbr_addr_type |= NbrAddrType::from_bits_truncate(value[0]);
+                Ok(())
+            }
+            (ADDR_TLV_GATEWAY, 0) => set_value(&mut gateway, value),
+            (ADDR_TLV_LINK_METRIC, _) => a.link_metrics.read(tlv, value),
+            _ => Ok(()),
+        }
+    })?;
+
+    a.gateway = gateway;
+    a.check()?;
+    Ok(a)
+}
+
+/// Check the addresses to write, every address and prefix length must be
+/// given once.
+fn check_addresses(addrs: &[TcAddress]) -> Result<(), Error> {
+    for (i, a) in addrs.iter().enumerate() {
+        a.check()?;
+        let duplicate = addrs[..i]
+            .iter()
+            .any(|b| b.addr == a.addr && b.prefix() == a.prefix());
+        if duplicate {
+            return Err(Error::InvalidMessage);
+        }
+    }
+
+    Ok(())
+}
+
+/// Write the TLVs of the last address block, with the addresses `block`.
+fn write_address_tlvs(
+    builder: &mut MessageBuilder,
+    block: &[TcAddress],
+) -> Result<(), Error> {
+    builder.add_address_tlvs_u8(ADDR_TLV_NBR_ADDR_TYPE, None, |i| {
+        Some(block[i].nbr_addr_type)
+            .filter(|t| !t.is_empty())
+            .map(|t| t.bits())
+    })?;
+    builder
+        .add_address_tlvs_u8(ADDR_TLV_GATEWAY, None, |i| block[i].gateway)?;
+    LinkMetrics::write(builder, block.len(), |i| block[i].link_metrics)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::Buf;
+
+    fn addr(bytes: &[u8]) -> Address {
+        Address::from_bytes(bytes).unwrap()
+    }
+
+    fn read(bin: &[u8]) -> Result<Tc<'_>, Error> {
+        let msg = Message::read(&mut Buf::new(bin)).unwrap();
+        Tc::from_message(&msg)
+    }
+
+    fn neighbor(last: u8, nbr_addr_type: NbrAddrType) -> TcAddress {
+        let mut a = TcAddress::new(addr(&[10, 0, 0, last]));
+        a.nbr_addr_type = nbr_addr_type;
+        a
+    }
+
+    #[test]
+    fn test_tc_roundtrip() {
+        let orig = [10, 0, 0, 1];
+        let mut tc = Tc::new(4, &orig, 9, 0x1234, 6000);
+        tc.interval_time = Some(2000);
+        tc.hop_limit = 10;
+        tc.hop_count = 1;
+        tc.mpr_types = Some(MprTypes::from_types(&[0, 3]).unwrap());
+        tc.mp_olsrv2 = true;
+        tc.source_route = true;
+
+        let mut addrs = [
+            neighbor(2, NbrAddrType::ORIGINATOR | NbrAddrType::ROUTABLE),
+            neighbor(3, NbrAddrType::ROUTABLE),
+            neighbor(4, NbrAddrType::ORIGINATOR),
+            TcAddress::network(addr(&[192, 168, 1, 0]), 24, 2),
+        ];
+        let metric = |metric| LinkMetric {
+            metric_type: 0,
+            flags: LinkMetricFlags::OUTGOING_NEIGHBOR,
+            metric,
+        };
+        addrs[0].add_link_metric(metric(0x100)).unwrap();
+        addrs[1].add_link_metric(metric(0x100)).unwrap();
+        addrs[3].add_link_metric(metric(0x200)).unwrap();
+
+        let mut buf = [0u8; 128];
+        let size = tc.write(&addrs, &mut buf).unwrap();
+        let parsed = read(&buf[..size]).unwrap();
+        assert_eq!(parsed.orig_addr, &orig[..]);
+        assert_eq!(parsed.seq_num, 9);
+        assert_eq!(parsed.hop_limit, 10);
+        assert_eq!(parsed.hop_count, 1);
+        assert_eq!(parsed.ansn, 0x1234);
+        assert!(parsed.complete);
+        assert_eq!(parsed.validity_time, time::decode(time::encode(6000)));
+        assert_eq!(parsed.interval_time, Some(2000));
+        assert_eq!(parsed.mpr_types, tc.mpr_types);
+        assert!(parsed.mp_olsrv2 && parsed.source_route);
+
+        assert!(parsed.addresses().eq(addrs.iter().cloned()));
+        let network = parsed.addresses().nth(3).unwrap();
+        assert_eq!(network.prefix_length, Some(24));
+        assert_eq!(network.gateway, Some(2));
+        assert_eq!(
+            network.link_metric(0, LinkMetricFlags::OUTGOING_NEIGHBOR),
+            Some(0x200)
+        );
+    }
+
+    #[test]
+    fn test_tc_invalid() {
+        let orig = [10, 0, 0, 1];
+        let tc = Tc::new(4, &orig, 1, 1, 6000);
+        let mut buf = [0u8; 64];
+
+        // NBR_ADDR_TYPE with a GATEWAY or a prefix isn't allowed.
+        let mut a = neighbor(2, NbrAddrType::ROUTABLE);
+        a.gateway = Some(1);
+        assert_eq!(tc.write(&[a], &mut buf), Err(Error::InvalidMessage));
+        a.gateway = None;
+        a.prefix_length = Some(24);
+        assert_eq!(tc.write(&[a], &mut buf), Err(Error::InvalidMessage));
+        let a = neighbor(2, NbrAddrType::ROUTABLE);
+        assert_eq!(tc.write(&[a, a], &mut buf), Err(Error::InvalidMessage));
+
+        // Same checks when reading, with messages written by hand.
+        let invalid = |f: &dyn Fn(&mut MessageBuilder)| {
+            let mut buf = [0u8; 64];
+            let mut hdr = MsgHeader::new(MSG_TYPE_TC, 4);
+            hdr.orig_addr = Some(&orig);
+            hdr.seq_num = Some(1);
+            hdr.hop_limit = Some(255);
+            hdr.hop_count = Some(0);
+            let mut builder = MessageBuilder::new(&mut buf, &hdr).unwrap();
+            f(&mut builder);
+            let size = builder.finish().unwrap();
+            read(&buf[..size]).err()
+        };
+        let ansn = [0, 1];
+        let validity = [time::encode(6000)];
+        let cont_seq_num = msg_tlv(MSG_TLV_CONT_SEQ_NUM, &ansn);
+        let validity_time = msg_tlv(MSG_TLV_VALIDITY_TIME, &validity);
+
+        assert_eq!(
+            invalid(&|b| {
+                b.add_tlv(&cont_seq_num).unwrap();
+                b.add_tlv(&validity_time).unwrap();
+            }),
+            None
+        );
+        assert_eq!(
+            invalid(&|b| b.add_tlv(&validity_time).unwrap()),
+            Some(Error::InvalidMessage)
+        );
+        assert_eq!(
+            invalid(&|b| {
+                b.add_tlv(&cont_seq_num).unwrap();
+                b.add_tlv(&cont_seq_num).unwrap();
+                b.add_tlv(&validity_time).unwrap();
+            }),
+            Some(Error::InvalidMessage)
+        );
+        assert_eq!(
+            invalid(&|b| {
+                b.add_tlv(&cont_seq_num).unwrap();
+                b.add_tlv(&validity_time).unwrap();
+                b.add_address_block(&[addr(&[10, 0, 0, 2])], None).unwrap();
+                b.add_address_tlvs_u8(ADDR_TLV_NBR_ADDR_TYPE, None, |_| {
+                    Some(NbrAddrType::ROUTABLE.bits())
+                })
+                .unwrap();
+                b.add_address_tlvs_u8(ADDR_TLV_GATEWAY, None, |_| Some(1))
+                    .unwrap();
+            }),
+            Some(Error::InvalidMessage)
+        );
+        assert_eq!(
+            invalid(&|b| {
+                b.add_tlv(&cont_seq_num).unwrap();
+                b.add_tlv(&validity_time).unwrap();
+                b.add_address_block(&[addr(&[10, 0, 0, 0])], Some(&[24]))
+                    .unwrap();
+                b.add_address_tlvs_u8(ADDR_TLV_NBR_ADDR_TYPE, None, |_| {
+                    Some(NbrAddrType::ORIGINATOR.bits())
+                })
+                .unwrap();
+            }),
+            Some(Error::InvalidMessage)
+        );
+
+        // A missing hop count.
+        let mut hdr = MsgHeader::new(MSG_TYPE_TC, 4);
+        hdr.orig_addr = Some(&orig);
+        hdr.seq_num = Some(1);
+        let mut builder = MessageBuilder::new(&mut buf, &hdr).unwrap();
+        builder.add_tlv(&cont_seq_num).unwrap();
+        builder.add_tlv(&validity_time).unwrap();
+        let size = builder.finish().unwrap();
+        assert_eq!(read(&buf[..size]).err(), Some(Error::InvalidMessage));
+    }
+
+    #[test]
+    fn test_tc_split() {
+        let orig = [10, 0, 0, 1];
+        let tc = Tc::new(4, &orig, core::u16::MAX, 7, 6000);
+        let mut addrs = [TcAddress::new(addr(&[0; 4])); 40];
+        for (i, a) in addrs.iter_mut().enumerate() {
+            *a = neighbor(i as u8, NbrAddrType::ROUTABLE);
+        }
+
+        // Fits in a single message.
+        let mut buf = [0u8; 512];
+        let mut count = 0;
+        let n = tc.write_split(&addrs, &mut buf, |_| {
+            count += 1;
+            Ok(())
+        });
+        assert_eq!((n, count), (Ok(1), 1));
+
+        let mut buf = [0u8; 48];
+        assert_eq!(tc.write(&addrs, &mut buf), Err(Error::BufferTooSmall));
+        let mut parts = 0;
+        let mut found = 0;
+        let n = tc
+            .write_split(&addrs, &mut buf, |msg| {
+                let parsed = read(msg).unwrap();
+                assert!(!parsed.complete);
+                assert_eq!(parsed.ansn, 7);
+                assert_eq!(parsed.seq_num, core::u16::MAX.wrapping_add(parts));
+                parts += 1;
+                for a in parsed.addresses() {
+                    assert_eq!(a, addrs[found]);
+                    found += 1;
+                }
+                Ok(())
+            })
+            .unwrap();
+        assert!(n > 1);
+        assert_eq!(usize::from(parts), n);
+        assert_eq!(found, addrs.len());
+
+        let mut buf = [0u8; 16];
+        let res = tc.write_split(&addrs, &mut buf, |_| Ok(()));
+        assert_eq!(res, Err(Error::BufferTooSmall));
+    }
+}
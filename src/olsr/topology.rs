@@ -0,0 +1,882 @@
+// Copyright 2020 Jean Pierre Dudey. See the LICENSE-MIT and
+// LICENSE-APACHE files at the top-level directory of this
+// distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, BinaryHeap};
+use std::convert::TryFrom;
+
+use crate::nhdp::{
+    Hello, HelloAddress, InterfaceId, LinkMetricFlags, Mpr, WILL_NEVER,
+};
+use crate::olsr::{metric, MprTypes, NbrAddrType, Tc};
+use crate::{Address, Clock, Error, SeqNum};
+
+/// Advertising Remote Router Tuple.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct AdvertisingRouterTuple {
+    /// `AR_orig_addr`
+    pub orig_addr: Address,
+    /// `AR_seq_number`, the last ANSN received from the router.
+    pub seq_num: u16,
+    /// Did the last TC of the router have a SOURCE_ROUTE TLV (RFC 8218)?
+    pub source_route: bool,
+    /// `AR_time`
+    pub time: u64,
+}
+
+/// Router Topology Tuple, a link between two routers.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct RouterTopologyTuple {
+    /// `TR_from_orig_addr`
+    pub from_orig_addr: Address,
+    /// `TR_to_orig_addr`
+    pub to_orig_addr: Address,
+    /// `TR_seq_number`
+    pub seq_num: u16,
+    /// `TR_metric`
+    pub metric: u32,
+    /// `TR_time`
+    pub time: u64,
+}
+
+/// Routable Address Topology Tuple, a routable address of a neighbor of a
+/// router.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct RoutableAddressTuple {
+    /// `TA_from_orig_addr`
+    pub from_orig_addr: Address,
+    /// `TA_dest_addr`
+    pub dest_addr: Address,
+    /// `TA_seq_number`
+    pub seq_num: u16,
+    /// `TA_metric`
+    pub metric: u32,
+    /// `TA_time`
+    pub time: u64,
+}
+
+/// Attached Network Tuple.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct AttachedNetworkTuple {
+    /// `AN_net_addr`
+    pub net_addr: Address,
+    /// Prefix length of `AN_net_addr`.
+    pub prefix_length: u8,
+    /// `AN_orig_addr`, the gateway.
+    pub orig_addr: Address,
+    /// `AN_seq_number`
+    pub seq_num: u16,
+    /// `AN_dist`, number of hops from the gateway to the network.
+    pub dist: u8,
+    /// `AN_metric`
+    pub metric: u32,
+    /// `AN_time`
+    pub time: u64,
+}
+
+/// A symmetric 1-hop neighbor, taken from the Neighbor Information Base for
+/// the routing table calculation.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct LocalNeighbor {
+    /// `N_orig_addr`, if known.
+    pub orig_addr: Option<Address>,
+    /// Willingness to be a routing MPR.
+    pub willingness: u8,
+    /// Did the HELLOs of the neighbor have an MP_OLSRv2 TLV (RFC 8218)?
+    pub mp_olsrv2: bool,
+    /// Symmetric links to the neighbor.
+    pub links: Vec<LocalLink>,
+    /// Symmetric 2-hop neighbor addresses through the neighbor, with
+    /// `N2_out_metric`.
+    pub two_hops: Vec<(Address, u32)>,
+}
+
+/// A symmetric link to a neighbor.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct LocalLink {
+    /// Local interface of the link.
+    pub interface: InterfaceId,
+    /// `L_neighbor_iface_addr_list`
+    pub neighbor_iface_addrs: Vec<Address>,
+    /// `L_out_metric`
+    pub metric: u32,
+}
+
+/// A route of the Routing Set.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct Route {
+    /// `R_dest_addr`
+    pub dest_addr: Address,
+    /// Prefix length of `R_dest_addr`.
+    pub prefix_length: u8,
+    /// `R_next_iface_addr`
+    pub next_hop: Address,
+    /// `R_local_iface_addr`, as the interface.
+    pub interface: InterfaceId,
+    /// `R_metric`
+    pub metric: u32,
+    /// `R_dist`
+    pub hop_count: u8,
+}
+
+/// Change between two routing tables.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum RouteChange {
+    /// A route to a new destination.
+    Add(Route),
+    /// A route to a destination that's no longer reachable.
+    Remove(Route),
+    /// A route to a destination that changed.
+    Replace {
+        /// The previous route.
+        old: Route,
+        /// The new route.
+        new: Route,
+    },
+}
+
+/// Routing Set, the routes by destination prefix.
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct RoutingTable {
+    routes: BTreeMap<(Address, u8), Route>,
+}
+
+impl RoutingTable {
+    /// Route to `dest_addr/prefix_length`.
+    pub fn get(
+        &self,
+        dest_addr: &Address,
+        prefix_length: u8,
+    ) -> Option<&Route> {
+        self.routes.get(&(*dest_addr, prefix_length))
+    }
+
+    /// Routes ordered by destination.
+    pub fn iter(&self) -> impl Iterator<Item = &Route> + '_ {
+        self.routes.values()
+    }
+
+    /// Number of routes.
+    pub fn len(&self) -> usize {
+        self.routes.len()
+    }
+
+    /// Is the table empty?
+    pub fn is_empty(&self) -> bool {
+        self.routes.is_empty()
+    }
+
+    /// Changes to apply to this table to get `new`, ordered by destination.
+    pub fn diff(&self, new: &RoutingTable) -> Vec<RouteChange> {
+        let mut changes = Vec::new();
+        for (dest, old) in self.routes.iter() {
+            match new.routes.get(dest) {
+                None => changes.push(RouteChange::Remove(*old)),
+                Some(new) if new != old => changes.push(RouteChange::Replace {
+                    old: *old,
+                    new: *new,
+                }),
+                Some(_) => (),
+            }
+        }
+        for (dest, new) in new.routes.iter() {
+            if !self.routes.contains_key(dest) {
+                changes.push(RouteChange::Add(*new));
+            }
+        }
+
+        changes.sort_by_key(|c| match c {
+            RouteChange::Add(r) | RouteChange::Remove(r) => {
+                (r.dest_addr, r.prefix_length)
+            }
+            RouteChange::Replace { new, .. } => {
+                (new.dest_addr, new.prefix_length)
+            }
+        });
+        changes
+    }
+
+    /// Add the route if there's no better one, with a smaller metric or
+    /// the same metric and fewer hops.
+    fn offer(&mut self, route: Route) {
+        let key = (route.dest_addr, route.prefix_length);
+        let better = self.routes.get(&key).map_or(true, |r| {
+            (route.metric, route.hop_count) < (r.metric, r.hop_count)
+        });
+        if better {
+            self.routes.insert(key, route);
+        }
+    }
+}
+
+/// RFC 7181 Topology Information Base.
+///
+/// It's updated with the received TC messages, and gives the routing table
+/// with the local information from the Neighbor Information Base (see
+/// [`routing_table`](#method.routing_table)). Tuples expire at the time
+/// given by the VALIDITY_TIME of the TC that created them.
+#[derive(Debug)]
+pub struct Topology<C> {
+    clock: C,
+    metric_type: u8,
+    routers: Vec<AdvertisingRouterTuple>,
+    router_topology: Vec<RouterTopologyTuple>,
+    routable_addrs: Vec<RoutableAddressTuple>,
+    attached_networks: Vec<AttachedNetworkTuple>,
+}
+
+impl<C: Clock> Topology<C> {
+    /// Create an empty Topology Information Base using the link metrics of
+    /// type `metric_type`.
+    pub fn new(clock: C, metric_type: u8) -> Self {
+        Topology {
+            clock,
+            metric_type,
+            routers: Vec::new(),
+            router_topology: Vec::new(),
+            routable_addrs: Vec::new(),
+            attached_networks: Vec::new(),
+        }
+    }
+
+    /// The clock.
+    pub fn clock(&self) -> &C {
+        &self.clock
+    }
+
+    /// Metric type of the link metrics used.
+    pub fn metric_type(&self) -> u8 {
+        self.metric_type
+    }
+
+    /// Advertising Remote Router Set.
+    pub fn advertising_routers(&self) -> &[AdvertisingRouterTuple] {
+        &self.routers
+    }
+
+    /// Router Topology Set.
+    pub fn router_topology(&self) -> &[RouterTopologyTuple] {
+        &self.router_topology
+    }
+
+    /// Routable Address Topology Set.
+    pub fn routable_addrs(&self) -> &[RoutableAddressTuple] {
+        &self.routable_addrs
+    }
+
+    /// Attached Network Set.
+    pub fn attached_networks(&self) -> &[AttachedNetworkTuple] {
+        &self.attached_networks
+    }
+
+    /// Remove the expired tuples.
+    pub fn expire(&mut self) {
+        let now = self.clock.now();
+        self.routers.retain(|t| t.time > now);
+        self.router_topology.retain(|t| t.time > now);
+        self.routable_addrs.retain(|t| t.time > now);
+        self.attached_networks.retain(|t| t.time > now);
+    }
+
+    /// Next time a tuple expires, when [`expire`](#method.expire) must be
+    /// called.
+    pub fn next_expiry(&self) -> Option<u64> {
+        let routers = self.routers.iter().map(|t| t.time);
+        let router_topology = self.router_topology.iter().map(|t| t.time);
+        let routable_addrs = self.routable_addrs.iter().map(|t| t.time);
+        let networks = self.attached_networks.iter().map(|t| t.time);
+
+        routers
+            .chain(router_topology)
+            .chain(routable_addrs)
+            .chain(networks)
+            .min()
+    }
+
+    /// Process a TC message (RFC 7181 section 16.3), returns `false` if it
+    /// was discarded because its ANSN is older than the last one from the
+    /// originator.
+    ///
+    /// TCs originated by this router must not be given. Addresses without a
+    /// LINK_METRIC TLV of the metric type for the outgoing neighbor
+    /// direction are ignored.
+    pub fn process_tc(&mut self, tc: &Tc) -> Result<bool, Error> {
+        let orig = Address::from_bytes(tc.orig_addr)
+            .filter(|a| a.len() == tc.address_length)
+            .ok_or(Error::InvalidMessage)?;
+        let now = self.clock.now();
+        let time = now.saturating_add(tc.validity_time);
+        let ansn = tc.ansn;
+
+        match self.routers.iter_mut().find(|r| r.orig_addr == orig) {
+            Some(r) => {
+                if is_newer(r.seq_num, ansn) {
+                    return Ok(false);
+                }
+                r.seq_num = ansn;
+                r.source_route = tc.source_route;
+                r.time = time;
+            }
+            None => self.routers.push(AdvertisingRouterTuple {
+                orig_addr: orig,
+                seq_num: ansn,
+                source_route: tc.source_route,
+                time,
+            }),
+        }
+
+        let max_prefix = (8 * tc.address_length) as u8;
+        for a in tc.addresses() {
+            let flags = LinkMetricFlags::OUTGOING_NEIGHBOR;
+            let metric = match a.link_metric(self.metric_type, flags) {
+                Some(m) => metric::decode(m),
+                None => continue,
+            };
+
+            if let Some(dist) = a.gateway {
+                let prefix_length = a.prefix_length.unwrap_or(max_prefix);
+                self.update_network(AttachedNetworkTuple {
+                    net_addr: a.addr,
+                    prefix_length,
+                    orig_addr: orig,
+                    seq_num: ansn,
+                    dist,
+                    metric,
+                    time,
+                });
+                continue;
+            }
+
+            if a.nbr_addr_type.contains(NbrAddrType::ORIGINATOR) {
+                let t = self.router_topology.iter_mut().find(|t| {
+                    t.from_orig_addr == orig && t.to_orig_addr == a.addr
+                });
+                let tuple = RouterTopologyTuple {
+                    from_orig_addr: orig,
+                    to_orig_addr: a.addr,
+                    seq_num: ansn,
+                    metric,
+                    time,
+                };
+                match t {
+                    Some(t) => *t = tuple,
+                    None => self.router_topology.push(tuple),
+                }
+            }
+            if a.nbr_addr_type.contains(NbrAddrType::ROUTABLE) {
+                let t = self.routable_addrs.iter_mut().find(|t| {
+                    t.from_orig_addr == orig && t.dest_addr == a.addr
+                });
+                let tuple = RoutableAddressTuple {
+                    from_orig_addr: orig,
+                    dest_addr: a.addr,
+                    seq_num: ansn,
+                    metric,
+                    time,
+                };
+                match t {
+                    Some(t) => *t = tuple,
+                    None => self.routable_addrs.push(tuple),
+                }
+            }
+        }
+
+        // A complete TC replaces everything advertised before by the
+        // originator.
+        if tc.complete {
+            let old = |from: &Address, seq_num: u16| {
+                *from == orig && is_newer(ansn, seq_num)
+            };
+            self.router_topology
+                .retain(|t| !old(&t.from_orig_addr, t.seq_num));
+            self.routable_addrs
+                .retain(|t| !old(&t.from_orig_addr, t.seq_num));
+            self.attached_networks
+                .retain(|t| !old(&t.orig_addr, t.seq_num));
+        }
+
+        Ok(true)
+    }
+
+    /// Calculate the routing table (RFC 7181 section 17.7) with the
+    /// symmetric 1-hop neighbors `neighbors` of the router with the
+    /// addresses `local_addrs`.
+    ///
+    /// The shortest paths by metric, then by number of hops, are found with
+    /// Dijkstra's algorithm. Neighbors with a routing willingness of
+    /// WILL_NEVER are only used as the last hop, and attached networks add
+    /// the gateway distance to the number of hops.
+    pub fn routing_table(
+        &self,
+        local_addrs: &[Address],
+        neighbors: &[LocalNeighbor],
+    ) -> RoutingTable {
+        let mut table = RoutingTable::default();
+        let mut offer = |dest_addr: Address,
+                         prefix_length: u8,
+                         hop: &Hop,
+                         metric: u64,
+                         hop_count: u8| {
+            let host = 8 * dest_addr.len() == usize::from(prefix_length);
+            if host && local_addrs.contains(&dest_addr) {
+                return;
+            }
+            if let Ok(metric) = u32::try_from(metric) {
+                table.offer(Route {
+                    dest_addr,
+                    prefix_length,
+                    next_hop: hop.next_hop,
+                    interface: hop.interface,
+                    metric,
+                    hop_count,
+                });
+            }
+        };
+        let max_prefix = |a: &Address| (8 * a.len()) as u8;
+
+        // Routers by originator address with the cost of the shortest path,
+        // the number of hops and the first hop.
+        let mut heap = BinaryHeap::new();
+        for n in neighbors {
+            for l in n.links.iter() {
+                for addr in l.neighbor_iface_addrs.iter() {
+                    let hop = Hop {
+                        next_hop: *addr,
+                        interface: l.interface,
+                    };
+                    let metric = u64::from(l.metric);
+                    offer(*addr, max_prefix(addr), &hop, metric, 1);
+                }
+            }
+
+            // Best link to the neighbor, N_out_metric.
+            let best = match n.links.iter().min_by_key(|l| l.metric) {
+                Some(l) if !l.neighbor_iface_addrs.is_empty() => l,
+                _ => continue,
+            };
+            let hop = Hop {
+                next_hop: best.neighbor_iface_addrs[0],
+                interface: best.interface,
+            };
+            let metric = u64::from(best.metric);
+            if n.willingness != WILL_NEVER {
+                for (addr, m) in n.two_hops.iter() {
+                    let m = metric + u64::from(*m);
+                    offer(*addr, max_prefix(addr), &hop, m, 2);
+                }
+            }
+            if let Some(orig) = n.orig_addr {
+                let expand = n.willingness != WILL_NEVER;
+                heap.push(Reverse((metric, 1u8, orig, hop, expand)));
+            }
+        }
+
+        let mut routers = BTreeMap::new();
+        while let Some(Reverse((metric, hops, orig, hop, expand))) = heap.pop()
+        {
+            if routers.contains_key(&orig) || local_addrs.contains(&orig) {
+                continue;
+            }
+            routers.insert(orig, (metric, hops, hop, expand));
+            if !expand {
+                continue;
+            }
+
+            for t in self.router_topology.iter() {
+                if t.from_orig_addr == orig
+                    && !routers.contains_key(&t.to_orig_addr)
+                {
+                    let m = metric + u64::from(t.metric);
+                    let h = hops.saturating_add(1);
+                    heap.push(Reverse((m, h, t.to_orig_addr, hop, true)));
+                }
+            }
+        }
+
+        for (orig, (metric, hops, hop, _)) in routers.iter() {
+            offer(*orig, max_prefix(orig), hop, *metric, *hops);
+        }
+
+        // Routable addresses and attached networks are behind the router,
+        // it must be willing to route.
+        for t in self.routable_addrs.iter() {
+            if let Some((metric, hops, hop, true)) =
+                routers.get(&t.from_orig_addr)
+            {
+                let m = metric + u64::from(t.metric);
+                let h = hops.saturating_add(1);
+                offer(t.dest_addr, max_prefix(&t.dest_addr), hop, m, h);
+            }
+        }
+        for t in self.attached_networks.iter() {
+            if let Some((metric, hops, hop, true)) = routers.get(&t.orig_addr) {
+                let m = metric + u64::from(t.metric);
+                let h = hops.saturating_add(t.dist);
+                offer(t.net_addr, t.prefix_length, hop, m, h);
+            }
+        }
+
+        table
+    }
+
+    /// Add or refresh an Attached Network Tuple.
+    fn update_network(&mut self, tuple: AttachedNetworkTuple) {
+        let t = self.attached_networks.iter_mut().find(|t| {
+            t.net_addr == tuple.net_addr
+                && t.prefix_length == tuple.prefix_length
+                && t.orig_addr == tuple.orig_addr
+        });
+        match t {
+            Some(t) => *t = tuple,
+            None => self.attached_networks.push(tuple),
+        }
+    }
+}
+
+/// Topology Information Bases of the topologies of a router (RFC 7722), one
+/// for each metric type, updated with the same TC messages.
+#[derive(Debug)]
+pub struct MultiTopology<C> {
+    mpr_types: MprTypes,
+    topologies: Vec<Topology<C>>,
+}
+
+impl<C: Clock + Clone> MultiTopology<C> {
+    /// Create an empty Topology Information Base for each metric type of
+    /// `mpr_types`.
+    pub fn new(clock: C, mpr_types: MprTypes) -> Self {
+        let topologies = mpr_types
+            .types()
+            .iter()
+            .map(|t| Topology::new(clock.clone(), *t))
+            .collect();
+        MultiTopology {
+            mpr_types,
+            topologies,
+        }
+    }
+
+    /// The metric types of the topologies.
+    pub fn mpr_types(&self) -> &MprTypes {
+        &self.mpr_types
+    }
+
+    /// Topology of the metric type `metric_type`.
+    pub fn topology(&self, metric_type: u8) -> Option<&Topology<C>> {
+        self.topologies
+            .iter()
+            .find(|t| t.metric_type() == metric_type)
+    }
+
+    /// Topologies in the order of the metric types.
+    pub fn topologies(&self) -> impl Iterator<Item = &Topology<C>> + '_ {
+        self.topologies.iter()
+    }
+
+    /// Process a TC message in the topologies the originator takes part
+    /// in, the ones of its MPR_TYPES TLV or the first one without it.
+    /// Returns `false` if a topology discarded it because of an old ANSN.
+    pub fn process_tc(&mut self, tc: &Tc) -> Result<bool, Error> {
+        let first = self.mpr_types.types().first().cloned();
+        let mut processed = true;
+        for t in self.topologies.iter_mut() {
+            if type_index(tc.mpr_types.as_ref(), first, t.metric_type())
+                .is_some()
+            {
+                processed &= t.process_tc(tc)?;
+            }
+        }
+
+        Ok(processed)
+    }
+
+    /// MPR value given by the originator of `hello` to its neighbor `a` in
+    /// the topology of the metric type `metric_type`, the part of the MPR
+    /// TLV of that type with MPR_TYPES or the value of the first topology
+    /// without it. `None` if either router doesn't take part in the
+    /// topology.
+    pub fn mpr(
+        &self,
+        hello: &Hello,
+        a: &HelloAddress,
+        metric_type: u8,
+    ) -> Option<Mpr> {
+        if !self.mpr_types.contains(metric_type) {
+            return None;
+        }
+
+        let first = self.mpr_types.types().first().cloned();
+        type_index(hello.mpr_types.as_ref(), first, metric_type)
+            .map(|i| a.mpr_for(i))
+    }
+
+    /// Calculate the routing table of each topology, with the symmetric
+    /// 1-hop neighbors given by `neighbors` for each metric type. See
+    /// [`Topology::routing_table`](struct.Topology.html#method.routing_table).
+    pub fn routing_tables<'n, F>(
+        &self,
+        local_addrs: &[Address],
+        mut neighbors: F,
+    ) -> Vec<(u8, RoutingTable)>
+    where
+        F: FnMut(u8) -> &'n [LocalNeighbor],
+    {
+        self.topologies
+            .iter()
+            .map(|t| {
+                let n = neighbors(t.metric_type());
+                (t.metric_type(), t.routing_table(local_addrs, n))
+            })
+            .collect()
+    }
+
+    /// Remove the expired tuples.
+    pub fn expire(&mut self) {
+        self.topologies.iter_mut().for_each(Topology::expire);
+    }
+
+    /// Next time a tuple expires, when [`expire`](#method.expire) must be
+    /// called.
+    pub fn next_expiry(&self) -> Option<u64> {
+        self.topologies
+            .iter()
+            .filter_map(Topology::next_expiry)
+            .min()
+    }
+}
+
+/// Position of `metric_type` in the MPR_TYPES `mpr_types` of a message, a
+/// message without them is only in the topology of the metric type `first`.
+fn type_index(
+    mpr_types: Option<&MprTypes>,
+    first: Option<u8>,
+    metric_type: u8,
+) -> Option<usize> {
+    match mpr_types {
+        Some(m) => m.index(metric_type),
+        None if Some(metric_type) == first => Some(0),
+        None => None,
+    }
+}
+
+/// First hop of a path.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Ord, PartialOrd)]
+struct Hop {
+    next_hop: Address,
+    interface: InterfaceId,
+}
+
+/// Is the sequence number `a` newer than `b`?
+fn is_newer(a: u16, b: u16) -> bool {
+    SeqNum(a).is_newer_than(SeqNum(b))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::nhdp::{LinkMetric, Nhdp, NhdpConfig, WILL_DEFAULT};
+    use crate::olsr::TcAddress;
+    use crate::{Buf, ManualClock, Message};
+
+    fn addr(bytes: &[u8]) -> Address {
+        Address::from_bytes(bytes).unwrap()
+    }
+
+    fn metric(m: u32) -> LinkMetric {
+        let flags = LinkMetricFlags::OUTGOING_NEIGHBOR;
+        LinkMetric::from_metric(0, flags, m).unwrap()
+    }
+
+    fn neighbor(last: u8, m: u32) -> TcAddress {
+        let mut a = TcAddress::new(addr(&[10, 0, 0, last]));
+        a.nbr_addr_type = NbrAddrType::ORIGINATOR | NbrAddrType::ROUTABLE;
+        a.add_link_metric(metric(m)).unwrap();
+        a
+    }
+
+    fn advertise(
+        topology: &mut Topology<&ManualClock>,
+        last: u8,
+        ansn: u16,
+        addrs: &[TcAddress],
+    ) -> Result<bool, Error> {
+        let orig = [10, 0, 0, last];
+        let mut buf = [0u8; 256];
+        let tc = Tc::new(4, &orig, 0, ansn, 10_000);
+        let size = tc.write(addrs, &mut buf).unwrap();
+        let msg = Message::read(&mut Buf::new(&buf[..size])).unwrap();
+        topology.process_tc(&Tc::from_message(&msg).unwrap())
+    }
+
+    #[test]
+    fn test_routing_table() {
+        let clock = ManualClock::new(0);
+        let mut nhdp = Nhdp::new(&clock, 4, NhdpConfig::default());
+        let local = addr(&[10, 0, 0, 1]);
+        let iface = nhdp.add_interface(&[local]);
+        let mut topology = Topology::new(&clock, 0);
+
+        // L - B - C - D and L - N, N doesn't route.
+        let neighbors = [(2, WILL_DEFAULT), (9, WILL_NEVER)]
+            .iter()
+            .map(|(last, willingness)| LocalNeighbor {
+                orig_addr: Some(addr(&[10, 0, 0, *last])),
+                willingness: *willingness,
+                mp_olsrv2: false,
+                links: vec![LocalLink {
+                    interface: iface,
+                    neighbor_iface_addrs: vec![addr(&[10, 0, 0, *last])],
+                    metric: 1,
+                }],
+                two_hops: Vec::new(),
+            })
+            .collect::<Vec<_>>();
+        let mut network = TcAddress::network(addr(&[192, 168, 0, 0]), 16, 2);
+        network.add_link_metric(metric(5)).unwrap();
+        assert_eq!(advertise(&mut topology, 2, 1, &[neighbor(3, 2)]), Ok(true));
+        let c = [neighbor(2, 2), neighbor(4, 3), network];
+        assert_eq!(advertise(&mut topology, 3, 1, &c), Ok(true));
+        assert_eq!(advertise(&mut topology, 9, 1, &[neighbor(5, 1)]), Ok(true));
+        assert_eq!(topology.advertising_routers().len(), 3);
+        assert_eq!(topology.router_topology().len(), 4);
+        assert_eq!(topology.attached_networks().len(), 1);
+
+        let table = topology.routing_table(&[local], &neighbors);
+        let route = |last: u8| table.get(&addr(&[10, 0, 0, last]), 32);
+        let next_hop = addr(&[10, 0, 0, 2]);
+        assert_eq!(route(1), None);
+        assert_eq!(route(2).map(|r| (r.metric, r.hop_count)), Some((1, 1)));
+        assert_eq!(route(9).map(|r| (r.metric, r.hop_count)), Some((1, 1)));
+        assert_eq!(route(3).map(|r| (r.metric, r.hop_count)), Some((3, 2)));
+        assert_eq!(route(4).map(|r| (r.metric, r.hop_count)), Some((6, 3)));
+        assert_eq!(route(4).map(|r| r.next_hop), Some(next_hop));
+        assert_eq!(route(4).map(|r| r.interface), Some(iface));
+        assert_eq!(route(5), None);
+        let network = table.get(&addr(&[192, 168, 0, 0]), 16).unwrap();
+        assert_eq!((network.metric, network.hop_count), (8, 4));
+        assert_eq!(table.len(), 5);
+
+        // An older ANSN is discarded, a complete TC replaces the topology.
+        clock.advance(1000);
+        let c = [neighbor(2, 2)];
+        assert_eq!(advertise(&mut topology, 3, 0, &c), Ok(false));
+        assert_eq!(advertise(&mut topology, 3, 2, &c), Ok(true));
+        let new = topology.routing_table(&[local], &neighbors);
+        let changes = table.diff(&new);
+        assert_eq!(
+            changes,
+            vec![
+                RouteChange::Remove(*route(4).unwrap()),
+                RouteChange::Remove(*network),
+            ]
+        );
+
+        // Everything expires with the validity time.
+        assert_eq!(topology.next_expiry(), Some(10_000));
+        clock.set(10_000);
+        topology.expire();
+        assert_eq!(topology.advertising_routers().len(), 1);
+        clock.set(11_000);
+        topology.expire();
+        assert_eq!(topology.next_expiry(), None);
+        assert_eq!(topology.routing_table(&[local], &neighbors).len(), 2);
+    }
+
+    #[test]
+    fn test_multi_topology() {
+        let clock = ManualClock::new(0);
+        let mut nhdp = Nhdp::new(&clock, 4, NhdpConfig::default());
+        let local = addr(&[10, 0, 0, 1]);
+        let iface = nhdp.add_interface(&[local]);
+        let mpr_types = MprTypes::from_types(&[0, 1]).unwrap();
+        let mut topology = MultiTopology::new(&clock, mpr_types);
+
+        // L - B - C and L - E - C, B is better with the metric type 0 and E
+        // with the metric type 1.
+        let neighbors = |metrics: [u32; 2]| {
+            [(2, metrics[0]), (5, metrics[1])]
+                .iter()
+                .map(|(last, metric)| LocalNeighbor {
+                    orig_addr: Some(addr(&[10, 0, 0, *last])),
+                    willingness: WILL_DEFAULT,
+                    mp_olsrv2: false,
+                    links: vec![LocalLink {
+                        interface: iface,
+                        neighbor_iface_addrs: vec![addr(&[10, 0, 0, *last])],
+                        metric: *metric,
+                    }],
+                    two_hops: Vec::new(),
+                })
+                .collect::<Vec<_>>()
+        };
+        let neighbors = [neighbors([1, 5]), neighbors([1, 1])];
+        let mut tc = |last: u8, mpr_types: Option<&[u8]>, metrics: &[u32]| {
+            let mut c = TcAddress::new(addr(&[10, 0, 0, 3]));
+            c.nbr_addr_type = NbrAddrType::ORIGINATOR;
+            for (t, m) in metrics.iter().enumerate() {
+                let flags = LinkMetricFlags::OUTGOING_NEIGHBOR;
+                let m = LinkMetric::from_metric(t as u8, flags, *m).unwrap();
+                c.add_link_metric(m).unwrap();
+            }
+            let orig = [10, 0, 0, last];
+            let mut tc = Tc::new(4, &orig, 0, 1, 10_000);
+            tc.mpr_types = mpr_types.map(|m| MprTypes::from_types(m).unwrap());
+            let mut buf = [0u8; 128];
+            let size = tc.write(&[c], &mut buf).unwrap();
+            let msg = Message::read(&mut Buf::new(&buf[..size])).unwrap();
+            topology.process_tc(&Tc::from_message(&msg).unwrap())
+        };
+        assert_eq!(tc(2, Some(&[0, 1]), &[1, 10]), Ok(true));
+        assert_eq!(tc(5, Some(&[0, 1]), &[1, 1]), Ok(true));
+        // Only in the first topology without MPR_TYPES, only in the second
+        // one with its metric type.
+        assert_eq!(tc(6, None, &[1, 1]), Ok(true));
+        assert_eq!(tc(7, Some(&[1]), &[1, 1]), Ok(true));
+
+        let routers = |t: u8| {
+            let t = topology.topology(t).unwrap();
+            t.advertising_routers().len()
+        };
+        assert_eq!((routers(0), routers(1)), (3, 3));
+        assert!(topology.topology(2).is_none());
+
+        let tables =
+            topology.routing_tables(&[local], |t| &neighbors[usize::from(t)]);
+        assert_eq!(tables.len(), 2);
+        let c = addr(&[10, 0, 0, 3]);
+        let route = |i: usize| {
+            let r = tables[i].1.get(&c, 32).unwrap();
+            (tables[i].0, r.next_hop, r.metric)
+        };
+        assert_eq!(route(0), (0, addr(&[10, 0, 0, 2]), 2));
+        assert_eq!(route(1), (1, addr(&[10, 0, 0, 5]), 2));
+
+        // The MPRs of each topology, from the part of its type in the MPR
+        // values or the single part in the first topology.
+        let mut hello = Hello::new(4, 6000);
+        let mut a = HelloAddress::new(local);
+        a.mpr = Mpr::FLOODING;
+        a.set_mpr_for(1, Mpr::ROUTING).unwrap();
+        hello.mpr_types = Some(MprTypes::from_types(&[1, 0]).unwrap());
+        assert_eq!(topology.mpr(&hello, &a, 0), Some(Mpr::ROUTING));
+        assert_eq!(topology.mpr(&hello, &a, 1), Some(Mpr::FLOODING));
+        assert_eq!(topology.mpr(&hello, &a, 2), None);
+        hello.mpr_types = None;
+        assert_eq!(topology.mpr(&hello, &a, 0), Some(Mpr::FLOODING));
+        assert_eq!(topology.mpr(&hello, &a, 1), None);
+
+        assert_eq!(topology.next_expiry(), Some(10_000));
+        clock.set(10_000);
+        topology.expire();
+        assert!(topology
+            .topologies()
+            .all(|t| t.router_topology().is_empty()));
+    }
+}
@@ -8,7 +8,9 @@
 // option. This file may not be copied, modified, or distributed
 // except according to those terms.
 
-use crate::{Buf, Error, Messages, TlvBlock, RFC5444_VERSION};
+use crate::{
+    Buf, BufMut, Error, Messages, MessagesMut, Tlv, TlvBlock, RFC5444_VERSION,
+};
 
 /// Packet
 #[derive(Debug)]
@@ -32,6 +34,58 @@ impl<'a> Packet<'a> {
     }
 }
 
+/// A packet in a mutable buffer, to change its fixed-size fields and those
+/// of its messages in place.
+#[derive(Debug)]
+pub struct PacketMut<'a> {
+    buf: &'a mut [u8],
+    /// Offset of `<pkt-seq-num>`.
+    seq_num: Option<usize>,
+    /// Offset of the first message.
+    messages: usize,
+}
+
+impl<'a> PacketMut<'a> {
+    /// View the packet in `buf`.
+    pub fn new(buf: &'a mut [u8]) -> Result<PacketMut<'a>, Error> {
+        let mut b = Buf::new(buf);
+        let hdr = PktHeader::read(&mut b)?;
+        let seq_num = hdr.seq_num.map(|_| 1);
+        let messages = b.pos();
+
+        Ok(PacketMut {
+            buf,
+            seq_num,
+            messages,
+        })
+    }
+
+    /// Get the bytes of the whole packet.
+    pub fn as_bytes(&self) -> &[u8] {
+        self.buf
+    }
+
+    /// `<pkt-seq-num>`
+    pub fn seq_num(&self) -> Option<u16> {
+        self.seq_num
+            .map(|i| u16::from_be_bytes([self.buf[i], self.buf[i + 1]]))
+    }
+
+    /// Set `<pkt-seq-num>`, fails with
+    /// [`Error::InvalidMessage`](enum.Error.html#variant.InvalidMessage) if
+    /// the packet doesn't have it.
+    pub fn set_seq_num(&mut self, seq_num: u16) -> Result<(), Error> {
+        let i = self.seq_num.ok_or(Error::InvalidMessage)?;
+        self.buf[i..i + 2].copy_from_slice(&seq_num.to_be_bytes());
+        Ok(())
+    }
+
+    /// Iterator over the messages.
+    pub fn messages(&mut self) -> MessagesMut<'_> {
+        MessagesMut::new(&mut self.buf[self.messages..])
+    }
+}
+
 /// Packet header.
 #[derive(Debug)]
 pub struct PktHeader<'a> {
@@ -82,6 +136,36 @@ impl<'a> PktHeader<'a> {
             tlv_block: block,
         })
     }
+
+    /// Write a packet header with `seq_num` and a TLV block with `tlvs`, the
+    /// TLV block is left out if `tlvs` is empty.
+    pub(crate) fn write(
+        buf: &mut BufMut,
+        seq_num: Option<u16>,
+        tlvs: &[Tlv],
+    ) -> Result<(), Error> {
+        let mut flags = PktHeaderFlags::empty();
+        flags.set(PktHeaderFlags::HAS_SEQ_NUM, seq_num.is_some());
+        flags.set(PktHeaderFlags::HAS_TLV, !tlvs.is_empty());
+
+        let tlvs_length: usize = tlvs.iter().map(Tlv::encoded_len).sum();
+        if tlvs_length > usize::from(core::u16::MAX) {
+            return Err(Error::InvalidTlvValue);
+        }
+
+        buf.put_u8((RFC5444_VERSION << 4) | flags.bits())?;
+        if let Some(seq_num) = seq_num {
+            buf.put_ne_u16(seq_num)?;
+        }
+        if !tlvs.is_empty() {
+            buf.put_ne_u16(tlvs_length as u16)?;
+            for tlv in tlvs {
+                tlv.write(buf)?;
+            }
+        }
+
+        Ok(())
+    }
 }
 
 bitflags! {
@@ -96,3 +180,43 @@ bitflags! {
         const RESERVED1   = 0x01;
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_packet_mut() {
+        // Packet with a sequence number and two messages without TLVs.
+        let mut buf = [
+            0x08, 0x00, 0x01, // <pkt-header>
+            0x01, 0x13, 0x00, 0x08, 0x00, 0x05, 0x00, 0x00, // hop count
+            0x02, 0x43, 0x00, 0x07, 0x03, 0x00, 0x00, // hop limit
+        ];
+
+        let mut packet = PacketMut::new(&mut buf).unwrap();
+        assert_eq!(packet.seq_num(), Some(1));
+        packet.set_seq_num(0xabcd).unwrap();
+        let mut messages = packet.messages();
+        let mut first = messages.next().unwrap().unwrap();
+        let mut second = messages.next().unwrap().unwrap();
+        assert!(messages.next().is_none());
+        assert_eq!(first.seq_num(), Some(5));
+        first.set_seq_num(6).unwrap();
+        assert_eq!(second.hop_limit(), Some(3));
+        second.set_hop_limit(2).unwrap();
+        assert_eq!(second.set_seq_num(1), Err(Error::InvalidMessage));
+
+        let packet = Packet::read(&buf).unwrap();
+        assert_eq!(packet.hdr.seq_num, Some(0xabcd));
+        let mut messages = packet.messages.iter();
+        let first = messages.next().unwrap().unwrap();
+        assert_eq!(first.hdr.seq_num, Some(6));
+        let second = messages.next().unwrap().unwrap();
+        assert_eq!(second.hdr.hop_limit, Some(2));
+
+        // Truncated message.
+        let mut packet = PacketMut::new(&mut buf[..10]).unwrap();
+        assert!(packet.messages().next().unwrap().is_err());
+    }
+}
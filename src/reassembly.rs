@@ -0,0 +1,428 @@
+// Copyright 2020 Jean Pierre Dudey. See the LICENSE-MIT and
+// LICENSE-APACHE files at the top-level directory of this
+// distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use std::collections::BTreeMap;
+
+use crate::olsr::{
+    CONT_SEQ_NUM_COMPLETE, CONT_SEQ_NUM_INCOMPLETE, MSG_TLV_CONT_SEQ_NUM,
+};
+use crate::{Address, Buf, Clock, Error, Message};
+
+/// Maximum number of messages of a logical message, more are dropped and
+/// the logical message is incomplete.
+const MAX_PARTS: usize = 256;
+
+/// Where a message is in the logical message it belongs to.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Part {
+    /// The message is the whole logical message.
+    Whole,
+    /// More messages follow.
+    More,
+    /// Last message, the logical message is complete once received.
+    Last,
+}
+
+/// A logical message put together from the messages carrying its parts.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Reassembled {
+    /// Message type.
+    pub r#type: u8,
+    /// `<msg-orig-addr>`
+    pub orig_addr: Address,
+    /// Identifier of the logical message among those of the originator.
+    pub key: u16,
+    /// Were all the messages received? `false` if the logical message timed
+    /// out before, or had more messages than could be kept.
+    pub complete: bool,
+    parts: Vec<Vec<u8>>,
+}
+
+impl Reassembled {
+    /// The messages, in the order they were received.
+    pub fn parts(&self) -> impl Iterator<Item = Message<'_>> {
+        self.parts
+            .iter()
+            .filter_map(|p| Message::read(&mut Buf::new(p)).ok())
+    }
+
+    /// Number of messages.
+    pub fn len(&self) -> usize {
+        self.parts.len()
+    }
+
+    /// Has it no messages? Never, it has at least one.
+    pub fn is_empty(&self) -> bool {
+        self.parts.is_empty()
+    }
+}
+
+#[derive(Debug)]
+struct Group {
+    msg: Reassembled,
+    /// `<msg-seq-num>` and header length of the messages, to drop
+    /// duplicates.
+    seen: Vec<(Option<u16>, usize)>,
+    /// Were messages dropped past `MAX_PARTS`?
+    dropped: bool,
+    time: u64,
+}
+
+/// Reassembly buffer for logical messages sent as several messages, like
+/// the OLSRv2 TCs split with CONT_SEQ_NUM INCOMPLETE.
+///
+/// The messages of a logical message have the same type and
+/// `<msg-orig-addr>`, and a key that identifies it: the `<msg-seq-num>` for
+/// [`receive`](#method.receive), or the one given to
+/// [`receive_with_key`](#method.receive_with_key). A logical message is
+/// handed over when complete, or by [`expire`](#method.expire) when
+/// `hold_time` milliseconds pass after its first message.
+#[derive(Debug)]
+pub struct Reassembly<C> {
+    clock: C,
+    hold_time: u64,
+    groups: BTreeMap<(u8, Address, u16), Group>,
+}
+
+impl<C: Clock> Reassembly<C> {
+    /// Create an empty buffer keeping incomplete logical messages for
+    /// `hold_time` milliseconds.
+    pub fn new(clock: C, hold_time: u64) -> Self {
+        Reassembly {
+            clock,
+            hold_time,
+            groups: BTreeMap::new(),
+        }
+    }
+
+    /// The clock.
+    pub fn clock(&self) -> &C {
+        &self.clock
+    }
+
+    /// Number of incomplete logical messages.
+    pub fn len(&self) -> usize {
+        self.groups.len()
+    }
+
+    /// Is the buffer empty?
+    pub fn is_empty(&self) -> bool {
+        self.groups.is_empty()
+    }
+
+    /// Add a message keyed on its `<msg-seq-num>`, see
+    /// [`receive_with_key`](#method.receive_with_key).
+    ///
+    /// The messages of a logical message share the `<msg-seq-num>` and carry
+    /// different parts of it, like subsets of its addresses, as written by
+    /// [`MessageSplitter`](struct.MessageSplitter.html).
+    pub fn receive(
+        &mut self,
+        msg: &Message,
+        part: Part,
+    ) -> Result<Option<Reassembled>, Error> {
+        let key = msg.hdr.seq_num.ok_or(Error::InvalidMessage)?;
+        self.receive_with_key(msg, key, part)
+    }
+
+    /// Add a TC, keyed on its ANSN. A TC with CONT_SEQ_NUM COMPLETE is a
+    /// whole logical message, one with INCOMPLETE is completed by
+    /// [`expire`](#method.expire).
+    pub fn receive_tc(
+        &mut self,
+        msg: &Message,
+    ) -> Result<Option<Reassembled>, Error> {
+        let mut cont_seq_num = None;
+        for tlv in msg.tlv_block.iter() {
+            let tlv = tlv?;
+            if tlv.r#type != MSG_TLV_CONT_SEQ_NUM {
+                continue;
+            }
+            let part = match tlv.type_ext() {
+                CONT_SEQ_NUM_COMPLETE => Part::Whole,
+                CONT_SEQ_NUM_INCOMPLETE => Part::More,
+                _ => continue,
+            };
+            let ansn = match tlv.value {
+                Some(&[a, b]) => u16::from_be_bytes([a, b]),
+                _ => return Err(Error::InvalidTlvValue),
+            };
+            if cont_seq_num.replace((ansn, part)).is_some() {
+                return Err(Error::InvalidMessage);
+            }
+        }
+
+        let (ansn, part) = cont_seq_num.ok_or(Error::InvalidMessage)?;
+        self.receive_with_key(msg, ansn, part)
+    }
+
+    /// Add a message of the logical message `key`, returns the logical
+    /// message if it's complete.
+    ///
+    /// Messages without `<msg-orig-addr>` can't be reassembled and fail
+    /// with [`Error::InvalidMessage`]. A message received twice, with the
+    /// same `<msg-seq-num>` and content apart from `<msg-hop-limit>` and
+    /// `<msg-hop-count>`, is ignored. A `Part::Whole` message replaces the
+    /// incomplete logical message with the same key.
+    ///
+    /// [`Error::InvalidMessage`]: enum.Error.html#variant.InvalidMessage
+    pub fn receive_with_key(
+        &mut self,
+        msg: &Message,
+        key: u16,
+        part: Part,
+    ) -> Result<Option<Reassembled>, Error> {
+        let orig_addr = msg
+            .hdr
+            .orig_addr
+            .and_then(Address::from_bytes)
+            .ok_or(Error::InvalidMessage)?;
+        let id = (msg.hdr.r#type, orig_addr, key);
+
+        if part == Part::Whole {
+            self.groups.remove(&id);
+            return Ok(Some(Reassembled {
+                r#type: msg.hdr.r#type,
+                orig_addr,
+                key,
+                complete: true,
+                parts: vec![msg.as_bytes().to_vec()],
+            }));
+        }
+
+        let time = self.clock.now().saturating_add(self.hold_time);
+        let group = self.groups.entry(id).or_insert_with(|| Group {
+            msg: Reassembled {
+                r#type: msg.hdr.r#type,
+                orig_addr,
+                key,
+                complete: false,
+                parts: Vec::new(),
+            },
+            seen: Vec::new(),
+            dropped: false,
+            time,
+        });
+
+        let seq_num = msg.hdr.seq_num;
+        let header_len = msg.hdr.header_len();
+        let body = &msg.as_bytes()[header_len..];
+        let duplicate = seq_num.is_some()
+            && group
+                .msg
+                .parts
+                .iter()
+                .zip(group.seen.iter())
+                .any(|(p, (s, h))| *s == seq_num && p[*h..] == *body);
+        if !duplicate {
+            if group.msg.parts.len() < MAX_PARTS {
+                group.msg.parts.push(msg.as_bytes().to_vec());
+                group.seen.push((seq_num, header_len));
+            } else {
+                group.dropped = true;
+            }
+        }
+
+        if part == Part::Last {
+            let mut group = self.groups.remove(&id).unwrap();
+            group.msg.complete = !group.dropped;
+            return Ok(Some(group.msg));
+        }
+
+        Ok(None)
+    }
+
+    /// Remove the logical messages that timed out, they are returned
+    /// incomplete.
+    pub fn expire(&mut self) -> Vec<Reassembled> {
+        let now = self.clock.now();
+        let expired: Vec<_> = self
+            .groups
+            .iter()
+            .filter(|(_, g)| g.time <= now)
+            .map(|(id, _)| *id)
+            .collect();
+
+        expired
+            .iter()
+            .filter_map(|id| self.groups.remove(id))
+            .map(|g| g.msg)
+            .collect()
+    }
+
+    /// Next time a logical message times out, when
+    /// [`expire`](#method.expire) must be called.
+    pub fn next_expiry(&self) -> Option<u64> {
+        self.groups.values().map(|g| g.time).min()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::olsr::{Tc, TcAddress};
+    use crate::{ManualClock, MessageBuilder, MsgHeader};
+
+    fn read(buf: &[u8]) -> Message<'_> {
+        Message::read(&mut Buf::new(buf)).unwrap()
+    }
+
+    fn message(buf: &mut [u8], seq_num: Option<u16>) -> usize {
+        let orig = [10, 0, 0, 1];
+        let mut hdr = MsgHeader::new(1, 4);
+        hdr.orig_addr = Some(&orig);
+        hdr.seq_num = seq_num;
+        MessageBuilder::new(buf, &hdr).unwrap().finish().unwrap()
+    }
+
+    /// Message with the `<msg-seq-num>` 7 and the addresses `10.0.0.last`.
+    fn subset(buf: &mut [u8], last: &[u8], hop_count: u8) -> usize {
+        let orig = [10, 0, 0, 1];
+        let mut hdr = MsgHeader::new(1, 4);
+        hdr.orig_addr = Some(&orig);
+        hdr.seq_num = Some(7);
+        hdr.hop_count = Some(hop_count);
+        let addrs: Vec<_> = last
+            .iter()
+            .map(|l| Address::from_bytes(&[10, 0, 0, *l]).unwrap())
+            .collect();
+        let mut builder = MessageBuilder::new(buf, &hdr).unwrap();
+        builder.add_address_block(&addrs, None).unwrap();
+        builder.finish().unwrap()
+    }
+
+    #[test]
+    fn test_reassembly() {
+        let clock = ManualClock::new(0);
+        let mut reassembly = Reassembly::new(&clock, 1000);
+        let mut buf = [0u8; 64];
+
+        // Two subsets of the addresses with the same <msg-seq-num>, the
+        // first one is received twice through different paths.
+        let len = subset(&mut buf, &[2, 3], 1);
+        let msg = read(&buf[..len]);
+        assert_eq!(reassembly.receive(&msg, Part::More), Ok(None));
+        let mut other = [0u8; 64];
+        let len = subset(&mut other, &[2, 3], 2);
+        let msg = read(&other[..len]);
+        assert_eq!(reassembly.receive(&msg, Part::More), Ok(None));
+        assert_eq!(reassembly.next_expiry(), Some(1000));
+        let len = subset(&mut other, &[4], 1);
+        let msg = read(&other[..len]);
+        let logical = reassembly.receive(&msg, Part::Last).unwrap().unwrap();
+        assert!(logical.complete);
+        assert_eq!(logical.key, 7);
+        assert_eq!(logical.orig_addr.as_bytes(), &[10, 0, 0, 1]);
+        // Both subsets are kept, the duplicate is dropped.
+        assert_eq!(logical.len(), 2);
+        let parts: Vec<_> = logical.parts().map(|m| m.as_bytes()).collect();
+        assert_eq!(parts, [&buf[..parts[0].len()], &other[..len]]);
+        assert!(reassembly.is_empty());
+
+        // Timeouts.
+        for seq_num in 1..4 {
+            let len = message(&mut buf, Some(seq_num));
+            let msg = read(&buf[..len]);
+            let res = reassembly.receive_with_key(&msg, 42, Part::More);
+            assert_eq!(res, Ok(None));
+            clock.advance(100);
+        }
+        assert_eq!(reassembly.len(), 1);
+        clock.set(999);
+        assert!(reassembly.expire().is_empty());
+        clock.set(1000);
+        let expired = reassembly.expire();
+        assert_eq!(expired.len(), 1);
+        assert!(!expired[0].complete);
+        let seq_nums = expired[0].parts().map(|m| m.hdr.seq_num.unwrap());
+        assert!(seq_nums.eq(1..4));
+        assert!(reassembly.is_empty());
+
+        // Without an originator.
+        let len = {
+            let hdr = MsgHeader::new(1, 4);
+            MessageBuilder::new(&mut buf, &hdr)
+                .unwrap()
+                .finish()
+                .unwrap()
+        };
+        let msg = read(&buf[..len]);
+        assert_eq!(
+            reassembly.receive_with_key(&msg, 0, Part::More),
+            Err(Error::InvalidMessage)
+        );
+    }
+
+    #[test]
+    fn test_reassembly_too_many_parts() {
+        let clock = ManualClock::new(0);
+        let mut reassembly = Reassembly::new(&clock, 1000);
+        let mut buf = [0u8; 64];
+
+        for seq_num in 0..MAX_PARTS as u16 + 1 {
+            let len = message(&mut buf, Some(seq_num));
+            let msg = read(&buf[..len]);
+            let res = reassembly.receive_with_key(&msg, 1, Part::More);
+            assert_eq!(res, Ok(None));
+        }
+
+        // The logical message ends, but a message was dropped.
+        let len = message(&mut buf, Some(0xffff));
+        let msg = read(&buf[..len]);
+        let logical = reassembly.receive_with_key(&msg, 1, Part::Last);
+        let logical = logical.unwrap().unwrap();
+        assert!(!logical.complete);
+        assert_eq!(logical.len(), MAX_PARTS);
+        assert!(reassembly.is_empty());
+    }
+
+    #[test]
+    fn test_reassembly_tc() {
+        let clock = ManualClock::new(0);
+        let mut reassembly = Reassembly::new(&clock, 1000);
+        let orig = [10, 0, 0, 1];
+        let tc = Tc::new(4, &orig, 1, 9, 6000);
+        let addrs: Vec<_> = (0..64u8)
+            .map(|i| {
+                TcAddress::new(Address::from_bytes(&[10, i, 0, 1]).unwrap())
+            })
+            .collect();
+
+        let mut buf = [0u8; 512];
+        let mut whole = None;
+        tc.write_split(&addrs, &mut buf, |msg| {
+            whole = reassembly.receive_tc(&read(msg))?;
+            Ok(())
+        })
+        .unwrap();
+        assert!(whole.unwrap().complete);
+
+        let mut buf = [0u8; 64];
+        let n = tc
+            .write_split(&addrs, &mut buf, |msg| {
+                assert_eq!(reassembly.receive_tc(&read(msg)), Ok(None));
+                Ok(())
+            })
+            .unwrap();
+        clock.set(1000);
+        let expired = reassembly.expire();
+        assert_eq!(expired.len(), 1);
+        assert_eq!(expired[0].key, 9);
+        assert_eq!(expired[0].len(), n);
+        let found: Vec<_> = expired[0]
+            .parts()
+            .flat_map(|m| {
+                let tc = Tc::from_message(&m).unwrap();
+                tc.addresses().collect::<Vec<_>>()
+            })
+            .collect();
+        assert_eq!(found, addrs);
+    }
+}
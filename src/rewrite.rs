@@ -0,0 +1,262 @@
+// Copyright 2020 Jean Pierre Dudey. See the LICENSE-MIT and
+// LICENSE-APACHE files at the top-level directory of this
+// distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Rewriting of messages.
+//!
+//! [`rewrite`](fn.rewrite.html) copies a message to a new buffer, asking a
+//! [`Rewrite`](trait.Rewrite.html) what to remove and add. Everything kept
+//! is copied byte for byte, including TLVs that aren't understood, and
+//! `<msg-size>` and the `<tlvs-length>` fields are recomputed.
+//!
+//! To append an ICV, add an ICV message TLV with a value of the right size,
+//! compute the ICV over the rewritten message with
+//! [`icv::message_coverage`](../icv/fn.message_coverage.html) and fill in
+//! the value with
+//! [`MessageMut::msg_tlv_value_mut`](../struct.MessageMut.html#method.msg_tlv_value_mut).
+
+use crate::tlv::for_each_raw_tlv;
+use crate::{
+    AddressBlock, Buf, Error, Message, MessageBuilder, MsgHeader, Tlv, TlvBlock,
+};
+
+/// Changes made by [`rewrite`](fn.rewrite.html), every method keeps the
+/// message as is by default.
+pub trait Rewrite {
+    /// Change the message header, `<msg-size>` is ignored.
+    fn header(&mut self, _hdr: &mut MsgHeader) {}
+
+    /// Keep the message TLV `tlv`?
+    fn keep_msg_tlv(&mut self, _tlv: &Tlv) -> bool {
+        true
+    }
+
+    /// Add message TLVs after the ones kept.
+    fn add_msg_tlvs(
+        &mut self,
+        _builder: &mut MessageBuilder,
+    ) -> Result<(), Error> {
+        Ok(())
+    }
+
+    /// Keep the address block `block`, number `index` in the message, and
+    /// its TLVs?
+    fn keep_address_block(
+        &mut self,
+        _index: usize,
+        _block: &AddressBlock,
+    ) -> bool {
+        true
+    }
+
+    /// Keep the address TLV `tlv` of the address block `block`?
+    fn keep_addr_tlv(
+        &mut self,
+        _index: usize,
+        _block: &AddressBlock,
+        _tlv: &Tlv,
+    ) -> bool {
+        true
+    }
+
+    /// Add address TLVs to the address block `block` after the ones kept.
+    fn add_addr_tlvs(
+        &mut self,
+        _index: usize,
+        _block: &AddressBlock,
+        _builder: &mut MessageBuilder,
+    ) -> Result<(), Error> {
+        Ok(())
+    }
+
+    /// Add address blocks at the end of the message.
+    fn add_address_blocks(
+        &mut self,
+        _builder: &mut MessageBuilder,
+    ) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+/// Write a copy of `msg` changed by `rewriter` to `buf`, returns the size of
+/// the new message.
+pub fn rewrite<R: Rewrite>(
+    msg: &Message,
+    buf: &mut [u8],
+    rewriter: &mut R,
+) -> Result<usize, Error> {
+    let mut hdr = MsgHeader::new(msg.hdr.r#type, msg.hdr.address_length);
+    hdr.orig_addr = msg.hdr.orig_addr;
+    hdr.hop_limit = msg.hdr.hop_limit;
+    hdr.hop_count = msg.hdr.hop_count;
+    hdr.seq_num = msg.hdr.seq_num;
+    rewriter.header(&mut hdr);
+
+    let mut builder = MessageBuilder::new(buf, &hdr)?;
+    for_each_raw_tlv(msg.tlv_block.as_bytes(), |tlv, raw| {
+        if rewriter.keep_msg_tlv(tlv) {
+            builder.add_raw_tlv(raw)?;
+        }
+        Ok(())
+    })?;
+    rewriter.add_msg_tlvs(&mut builder)?;
+
+    let bytes = msg.address_tlv.buf.buf;
+    let mut blocks = Buf::new(bytes);
+    let mut index = 0;
+    while !blocks.is_eof() {
+        let start = blocks.pos();
+        let block = AddressBlock::read(&mut blocks, msg.hdr.address_length)?;
+        let raw = &bytes[start..blocks.pos()];
+        let tlvs = TlvBlock::read(&mut blocks)?;
+
+        if rewriter.keep_address_block(index, &block) {
+            builder.add_raw_address_block(raw, block.num_addr)?;
+            for_each_raw_tlv(tlvs.as_bytes(), |tlv, raw| {
+                if rewriter.keep_addr_tlv(index, &block, tlv) {
+                    builder.add_raw_tlv(raw)?;
+                }
+                Ok(())
+            })?;
+            rewriter.add_addr_tlvs(index, &block, &mut builder)?;
+        }
+        index += 1;
+    }
+    rewriter.add_address_blocks(&mut builder)?;
+
+    builder.finish()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::Address;
+
+    fn addr(bytes: &[u8]) -> Address {
+        Address::from_bytes(bytes).unwrap()
+    }
+
+    fn tlv(r#type: u8, value: &[u8]) -> Tlv<'_> {
+        Tlv {
+            r#type,
+            type_ext: None,
+            start_index: None,
+            stop_index: None,
+            value: Some(value),
+            multi_value: false,
+        }
+    }
+
+    fn message(buf: &mut [u8]) -> usize {
+        let orig = [10, 0, 0, 1];
+        let mut hdr = MsgHeader::new(1, 4);
+        hdr.orig_addr = Some(&orig);
+        hdr.hop_limit = Some(255);
+        hdr.seq_num = Some(1);
+        let mut builder = MessageBuilder::new(buf, &hdr).unwrap();
+        builder.add_tlv(&tlv(1, &[1])).unwrap();
+        // TLV with an extended <length>.
+        builder.add_tlv(&tlv(200, &[0; 300])).unwrap();
+        builder.add_tlv(&tlv(2, &[2, 2])).unwrap();
+        let addrs = [addr(&[10, 0, 0, 2]), addr(&[10, 0, 0, 3])];
+        builder.add_address_block(&addrs, None).unwrap();
+        builder.add_address_tlvs_u8(3, None, |_| Some(3)).unwrap();
+        builder
+            .add_address_tlvs_u8(4, None, |i| Some(i as u8))
+            .unwrap();
+        builder
+            .add_address_block(&[addr(&[10, 1, 0, 0])], Some(&[16]))
+            .unwrap();
+        builder.add_address_tlvs_u8(5, None, |_| Some(5)).unwrap();
+        builder.finish().unwrap()
+    }
+
+    struct Keep;
+
+    impl Rewrite for Keep {}
+
+    struct Change;
+
+    impl Rewrite for Change {
+        fn header(&mut self, hdr: &mut MsgHeader) {
+            hdr.hop_limit = Some(1);
+        }
+
+        fn keep_msg_tlv(&mut self, tlv: &Tlv) -> bool {
+            tlv.r#type != 200
+        }
+
+        fn add_msg_tlvs(
+            &mut self,
+            builder: &mut MessageBuilder,
+        ) -> Result<(), Error> {
+            builder.add_tlv(&tlv(9, &[9]))
+        }
+
+        fn keep_address_block(
+            &mut self,
+            index: usize,
+            _block: &AddressBlock,
+        ) -> bool {
+            index == 0
+        }
+
+        fn keep_addr_tlv(
+            &mut self,
+            _index: usize,
+            _block: &AddressBlock,
+            tlv: &Tlv,
+        ) -> bool {
+            tlv.r#type != 3
+        }
+
+        fn add_addr_tlvs(
+            &mut self,
+            _index: usize,
+            block: &AddressBlock,
+            builder: &mut MessageBuilder,
+        ) -> Result<(), Error> {
+            assert_eq!(block.num_addr, 2);
+            builder.add_address_tlvs_u8(6, None, |_| Some(6))
+        }
+    }
+
+    #[test]
+    fn test_rewrite() {
+        let mut buf = [0u8; 512];
+        let size = message(&mut buf);
+        let msg = Message::read(&mut Buf::new(&buf[..size])).unwrap();
+
+        // Nothing changed.
+        let mut out = [0u8; 512];
+        let len = rewrite(&msg, &mut out, &mut Keep).unwrap();
+        assert_eq!(&out[..len], &buf[..size]);
+        assert_eq!(
+            rewrite(&msg, &mut out[..size - 1], &mut Keep),
+            Err(Error::BufferTooSmall)
+        );
+
+        let len = rewrite(&msg, &mut out, &mut Change).unwrap();
+        let new = Message::read(&mut Buf::new(&out[..len])).unwrap();
+        assert_eq!(new.hdr.size(), len);
+        assert_eq!(new.hdr.hop_limit, Some(1));
+        assert_eq!(new.hdr.seq_num, Some(1));
+        let types = new.tlv_block.iter().map(|t| t.unwrap().r#type);
+        assert!(types.eq([1, 2, 9].iter().cloned()));
+
+        let mut blocks = new.address_tlv.iter();
+        let (block, tlvs) = blocks.next().unwrap().unwrap();
+        assert_eq!(block.get_addr(1), addr(&[10, 0, 0, 3]));
+        let types = tlvs.iter().map(|t| t.unwrap().r#type);
+        assert!(types.eq([4, 6].iter().cloned()));
+        let tlv = tlvs.iter().next().unwrap().unwrap();
+        assert_eq!(tlv.value_at(1, 2), Ok(Some(&[1][..])));
+        assert!(blocks.next().is_none());
+    }
+}
@@ -0,0 +1,339 @@
+// Copyright 2020 Jean Pierre Dudey. See the LICENSE-MIT and
+// LICENSE-APACHE files at the top-level directory of this
+// distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use crate::Clock;
+
+/// A source of random numbers, for jitter.
+pub trait Rng {
+    /// A random number, uniformly distributed.
+    fn next_u32(&mut self) -> u32;
+}
+
+impl<R: Rng + ?Sized> Rng for &mut R {
+    fn next_u32(&mut self) -> u32 {
+        (**self).next_u32()
+    }
+}
+
+/// Why a message must be sent.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Reason {
+    /// Periodic message.
+    Periodic,
+    /// Message triggered by a change.
+    Triggered,
+}
+
+/// A message to generate and send now.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct Emission {
+    /// Index of the timer of the message.
+    pub timer: usize,
+    /// Why it's sent.
+    pub reason: Reason,
+}
+
+/// State of the emission of a kind of message, for example the HELLOs of
+/// an interface.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Timer {
+    /// Period of the periodic messages, 0 if not periodic.
+    interval: u64,
+    max_jitter: u64,
+    min_interval: u64,
+    last_sent: Option<u64>,
+    /// Next message to send.
+    next: Option<(u64, Reason)>,
+}
+
+/// Scheduler of the generation of messages, jittered as in RFC 5148.
+///
+/// Each kind of message has a [`Timer`](struct.Timer.html), in a slice
+/// given by the caller and referred to by its index. Messages are either
+/// periodic, sent every `interval` milliseconds minus a jitter of up to
+/// `max_jitter`, or triggered, sent after a jitter of up to `max_jitter`
+/// and never sooner than `min_interval` after the previous message. Sending
+/// a message restarts the period, so a triggered message replaces the
+/// pending periodic one.
+///
+/// [`poll`](#method.poll) returns the messages to generate in time order.
+/// Once encoded they can be queued in an
+/// [`Aggregator`](struct.Aggregator.html) with a deadline of `now`, and
+/// forwarded messages with one from [`jittered`](#method.jittered).
+#[derive(Debug)]
+pub struct Scheduler<'a, C, R> {
+    clock: C,
+    rng: R,
+    timers: &'a mut [Timer],
+}
+
+impl<'a, C: Clock, R: Rng> Scheduler<'a, C, R> {
+    /// Create a scheduler with the stopped timers `timers`.
+    pub fn new(clock: C, rng: R, timers: &'a mut [Timer]) -> Self {
+        for timer in timers.iter_mut() {
+            *timer = Timer::default();
+        }
+
+        Scheduler { clock, rng, timers }
+    }
+
+    /// The clock.
+    pub fn clock(&self) -> &C {
+        &self.clock
+    }
+
+    /// Send messages with timer `timer` every `interval` milliseconds,
+    /// jittered by up to `max_jitter`. `max_jitter` is at most half the
+    /// interval, as required by RFC 5148, it's reduced if larger. The
+    /// first message is sent after the jitter.
+    ///
+    /// # Panics
+    ///
+    /// If `timer` is out of range.
+    pub fn set_periodic(
+        &mut self,
+        timer: usize,
+        interval: u64,
+        max_jitter: u64,
+    ) {
+        let max_jitter = max_jitter.min(interval / 2);
+        let at = self.jittered(max_jitter);
+        let t = &mut self.timers[timer];
+        t.interval = interval;
+        t.max_jitter = max_jitter;
+        t.next = Some((at, Reason::Periodic));
+    }
+
+    /// Set the jitter of triggered messages, for timers without periodic
+    /// messages, and the minimum time between two messages.
+    ///
+    /// # Panics
+    ///
+    /// If `timer` is out of range.
+    pub fn set_limits(
+        &mut self,
+        timer: usize,
+        max_jitter: u64,
+        min_interval: u64,
+    ) {
+        let t = &mut self.timers[timer];
+        if t.interval == 0 {
+            t.max_jitter = max_jitter;
+        }
+        t.min_interval = min_interval;
+    }
+
+    /// Stop sending messages with `timer`.
+    ///
+    /// # Panics
+    ///
+    /// If `timer` is out of range.
+    pub fn stop(&mut self, timer: usize) {
+        self.timers[timer] = Timer::default();
+    }
+
+    /// Trigger a message with `timer`. Nothing changes if a triggered
+    /// message is already pending, or a periodic one that is sent sooner.
+    ///
+    /// # Panics
+    ///
+    /// If `timer` is out of range.
+    pub fn trigger(&mut self, timer: usize) {
+        let max_jitter = self.timers[timer].max_jitter;
+        let mut at = self.jittered(max_jitter);
+
+        let t = &mut self.timers[timer];
+        if let Some(last_sent) = t.last_sent {
+            at = at.max(last_sent.saturating_add(t.min_interval));
+        }
+        match t.next {
+            Some((_, Reason::Triggered)) => {}
+            Some((next, Reason::Periodic)) if next <= at => {}
+            _ => t.next = Some((at, Reason::Triggered)),
+        }
+    }
+
+    /// Time plus a random jitter of up to `max_jitter` milliseconds, for
+    /// the deadline of forwarded messages.
+    pub fn jittered(&mut self, max_jitter: u64) -> u64 {
+        let jitter = if max_jitter == 0 {
+            0
+        } else {
+            u64::from(self.rng.next_u32()) % max_jitter.saturating_add(1)
+        };
+        self.clock.now().saturating_add(jitter)
+    }
+
+    /// Next time a message must be sent, when [`poll`](#method.poll) must be
+    /// called.
+    pub fn next_deadline(&self) -> Option<u64> {
+        self.timers
+            .iter()
+            .filter_map(|t| t.next)
+            .map(|(at, _)| at)
+            .min()
+    }
+
+    /// The next message to send now, the earliest one if there are several.
+    pub fn poll(&mut self) -> Option<Emission> {
+        let now = self.clock.now();
+        let (timer, reason) = self
+            .timers
+            .iter()
+            .enumerate()
+            .filter_map(|(i, t)| t.next.map(|(at, r)| (at, i, r)))
+            .filter(|(at, _, _)| *at <= now)
+            .min_by_key(|(at, i, _)| (*at, *i))
+            .map(|(_, i, r)| (i, r))?;
+
+        let t = self.timers[timer];
+        let next = if t.interval > 0 {
+            let jitter = self.jittered(t.max_jitter) - now;
+            let at = now.saturating_add(t.interval).saturating_sub(jitter);
+            Some((at, Reason::Periodic))
+        } else {
+            None
+        };
+        let t = &mut self.timers[timer];
+        t.last_sent = Some(now);
+        t.next = next;
+
+        Some(Emission { timer, reason })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::ManualClock;
+
+    /// Xorshift.
+    struct TestRng(u32);
+
+    impl Rng for TestRng {
+        fn next_u32(&mut self) -> u32 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 17;
+            self.0 ^= self.0 << 5;
+            self.0
+        }
+    }
+
+    const HELLO: usize = 0;
+    const TC: usize = 1;
+
+    #[test]
+    fn test_scheduler() {
+        let clock = ManualClock::new(0);
+        let mut timers = [Timer::default(); 2];
+        let mut rng = TestRng(1);
+        let mut sched = Scheduler::new(&clock, &mut rng, &mut timers);
+        sched.set_periodic(HELLO, 2000, 500);
+        sched.set_limits(HELLO, 0, 500);
+        sched.set_periodic(TC, 5000, 4000);
+        assert!(sched.next_deadline().unwrap() <= 500);
+
+        // Periodic messages, jittered.
+        let mut count = [0; 2];
+        let mut last = [0; 2];
+        for now in 0..20_000 {
+            clock.set(now);
+            while let Some(e) = sched.poll() {
+                assert_eq!(e.reason, Reason::Periodic);
+                if count[e.timer] > 0 {
+                    let interval = now - last[e.timer];
+                    match e.timer {
+                        HELLO => {
+                            assert!(interval >= 1500 && interval <= 2000)
+                        }
+                        _ => assert!(interval >= 2500 && interval <= 5000),
+                    }
+                }
+                count[e.timer] += 1;
+                last[e.timer] = now;
+            }
+        }
+        assert!(count[HELLO] >= 10);
+        assert!(count[TC] >= 4);
+
+        // A triggered HELLO replaces the periodic one, and respects the
+        // minimum interval.
+        clock.set(last[HELLO] + 100);
+        sched.trigger(HELLO);
+        assert_eq!(sched.poll(), None);
+        clock.set(last[HELLO] + 500);
+        assert_eq!(
+            sched.poll(),
+            Some(Emission {
+                timer: HELLO,
+                reason: Reason::Triggered
+            })
+        );
+        assert!(sched.next_deadline().unwrap() >= clock.now() + 1500);
+
+        sched.stop(TC);
+        sched.stop(HELLO);
+        assert_eq!(sched.next_deadline(), None);
+
+        // Triggered only.
+        sched.set_limits(TC, 100, 0);
+        sched.trigger(TC);
+        let at = sched.next_deadline().unwrap();
+        assert!(at <= clock.now() + 100);
+        sched.trigger(TC);
+        assert_eq!(sched.next_deadline(), Some(at));
+        clock.set(at);
+        assert_eq!(sched.poll().map(|e| e.reason), Some(Reason::Triggered));
+        assert_eq!(sched.next_deadline(), None);
+
+        // The next period saturates at the end of time.
+        clock.set(core::u64::MAX - 10);
+        sched.set_periodic(HELLO, core::u64::MAX, core::u64::MAX);
+        clock.set(core::u64::MAX);
+        assert_eq!(sched.poll().map(|e| e.reason), Some(Reason::Periodic));
+        assert!(sched.next_deadline().is_some());
+    }
+
+    #[test]
+    fn test_scheduler_aggregation() {
+        use crate::{Aggregator, MessageBuilder, MsgHeader, Packet, Slot};
+
+        let clock = ManualClock::new(0);
+        let mut timers = [Timer::default(); 2];
+        let mut sched = Scheduler::new(&clock, TestRng(7), &mut timers);
+        let mut data = [0u8; 256];
+        let mut slots = [Slot::default(); 4];
+        let mut queue = Aggregator::new(&clock, 128, &mut data, &mut slots);
+        sched.set_periodic(HELLO, 2000, 500);
+        sched.set_periodic(TC, 2000, 500);
+
+        let mut packets = 0;
+        let mut messages = 0;
+        let mut out = [0u8; 128];
+        for now in 0..10_000 {
+            clock.set(now);
+            while let Some(e) = sched.poll() {
+                let mut buf = [0u8; 16];
+                let hdr = MsgHeader::new(e.timer as u8, 4);
+                let builder = MessageBuilder::new(&mut buf, &hdr).unwrap();
+                let len = builder.finish().unwrap();
+                // Wait up to 100 ms for other messages.
+                queue.push(&buf[..len], 0, now + 100).unwrap();
+            }
+            if let Some(len) = queue.poll(&mut out, &[]).unwrap() {
+                let packet = Packet::read(&out[..len]).unwrap();
+                messages += packet.messages.iter().count();
+                packets += 1;
+            }
+        }
+        assert!(messages >= 2 * 5);
+        assert!(packets <= messages);
+    }
+}
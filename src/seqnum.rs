@@ -0,0 +1,94 @@
+// Copyright 2020 Jean Pierre Dudey. See the LICENSE-MIT and
+// LICENSE-APACHE files at the top-level directory of this
+// distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use core::cmp::Ordering;
+
+/// A 16-bit sequence number that wraps around.
+///
+/// Sequence numbers are compared as in RFC 7181 section 21: `a` is newer
+/// than `b` if `a > b` and `a - b < 2^15`, or if `a < b` and
+/// `b - a >= 2^15`. The comparison isn't transitive, so `SeqNum` is only
+/// `PartialOrd`.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash, Default)]
+pub struct SeqNum(pub u16);
+
+impl SeqNum {
+    /// The next sequence number.
+    pub fn next(self) -> SeqNum {
+        self.wrapping_add(1)
+    }
+
+    /// The sequence number `n` after this one.
+    pub fn wrapping_add(self, n: u16) -> SeqNum {
+        SeqNum(self.0.wrapping_add(n))
+    }
+
+    /// Is this sequence number newer than `other`?
+    pub fn is_newer_than(self, other: SeqNum) -> bool {
+        let (a, b) = (self.0, other.0);
+        (a > b && a - b < 0x8000) || (a < b && b - a >= 0x8000)
+    }
+
+    /// Number of sequence numbers from `older` to this one, `None` if
+    /// `older` isn't older.
+    pub fn distance(self, older: SeqNum) -> Option<u16> {
+        if self.is_newer_than(older) {
+            Some(self.0.wrapping_sub(older.0))
+        } else {
+            None
+        }
+    }
+}
+
+impl PartialOrd for SeqNum {
+    fn partial_cmp(&self, other: &SeqNum) -> Option<Ordering> {
+        Some(if self == other {
+            Ordering::Equal
+        } else if self.is_newer_than(*other) {
+            Ordering::Greater
+        } else {
+            Ordering::Less
+        })
+    }
+}
+
+impl From<u16> for SeqNum {
+    fn from(n: u16) -> SeqNum {
+        SeqNum(n)
+    }
+}
+
+impl From<SeqNum> for u16 {
+    fn from(n: SeqNum) -> u16 {
+        n.0
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_seq_num() {
+        let s = SeqNum;
+        assert!(s(1) > s(0));
+        assert!(s(0) > s(core::u16::MAX));
+        assert!(s(core::u16::MAX) < s(0));
+        assert!(s(0x7fff) > s(0));
+        assert!(s(0x8000) < s(0));
+        assert!(s(0) > s(0x8000));
+        assert_eq!(s(5).partial_cmp(&s(5)), Some(Ordering::Equal));
+        assert!(!s(5).is_newer_than(s(5)));
+
+        assert_eq!(s(core::u16::MAX).next(), s(0));
+        assert_eq!(s(2).distance(s(core::u16::MAX)), Some(3));
+        assert_eq!(s(core::u16::MAX).distance(s(2)), None);
+    }
+}
@@ -0,0 +1,215 @@
+// Copyright 2020 Jean Pierre Dudey. See the LICENSE-MIT and
+// LICENSE-APACHE files at the top-level directory of this
+// distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use std::collections::BTreeMap;
+use std::mem;
+
+use crate::Clock;
+
+/// Identification-based Duplicate Packet Detection (RFC 6621 section 6.1).
+///
+/// Packets are identified by a flow `K` and an identifier within it: the
+/// source and destination addresses, protocol and fragment fields with the
+/// IPv4 ID, or the TaggerId or source address with the sequence number of
+/// the SMF_DPD IPv6 option. Identifiers are remembered for `hold_time`
+/// milliseconds, which must be longer than the time it takes them to wrap
+/// around.
+#[derive(Debug)]
+pub struct IDpd<C, K> {
+    clock: C,
+    hold_time: u64,
+    entries: BTreeMap<(K, u64), u64>,
+}
+
+impl<C: Clock, K: Ord> IDpd<C, K> {
+    /// Create an empty cache keeping identifiers for `hold_time`
+    /// milliseconds.
+    pub fn new(clock: C, hold_time: u64) -> Self {
+        IDpd {
+            clock,
+            hold_time,
+            entries: BTreeMap::new(),
+        }
+    }
+
+    /// The clock.
+    pub fn clock(&self) -> &C {
+        &self.clock
+    }
+
+    /// Number of identifiers remembered.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Is the cache empty?
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Remember the packet `id` of `flow`, returns `false` if it's a
+    /// duplicate.
+    pub fn receive(&mut self, flow: K, id: u64) -> bool {
+        let now = self.clock.now();
+        let time = now.saturating_add(self.hold_time);
+        insert(&mut self.entries, (flow, id), now, time)
+    }
+
+    /// Forget the identifiers received `hold_time` milliseconds ago.
+    pub fn expire(&mut self) {
+        expire(&mut self.entries, self.clock.now());
+    }
+
+    /// Next time an identifier is forgotten, when
+    /// [`expire`](#method.expire) must be called.
+    pub fn next_expiry(&self) -> Option<u64> {
+        self.entries.values().cloned().min()
+    }
+}
+
+/// Hash-based Duplicate Packet Detection (RFC 6621 section 6.2).
+///
+/// Packets are identified by a 64-bit hash of their invariant part, the IP
+/// header fields changed by forwarding (as the TTL or Hop Limit) must be
+/// zeroed first. Hashes are remembered for `hold_time` milliseconds.
+#[derive(Debug)]
+pub struct HDpd<C> {
+    clock: C,
+    hold_time: u64,
+    entries: BTreeMap<u64, u64>,
+}
+
+impl<C: Clock> HDpd<C> {
+    /// Create an empty cache keeping hashes for `hold_time` milliseconds.
+    pub fn new(clock: C, hold_time: u64) -> Self {
+        HDpd {
+            clock,
+            hold_time,
+            entries: BTreeMap::new(),
+        }
+    }
+
+    /// The clock.
+    pub fn clock(&self) -> &C {
+        &self.clock
+    }
+
+    /// Number of hashes remembered.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Is the cache empty?
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Remember `packet`, returns `false` if it's a duplicate.
+    pub fn receive(&mut self, packet: &[u8]) -> bool {
+        self.receive_hash(hash(packet))
+    }
+
+    /// Remember a packet with the hash `hash` computed by the caller, for
+    /// example with another function than [`hash`](fn.hash.html). Returns
+    /// `false` if it's a duplicate.
+    pub fn receive_hash(&mut self, hash: u64) -> bool {
+        let now = self.clock.now();
+        let time = now.saturating_add(self.hold_time);
+        insert(&mut self.entries, hash, now, time)
+    }
+
+    /// Forget the hashes received `hold_time` milliseconds ago.
+    pub fn expire(&mut self) {
+        expire(&mut self.entries, self.clock.now());
+    }
+
+    /// Next time a hash is forgotten, when [`expire`](#method.expire) must
+    /// be called.
+    pub fn next_expiry(&self) -> Option<u64> {
+        self.entries.values().cloned().min()
+    }
+}
+
+/// Hash of a packet used by [`HDpd`](struct.HDpd.html), 64-bit FNV-1a.
+pub fn hash(packet: &[u8]) -> u64 {
+    packet.iter().fold(0xcbf2_9ce4_8422_2325, |h, b| {
+        (h ^ u64::from(*b)).wrapping_mul(0x0000_0100_0000_01b3)
+    })
+}
+
+/// Insert `key` expiring at `time` unless it's already there and not
+/// expired at `now`. The expiration time of duplicates isn't refreshed.
+fn insert<K: Ord>(
+    entries: &mut BTreeMap<K, u64>,
+    key: K,
+    now: u64,
+    time: u64,
+) -> bool {
+    let t = entries.entry(key).or_insert(0);
+    if *t > now {
+        return false;
+    }
+
+    *t = time;
+    true
+}
+
+/// Remove the keys expired at `now`.
+fn expire<K: Ord>(entries: &mut BTreeMap<K, u64>, now: u64) {
+    let all = mem::replace(entries, BTreeMap::new());
+    *entries = all.into_iter().filter(|(_, t)| *t > now).collect();
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::ManualClock;
+
+    #[test]
+    fn test_i_dpd() {
+        let clock = ManualClock::new(0);
+        let mut dpd = IDpd::new(&clock, 1000);
+        assert!(dpd.receive((1, 2), 7));
+        assert!(!dpd.receive((1, 2), 7));
+        // Another flow or identifier is another packet.
+        assert!(dpd.receive((1, 3), 7));
+        assert!(dpd.receive((1, 2), 8));
+        assert_eq!(dpd.len(), 3);
+
+        clock.set(500);
+        assert!(dpd.receive((1, 2), 9));
+        assert_eq!(dpd.next_expiry(), Some(1000));
+        clock.set(1000);
+        // Expired entries aren't duplicates, even before expire is called.
+        assert!(dpd.receive((1, 2), 7));
+        dpd.expire();
+        assert_eq!(dpd.len(), 2);
+        assert_eq!(dpd.next_expiry(), Some(1500));
+    }
+
+    #[test]
+    fn test_h_dpd() {
+        let clock = ManualClock::new(0);
+        let mut dpd = HDpd::new(&clock, 1000);
+        assert_eq!(hash(&[]), 0xcbf2_9ce4_8422_2325);
+        assert_eq!(hash(b"a"), 0xaf63_dc4c_8601_ec8c);
+
+        assert!(dpd.receive(&[1, 2, 3]));
+        assert!(!dpd.receive(&[1, 2, 3]));
+        assert!(dpd.receive(&[1, 2, 4]));
+        assert!(!dpd.receive_hash(hash(&[1, 2, 4])));
+
+        clock.set(1000);
+        dpd.expire();
+        assert!(dpd.is_empty());
+        assert_eq!(dpd.next_expiry(), None);
+        assert!(dpd.receive(&[1, 2, 3]));
+    }
+}
@@ -0,0 +1,170 @@
+// Copyright 2020 Jean Pierre Dudey. See the LICENSE-MIT and
+// LICENSE-APACHE files at the top-level directory of this
+// distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Simplified Multicast Forwarding (SMF), RFC 6621.
+//!
+//! SMF floods multicast packets through a MANET: each router drops the
+//! packets it already saw, with Identification-based or Hash-based
+//! Duplicate Packet Detection ([`IDpd`](struct.IDpd.html) and
+//! [`HDpd`](struct.HDpd.html)), and relays the others if it's in the relay
+//! set chosen by the Relay Set Selection Algorithm (RSSA) of the network
+//! ([`RelayGraph`](struct.RelayGraph.html)).
+//!
+//! Routers advertise their RSSA in an SMF_TYPE message TLV of their NHDP
+//! HELLOs, and the RSSA of their neighbors in SMF_NBR_TYPE address TLVs.
+//! Both are read and written by [`Hello`](../nhdp/struct.Hello.html).
+//!
+//! The duplicate detection and relay set selection need the `use_std`
+//! feature.
+
+#[cfg(feature = "use_std")]
+mod dpd;
+#[cfg(feature = "use_std")]
+mod relay;
+
+#[cfg(feature = "use_std")]
+pub use self::dpd::{hash, HDpd, IDpd};
+#[cfg(feature = "use_std")]
+pub use self::relay::{RelayGraph, RelaySet};
+
+use crate::Error;
+
+/// SMF_TYPE message TLV type, specific to HELLOs.
+pub const MSG_TLV_SMF_TYPE: u8 = 128;
+/// SMF_NBR_TYPE address TLV type, specific to HELLOs.
+pub const ADDR_TLV_SMF_NBR_TYPE: u8 = 128;
+
+/// Router priority used when a router doesn't advertise one.
+pub const DEFAULT_ROUTER_PRIORITY: u8 = 64;
+
+/// Relay Set Selection Algorithm.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Ord, PartialOrd)]
+pub enum Rssa {
+    /// Classical flooding, every router relays.
+    Cf,
+    /// Source-based MPR, routers relay the packets of the neighbors that
+    /// selected them as MPR.
+    SMpr,
+    /// Essential Connected Dominating Set, RFC 5614.
+    ECds,
+    /// MPR-based Connected Dominating Set.
+    MprCds,
+}
+
+impl Rssa {
+    /// Value of the RSSA in SMF_TYPE and SMF_NBR_TYPE TLVs.
+    pub fn value(self) -> u8 {
+        match self {
+            Rssa::Cf => 0,
+            Rssa::SMpr => 1,
+            Rssa::ECds => 2,
+            Rssa::MprCds => 3,
+        }
+    }
+
+    /// RSSA with the value `value`, `None` if it's unknown.
+    pub fn from_value(value: u8) -> Option<Rssa> {
+        match value {
+            0 => Some(Rssa::Cf),
+            1 => Some(Rssa::SMpr),
+            2 => Some(Rssa::ECds),
+            3 => Some(Rssa::MprCds),
+            _ => None,
+        }
+    }
+}
+
+/// Value of an SMF_TYPE TLV: the RSSA of a router, optionally followed by
+/// its router priority.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct SmfType {
+    /// RSSA of the router.
+    pub rssa: Rssa,
+    /// Router priority (RtrPri) used by E-CDS and MPR-CDS,
+    /// [`DEFAULT_ROUTER_PRIORITY`](constant.DEFAULT_ROUTER_PRIORITY.html)
+    /// if absent.
+    pub router_priority: Option<u8>,
+}
+
+impl SmfType {
+    /// Create an SMF_TYPE without router priority.
+    pub fn new(rssa: Rssa) -> SmfType {
+        SmfType {
+            rssa,
+            router_priority: None,
+        }
+    }
+
+    /// Read an SMF_TYPE TLV value.
+    ///
+    /// Fails with
+    /// [`Error::InvalidTlvValue`](../enum.Error.html#variant.InvalidTlvValue)
+    /// if the value isn't 1 or 2 bytes long, returns `None` if the RSSA is
+    /// unknown.
+    pub fn from_value(value: &[u8]) -> Result<Option<SmfType>, Error> {
+        let router_priority = match value.len() {
+            1 => None,
+            2 => Some(value[1]),
+            _ => return Err(Error::InvalidTlvValue),
+        };
+
+        Ok(Rssa::from_value(value[0]).map(|rssa| SmfType {
+            rssa,
+            router_priority,
+        }))
+    }
+
+    /// Encode the TLV value in `buf`, returns the used part of it.
+    pub fn value<'b>(&self, buf: &'b mut [u8; 2]) -> &'b [u8] {
+        buf[0] = self.rssa.value();
+        match self.router_priority {
+            Some(p) => {
+                buf[1] = p;
+                &buf[..]
+            }
+            None => &buf[..1],
+        }
+    }
+
+    /// The router priority, or the default one if absent.
+    pub fn priority(&self) -> u8 {
+        self.router_priority.unwrap_or(DEFAULT_ROUTER_PRIORITY)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_smf_type() {
+        let mut buf = [0u8; 2];
+        let mut smf_type = SmfType::new(Rssa::ECds);
+        assert_eq!(smf_type.value(&mut buf), &[2][..]);
+        assert_eq!(smf_type.priority(), DEFAULT_ROUTER_PRIORITY);
+        assert_eq!(SmfType::from_value(&[2]), Ok(Some(smf_type)));
+
+        smf_type.router_priority = Some(100);
+        assert_eq!(smf_type.value(&mut buf), &[2, 100][..]);
+        assert_eq!(SmfType::from_value(&[2, 100]), Ok(Some(smf_type)));
+        assert_eq!(smf_type.priority(), 100);
+
+        assert_eq!(SmfType::from_value(&[200]), Ok(None));
+        assert_eq!(SmfType::from_value(&[]), Err(Error::InvalidTlvValue));
+        assert_eq!(
+            SmfType::from_value(&[0, 1, 2]),
+            Err(Error::InvalidTlvValue)
+        );
+
+        for v in 0..4 {
+            assert_eq!(Rssa::from_value(v).map(Rssa::value), Some(v));
+        }
+    }
+}
@@ -0,0 +1,316 @@
+// Copyright 2020 Jean Pierre Dudey. See the LICENSE-MIT and
+// LICENSE-APACHE files at the top-level directory of this
+// distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::nhdp::{InterfaceId, LinkStatus, Nhdp};
+use crate::smf::{Rssa, DEFAULT_ROUTER_PRIORITY};
+use crate::{Address, Clock};
+
+/// Packets relayed by a router, the result of relay set selection.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum RelaySet<K> {
+    /// Relay every packet.
+    All,
+    /// Relay no packet.
+    None,
+    /// Relay the packets received from these neighbors, the MPR selectors
+    /// with S-MPR.
+    From(BTreeSet<K>),
+}
+
+impl<K: Ord> RelaySet<K> {
+    /// Is a packet received from `prev_hop` relayed?
+    pub fn relays(&self, prev_hop: &K) -> bool {
+        match self {
+            RelaySet::All => true,
+            RelaySet::None => false,
+            RelaySet::From(selectors) => selectors.contains(prev_hop),
+        }
+    }
+}
+
+/// Symmetric 1-hop (N1) and 2-hop (N2) neighbors of a router, the input of
+/// relay set selection.
+///
+/// Routers are identified by `K`, a 2-hop neighbor that is also a 1-hop
+/// neighbor must have the same key in both sets. Routers are ordered by
+/// router priority, then by key. The MPR selectors are the neighbors that
+/// selected the router as flooding MPR, the router's own MPRs are chosen
+/// with [`NeighborGraph`](../olsr/struct.NeighborGraph.html).
+#[derive(Debug, Clone)]
+pub struct RelayGraph<K> {
+    me: K,
+    priority: u8,
+    /// N1, with `true` for MPR selectors.
+    n1: BTreeMap<K, bool>,
+    /// Links between the routers of N1 and N2, in both directions.
+    links: BTreeMap<K, BTreeSet<K>>,
+    priorities: BTreeMap<K, u8>,
+}
+
+impl<K: Ord + Copy> RelayGraph<K> {
+    /// Create a graph without neighbors for the router `me` with the
+    /// router priority `priority`.
+    pub fn new(me: K, priority: u8) -> RelayGraph<K> {
+        RelayGraph {
+            me,
+            priority,
+            n1: BTreeMap::new(),
+            links: BTreeMap::new(),
+            priorities: BTreeMap::new(),
+        }
+    }
+
+    /// Add the 1-hop neighbor `y`.
+    pub fn add_neighbor(&mut self, y: K) {
+        self.n1.entry(y).or_insert(false);
+    }
+
+    /// Add the 1-hop neighbor `y` that selected the router as MPR.
+    pub fn add_mpr_selector(&mut self, y: K) {
+        self.n1.insert(y, true);
+    }
+
+    /// Add the 2-hop neighbor `x` reachable through `y`, or a link between
+    /// the 1-hop neighbors `y` and `x`.
+    pub fn add_two_hop(&mut self, y: K, x: K) {
+        if x == self.me || y == x {
+            return;
+        }
+        self.links.entry(y).or_default().insert(x);
+        self.links.entry(x).or_default().insert(y);
+    }
+
+    /// Set the router priority of a neighbor, the default is
+    /// [`DEFAULT_ROUTER_PRIORITY`](constant.DEFAULT_ROUTER_PRIORITY.html).
+    pub fn set_priority(&mut self, k: K, priority: u8) {
+        self.priorities.insert(k, priority);
+    }
+
+    /// Select the relay set with `rssa`.
+    ///
+    /// - Classical flooding relays every packet.
+    /// - S-MPR relays the packets of the MPR selectors.
+    /// - E-CDS (RFC 6621 appendix B) relays every packet unless the router
+    ///   has fewer than 2 neighbors, or all its neighbors are connected to
+    ///   the neighbor with the highest priority through routers with a
+    ///   higher priority than its own.
+    /// - MPR-CDS (RFC 6621 appendix C) relays every packet if the router
+    ///   has a higher priority than all its neighbors, or was selected as
+    ///   MPR by the neighbor with the highest priority.
+    pub fn select(&self, rssa: Rssa) -> RelaySet<K> {
+        let relay = match rssa {
+            Rssa::Cf => true,
+            Rssa::SMpr => {
+                let selectors = self.n1.iter().filter(|(_, s)| **s);
+                return RelaySet::From(selectors.map(|(y, _)| *y).collect());
+            }
+            Rssa::ECds => self.e_cds(),
+            Rssa::MprCds => self.mpr_cds(),
+        };
+
+        if relay {
+            RelaySet::All
+        } else {
+            RelaySet::None
+        }
+    }
+
+    fn rank(&self, k: K) -> (u8, K) {
+        let priority = self.priorities.get(&k).cloned();
+        (priority.unwrap_or(DEFAULT_ROUTER_PRIORITY), k)
+    }
+
+    /// Neighbor with the highest priority.
+    fn max_neighbor(&self) -> Option<K> {
+        self.n1.keys().cloned().max_by_key(|y| self.rank(*y))
+    }
+
+    fn e_cds(&self) -> bool {
+        if self.n1.len() < 2 {
+            return false;
+        }
+        let me = (self.priority, self.me);
+        let highest = self
+            .n1
+            .keys()
+            .chain(self.links.keys())
+            .all(|k| self.rank(*k) < me);
+        if highest {
+            return true;
+        }
+
+        // Visit the routers reachable from the neighbor with the highest
+        // priority, only going through routers with a higher priority.
+        let start = match self.max_neighbor() {
+            Some(y) => y,
+            None => return false,
+        };
+        let mut visited = BTreeSet::new();
+        visited.insert(start);
+        let mut stack = vec![start];
+        while let Some(k) = stack.pop() {
+            if self.rank(k) < me {
+                continue;
+            }
+            for x in self.links.get(&k).into_iter().flatten() {
+                if visited.insert(*x) {
+                    stack.push(*x);
+                }
+            }
+        }
+
+        !self.n1.keys().all(|y| visited.contains(y))
+    }
+
+    fn mpr_cds(&self) -> bool {
+        let me = (self.priority, self.me);
+        match self.max_neighbor() {
+            Some(y) if self.rank(y) > me => self.n1[&y],
+            _ => true,
+        }
+    }
+}
+
+impl RelayGraph<Address> {
+    /// Graph of the symmetric neighbors and 2-hop neighbors of the
+    /// interface `id` of `nhdp`, for the router `me` with the router
+    /// priority `priority`.
+    ///
+    /// Neighbors are identified by the first address of their link tuple,
+    /// 2-hop neighbors by their address. The MPR selectors and router
+    /// priorities of the neighbors aren't known by NHDP, they must be
+    /// added by the caller.
+    pub fn from_nhdp<C: Clock>(
+        nhdp: &Nhdp<C>,
+        id: InterfaceId,
+        me: Address,
+        priority: u8,
+    ) -> RelayGraph<Address> {
+        let now = nhdp.clock().now();
+        let symmetric = nhdp
+            .links(id)
+            .iter()
+            .filter(|l| l.status(now) == Some(LinkStatus::Symmetric))
+            .filter(|l| !l.neighbor_iface_addrs.is_empty())
+            .collect::<Vec<_>>();
+        let key = |addr: &Address| {
+            symmetric
+                .iter()
+                .find(|l| l.neighbor_iface_addrs.contains(addr))
+                .map(|l| l.neighbor_iface_addrs[0])
+        };
+
+        let mut graph = RelayGraph::new(me, priority);
+        for link in &symmetric {
+            graph.add_neighbor(link.neighbor_iface_addrs[0]);
+        }
+        for two_hop in nhdp.two_hops(id) {
+            let y = match two_hop.neighbor_iface_addrs.first().and_then(key) {
+                Some(y) => y,
+                None => continue,
+            };
+            if nhdp.is_local(&two_hop.two_hop_addr) {
+                continue;
+            }
+            let x = key(&two_hop.two_hop_addr).unwrap_or(two_hop.two_hop_addr);
+            graph.add_two_hop(y, x);
+        }
+
+        graph
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Line 1 - 2 - 3 - 4, with 2 and 3 also linked to 5.
+    fn graph(me: u8) -> RelayGraph<u8> {
+        let links = [(1, 2), (2, 3), (3, 4), (2, 5), (3, 5)];
+        let mut graph = RelayGraph::new(me, DEFAULT_ROUTER_PRIORITY);
+        for (a, b) in links.iter().cloned() {
+            let (y, other) = match (a == me, b == me) {
+                (true, _) => (b, a),
+                (_, true) => (a, b),
+                _ => continue,
+            };
+            graph.add_neighbor(y);
+            for (c, d) in links.iter().cloned() {
+                if c == y && d != other {
+                    graph.add_two_hop(y, d);
+                } else if d == y && c != other {
+                    graph.add_two_hop(y, c);
+                }
+            }
+        }
+        graph
+    }
+
+    #[test]
+    fn test_cf_and_s_mpr() {
+        let mut g = graph(2);
+        assert_eq!(g.select(Rssa::Cf), RelaySet::All);
+        assert_eq!(g.select(Rssa::SMpr), RelaySet::From(BTreeSet::new()));
+
+        g.add_mpr_selector(1);
+        let set = g.select(Rssa::SMpr);
+        assert!(set.relays(&1));
+        assert!(!set.relays(&3));
+        assert!(RelaySet::All.relays(&3));
+        assert!(!RelaySet::None.relays(&3));
+    }
+
+    #[test]
+    fn test_e_cds() {
+        // With equal priorities the highest key wins. 1 and 4 have a single
+        // neighbor, 5 has the highest priority.
+        let relays = (1..=5)
+            .filter(|me| graph(*me).select(Rssa::ECds) == RelaySet::All)
+            .collect::<Vec<_>>();
+        assert_eq!(relays, vec![2, 3, 5]);
+
+        // 3 isn't needed once 5 reaches 4.
+        let mut g = graph(3);
+        g.add_two_hop(5, 4);
+        assert_eq!(g.select(Rssa::ECds), RelaySet::None);
+        // Unless 5 has a lower priority than 3.
+        g.set_priority(5, 0);
+        assert_eq!(g.select(Rssa::ECds), RelaySet::All);
+
+        // The highest priority router always relays.
+        let mut g = RelayGraph::new(1, 200);
+        g.add_neighbor(2);
+        g.add_neighbor(3);
+        g.add_two_hop(2, 3);
+        assert_eq!(g.select(Rssa::ECds), RelaySet::All);
+        // Unless its neighbors are connected through higher priorities.
+        g.set_priority(2, 201);
+        assert_eq!(g.select(Rssa::ECds), RelaySet::None);
+    }
+
+    #[test]
+    fn test_mpr_cds() {
+        let mut g = graph(5);
+        assert_eq!(g.select(Rssa::MprCds), RelaySet::All);
+
+        let mut g2 = graph(2);
+        assert_eq!(g2.select(Rssa::MprCds), RelaySet::None);
+        g2.add_mpr_selector(5);
+        assert_eq!(g2.select(Rssa::MprCds), RelaySet::All);
+        // The MPR selector isn't the highest neighbor anymore.
+        g2.set_priority(3, 100);
+        assert_eq!(g2.select(Rssa::MprCds), RelaySet::None);
+
+        g.set_priority(3, 100);
+        assert_eq!(g.select(Rssa::MprCds), RelaySet::None);
+    }
+}
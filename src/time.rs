@@ -0,0 +1,125 @@
+// Copyright 2020 Jean Pierre Dudey. See the LICENSE-MIT and
+// LICENSE-APACHE files at the top-level directory of this
+// distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! RFC 5497 representation of time values.
+//!
+//! A time value is a byte holding `b` in its 5 high bits and `a` in its 3
+//! low bits, for a time of `(1 + a/8) * 2^b * C` where `C` is 1/1024
+//! seconds. Times are given in milliseconds here.
+
+use crate::Error;
+
+/// Time TLV type for INTERVAL_TIME.
+pub const INTERVAL_TIME: u8 = 0;
+/// Time TLV type for VALIDITY_TIME.
+pub const VALIDITY_TIME: u8 = 1;
+
+/// Smallest time that can be represented, in milliseconds (rounded up).
+pub const MIN_TIME: u64 = 1;
+/// Largest time that can be represented, in milliseconds (rounded down).
+pub const MAX_TIME: u64 = 3_932_160_000;
+
+/// Time of `code`, `(8 + a) * 2^b` in units of `C/8`.
+fn units(code: u8) -> u64 {
+    let a = u64::from(code & 0x07);
+    let b = u32::from(code >> 3);
+    (8 + a) << b
+}
+
+/// Decode a time value, in milliseconds rounded down.
+pub fn decode(code: u8) -> u64 {
+    units(code) * 1000 / 8192
+}
+
+/// Encode a time in milliseconds, rounding up to the next time that can be
+/// represented.
+///
+/// Times larger than [`MAX_TIME`](constant.MAX_TIME.html) are encoded as
+/// the largest time value.
+pub fn encode(ms: u64) -> u8 {
+    // Smallest code such that `units(code) * 1000 >= ms * 8192`.
+    let target = ms.saturating_mul(8192);
+    (0..=core::u8::MAX)
+        .find(|&code| units(code) * 1000 >= target)
+        .unwrap_or(core::u8::MAX)
+}
+
+/// Decode the value of a time TLV for a message with a hop count of
+/// `hop_count`, in milliseconds.
+///
+/// The value is either a single time value, or the multi-value form
+/// `<t_1><d_1><t_2>...<d_n-1><t_n>` where `t_i` applies to the hop counts
+/// larger than `d_i-1` and up to `d_i`. Messages without a hop count should
+/// use 255.
+pub fn decode_tlv_value(value: &[u8], hop_count: u8) -> Result<u64, Error> {
+    if value.len() % 2 == 0 {
+        return Err(Error::InvalidTlvValue);
+    }
+
+    // The hop counts must be increasing, check them all before returning.
+    let pairs = value[..value.len() - 1].chunks(2);
+    let mut prev = None;
+    for pair in pairs.clone() {
+        if prev.map_or(false, |prev| pair[1] <= prev) {
+            return Err(Error::InvalidTlvValue);
+        }
+        prev = Some(pair[1]);
+    }
+
+    let t = pairs
+        .filter(|pair| hop_count <= pair[1])
+        .map(|pair| pair[0])
+        .next()
+        .unwrap_or(value[value.len() - 1]);
+    Ok(decode(t))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_time_codec() {
+        assert_eq!(decode(0), 0);
+        assert_eq!(decode(core::u8::MAX), MAX_TIME);
+        // 1 second is 1024 * C.
+        assert_eq!(decode(80), 1000);
+        assert_eq!(encode(1000), 80);
+        assert_eq!(encode(0), 0);
+        assert_eq!(encode(MIN_TIME), 1);
+        assert_eq!(encode(MAX_TIME), core::u8::MAX);
+        assert_eq!(encode(core::u64::MAX), core::u8::MAX);
+
+        // Encoding rounds up.
+        for ms in 1..20_000 {
+            let code = encode(ms);
+            assert!(units(code) * 1000 >= ms * 8192);
+            assert!(code == 0 || units(code - 1) * 1000 < ms * 8192);
+        }
+        for code in 0..=core::u8::MAX {
+            assert!(encode(decode(code)) <= code);
+        }
+    }
+
+    #[test]
+    fn test_decode_tlv_value() {
+        assert_eq!(decode_tlv_value(&[80], 0), Ok(1000));
+        assert_eq!(decode_tlv_value(&[80, 2, 88, 5, 96], 0), Ok(1000));
+        assert_eq!(decode_tlv_value(&[80, 2, 88, 5, 96], 2), Ok(1000));
+        assert_eq!(decode_tlv_value(&[80, 2, 88, 5, 96], 3), Ok(2000));
+        assert_eq!(decode_tlv_value(&[80, 2, 88, 5, 96], 255), Ok(4000));
+        assert_eq!(decode_tlv_value(&[], 0), Err(Error::InvalidTlvValue));
+        assert_eq!(decode_tlv_value(&[80, 2], 0), Err(Error::InvalidTlvValue));
+        assert_eq!(
+            decode_tlv_value(&[80, 5, 88, 2, 96], 0),
+            Err(Error::InvalidTlvValue)
+        );
+    }
+}
@@ -0,0 +1,684 @@
+// Copyright 2020 Jean Pierre Dudey. See the LICENSE-MIT and
+// LICENSE-APACHE files at the top-level directory of this
+// distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! RFC 7182 TIMESTAMP TLVs and replay protection.
+
+use crate::{Address, Clock, Error, Message, Tlv};
+
+/// TIMESTAMP packet TLV type.
+pub const PKT_TLV_TIMESTAMP: u8 = 6;
+/// TIMESTAMP message TLV type.
+pub const MSG_TLV_TIMESTAMP: u8 = 6;
+/// TIMESTAMP address block TLV type.
+pub const ADDR_TLV_TIMESTAMP: u8 = 6;
+
+/// TIMESTAMP type extension of an unsigned sequence number.
+pub const TIMESTAMP_EXT_SEQ_NUM: u8 = 0;
+/// TIMESTAMP type extension of a POSIX time.
+pub const TIMESTAMP_EXT_POSIX: u8 = 1;
+/// TIMESTAMP type extension of a random nonce.
+pub const TIMESTAMP_EXT_NONCE: u8 = 2;
+
+/// Largest value accepted for
+/// [`ReplayConfig::seq_window`](struct.ReplayConfig.html#structfield.seq_window).
+pub const MAX_SEQ_WINDOW: u32 = 63;
+
+/// Largest value accepted for
+/// [`ReplayConfig::nonce_history`](struct.ReplayConfig.html#structfield.nonce_history).
+pub const MAX_NONCE_HISTORY: usize = 64;
+
+/// Value of a TIMESTAMP TLV.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Timestamp<'a> {
+    /// Sequence number, wraps around at `8 * width` bits.
+    SeqNum {
+        /// Sequence number.
+        value: u64,
+        /// Size of the sequence number in bytes.
+        width: usize,
+    },
+    /// Seconds since the POSIX epoch, an originator can't send more than a
+    /// message per second with it.
+    Posix(u64),
+    /// Random nonce, only the last nonces of an originator are remembered.
+    Nonce(&'a [u8]),
+}
+
+impl<'a> Timestamp<'a> {
+    /// Read the value of a TIMESTAMP TLV, the TLV type isn't checked.
+    ///
+    /// Returns `None` on an unknown type extension.
+    pub fn from_tlv(tlv: &Tlv<'a>) -> Result<Option<Timestamp<'a>>, Error> {
+        let value = tlv.value.ok_or(Error::InvalidTlvValue)?;
+
+        // An absent <tlv-type-ext> has the same meaning as 0.
+        match tlv.type_ext.unwrap_or(TIMESTAMP_EXT_SEQ_NUM) {
+            TIMESTAMP_EXT_SEQ_NUM => Ok(Some(Timestamp::SeqNum {
+                value: read_uint(value)?,
+                width: value.len(),
+            })),
+            TIMESTAMP_EXT_POSIX => {
+                Ok(Some(Timestamp::Posix(read_uint(value)?)))
+            }
+            TIMESTAMP_EXT_NONCE => Ok(Some(Timestamp::Nonce(value))),
+            _ => Ok(None),
+        }
+    }
+}
+
+/// Read a network-endian unsigned integer of 1 to 8 bytes.
+fn read_uint(bytes: &[u8]) -> Result<u64, Error> {
+    if bytes.is_empty() || bytes.len() > 8 {
+        return Err(Error::InvalidTlvValue);
+    }
+
+    Ok(bytes.iter().fold(0u64, |acc, b| (acc << 8) | u64::from(*b)))
+}
+
+/// Why a message was rejected by the [`ReplayFilter`](struct.ReplayFilter.html).
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Rejection {
+    /// The message has no `<msg-orig-addr>`.
+    MissingOriginator,
+    /// The message has no TIMESTAMP TLV of a known type extension.
+    MissingTimestamp,
+    /// A TIMESTAMP TLV couldn't be parsed.
+    Invalid(Error),
+    /// The timestamp was already received from this originator.
+    Replayed,
+    /// The timestamp is older than the window, or too far from the clock.
+    Stale,
+}
+
+#[cfg(feature = "use_std")]
+impl std::fmt::Display for Rejection {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> Result<(), std::fmt::Error> {
+        match *self {
+            Rejection::MissingOriginator => write!(f, "Missing originator"),
+            Rejection::MissingTimestamp => write!(f, "Missing timestamp"),
+            Rejection::Invalid(ref e) => write!(f, "Invalid timestamp: {}", e),
+            Rejection::Replayed => write!(f, "Replayed timestamp"),
+            Rejection::Stale => write!(f, "Stale timestamp"),
+        }
+    }
+}
+
+#[cfg(feature = "use_std")]
+impl std::error::Error for Rejection {}
+
+/// Replay filter configuration.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct ReplayConfig {
+    /// How many sequence numbers behind the highest received one are still
+    /// accepted (if not received before), at most
+    /// [`MAX_SEQ_WINDOW`](constant.MAX_SEQ_WINDOW.html).
+    pub seq_window: u32,
+    /// Maximum difference in seconds between a POSIX timestamp and the
+    /// clock.
+    pub posix_tolerance: u64,
+    /// How many nonces of an originator are remembered, from 1 to
+    /// [`MAX_NONCE_HISTORY`](constant.MAX_NONCE_HISTORY.html). A message
+    /// with an older nonce is accepted again, size it for the messages an
+    /// originator sends in their validity time.
+    pub nonce_history: usize,
+    /// Time in milliseconds after which an originator that didn't send
+    /// anything is forgotten.
+    pub hold_time: u64,
+}
+
+impl Default for ReplayConfig {
+    fn default() -> ReplayConfig {
+        ReplayConfig {
+            seq_window: 32,
+            posix_tolerance: 5,
+            nonce_history: 16,
+            hold_time: 60_000,
+        }
+    }
+}
+
+/// Sliding window of received sequence numbers.
+#[derive(Debug, Clone, Copy)]
+struct SeqWindow {
+    highest: u64,
+    /// Bit `n` is set if `highest - n` was received.
+    bitmap: u64,
+    width: usize,
+}
+
+/// Replay state of a single originator.
+///
+/// Storage for the [`ReplayFilter`](struct.ReplayFilter.html), it's opaque
+/// and meant to be allocated by the caller as an array of
+/// [`ReplayEntry::EMPTY`](#associatedconstant.EMPTY).
+#[derive(Debug, Clone, Copy)]
+pub struct ReplayEntry {
+    originator: Option<Address>,
+    last_seen: u64,
+    seq: Option<SeqWindow>,
+    posix: Option<u64>,
+    nonces: [u64; MAX_NONCE_HISTORY],
+    nonce_len: usize,
+    nonce_next: usize,
+}
+
+impl ReplayEntry {
+    /// An unused entry.
+    pub const EMPTY: ReplayEntry = ReplayEntry {
+        originator: None,
+        last_seen: 0,
+        seq: None,
+        posix: None,
+        nonces: [0; MAX_NONCE_HISTORY],
+        nonce_len: 0,
+        nonce_next: 0,
+    };
+
+    fn new(originator: Address) -> ReplayEntry {
+        ReplayEntry {
+            originator: Some(originator),
+            ..ReplayEntry::EMPTY
+        }
+    }
+
+    /// Check a timestamp, recording it if it's fresh.
+    fn apply(
+        &mut self,
+        ts: &Timestamp,
+        posix_now: Option<u64>,
+        config: &ReplayConfig,
+    ) -> Result<(), Rejection> {
+        match *ts {
+            Timestamp::SeqNum { value, width } => {
+                self.apply_seq_num(value, width, config)
+            }
+            Timestamp::Posix(time) => {
+                if let Some(now) = posix_now {
+                    let diff = if time > now { time - now } else { now - time };
+                    if diff > config.posix_tolerance {
+                        return Err(Rejection::Stale);
+                    }
+                }
+
+                match self.posix {
+                    Some(last) if time <= last => Err(Rejection::Replayed),
+                    _ => {
+                        self.posix = Some(time);
+                        Ok(())
+                    }
+                }
+            }
+            Timestamp::Nonce(nonce) => {
+                let hash = fnv1a(nonce);
+                if self.nonces[..self.nonce_len].contains(&hash) {
+                    return Err(Rejection::Replayed);
+                }
+
+                let history =
+                    config.nonce_history.max(1).min(MAX_NONCE_HISTORY);
+                self.nonces[self.nonce_next] = hash;
+                self.nonce_next = (self.nonce_next + 1) % history;
+                if self.nonce_len < history {
+                    self.nonce_len += 1;
+                }
+                Ok(())
+            }
+        }
+    }
+
+    fn apply_seq_num(
+        &mut self,
+        value: u64,
+        width: usize,
+        config: &ReplayConfig,
+    ) -> Result<(), Rejection> {
+        let window = match self.seq {
+            // A change of width means the originator was reconfigured, start
+            // a new window.
+            Some(ref mut w) if w.width == width => w,
+            _ => {
+                self.seq = Some(SeqWindow {
+                    highest: value,
+                    bitmap: 1,
+                    width,
+                });
+                return Ok(());
+            }
+        };
+
+        let mask = if width >= 8 {
+            core::u64::MAX
+        } else {
+            (1u64 << (8 * width)) - 1
+        };
+        let half = (mask >> 1) + 1;
+
+        let ahead = value.wrapping_sub(window.highest) & mask;
+        if ahead == 0 {
+            return Err(Rejection::Replayed);
+        }
+
+        if ahead < half {
+            window.bitmap = if ahead >= 64 {
+                0
+            } else {
+                window.bitmap << ahead
+            };
+            window.bitmap |= 1;
+            window.highest = value;
+            return Ok(());
+        }
+
+        let behind = window.highest.wrapping_sub(value) & mask;
+        let limit = u64::from(config.seq_window.min(MAX_SEQ_WINDOW));
+        if behind > limit {
+            return Err(Rejection::Stale);
+        }
+
+        let bit = 1u64 << behind;
+        if window.bitmap & bit != 0 {
+            return Err(Rejection::Replayed);
+        }
+
+        window.bitmap |= bit;
+        Ok(())
+    }
+}
+
+/// FNV-1a hash, used to store nonces of any length in a fixed size.
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, b| {
+        (hash ^ u64::from(*b)).wrapping_mul(0x0000_0100_0000_01b3)
+    })
+}
+
+/// Replay filter based on RFC 7182 TIMESTAMP TLVs.
+///
+/// Keeps a window of received timestamps per originator, in the storage
+/// given by the caller. When the storage is full the least recently seen
+/// originator is forgotten, so size it for the expected neighbourhood.
+///
+/// The window of sequence numbers and nonces is limited by
+/// [`ReplayConfig::seq_window`] and [`ReplayConfig::nonce_history`]: a
+/// message older than that, or from an originator forgotten after
+/// [`ReplayConfig::hold_time`], is accepted again. POSIX timestamps don't
+/// have this limit.
+///
+/// [`ReplayConfig::seq_window`]: struct.ReplayConfig.html#structfield.seq_window
+/// [`ReplayConfig::nonce_history`]: struct.ReplayConfig.html#structfield.nonce_history
+/// [`ReplayConfig::hold_time`]: struct.ReplayConfig.html#structfield.hold_time
+///
+/// Timestamps are only meaningful when protected by an ICV, verify the
+/// message integrity before passing it to the filter, otherwise a forged
+/// message can advance the window of an originator.
+#[derive(Debug)]
+pub struct ReplayFilter<'s, C> {
+    entries: &'s mut [ReplayEntry],
+    clock: C,
+    config: ReplayConfig,
+}
+
+impl<'s, C: Clock> ReplayFilter<'s, C> {
+    /// Create a new `ReplayFilter`.
+    ///
+    /// # Panics
+    ///
+    /// If `entries` is empty.
+    pub fn new(
+        entries: &'s mut [ReplayEntry],
+        clock: C,
+        config: ReplayConfig,
+    ) -> ReplayFilter<'s, C> {
+        assert!(!entries.is_empty());
+
+        for entry in entries.iter_mut() {
+            *entry = ReplayEntry::EMPTY;
+        }
+
+        ReplayFilter {
+            entries,
+            clock,
+            config,
+        }
+    }
+
+    /// Accept or reject a message, using the TIMESTAMP message TLVs and the
+    /// originator address.
+    ///
+    /// All the TIMESTAMP TLVs of the message must be fresh for it to be
+    /// accepted, and only then they're recorded.
+    pub fn check(&mut self, msg: &Message<'_>) -> Result<(), Rejection> {
+        let originator =
+            msg.hdr.orig_addr.ok_or(Rejection::MissingOriginator)?;
+        let (index, mut entry) = self.lookup(originator)?;
+        let posix_now = self.clock.posix_time();
+
+        let mut found = false;
+        for tlv in msg.tlv_block.iter() {
+            let tlv = tlv.map_err(Rejection::Invalid)?;
+            if tlv.r#type != MSG_TLV_TIMESTAMP {
+                continue;
+            }
+
+            if let Some(ts) =
+                Timestamp::from_tlv(&tlv).map_err(Rejection::Invalid)?
+            {
+                entry.apply(&ts, posix_now, &self.config)?;
+                found = true;
+            }
+        }
+
+        if !found {
+            return Err(Rejection::MissingTimestamp);
+        }
+
+        self.commit(index, entry);
+        Ok(())
+    }
+
+    /// Accept or reject a single timestamp received from `originator`.
+    ///
+    /// Useful for packet TIMESTAMP TLVs, where the originator is the source
+    /// address of the packet.
+    pub fn check_timestamp(
+        &mut self,
+        originator: &[u8],
+        ts: &Timestamp,
+    ) -> Result<(), Rejection> {
+        let (index, mut entry) = self.lookup(originator)?;
+        let posix_now = self.clock.posix_time();
+
+        entry.apply(ts, posix_now, &self.config)?;
+
+        self.commit(index, entry);
+        Ok(())
+    }
+
+    /// Forget every originator.
+    pub fn clear(&mut self) {
+        for entry in self.entries.iter_mut() {
+            *entry = ReplayEntry::EMPTY;
+        }
+    }
+
+    /// Find the entry of `originator`, or the slot where it will be stored
+    /// with a new entry.
+    fn lookup(
+        &self,
+        originator: &[u8],
+    ) -> Result<(usize, ReplayEntry), Rejection> {
+        let addr = Address::from_bytes(originator)
+            .ok_or(Rejection::MissingOriginator)?;
+        let now = self.clock.now();
+
+        let mut victim = 0;
+        let mut victim_seen = core::u64::MAX;
+        for (i, entry) in self.entries.iter().enumerate() {
+            let expired =
+                now.saturating_sub(entry.last_seen) > self.config.hold_time;
+
+            match entry.originator {
+                Some(o) if o == addr => {
+                    if expired {
+                        return Ok((i, ReplayEntry::new(addr)));
+                    }
+                    return Ok((i, *entry));
+                }
+                Some(_) if !expired => {
+                    if entry.last_seen < victim_seen {
+                        victim = i;
+                        victim_seen = entry.last_seen;
+                    }
+                }
+                // Free or expired slots are always preferred.
+                _ => {
+                    victim = i;
+                    victim_seen = 0;
+                }
+            }
+        }
+
+        Ok((victim, ReplayEntry::new(addr)))
+    }
+
+    fn commit(&mut self, index: usize, mut entry: ReplayEntry) {
+        entry.last_seen = self.clock.now();
+        self.entries[index] = entry;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::Buf;
+    use core::cell::Cell;
+
+    struct TestClock {
+        now: Cell<u64>,
+        posix: Cell<Option<u64>>,
+    }
+
+    impl TestClock {
+        fn new() -> TestClock {
+            TestClock {
+                now: Cell::new(0),
+                posix: Cell::new(None),
+            }
+        }
+    }
+
+    impl Clock for TestClock {
+        fn now(&self) -> u64 {
+            self.now.get()
+        }
+
+        fn posix_time(&self) -> Option<u64> {
+            self.posix.get()
+        }
+    }
+
+    const ORIG_A: &[u8] = &[10, 0, 0, 1];
+    const ORIG_B: &[u8] = &[10, 0, 0, 2];
+
+    fn seq16(value: u16) -> Timestamp<'static> {
+        Timestamp::SeqNum {
+            value: u64::from(value),
+            width: 2,
+        }
+    }
+
+    #[test]
+    fn test_timestamp_from_tlv() {
+        // TIMESTAMP TLV, sequence number 0x1234 without type extension.
+        const BIN: &[u8] = &[0x06, 0x10, 0x02, 0x12, 0x34];
+        let tlv = Tlv::read(&mut Buf::new(BIN)).unwrap();
+        assert_eq!(
+            Timestamp::from_tlv(&tlv).unwrap(),
+            Some(Timestamp::SeqNum {
+                value: 0x1234,
+                width: 2
+            })
+        );
+
+        // POSIX time of 9 bytes.
+        const BIN_LONG: &[u8] =
+            &[0x06, 0x90, 0x01, 0x09, 0, 0, 0, 0, 0, 0, 0, 0, 1];
+        let tlv = Tlv::read(&mut Buf::new(BIN_LONG)).unwrap();
+        assert_eq!(Timestamp::from_tlv(&tlv), Err(Error::InvalidTlvValue));
+
+        // Unknown type extension.
+        const BIN_UNKNOWN: &[u8] = &[0x06, 0x90, 0x03, 0x01, 0x00];
+        let tlv = Tlv::read(&mut Buf::new(BIN_UNKNOWN)).unwrap();
+        assert_eq!(Timestamp::from_tlv(&tlv).unwrap(), None);
+    }
+
+    #[test]
+    fn test_replay_seq_num_wraparound() {
+        let clock = TestClock::new();
+        let mut entries = [ReplayEntry::EMPTY; 2];
+        let mut filter =
+            ReplayFilter::new(&mut entries, &clock, ReplayConfig::default());
+
+        assert_eq!(filter.check_timestamp(ORIG_A, &seq16(0xfffe)), Ok(()));
+        assert_eq!(filter.check_timestamp(ORIG_A, &seq16(0x0001)), Ok(()));
+        assert_eq!(filter.check_timestamp(ORIG_A, &seq16(0xffff)), Ok(()));
+        assert_eq!(
+            filter.check_timestamp(ORIG_A, &seq16(0xffff)),
+            Err(Rejection::Replayed)
+        );
+        assert_eq!(
+            filter.check_timestamp(ORIG_A, &seq16(0xfffe)),
+            Err(Rejection::Replayed)
+        );
+        assert_eq!(
+            filter.check_timestamp(ORIG_A, &seq16(0xff00)),
+            Err(Rejection::Stale)
+        );
+
+        // Other originators have their own window.
+        assert_eq!(filter.check_timestamp(ORIG_B, &seq16(0xfffe)), Ok(()));
+    }
+
+    #[test]
+    fn test_replay_seq_num_32bit() {
+        let clock = TestClock::new();
+        let mut entries = [ReplayEntry::EMPTY; 1];
+        let mut filter =
+            ReplayFilter::new(&mut entries, &clock, ReplayConfig::default());
+
+        let seq32 = |value: u32| Timestamp::SeqNum {
+            value: u64::from(value),
+            width: 4,
+        };
+
+        assert_eq!(filter.check_timestamp(ORIG_A, &seq32(0xffff_fff0)), Ok(()));
+        // Would be older on a 16-bit sequence number.
+        assert_eq!(filter.check_timestamp(ORIG_A, &seq32(0x0001_0000)), Ok(()));
+        assert_eq!(
+            filter.check_timestamp(ORIG_A, &seq32(0xffff_fff0)),
+            Err(Rejection::Stale)
+        );
+    }
+
+    #[test]
+    fn test_replay_posix() {
+        let clock = TestClock::new();
+        clock.posix.set(Some(1_000_000));
+        let mut entries = [ReplayEntry::EMPTY; 1];
+        let mut filter =
+            ReplayFilter::new(&mut entries, &clock, ReplayConfig::default());
+
+        assert_eq!(
+            filter.check_timestamp(ORIG_A, &Timestamp::Posix(999_000)),
+            Err(Rejection::Stale)
+        );
+        assert_eq!(
+            filter.check_timestamp(ORIG_A, &Timestamp::Posix(1_000_002)),
+            Ok(())
+        );
+        assert_eq!(
+            filter.check_timestamp(ORIG_A, &Timestamp::Posix(1_000_002)),
+            Err(Rejection::Replayed)
+        );
+        assert_eq!(
+            filter.check_timestamp(ORIG_A, &Timestamp::Posix(1_000_003)),
+            Ok(())
+        );
+        assert_eq!(
+            filter.check_timestamp(ORIG_A, &Timestamp::Posix(1_000_001)),
+            Err(Rejection::Replayed)
+        );
+    }
+
+    #[test]
+    fn test_replay_nonce() {
+        let clock = TestClock::new();
+        let mut entries = [ReplayEntry::EMPTY; 1];
+        let mut filter =
+            ReplayFilter::new(&mut entries, &clock, ReplayConfig::default());
+
+        let n0 = Timestamp::Nonce(&[0xde, 0xad]);
+        let n1 = Timestamp::Nonce(&[0xbe, 0xef]);
+        assert_eq!(filter.check_timestamp(ORIG_A, &n0), Ok(()));
+        assert_eq!(filter.check_timestamp(ORIG_A, &n1), Ok(()));
+        assert_eq!(
+            filter.check_timestamp(ORIG_A, &n0),
+            Err(Rejection::Replayed)
+        );
+
+        // Only the last `nonce_history` nonces are remembered.
+        let config = ReplayConfig {
+            nonce_history: 2,
+            ..ReplayConfig::default()
+        };
+        let mut filter = ReplayFilter::new(&mut entries, &clock, config);
+        let n2 = Timestamp::Nonce(&[0xca, 0xfe]);
+        for n in [n0, n1, n2].iter() {
+            assert_eq!(filter.check_timestamp(ORIG_A, n), Ok(()));
+        }
+        assert_eq!(
+            filter.check_timestamp(ORIG_A, &n1),
+            Err(Rejection::Replayed)
+        );
+        assert_eq!(filter.check_timestamp(ORIG_A, &n0), Ok(()));
+    }
+
+    #[test]
+    fn test_replay_hold_time_and_eviction() {
+        let clock = TestClock::new();
+        let mut entries = [ReplayEntry::EMPTY; 1];
+        let config = ReplayConfig {
+            hold_time: 1000,
+            ..ReplayConfig::default()
+        };
+        let mut filter = ReplayFilter::new(&mut entries, &clock, config);
+
+        assert_eq!(filter.check_timestamp(ORIG_A, &seq16(10)), Ok(()));
+        clock.now.set(500);
+        assert_eq!(
+            filter.check_timestamp(ORIG_A, &seq16(10)),
+            Err(Rejection::Replayed)
+        );
+
+        // ORIG_A is forgotten once the hold time passes.
+        clock.now.set(2000);
+        assert_eq!(filter.check_timestamp(ORIG_A, &seq16(10)), Ok(()));
+
+        // ORIG_B takes the only slot.
+        clock.now.set(2100);
+        assert_eq!(filter.check_timestamp(ORIG_B, &seq16(5)), Ok(()));
+        assert_eq!(filter.check_timestamp(ORIG_A, &seq16(10)), Ok(()));
+    }
+
+    #[test]
+    fn test_replay_check_message() {
+        // Message type 1, originator 10.0.0.1, TIMESTAMP TLV with a sequence
+        // number of 7 and a nonce.
+        const BIN: &[u8] = &[
+            0x01, 0x83, 0x00, 0x17, 0x0a, 0x00, 0x00, 0x01, 0x00, 0x0d, 0x06,
+            0x10, 0x02, 0x00, 0x07, 0x06, 0x90, 0x02, 0x04, 0x01, 0x02, 0x03,
+            0x04,
+        ];
+        // Same message without originator.
+        const BIN_NO_ORIG: &[u8] = &[0x01, 0x03, 0x00, 0x06, 0x00, 0x00];
+
+        let clock = TestClock::new();
+        let mut entries = [ReplayEntry::EMPTY; 4];
+        let mut filter =
+            ReplayFilter::new(&mut entries, &clock, ReplayConfig::default());
+
+        let msg = Message::read(&mut Buf::new(BIN)).unwrap();
+        assert_eq!(filter.check(&msg), Ok(()));
+        assert_eq!(filter.check(&msg), Err(Rejection::Replayed));
+
+        let msg = Message::read(&mut Buf::new(BIN_NO_ORIG)).unwrap();
+        assert_eq!(filter.check(&msg), Err(Rejection::MissingOriginator));
+    }
+}
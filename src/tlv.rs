@@ -8,7 +8,7 @@
 // option. This file may not be copied, modified, or distributed
 // except according to those terms.
 
-use crate::{Buf, Error};
+use crate::{Buf, BufMut, Error};
 
 bitflags! {
     struct TlvFlags: u8 {
@@ -36,6 +36,10 @@ pub struct Tlv<'a> {
     pub stop_index: Option<u8>,
     /// Value
     pub value: Option<&'a [u8]>,
+    /// The value is split in equal parts between the addresses from
+    /// `start_index` to `stop_index`, `false` for a single value of all
+    /// of them.
+    pub multi_value: bool,
 }
 
 impl<'a> Tlv<'a> {
@@ -102,8 +106,150 @@ impl<'a> Tlv<'a> {
             start_index,
             stop_index,
             value,
+            multi_value: flags.contains(TlvFlags::IS_MULTI_VALUE),
         })
     }
+
+    /// Size in bytes of the encoded `<tlv>`.
+    pub fn encoded_len(&self) -> usize {
+        let mut len = 2;
+        if self.type_ext.is_some() {
+            len += 1;
+        }
+        if self.start_index.is_some() {
+            len += 1;
+        }
+        if self.stop_index.is_some() {
+            len += 1;
+        }
+        if let Some(value) = self.value {
+            len += if value.len() > 255 { 2 } else { 1 };
+            len += value.len();
+        }
+        len
+    }
+
+    /// Write a `<tlv>`
+    pub fn write(&self, buf: &mut BufMut) -> Result<(), Error> {
+        self.write_head(buf, self.value.map(|v| v.len()))?;
+        if let Some(value) = self.value {
+            buf.put_bytes(value)?;
+        }
+
+        Ok(())
+    }
+
+    /// Write everything but the `<value>` of a `<tlv>`, with a value of
+    /// `value_len` bytes instead of the one of `self`.
+    pub(crate) fn write_head(
+        &self,
+        buf: &mut BufMut,
+        value_len: Option<usize>,
+    ) -> Result<(), Error> {
+        let mut flags = TlvFlags::empty();
+        if self.type_ext.is_some() {
+            flags |= TlvFlags::HAS_TYPE_EXT;
+        }
+        match (self.start_index, self.stop_index) {
+            (None, _) => (),
+            (Some(_), None) => flags |= TlvFlags::HAS_SINGLE_INDEX,
+            (Some(_), Some(_)) => flags |= TlvFlags::HAS_MULTI_INDEX,
+        }
+        if let Some(value_len) = value_len {
+            if value_len > usize::from(core::u16::MAX) {
+                return Err(Error::InvalidTlvValue);
+            }
+
+            flags |= TlvFlags::HAS_VALUE;
+            if value_len > 255 {
+                flags |= TlvFlags::HAS_EXT_LEN;
+            }
+            if self.multi_value {
+                flags |= TlvFlags::IS_MULTI_VALUE;
+            }
+        }
+
+        buf.put_u8(self.r#type)?;
+        buf.put_u8(flags.bits())?;
+        if let Some(type_ext) = self.type_ext {
+            buf.put_u8(type_ext)?;
+        }
+        if let Some(start_index) = self.start_index {
+            buf.put_u8(start_index)?;
+            if let Some(stop_index) = self.stop_index {
+                buf.put_u8(stop_index)?;
+            }
+        }
+        if let Some(value_len) = value_len {
+            if value_len > 255 {
+                buf.put_ne_u16(value_len as u16)?;
+            } else {
+                buf.put_u8(value_len as u8)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Type extension, an absent `<tlv-type-ext>` is the same as 0.
+    pub fn type_ext(&self) -> u8 {
+        self.type_ext.unwrap_or(0)
+    }
+
+    /// Inclusive range of indexes of the addresses the TLV applies to, on an
+    /// address block of `num_addr` addresses.
+    pub fn index_range(
+        &self,
+        num_addr: usize,
+    ) -> Result<(usize, usize), Error> {
+        let (start, stop) = match (self.start_index, self.stop_index) {
+            (None, _) => (0, num_addr.saturating_sub(1)),
+            (Some(start), None) => (usize::from(start), usize::from(start)),
+            (Some(start), Some(stop)) => {
+                (usize::from(start), usize::from(stop))
+            }
+        };
+
+        if num_addr == 0 || start > stop || stop >= num_addr {
+            return Err(Error::InvalidTlvIndex);
+        }
+
+        Ok((start, stop))
+    }
+
+    /// Value of the TLV for the address at `index`, on an address block of
+    /// `num_addr` addresses.
+    ///
+    /// Returns `None` if the TLV doesn't apply to that address, and an empty
+    /// value if the TLV has no value.
+    pub fn value_at(
+        &self,
+        index: usize,
+        num_addr: usize,
+    ) -> Result<Option<&'a [u8]>, Error> {
+        let (start, stop) = self.index_range(num_addr)?;
+        if index < start || index > stop {
+            return Ok(None);
+        }
+
+        let value = match self.value {
+            Some(value) => value,
+            None => return Ok(Some(&[])),
+        };
+
+        if !self.multi_value {
+            return Ok(Some(value));
+        }
+
+        let count = stop - start + 1;
+        if value.len() % count != 0 {
+            return Err(Error::InvalidTlvValue);
+        }
+
+        let size = value.len() / count;
+        let offset = (index - start) * size;
+        Ok(Some(&value[offset..offset + size]))
+    }
 }
 
 /// TLV block
@@ -121,6 +267,11 @@ impl<'a> TlvBlock<'a> {
         Ok(TlvBlock { buf: block })
     }
 
+    /// Get the bytes of the TLVs, without the `<tlvs-length>` field.
+    pub fn as_bytes(&self) -> &'a [u8] {
+        self.buf.buf
+    }
+
     /// Iterator over a TLV block entries
     pub fn iter(&self) -> TlvBlockIter<'a> {
         TlvBlockIter {
@@ -150,3 +301,21 @@ impl<'a> Iterator for TlvBlockIter<'a> {
         }
     }
 }
+
+/// Call `f` with each TLV of the TLVs `tlvs` and its raw bytes.
+pub(crate) fn for_each_raw_tlv<'a, F>(
+    tlvs: &'a [u8],
+    mut f: F,
+) -> Result<(), Error>
+where
+    F: FnMut(&Tlv<'a>, &'a [u8]) -> Result<(), Error>,
+{
+    let mut buf = Buf::new(tlvs);
+    while !buf.is_eof() {
+        let start = buf.pos();
+        let tlv = Tlv::read(&mut buf)?;
+        f(&tlv, &tlvs[start..buf.pos()])?;
+    }
+
+    Ok(())
+}
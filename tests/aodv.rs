@@ -0,0 +1,191 @@
+// Copyright 2020 Jean Pierre Dudey. See the LICENSE-MIT and
+// LICENSE-APACHE files at the top-level directory of this
+// distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+#![cfg(feature = "use_std")]
+
+use rfc5444::aodv::{
+    Aodv, AodvConfig, Event, NeighborState, RouteState, Routing,
+};
+use rfc5444::{Address, Buf, Clock, ManualClock, Message};
+
+/// Routers on a line, each one a neighbor of the previous and the next one.
+struct Line<'a> {
+    addrs: Vec<Address>,
+    nodes: Vec<Aodv<&'a ManualClock>>,
+    links: Vec<bool>,
+}
+
+impl<'a> Line<'a> {
+    fn new(clock: &'a ManualClock, n: u8) -> Self {
+        let mut addrs = Vec::new();
+        let mut nodes = Vec::new();
+        for i in 1..=n {
+            let addr = Address::from_bytes(&[10, 0, 0, i]).unwrap();
+            let mut node = Aodv::new(clock, 4, AodvConfig::default());
+            node.add_client(addr, None);
+            addrs.push(addr);
+            nodes.push(node);
+        }
+        let links = vec![true; n as usize - 1];
+
+        Line {
+            addrs,
+            nodes,
+            links,
+        }
+    }
+
+    fn index(&self, addr: &Address) -> usize {
+        self.addrs.iter().position(|a| a == addr).unwrap()
+    }
+
+    fn linked(&self, i: usize, j: usize) -> bool {
+        (i + 1 == j || j + 1 == i) && self.links[i.min(j)]
+    }
+
+    /// Deliver the messages until there are none left.
+    fn run(&mut self) {
+        loop {
+            let mut sent = false;
+            for i in 0..self.nodes.len() {
+                while let Some(send) = self.nodes[i].poll_send() {
+                    sent = true;
+                    let from = self.addrs[i];
+                    for j in 0..self.nodes.len() {
+                        let to = send.to.map_or(true, |a| a == self.addrs[j]);
+                        if !to || !self.linked(i, j) {
+                            continue;
+                        }
+
+                        let msg =
+                            Message::read(&mut Buf::new(&send.msg)).unwrap();
+                        self.nodes[j].receive(&from, &msg).unwrap();
+                    }
+                }
+            }
+            if !sent {
+                return;
+            }
+        }
+    }
+
+    /// Call `expire` on every node until `end`.
+    fn run_until(&mut self, clock: &ManualClock, end: u64) {
+        loop {
+            let next = self.nodes.iter().filter_map(|n| n.next_expiry()).min();
+            match next {
+                Some(t) if t <= end => {
+                    clock.set(t.max(clock.now()));
+                    for node in self.nodes.iter_mut() {
+                        node.expire();
+                    }
+                    self.run();
+                }
+                _ => break,
+            }
+        }
+        clock.set(end);
+    }
+
+    /// Path followed by a data packet from node `src` to node `dest`.
+    fn forward(&mut self, src: usize, dest: usize) -> Vec<usize> {
+        let (s, d) = (self.addrs[src], self.addrs[dest]);
+        let mut path = vec![src];
+        let mut i = src;
+        while i != dest {
+            match self.nodes[i].route(&s, &d) {
+                Routing::Forward(next_hop) => i = self.index(&next_hop),
+                r => panic!("{:?} at node {}", r, i),
+            }
+            path.push(i);
+        }
+        path
+    }
+}
+
+#[test]
+fn test_line_discovery_and_forwarding() {
+    let clock = ManualClock::new(0);
+    let mut line = Line::new(&clock, 4);
+    let (a, d) = (line.addrs[0], line.addrs[3]);
+
+    assert_eq!(line.nodes[0].route(&a, &d), Routing::Discovering);
+    line.run();
+    assert_eq!(line.nodes[0].poll_event(), Some(Event::RouteFound(d)));
+
+    // The RREPs asked for RREP_Acks, the links on the path are confirmed.
+    for i in 1..4 {
+        let n = line.nodes[i].neighbors();
+        let prev = n.iter().find(|n| n.addr == line.addrs[i - 1]).unwrap();
+        assert_eq!(prev.state, NeighborState::Confirmed);
+    }
+    let route = line.nodes[3].routes()[0];
+    assert_eq!((route.addr, route.metric), (a, 3));
+    assert_eq!(route.state, RouteState::Idle);
+    let route = line.nodes[0].routes().iter().find(|r| r.addr == d).unwrap();
+    assert_eq!((route.next_hop, route.metric), (line.addrs[1], 3));
+
+    // Data packets make the routes Active, both ways.
+    assert_eq!(line.forward(0, 3), [0, 1, 2, 3]);
+    assert_eq!(line.forward(3, 0), [3, 2, 1, 0]);
+    for node in line.nodes[..3].iter() {
+        let route = node.routes().iter().find(|r| r.addr == d).unwrap();
+        assert_eq!(route.state, RouteState::Active);
+    }
+
+    // Unused routes become Idle.
+    clock.set(5_000);
+    line.nodes[1].expire();
+    let route = line.nodes[1].routes().iter().find(|r| r.addr == d).unwrap();
+    assert_eq!(route.state, RouteState::Idle);
+}
+
+#[test]
+fn test_line_link_break() {
+    let clock = ManualClock::new(0);
+    let mut line = Line::new(&clock, 4);
+    let (a, d) = (line.addrs[0], line.addrs[3]);
+
+    line.nodes[0].route(&a, &d);
+    line.run();
+    assert_eq!(line.nodes[0].poll_event(), Some(Event::RouteFound(d)));
+    line.forward(0, 3);
+
+    // B-C breaks, B sends a RERR for D that reaches A.
+    line.links[1] = false;
+    let c = line.addrs[2];
+    line.nodes[1].link_broken(&c);
+    line.run();
+    let lost = Event::RouteLost {
+        addr: d,
+        prefix_length: 32,
+    };
+    assert_eq!(line.nodes[1].poll_event(), Some(lost));
+    assert_eq!(line.nodes[0].poll_event(), Some(lost));
+    let route = line.nodes[0].routes().iter().find(|r| r.addr == d).unwrap();
+    assert_eq!(route.state, RouteState::Invalid);
+
+    // D can't be found anymore.
+    assert_eq!(line.nodes[0].route(&a, &d), Routing::Discovering);
+    line.run();
+    line.run_until(&clock, 13_999);
+    assert_eq!(line.nodes[0].poll_event(), None);
+    line.run_until(&clock, 14_000);
+    assert_eq!(line.nodes[0].poll_event(), Some(Event::DiscoveryFailed(d)));
+    assert_eq!(line.nodes[0].route(&a, &d), Routing::Unreachable);
+
+    // After the hold down, with the link back, it is found again.
+    line.links[1] = true;
+    line.run_until(&clock, 24_000);
+    assert_eq!(line.nodes[0].route(&a, &d), Routing::Discovering);
+    line.run();
+    assert_eq!(line.nodes[0].poll_event(), Some(Event::RouteFound(d)));
+    assert_eq!(line.forward(0, 3), [0, 1, 2, 3]);
+}
@@ -61,3 +61,34 @@ fn test_parse_nhdp() {
 
     assert!(msg.tlv_block.iter().next().is_none());
 }
+
+#[test]
+fn test_hello_nhdp() {
+    use rfc5444::nhdp::{Hello, HelloAddress, LinkStatus, LocalIf};
+    use rfc5444::{Address, Buf, Message};
+
+    // The message above isn't a HELLO.
+    let pkt = rfc5444::Packet::read(RESULT).unwrap();
+    let msg = pkt.messages.iter().next().unwrap().unwrap();
+    assert!(Hello::from_message(&msg).is_err());
+
+    let mut hello = Hello::new(4, 6000);
+    hello.interval_time = Some(2000);
+    let mut local =
+        HelloAddress::new(Address::from_bytes(&[10, 1, 0, 101]).unwrap());
+    local.local_if = Some(LocalIf::ThisIf);
+    let mut neighbors = [local; 3];
+    for (i, n) in neighbors.iter_mut().enumerate() {
+        n.addr = Address::from_bytes(&[10, 1, 0, 102 + i as u8]).unwrap();
+        n.local_if = None;
+        n.link_status = Some(LinkStatus::Symmetric);
+    }
+    let addrs = [local, neighbors[0], neighbors[1], neighbors[2]];
+
+    let mut buf = [0u8; 64];
+    let size = hello.write(&addrs, &mut buf).unwrap();
+    let msg = Message::read(&mut Buf::new(&buf[..size])).unwrap();
+    let parsed = Hello::from_message(&msg).unwrap();
+    assert_eq!(parsed.interval_time, Some(2000));
+    assert!(parsed.addresses().eq(addrs.iter().cloned()));
+}
@@ -0,0 +1,74 @@
+// Copyright 2020 Jean Pierre Dudey. See the LICENSE-MIT and
+// LICENSE-APACHE files at the top-level directory of this
+// distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+#![cfg(feature = "use_std")]
+
+use rfc5444::nhdp::{Hello, InterfaceId, Nhdp, NhdpConfig};
+use rfc5444::smf::{
+    RelayGraph, RelaySet, Rssa, SmfType, DEFAULT_ROUTER_PRIORITY,
+};
+use rfc5444::{Address, Buf, ManualClock, Message};
+
+struct Router<'a> {
+    addr: Address,
+    nhdp: Nhdp<&'a ManualClock>,
+    iface: InterfaceId,
+}
+
+impl<'a> Router<'a> {
+    fn new(clock: &'a ManualClock, i: u8) -> Self {
+        let addr = Address::from_bytes(&[10, 0, 0, i]).unwrap();
+        let mut nhdp = Nhdp::new(clock, 4, NhdpConfig::default());
+        let iface = nhdp.add_interface(&[addr]);
+        Router { addr, nhdp, iface }
+    }
+
+    /// Send a HELLO advertising E-CDS to `to`.
+    fn send(&mut self, to: &mut Router) {
+        let (mut hello, addrs) = self.nhdp.hello(self.iface).unwrap();
+        hello.smf_type = Some(SmfType::new(Rssa::ECds));
+        let mut buf = [0u8; 256];
+        let size = hello.write(&addrs, &mut buf).unwrap();
+        let msg = Message::read(&mut Buf::new(&buf[..size])).unwrap();
+        let hello = Hello::from_message(&msg).unwrap();
+        assert_eq!(hello.smf_type, Some(SmfType::new(Rssa::ECds)));
+        to.nhdp.process_hello(to.iface, &self.addr, &hello).unwrap();
+    }
+
+    fn relay_set(&self) -> RelaySet<Address> {
+        let graph = RelayGraph::from_nhdp(
+            &self.nhdp,
+            self.iface,
+            self.addr,
+            DEFAULT_ROUTER_PRIORITY,
+        );
+        graph.select(Rssa::ECds)
+    }
+}
+
+#[test]
+fn test_e_cds_line() {
+    // 1 - 2 - 3, three rounds of HELLOs to learn the 2-hop neighbors.
+    let clock = ManualClock::new(1000);
+    let mut routers =
+        (1..=3).map(|i| Router::new(&clock, i)).collect::<Vec<_>>();
+    for _ in 0..3 {
+        for i in 0..2 {
+            let (a, b) = routers.split_at_mut(i + 1);
+            a[i].send(&mut b[0]);
+            b[0].send(&mut a[i]);
+        }
+    }
+
+    // Only the middle router relays, the ends have a single neighbor.
+    assert_eq!(routers[0].relay_set(), RelaySet::None);
+    assert_eq!(routers[1].relay_set(), RelaySet::All);
+    assert_eq!(routers[2].relay_set(), RelaySet::None);
+}